@@ -0,0 +1,8 @@
+fn main() {
+    // Cargo only gives the target triple to build scripts, pass it on for `info version`
+    println!(
+        "cargo:rustc-env=RUSTYBUG_TARGET={}",
+        std::env::var("TARGET").unwrap_or_else(|_| "unknown".to_string())
+    );
+    println!("cargo:rerun-if-changed=build.rs");
+}
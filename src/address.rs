@@ -0,0 +1,259 @@
+//! Addresses and lengths given to the commands that read or write memory, `x rsp+16 4g` or
+//! `snapshot save a 0x404028 16`, so they all accept the same forms with the same errors.
+//!
+//! An address is a number, decimal or hex starting with 0x, or a general purpose register with
+//! an optional offset, e.g. `rsp`, `rbp-0x8` or `rip+2`. Register relative addresses are worked
+//! out from the registers when the command is run, so a `display` of one follows the register.
+//!
+//! A length is a count of items with an optional size suffix, `b` for bytes, `h` for 2 byte
+//! halfwords, `w` for 4 byte words and `g` for 8 byte giants. Without a suffix the items are
+//! bytes. Suffixes only go on decimal counts as the letters are hex digits, `0x1b` is 27 bytes.
+use crate::process::{Process, ProcessError, Registers};
+use std::fmt;
+use thiserror::Error;
+
+/// Most bytes a single command will read, anything bigger is almost certainly a typo.
+pub const MAX_LENGTH: usize = 1 << 20;
+
+#[derive(Debug, Error)]
+pub enum AddressError {
+    #[error("nothing provided")]
+    Empty,
+    #[error("invalid number \"{0}\"")]
+    InvalidNumber(String),
+    #[error("\"{0}\" isn't a number or general purpose register")]
+    UnknownRegister(String),
+    #[error("unknown size '{0}', expected one of: b, h, w, g")]
+    UnknownUnit(char),
+    #[error("length has to be at least 1")]
+    ZeroLength,
+    #[error("length is more than the {MAX_LENGTH} bytes that can be used at once")]
+    TooLong,
+    #[error("{0} is outside the address space")]
+    OutOfRange(String),
+    #[error(transparent)]
+    Process(#[from] ProcessError),
+}
+
+/// Where a memory command starts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Address {
+    Absolute(u64),
+    /// The value of a general purpose register plus an offset
+    Register {
+        name: String,
+        offset: i64,
+    },
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Absolute(addr) => write!(f, "0x{:x}", addr),
+            Self::Register { name, offset: 0 } => write!(f, "{}", name),
+            Self::Register { name, offset } if *offset < 0 => {
+                write!(f, "{}-0x{:x}", name, offset.unsigned_abs())
+            }
+            Self::Register { name, offset } => write!(f, "{}+0x{:x}", name, offset),
+        }
+    }
+}
+
+impl From<u64> for Address {
+    fn from(addr: u64) -> Self {
+        Self::Absolute(addr)
+    }
+}
+
+impl Address {
+    /// Works out the address, reading the registers of `process` if it's register relative.
+    pub fn resolve(&self, process: &Process) -> Result<u64, AddressError> {
+        match self {
+            Self::Absolute(addr) => Ok(*addr),
+            Self::Register { name, offset } => {
+                let value = process.get_gp_registers()?.get(name).unwrap_or_default();
+                value
+                    .checked_add_signed(*offset)
+                    .ok_or_else(|| AddressError::OutOfRange(self.to_string()))
+            }
+        }
+    }
+
+    /// Works out the address like `resolve`, checking `len` bytes from it don't run past the end
+    /// of the address space.
+    pub fn resolve_range(&self, process: &Process, len: usize) -> Result<u64, AddressError> {
+        let addr = self.resolve(process)?;
+        match addr.checked_add(len as u64) {
+            Some(_) => Ok(addr),
+            None => Err(AddressError::OutOfRange(format!("0x{:x} + {}", addr, len))),
+        }
+    }
+}
+
+/// Parses a number as 0x prefixed hexadecimal or decimal.
+pub fn parse_number(s: &str) -> Result<u64, AddressError> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse::<u64>(),
+    }
+    .map_err(|_| AddressError::InvalidNumber(s.to_string()))
+}
+
+/// Parses an address, a number or a register with an optional `+` or `-` offset.
+pub fn parse_address(s: &str) -> Result<Address, AddressError> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err(AddressError::Empty);
+    }
+    // Skip the first character so the offset is never the whole thing
+    let split = s
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| matches!(c, '+' | '-'));
+    let (base, offset) = match split {
+        Some((i, _)) => s.split_at(i),
+        None => (s, ""),
+    };
+    let offset = match offset.split_at_checked(1) {
+        None => 0,
+        Some((sign, value)) => {
+            let value = i64::try_from(parse_number(value)?)
+                .map_err(|_| AddressError::OutOfRange(s.to_string()))?;
+            if sign == "-" {
+                -value
+            } else {
+                value
+            }
+        }
+    };
+    if s.as_bytes()[0].is_ascii_digit() {
+        parse_number(base)?
+            .checked_add_signed(offset)
+            .map(Address::Absolute)
+            .ok_or_else(|| AddressError::OutOfRange(s.to_string()))
+    } else if Registers::GENERAL_PURPOSE.contains(&base) {
+        Ok(Address::Register {
+            name: base.to_string(),
+            offset,
+        })
+    } else {
+        Err(AddressError::UnknownRegister(base.to_string()))
+    }
+}
+
+/// Parses a length in bytes, a count of items with an optional b, h, w or g size suffix.
+pub fn parse_length(s: &str) -> Result<usize, AddressError> {
+    let s = s.trim();
+    let (count, size) = match s.chars().last() {
+        None => return Err(AddressError::Empty),
+        Some(c) if c.is_ascii_alphabetic() && !s.starts_with("0x") => {
+            let size = match c {
+                'b' => 1,
+                'h' => 2,
+                'w' => 4,
+                'g' => 8,
+                c => return Err(AddressError::UnknownUnit(c)),
+            };
+            (&s[..s.len() - 1], size)
+        }
+        Some(_) => (s, 1),
+    };
+    let count = parse_number(count)?;
+    let len = count
+        .checked_mul(size)
+        .filter(|len| *len <= MAX_LENGTH as u64)
+        .ok_or(AddressError::TooLong)?;
+    match len {
+        0 => Err(AddressError::ZeroLength),
+        len => Ok(len as usize),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(name: &str, offset: i64) -> Address {
+        Address::Register {
+            name: name.to_string(),
+            offset,
+        }
+    }
+
+    #[test]
+    fn addresses() {
+        assert_eq!(parse_address("0x1000").unwrap(), Address::Absolute(0x1000));
+        assert_eq!(parse_address("4096").unwrap(), Address::Absolute(0x1000));
+        assert_eq!(
+            parse_address("0x1000+16").unwrap(),
+            Address::Absolute(0x1010)
+        );
+        assert_eq!(
+            parse_address("0x1000-0x10").unwrap(),
+            Address::Absolute(0xff0)
+        );
+        assert_eq!(parse_address("rsp").unwrap(), register("rsp", 0));
+        assert_eq!(parse_address("rsp+16").unwrap(), register("rsp", 16));
+        assert_eq!(parse_address("rbp-0x8").unwrap(), register("rbp", -8));
+
+        assert!(matches!(parse_address(""), Err(AddressError::Empty)));
+        assert!(matches!(
+            parse_address("main"),
+            Err(AddressError::UnknownRegister(_))
+        ));
+        assert!(matches!(
+            parse_address("rsp+lots"),
+            Err(AddressError::InvalidNumber(_))
+        ));
+        assert!(matches!(
+            parse_address("0x10-0x20"),
+            Err(AddressError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            parse_address("rsp+0xffffffffffffffff"),
+            Err(AddressError::OutOfRange(_))
+        ));
+        assert!(parse_address("0x1000 16").is_err());
+    }
+
+    #[test]
+    fn address_display_round_trips() {
+        for s in ["0x1000", "rsp", "rsp+0x10", "rbp-0x8"] {
+            assert_eq!(parse_address(s).unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn lengths() {
+        assert_eq!(parse_length("16").unwrap(), 16);
+        assert_eq!(parse_length("0x20").unwrap(), 32);
+        assert_eq!(parse_length("0x1b").unwrap(), 27);
+        assert_eq!(parse_length("64b").unwrap(), 64);
+        assert_eq!(parse_length("3h").unwrap(), 6);
+        assert_eq!(parse_length("16w").unwrap(), 64);
+        assert_eq!(parse_length("8g").unwrap(), 64);
+
+        assert!(matches!(parse_length("0"), Err(AddressError::ZeroLength)));
+        assert!(matches!(parse_length("0g"), Err(AddressError::ZeroLength)));
+        assert!(matches!(
+            parse_length("4q"),
+            Err(AddressError::UnknownUnit('q'))
+        ));
+        assert!(matches!(
+            parse_length("g"),
+            Err(AddressError::InvalidNumber(_))
+        ));
+        assert!(matches!(
+            parse_length("lots"),
+            Err(AddressError::UnknownUnit('s'))
+        ));
+        assert!(matches!(
+            parse_length("1048577"),
+            Err(AddressError::TooLong)
+        ));
+        assert!(matches!(
+            parse_length("4611686018427387904g"),
+            Err(AddressError::TooLong)
+        ));
+    }
+}
@@ -0,0 +1,350 @@
+//! A small x86-64 assembler for patching a running process, `asm 0x401000 nop`. Only a handful
+//! of common instructions are supported: `nop`, `ret`, `int3`, `ud2`, `hlt`, `leave`,
+//! `syscall`, `jmp`/`call`/`j<cc>` to an absolute address, `push`/`pop` of a 64 bit register and
+//! `mov <reg>, <imm>`.
+//!
+//! Jumps and calls are encoded relative to the address the instruction is written at, using the
+//! short form where the target is close enough.
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum AsmError {
+    #[error("empty instruction")]
+    Empty,
+    #[error("unsupported instruction \"{0}\"")]
+    UnknownMnemonic(String),
+    #[error("{mnemonic} expects {expected}")]
+    WrongOperands {
+        mnemonic: String,
+        expected: &'static str,
+    },
+    #[error("invalid operand \"{0}\"")]
+    InvalidOperand(String),
+    #[error("0x{target:x} is too far from 0x{from:x} for a relative jump")]
+    OutOfRange { from: u64, target: u64 },
+}
+
+/// Bytes written into the process and the bytes they replaced.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Patch {
+    pub addr: u64,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+/// The 64 bit general purpose registers in encoding order.
+const REGISTERS: &[&str] = &[
+    "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15",
+];
+
+/// Condition code suffixes for `j<cc>` with the value that goes in the opcode, including the
+/// aliases assemblers accept.
+const CONDITIONS: &[(&str, u8)] = &[
+    ("o", 0x0),
+    ("no", 0x1),
+    ("b", 0x2),
+    ("c", 0x2),
+    ("nae", 0x2),
+    ("ae", 0x3),
+    ("nb", 0x3),
+    ("nc", 0x3),
+    ("e", 0x4),
+    ("z", 0x4),
+    ("ne", 0x5),
+    ("nz", 0x5),
+    ("be", 0x6),
+    ("na", 0x6),
+    ("a", 0x7),
+    ("nbe", 0x7),
+    ("s", 0x8),
+    ("ns", 0x9),
+    ("p", 0xa),
+    ("pe", 0xa),
+    ("np", 0xb),
+    ("po", 0xb),
+    ("l", 0xc),
+    ("nge", 0xc),
+    ("ge", 0xd),
+    ("nl", 0xd),
+    ("le", 0xe),
+    ("ng", 0xe),
+    ("g", 0xf),
+    ("nle", 0xf),
+];
+
+/// A register by its number in instruction encodings, `rax` is 0 and `r15` 15.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Register(u8);
+
+impl Register {
+    fn low_bits(&self) -> u8 {
+        self.0 & 0x7
+    }
+
+    /// Registers r8-r15 need the REX.B bit set to be reached.
+    fn is_extended(&self) -> bool {
+        self.0 >= 8
+    }
+}
+
+impl FromStr for Register {
+    type Err = AsmError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        REGISTERS
+            .iter()
+            .position(|r| *r == s)
+            .map(|i| Self(i as u8))
+            .ok_or_else(|| AsmError::InvalidOperand(s.to_string()))
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(REGISTERS[self.0 as usize])
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Instruction {
+    Nop,
+    Ret,
+    Int3,
+    Ud2,
+    Hlt,
+    Leave,
+    Syscall,
+    Jmp(u64),
+    Call(u64),
+    /// Conditional jump, the condition code and target address
+    Jcc(u8, u64),
+    Push(Register),
+    Pop(Register),
+    MovImm(Register, u64),
+}
+
+impl FromStr for Instruction {
+    type Err = AsmError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        let (mnemonic, operands) = s.split_once(char::is_whitespace).unwrap_or((&s, ""));
+        let operands = operands
+            .split(',')
+            .map(str::trim)
+            .filter(|op| !op.is_empty())
+            .collect::<Vec<_>>();
+        let wrong_operands = |expected| AsmError::WrongOperands {
+            mnemonic: mnemonic.to_string(),
+            expected,
+        };
+        let no_operands = |instruction| {
+            if operands.is_empty() {
+                Ok(instruction)
+            } else {
+                Err(wrong_operands("no operands"))
+            }
+        };
+        let target = || match operands.as_slice() {
+            [target] => parse_number(target),
+            _ => Err(wrong_operands("a target address")),
+        };
+        let register = || match operands.as_slice() {
+            [register] => Register::from_str(register),
+            _ => Err(wrong_operands("a register")),
+        };
+
+        match mnemonic {
+            "" => Err(AsmError::Empty),
+            "nop" => no_operands(Self::Nop),
+            "ret" => no_operands(Self::Ret),
+            "int3" => no_operands(Self::Int3),
+            "ud2" => no_operands(Self::Ud2),
+            "hlt" => no_operands(Self::Hlt),
+            "leave" => no_operands(Self::Leave),
+            "syscall" => no_operands(Self::Syscall),
+            "jmp" => Ok(Self::Jmp(target()?)),
+            "call" => Ok(Self::Call(target()?)),
+            "push" => Ok(Self::Push(register()?)),
+            "pop" => Ok(Self::Pop(register()?)),
+            "mov" => match operands.as_slice() {
+                [register, imm] => {
+                    let imm = match imm.strip_prefix('-') {
+                        Some(imm) => parse_number(imm)?.wrapping_neg(),
+                        None => parse_number(imm)?,
+                    };
+                    Ok(Self::MovImm(Register::from_str(register)?, imm))
+                }
+                _ => Err(wrong_operands("a register and an immediate")),
+            },
+            x => match x
+                .strip_prefix('j')
+                .and_then(|cc| CONDITIONS.iter().find(|(name, _)| *name == cc))
+            {
+                Some((_, cc)) => Ok(Self::Jcc(*cc, target()?)),
+                None => Err(AsmError::UnknownMnemonic(mnemonic.to_string())),
+            },
+        }
+    }
+}
+
+fn parse_number(s: &str) -> Result<u64, AsmError> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse::<u64>(),
+    }
+    .map_err(|_| AsmError::InvalidOperand(s.to_string()))
+}
+
+impl Instruction {
+    /// Encodes the instruction as if it was at `addr`, which only matters for jumps and calls.
+    pub fn encode(&self, addr: u64) -> Result<Vec<u8>, AsmError> {
+        let bytes = match *self {
+            Self::Nop => vec![0x90],
+            Self::Ret => vec![0xc3],
+            Self::Int3 => vec![0xcc],
+            Self::Ud2 => vec![0x0f, 0x0b],
+            Self::Hlt => vec![0xf4],
+            Self::Leave => vec![0xc9],
+            Self::Syscall => vec![0x0f, 0x05],
+            Self::Jmp(target) => match short_offset(addr, 2, target) {
+                Some(rel) => vec![0xeb, rel as u8],
+                None => relative(&[0xe9], addr, target)?,
+            },
+            Self::Call(target) => relative(&[0xe8], addr, target)?,
+            Self::Jcc(cc, target) => match short_offset(addr, 2, target) {
+                Some(rel) => vec![0x70 | cc, rel as u8],
+                None => relative(&[0x0f, 0x80 | cc], addr, target)?,
+            },
+            Self::Push(reg) => with_rex_b(reg, 0x50 + reg.low_bits()),
+            Self::Pop(reg) => with_rex_b(reg, 0x58 + reg.low_bits()),
+            Self::MovImm(reg, imm) => {
+                let rex_w = 0x48 | reg.is_extended() as u8;
+                if let Ok(imm) = u32::try_from(imm) {
+                    // Writing the 32 bit register zeroes the top half so this is the shortest
+                    let mut bytes = with_rex_b(reg, 0xb8 + reg.low_bits());
+                    bytes.extend_from_slice(&imm.to_le_bytes());
+                    bytes
+                } else if let Ok(imm) = i32::try_from(imm as i64) {
+                    // Sign extended 32 bit immediate
+                    let mut bytes = vec![rex_w, 0xc7, 0xc0 | reg.low_bits()];
+                    bytes.extend_from_slice(&imm.to_le_bytes());
+                    bytes
+                } else {
+                    let mut bytes = vec![rex_w, 0xb8 + reg.low_bits()];
+                    bytes.extend_from_slice(&imm.to_le_bytes());
+                    bytes
+                }
+            }
+        };
+        Ok(bytes)
+    }
+}
+
+/// The 8 bit offset to `target` for an instruction of `len` bytes at `addr` if it fits.
+fn short_offset(addr: u64, len: u64, target: u64) -> Option<i8> {
+    i8::try_from(target.wrapping_sub(addr.wrapping_add(len)) as i64).ok()
+}
+
+/// An opcode followed by a 32 bit offset to `target`.
+fn relative(opcode: &[u8], addr: u64, target: u64) -> Result<Vec<u8>, AsmError> {
+    let len = opcode.len() as u64 + 4;
+    let rel = i32::try_from(target.wrapping_sub(addr.wrapping_add(len)) as i64)
+        .map_err(|_| AsmError::OutOfRange { from: addr, target })?;
+    let mut bytes = opcode.to_vec();
+    bytes.extend_from_slice(&rel.to_le_bytes());
+    Ok(bytes)
+}
+
+fn with_rex_b(reg: Register, opcode: u8) -> Vec<u8> {
+    if reg.is_extended() {
+        vec![0x41, opcode]
+    } else {
+        vec![opcode]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble(s: &str, addr: u64) -> Vec<u8> {
+        Instruction::from_str(s).unwrap().encode(addr).unwrap()
+    }
+
+    #[test]
+    fn simple_instructions() {
+        assert_eq!(assemble("nop", 0), vec![0x90]);
+        assert_eq!(assemble(" RET ", 0), vec![0xc3]);
+        assert_eq!(assemble("int3", 0), vec![0xcc]);
+        assert_eq!(assemble("syscall", 0), vec![0x0f, 0x05]);
+        assert_eq!(assemble("push rbp", 0), vec![0x55]);
+        assert_eq!(assemble("push r12", 0), vec![0x41, 0x54]);
+        assert_eq!(assemble("pop r15", 0), vec![0x41, 0x5f]);
+    }
+
+    #[test]
+    fn relative_jumps() {
+        assert_eq!(assemble("jmp 0x401020", 0x401000), vec![0xeb, 0x1e]);
+        assert_eq!(assemble("jmp 0x400ff0", 0x401000), vec![0xeb, 0xee]);
+        assert_eq!(
+            assemble("jmp 0x402000", 0x401000),
+            vec![0xe9, 0xfb, 0x0f, 0x00, 0x00]
+        );
+        assert_eq!(
+            assemble("call 0x401000", 0x401100),
+            vec![0xe8, 0xfb, 0xfe, 0xff, 0xff]
+        );
+        assert_eq!(assemble("je 0x401010", 0x401000), vec![0x74, 0x0e]);
+        assert_eq!(
+            assemble("jnz 0x402000", 0x401000),
+            vec![0x0f, 0x85, 0xfa, 0x0f, 0x00, 0x00]
+        );
+        assert_eq!(
+            Instruction::from_str("jmp 0x7fff00000000")
+                .unwrap()
+                .encode(0x401000),
+            Err(AsmError::OutOfRange {
+                from: 0x401000,
+                target: 0x7fff00000000
+            })
+        );
+    }
+
+    #[test]
+    fn move_immediates() {
+        assert_eq!(assemble("mov rax, 1", 0), vec![0xb8, 1, 0, 0, 0]);
+        assert_eq!(assemble("mov r9, 0x10", 0), vec![0x41, 0xb9, 0x10, 0, 0, 0]);
+        assert_eq!(
+            assemble("mov rdi, -1", 0),
+            vec![0x48, 0xc7, 0xc7, 0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(
+            assemble("mov rax, 0x123456789", 0),
+            vec![0x48, 0xb8, 0x89, 0x67, 0x45, 0x23, 0x01, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn invalid_instructions() {
+        assert_eq!(
+            Instruction::from_str("frobnicate"),
+            Err(AsmError::UnknownMnemonic("frobnicate".to_string()))
+        );
+        assert_eq!(
+            Instruction::from_str("nop 1"),
+            Err(AsmError::WrongOperands {
+                mnemonic: "nop".to_string(),
+                expected: "no operands"
+            })
+        );
+        assert_eq!(
+            Instruction::from_str("push eax"),
+            Err(AsmError::InvalidOperand("eax".to_string()))
+        );
+        assert_eq!(Instruction::from_str(""), Err(AsmError::Empty));
+    }
+}
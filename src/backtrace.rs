@@ -0,0 +1,96 @@
+//! Stack unwinding by following the chain of saved frame pointers. This relies on the program
+//! being compiled with frame pointers, which is the default for unoptimised C but not for Rust
+//! or optimised code. Each frame looks like:
+//!
+//! ```text
+//! rbp + 8: return address into the caller
+//! rbp:     caller's rbp
+//! ```
+use crate::process::{Process, ProcessError};
+
+/// Default for the `backtrace-limit` setting.
+pub const DEFAULT_MAX_FRAMES: usize = 64;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Frame {
+    /// Address of the instruction being executed in this frame
+    pub pc: u64,
+    /// Value of the frame pointer in this frame
+    pub frame_pointer: u64,
+    /// Whether `pc` is a return address, which is true for every frame but the innermost
+    pub is_return_address: bool,
+}
+
+impl Frame {
+    /// Address to use when looking up the function or line of the frame. A return address is
+    /// the instruction after the call which may be on a different line or even past the end of
+    /// the function, so use an address inside the call instruction instead.
+    pub fn lookup_address(&self) -> u64 {
+        if self.is_return_address {
+            self.pc - 1
+        } else {
+            self.pc
+        }
+    }
+}
+
+/// Why the stack walk ended before reaching the outermost frame.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Truncated {
+    /// Reached the maximum number of frames
+    Limit(usize),
+    /// The saved frame pointer didn't move up the stack, so following it would loop forever or
+    /// wander off into a corrupt stack
+    Cycle { frame_pointer: u64, saved: u64 },
+    /// The frame pointer pointed at memory we couldn't read
+    Unreadable(u64),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Backtrace {
+    /// Innermost frame first
+    pub frames: Vec<Frame>,
+    pub truncated: Option<Truncated>,
+}
+
+/// Walks the frame pointer chain of a stopped process, returning at most `limit` frames.
+pub fn walk(process: &Process, limit: usize) -> Result<Backtrace, ProcessError> {
+    let regs = process.get_gp_registers()?.regs;
+    let mut frames = vec![];
+    let mut frame = Frame {
+        pc: regs.rip,
+        frame_pointer: regs.rbp,
+        is_return_address: false,
+    };
+    let truncated = loop {
+        if frames.len() >= limit {
+            break Some(Truncated::Limit(limit));
+        }
+        frames.push(frame);
+        // The ABI has the outermost frame zero the frame pointer
+        if frame.frame_pointer == 0 {
+            break None;
+        }
+        let Ok(data) = process.read_memory(frame.frame_pointer, 16) else {
+            break Some(Truncated::Unreadable(frame.frame_pointer));
+        };
+        let saved = u64::from_ne_bytes(data[..8].try_into().unwrap());
+        let return_address = u64::from_ne_bytes(data[8..].try_into().unwrap());
+        if return_address == 0 {
+            break None;
+        }
+        // The stack grows down so callers' frames are always at higher addresses
+        if saved != 0 && saved <= frame.frame_pointer {
+            break Some(Truncated::Cycle {
+                frame_pointer: frame.frame_pointer,
+                saved,
+            });
+        }
+        frame = Frame {
+            pc: return_address,
+            frame_pointer: saved,
+            is_return_address: true,
+        };
+    };
+    Ok(Backtrace { frames, truncated })
+}
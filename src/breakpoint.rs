@@ -1,8 +1,9 @@
+use crate::condition::ResolvedCondition;
 use crate::ptrace_control::*;
 use nix::unistd::Pid;
 use nix::{Error, Result};
-use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::error;
 
 static CURRENT_ID: AtomicU64 = AtomicU64::new(0);
 
@@ -11,7 +12,14 @@ static CURRENT_ID: AtomicU64 = AtomicU64::new(0);
 /// architectures the equivalent instructions will have to be found and also the architectures
 /// added to the CI.
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-const INT: u64 = 0xCC;
+const INT: u8 = 0xCC;
+
+/// Number of debug address registers, DR0 to DR3, so the most hardware breakpoints that can be
+/// enabled at once. Every thread has its own but a breakpoint is in the same one in all of them.
+pub const HARDWARE_BREAKPOINT_SLOTS: usize = 4;
+
+/// Debug control register, which enables the address registers and sets what they match on.
+const DR7: usize = 7;
 
 /// Breakpoint construct used to monitor program execution. As tarpaulin is an
 /// automated process, this will likely have less functionality than most
@@ -21,110 +29,249 @@ pub struct Breakpoint {
     pub id: u64,
     /// Program counter
     pub pc: u64,
-    /// Bottom byte of address data.
-    /// This is replaced to enable the interrupt. Rest of data is never changed.
+    /// The byte at the address, this is replaced to enable the interrupt.
     data: u8,
-    /// Reading from memory with ptrace gives addresses aligned to bytes.
-    /// We therefore need to know the shift to place the breakpoint in the right place
-    shift: u64,
-    /// Map of the state of the breakpoint on each thread/process
-    is_running: HashMap<Pid, bool>,
+    /// Whether the interrupt is currently written into the program
+    enabled: bool,
+    /// Number of times the breakpoint has been hit
+    hit_count: u64,
+    /// Number of upcoming hits to carry on past rather than stopping
+    ignore_count: u64,
+    /// Remove the breakpoint once it's been hit
+    temporary: bool,
+    /// Only stop at the breakpoint when this holds
+    condition: Option<ResolvedCondition>,
+    /// Only stop when this thread hits the breakpoint, other threads carry on past it
+    thread: Option<Pid>,
+    /// Uses a debug register instead of writing an interrupt into the code
+    hardware: bool,
+    /// The debug address register a hardware breakpoint is in while it's enabled
+    slot: Option<usize>,
 }
 
 impl Breakpoint {
     /// Creates a new breakpoint for the given process and program counter.
     pub fn new(pid: Pid, pc: u64) -> Result<Self> {
-        let aligned = align_address(pc);
-        let data = read_address(pid, aligned)?;
-        let shift = 8 * (pc - aligned);
-        let data = ((data >> shift) & 0xFF) as u8;
-        let id = CURRENT_ID.fetch_add(1, Ordering::SeqCst);
-
-        let mut b = Breakpoint {
-            id,
-            pc,
-            data,
-            shift,
-            is_running: HashMap::new(),
-        };
+        let data = read_byte(pid, pc)?;
+        let mut b = Self::unset(pc, data, false);
         match b.enable(pid) {
             Ok(_) => Ok(b),
             Err(e) => Err(e),
         }
     }
 
+    /// Creates a breakpoint in debug address register `slot` of the thread, so the code is left
+    /// untouched.
+    pub fn new_hardware(pid: Pid, pc: u64, slot: usize) -> Result<Self> {
+        let mut b = Self::unset(pc, 0, true);
+        b.enable_hardware(pid, slot)?;
+        Ok(b)
+    }
+
+    fn unset(pc: u64, data: u8, hardware: bool) -> Self {
+        Breakpoint {
+            id: CURRENT_ID.fetch_add(1, Ordering::SeqCst),
+            pc,
+            data,
+            enabled: false,
+            hit_count: 0,
+            ignore_count: 0,
+            temporary: false,
+            condition: None,
+            thread: None,
+            hardware,
+            slot: None,
+        }
+    }
+
+    /// Whether the process is stopped at this breakpoint. This relies on the program counter
+    /// being rewound after the interrupt is hit so it points at the breakpoint again.
     pub fn has_hit(&self, pid: Pid) -> Result<bool> {
         let pc = current_instruction_pointer(pid)? as u64;
-        Ok(pc == self.pc || pc - 1 == self.pc)
+        Ok(pc == self.pc)
     }
 
     pub fn jump_to(&mut self, pid: Pid) -> Result<()> {
         set_instruction_pointer(pid, self.pc).map(|_| ())
     }
 
-    /// Attaches the current breakpoint.
-    pub fn enable(&mut self, pid: Pid) -> Result<()> {
-        let data = read_address(pid, self.aligned_address())?;
-        self.is_running.insert(pid, true);
-        let mut intdata = data & (!(0xFFu64 << self.shift) as i64);
-        intdata |= (INT << self.shift) as i64;
-        if data == intdata {
-            Err(Error::UnknownErrno)
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn is_hardware(&self) -> bool {
+        self.hardware
+    }
+
+    /// The debug address register a hardware breakpoint is in while it's enabled.
+    pub fn slot(&self) -> Option<usize> {
+        self.slot
+    }
+
+    /// The byte the interrupt replaced.
+    pub fn original_byte(&self) -> u8 {
+        self.data
+    }
+
+    pub fn hit_count(&self) -> u64 {
+        self.hit_count
+    }
+
+    pub fn ignore_count(&self) -> u64 {
+        self.ignore_count
+    }
+
+    /// Carries on past the next `count` hits rather than stopping.
+    pub fn set_ignore_count(&mut self, count: u64) {
+        self.ignore_count = count;
+    }
+
+    pub fn is_temporary(&self) -> bool {
+        self.temporary
+    }
+
+    pub fn set_temporary(&mut self, temporary: bool) {
+        self.temporary = temporary;
+    }
+
+    pub fn condition(&self) -> Option<&ResolvedCondition> {
+        self.condition.as_ref()
+    }
+
+    pub fn set_condition(&mut self, condition: Option<ResolvedCondition>) {
+        self.condition = condition;
+    }
+
+    pub fn thread(&self) -> Option<Pid> {
+        self.thread
+    }
+
+    pub fn set_thread(&mut self, thread: Option<Pid>) {
+        self.thread = thread;
+    }
+
+    /// Records `byte` as the original byte under the breakpoint when that memory is overwritten,
+    /// returning the byte that should actually be written so the breakpoint stays in place.
+    pub(crate) fn overwrite(&mut self, byte: u8) -> u8 {
+        self.data = byte;
+        if self.enabled && !self.hardware {
+            INT
         } else {
-            write_to_address(pid, self.aligned_address(), intdata)
+            byte
         }
     }
 
-    pub fn disable(&self, pid: Pid) -> Result<()> {
-        // I require the bit fiddlin this end.
-        let data = read_address(pid, self.aligned_address())?;
-        let mut orgdata = data & (!(0xFFu64 << self.shift) as i64);
-        orgdata |= i64::from(self.data) << self.shift;
-        write_to_address(pid, self.aligned_address(), orgdata)
+    /// Counts a hit, returning false if it's one to ignore.
+    pub(crate) fn record_hit(&mut self) -> bool {
+        self.hit_count += 1;
+        if self.ignore_count > 0 {
+            self.ignore_count -= 1;
+            false
+        } else {
+            true
+        }
     }
 
-    /// Processes the breakpoint. This steps over the breakpoint
-    pub fn process(&mut self, pid: Pid, reenable: bool) -> Result<bool> {
-        let is_running = match self.is_running.get(&pid) {
-            Some(r) => *r,
-            None => true,
-        };
-        if is_running {
-            let _ = self.enable(pid);
-            self.step(pid)?;
-            self.is_running.insert(pid, false);
-            Ok(true)
+    /// Attaches the current breakpoint. Hardware breakpoints need a debug register to go in, see
+    /// `enable_hardware`.
+    pub fn enable(&mut self, pid: Pid) -> Result<()> {
+        if self.enabled {
+            return Ok(());
+        }
+        if self.hardware {
+            return Err(Error::EINVAL);
+        }
+        if read_byte(pid, self.pc)? == INT {
+            Err(Error::UnknownErrno)
         } else {
-            self.disable(pid)?;
-            if reenable {
-                self.enable(pid)?;
-            }
-            self.is_running.insert(pid, true);
-            Ok(false)
+            write_memory_range(pid, self.pc, &[INT])?;
+            self.enabled = true;
+            Ok(())
         }
     }
 
-    /// Call this when a ptrace thread is killed. Won't re-enable the breakpoint
-    /// so may lose the ability to instrument this line.
-    pub fn thread_killed(&mut self, pid: Pid) {
-        self.is_running.remove(&pid);
+    /// Enables a hardware breakpoint in debug address register `slot` of the thread. The slots
+    /// are handed out by the process so a breakpoint is in the same one in every thread.
+    pub fn enable_hardware(&mut self, pid: Pid, slot: usize) -> Result<()> {
+        if self.enabled {
+            return Ok(());
+        }
+        self.slot = Some(slot);
+        if let Err(e) = self.arm(pid) {
+            self.slot = None;
+            return Err(e);
+        }
+        self.enabled = true;
+        Ok(())
     }
 
-    /// Steps past the current breakpoint.
-    /// For more advanced coverage may interrogate the variables of a branch.
-    fn step(&mut self, pid: Pid) -> Result<()> {
-        // Remove the breakpoint, reset the program counter to step before it
-        // hit the breakpoint then step to execute the original instruction.
-        self.disable(pid)?;
-        self.jump_to(pid)?;
+    /// Restores the original instruction. With `verify` the interrupt is checked to still be
+    /// there first, if something else has written over it the code has changed underneath us and
+    /// putting back the byte we saved would undo that change, so the new code is left in place.
+    pub fn disable(&mut self, pid: Pid, verify: bool) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if let Some(slot) = self.slot.take() {
+            disarm(pid, slot)?;
+            self.enabled = false;
+            return Ok(());
+        }
+        let current = read_byte(pid, self.pc)?;
+        if verify && current != INT {
+            error!(
+                "Breakpoint {} at 0x{:x} should be an int3 but is 0x{:02x}, the code changed \
+                 underneath us so the original byte 0x{:02x} isn't being restored",
+                self.id, self.pc, current, self.data
+            );
+            self.data = current;
+            self.enabled = false;
+            return Ok(());
+        }
+        write_memory_range(pid, self.pc, &[self.data])?;
+        self.enabled = false;
         Ok(())
     }
 
+    /// Puts the original instruction back in another process with a copy of our memory, like a
+    /// forked child, so it runs without trapping. The breakpoint itself is left as it is.
+    pub fn remove_from(&self, pid: Pid) -> Result<()> {
+        // Debug registers aren't copied to forked children
+        if !self.enabled || self.hardware {
+            return Ok(());
+        }
+        write_memory_range(pid, self.pc, &[self.data])
+    }
+
+    /// Writes an enabled hardware breakpoint into a thread's debug registers. Each thread has
+    /// its own, so threads started after the breakpoint was set need it adding.
+    pub(crate) fn arm(&self, pid: Pid) -> Result<()> {
+        let Some(slot) = self.slot else {
+            return Ok(());
+        };
+        write_debug_register(pid, slot, self.pc)?;
+        let dr7 = read_debug_register(pid, DR7)?;
+        // A condition and length of 0 break when the instruction at the address is executed
+        let dr7 = (dr7 & !(0xF << (16 + 4 * slot))) | local_enable(slot);
+        write_debug_register(pid, DR7, dr7)
+    }
+
     pub fn aligned_address(&self) -> u64 {
         align_address(self.pc)
     }
 }
 
+/// Turns off debug address register `slot` in a thread.
+pub(crate) fn disarm(pid: Pid, slot: usize) -> Result<()> {
+    let dr7 = read_debug_register(pid, DR7)?;
+    write_debug_register(pid, DR7, dr7 & !local_enable(slot))
+}
+
+/// The DR7 bit that enables a debug address register for the current thread.
+fn local_enable(slot: usize) -> u64 {
+    1 << (2 * slot)
+}
+
 #[inline(always)]
 pub(crate) fn align_address(addr: u64) -> u64 {
     addr & !0x7u64
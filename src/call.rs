@@ -0,0 +1,148 @@
+//! Calling functions in the process being debugged, `call add(1, 2)`. Only integer and pointer
+//! arguments and return values are supported.
+//!
+//! The call follows the System V x86-64 ABI:
+//!
+//! * Up to six arguments are passed in `rdi`, `rsi`, `rdx`, `rcx`, `r8` and `r9`.
+//! * The result is read from `rax`.
+//! * The stack pointer is moved below the 128 byte red zone so the interrupted function's
+//!   temporaries aren't overwritten, then aligned so it's 16 byte aligned before the return
+//!   address is pushed.
+//! * `al` is zeroed, it holds the number of vector registers used when calling a variadic
+//!   function.
+//!
+//! The return address pushed is the program's entry point, which only runs once at startup, with
+//! a breakpoint there to catch the return. Once the function returns, or the call fails, every
+//! register is put back how it was before the call so the process carries on from where it was
+//! stopped. Memory the function changed, including globals, is left as it is.
+//!
+//! If the function gets a signal, like a SIGSEGV from a bad pointer, the call is abandoned. With
+//! `unwind-on-signal` on, the default, registers are restored and the signal dropped as for any
+//! other failure. With it off the process is left where the signal stopped it, inside the called
+//! function, to look around at the crash. The call's frame is still on the stack then, so
+//! finishing out of it returns to the entry point rather than where the process was.
+use crate::process::{Process, ProcessError, State, TrapType};
+use nix::sys::signal::Signal;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Registers arguments are passed in, in order.
+pub const ARG_REGISTERS: &[&str] = &["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+/// How long a called function gets to return before it's interrupted.
+pub const CALL_TIMEOUT: Duration = Duration::from_secs(10);
+/// Bytes below the stack pointer a function can use without moving it.
+const RED_ZONE: u64 = 128;
+
+#[derive(Debug, Error)]
+pub enum CallError {
+    #[error("at most 6 arguments can be passed, got {0}")]
+    TooManyArgs(usize),
+    #[error("the process has to be stopped to call a function")]
+    NotStopped,
+    #[error("process stopped at 0x{pc:x} before the function returned, registers were restored")]
+    Interrupted { pc: u64 },
+    #[error(
+        "call aborted by {signal} at 0x{pc:x} before the function returned, registers were \
+         restored"
+    )]
+    Unwound { signal: Signal, pc: u64 },
+    #[error(
+        "call aborted by {signal} at 0x{pc:x} before the function returned, the process was left \
+         where it stopped"
+    )]
+    Signalled { signal: Signal, pc: u64 },
+    #[error("process exited during the call")]
+    Exited,
+    #[error("function didn't return within {0:?}, registers were restored")]
+    TimedOut(Duration),
+    #[error(transparent)]
+    Process(#[from] ProcessError),
+}
+
+/// Calls the function at `function` with integer arguments returning the value left in `rax`.
+/// `return_address` should be somewhere the process won't otherwise reach during the call. If
+/// the function gets a signal registers are only restored when `unwind_on_signal` is set.
+pub fn call(
+    process: &mut Process,
+    function: u64,
+    return_address: u64,
+    args: &[u64],
+    unwind_on_signal: bool,
+) -> Result<u64, CallError> {
+    if args.len() > ARG_REGISTERS.len() {
+        return Err(CallError::TooManyArgs(args.len()));
+    }
+    if process.state() != State::Stopped {
+        return Err(CallError::NotStopped);
+    }
+    let saved = process.get_all_registers()?;
+
+    let mut regs = saved.regs;
+    let sp = ((regs.rsp - RED_ZONE) & !0xf) - 8;
+    process.write_memory(sp, &return_address.to_le_bytes())?;
+    regs.rsp = sp;
+    regs.rip = function;
+    regs.rax = 0;
+    // Stop the kernel restarting a syscall the process was stopped in once it's resumed
+    regs.orig_rax = u64::MAX;
+    let arg_registers = [
+        &mut regs.rdi,
+        &mut regs.rsi,
+        &mut regs.rdx,
+        &mut regs.rcx,
+        &mut regs.r8,
+        &mut regs.r9,
+    ];
+    for (register, arg) in arg_registers.into_iter().zip(args) {
+        *register = *arg;
+    }
+    process.write_gp_registers(regs)?;
+
+    // Use a breakpoint the user already has at the return address rather than removing it after
+    let temporary = match process.breakpoint_at(return_address) {
+        Some(_) => None,
+        None => Some(process.set_breakpoint(return_address)?),
+    };
+
+    let mut result = run_until_return(process, return_address);
+    if matches!(result, Err(CallError::Exited)) {
+        return result;
+    }
+    if let Some(id) = temporary {
+        process.remove_breakpoint(id)?;
+    }
+    if let Err(CallError::Interrupted { pc }) = result {
+        if let Some(signal) = process.current_signal() {
+            if !unwind_on_signal {
+                return Err(CallError::Signalled { signal, pc });
+            }
+            result = Err(CallError::Unwound { signal, pc });
+        }
+    }
+    process.write_all_registers(saved)?;
+    // A signal the called function stopped with belongs to the call, not the interrupted code
+    process.discard_signal();
+    result
+}
+
+fn run_until_return(process: &mut Process, return_address: u64) -> Result<u64, CallError> {
+    process.resume()?;
+    let stop = match process.blocking_wait_on_signal(CALL_TIMEOUT) {
+        Ok(stop) => stop,
+        Err(ProcessError::Timeout) => {
+            process.stop()?;
+            process.blocking_wait_on_signal(CALL_TIMEOUT)?;
+            return Err(CallError::TimedOut(CALL_TIMEOUT));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if stop.reason.is_closed() {
+        return Err(CallError::Exited);
+    }
+    let pc = process.pc()?;
+    if stop.trap_reason == Some(TrapType::SoftwareBreak) && pc == return_address {
+        Ok(process.get_gp_registers()?.regs.rax)
+    } else {
+        Err(CallError::Interrupted { pc })
+    }
+}
@@ -1,5 +1,16 @@
-use std::path::PathBuf;
+use crate::address::{parse_address, parse_length, parse_number, Address};
+use crate::asm::{AsmError, Instruction};
+use crate::condition::{Condition, ConditionError};
+use crate::dprintf::{Dprintf, DprintfError};
+use crate::eval::{EvalError, Expr};
+use crate::parse_env_var;
+use crate::process::{parse_signal, RegisterGroup, Registers};
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 use thiserror::Error;
 use tracing::error;
 
@@ -17,6 +28,12 @@ pub enum ParseError {
     InvalidLocation(LocationError),
     #[error("invalid expression given {0}")]
     InvalidExpression(ExpressionError),
+    #[error("invalid dprintf: {0}")]
+    InvalidDprintf(DprintfError),
+    #[error("invalid condition: {0}")]
+    InvalidCondition(ConditionError),
+    #[error("invalid instruction: {0}")]
+    InvalidInstruction(AsmError),
 }
 
 #[derive(Debug, Error, Eq, PartialEq)]
@@ -39,33 +56,679 @@ pub enum LocationError {
 pub enum ExpressionError {
     #[error("invalid expression")]
     InvalidExpression,
+    #[error(transparent)]
+    Eval(#[from] EvalError),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Command {
     Quit,
     ToggleLogs,
+    ToggleBreakpoints,
+    ToggleOutput,
     Help,
     Restart,
-    Load(PathBuf),
+    Load {
+        path: PathBuf,
+        /// Arguments passed to the program
+        args: Vec<String>,
+    },
     Attach(i32),
+    /// Remove the breakpoints and let the process run on without the debugger
+    Detach,
+    /// Set an environment variable for the next time the program is launched
+    SetEnv {
+        key: String,
+        value: String,
+    },
+    /// List the environment variables set with `env`
+    ListEnv,
+    /// Set the directory the program is launched in, or show it without one
+    Cwd(Option<PathBuf>),
     Continue,
+    /// Continue without waiting for the process to stop before running more commands
+    ContinueBackground,
+    /// Stop the running process, like CTRL+C
+    Interrupt,
+    /// Send a line of text to the program's stdin, the newline isn't included
+    Input(String),
+    /// Continue delivering a signal, or no signal, instead of the one the process stopped with
+    Signal(Option<Signal>),
+    /// Choose whether a signal the process stops with is delivered when it continues
+    Handle {
+        signal: Signal,
+        pass: bool,
+    },
+    /// Toggle stopping on entry to and exit from every syscall
+    CatchSyscalls,
+    /// Report the next stop without resuming the process, waiting up to the timeout for it or
+    /// only checking for one that's already happened without a timeout
+    Wait(Option<Duration>),
+    /// Continue, only stopping at breakpoint `id` once it's been hit `hits` times
+    ContinueUntil {
+        id: u64,
+        hits: u64,
+    },
+    /// Step to the next source line, into any function called with line info
     Step,
+    /// Step a single instruction
+    StepInstruction,
+    /// Step over the current source line
+    Next,
+    /// Continue until the current function returns
+    Finish,
+    /// Continue to a location with a breakpoint that's removed afterwards
+    RunTo(Location),
+    /// A breakpoint that's removed once it's hit
+    TempBreak(Location),
+    /// A breakpoint in a debug register rather than the code
+    HardwareBreak(Location),
     Break(Location),
+    ConditionalBreak {
+        location: Location,
+        condition: Condition,
+    },
+    /// A breakpoint that only stops the given thread
+    ThreadBreak {
+        location: Location,
+        thread: Pid,
+    },
     Null,
     Print(Expression),
     ListBreakpoints,
+    /// Show the source around a location
+    ListSource(Location),
     Status,
+    StartTrace(PathBuf),
+    StopTrace,
+    Delete(u64),
+    /// Don't stop at a breakpoint for its next `count` hits
+    Ignore {
+        id: u64,
+        count: u64,
+    },
+    HelpFor(String),
+    Set {
+        name: String,
+        value: String,
+    },
+    /// Show a setting, or all of them if no name is given
+    Show(Option<String>),
+    ReloadSymbols,
+    /// Compare the functions in the program against another executable
+    DiffSymbols(PathBuf),
+    ListThreads,
+    Backtrace,
+    /// List recent stops, or show the stop at the given index in the source view
+    StopHistory(Option<usize>),
+    Dprintf {
+        location: Location,
+        dprintf: Dprintf,
+    },
+    /// Save a snapshot of the registers and the `(address, length)` memory ranges
+    SaveSnapshot {
+        name: String,
+        ranges: Vec<(Address, usize)>,
+    },
+    DiffSnapshot(String),
+    /// Call a function in the process with integer arguments
+    Call {
+        function: String,
+        args: Vec<i64>,
+    },
+    /// Assemble an instruction and write it at the address
+    Assemble {
+        addr: Address,
+        instruction: Instruction,
+    },
+    /// Decode every instruction in a function
+    Disassemble(String),
+    /// Dump `len` bytes of memory starting at `addr`
+    Examine {
+        addr: Address,
+        len: usize,
+    },
+    Info(InfoKind),
+    /// Show something every time the process stops
+    Display(DisplayExpr),
+    /// Stop showing the display with the given number
+    Undisplay(u64),
+}
+
+/// Something `display` shows every time the process stops.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DisplayExpr {
+    /// Shown the same as `print`
+    Print(Expression),
+    /// Shown the same as `examine`
+    Examine { addr: Address, len: usize },
+}
+
+impl fmt::Display for DisplayExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Print(expr) => write!(f, "{}", expr),
+            Self::Examine { addr, len } => write!(f, "x {} {}", addr, len),
+        }
+    }
+}
+
+/// What an `info` command shows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InfoKind {
+    /// Version, target and features of this build of rustybug
+    Version,
+    /// Registers in a group, all of them by default
+    Registers(RegisterGroup),
+    /// Arguments and environment the program was started with
+    InferiorArgs,
+    /// Addresses of the code for the line a location is on, or the current line
+    Line(Option<Location>),
+    /// Compilers and languages the debug info came from
+    Compiler,
+    /// Scheduler state and signal masks of the process
+    SignalsState,
+    /// Expressions shown every time the process stops
+    Display,
+    /// Threads being traced and where each one is
+    Threads,
+}
+
+/// What can follow `info`, listed when it's missing or not one of these.
+const INFO_KINDS: &str =
+    "version, registers, inferior-args, line, compiler, signals-state, display, threads";
+
+impl FromStr for InfoKind {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "version" => Ok(Self::Version),
+            "inferior-args" => Ok(Self::InferiorArgs),
+            "compiler" => Ok(Self::Compiler),
+            "signals-state" => Ok(Self::SignalsState),
+            "display" => Ok(Self::Display),
+            "threads" => Ok(Self::Threads),
+            "line" => Ok(Self::Line(None)),
+            x if x.starts_with("line ") => {
+                let location = Location::from_str(x.trim_start_matches("line "))
+                    .map_err(ParseError::InvalidLocation)?;
+                Ok(Self::Line(Some(location)))
+            }
+            "registers" => Ok(Self::Registers(RegisterGroup::default())),
+            x if x.starts_with("registers ") => {
+                let group = x.trim_start_matches("registers ").trim();
+                RegisterGroup::NAMES
+                    .iter()
+                    .find(|(name, _)| *name == group)
+                    .map(|(_, group)| Self::Registers(*group))
+                    .ok_or_else(|| ParseError::InvalidArgument {
+                        index: 1,
+                        arg: group.to_string(),
+                        msg: "expected one of: all, general, segment, flags".to_string(),
+                    })
+            }
+            x => Err(ParseError::InvalidArgument {
+                index: 0,
+                arg: x.to_string(),
+                msg: format!("expected one of: {}", INFO_KINDS),
+            }),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Expression {
     Registers,
+    /// A global or static variable
+    Variable(String),
+    /// Integer arithmetic on registers, variables and memory
+    Evaluate(Expr),
 }
 
+/// Bytes shown by `examine` when no length is given.
+pub const DEFAULT_EXAMINE_LEN: usize = 64;
+
+const QUIT_HELP: &str = "q, quit
+
+Quits rustybug, a launched program is killed and an attached one is detached from.";
+
+const LOGS_HELP: &str = "logs
+
+Toggles showing the debug logs panel.";
+
+const BREAKPOINTS_HELP: &str = "breakpoints
+
+Toggles showing the breakpoints panel which lists every breakpoint with its location,
+whether it's enabled and how many times it's been hit.";
+
+const OUTPUT_HELP: &str = "output
+
+Toggles showing the output panel with what the program has written to stdout. Only the last
+output-lines lines are kept, scroll through them with the mouse wheel.";
+
+const HELP_HELP: &str = "?, help
+
+Shows the help popup with a summary of every command. Use `<COMMAND> --help` for more
+detail on a single command.";
+
+const CONTINUE_HELP: &str = "c, cont, continue
+
+Resumes the stopped process until it hits a breakpoint, receives a signal or exits. If
+stopped on a breakpoint it is stepped over first.
+
+continue until <ID> hits <N>
+
+Continues without stopping at breakpoint <ID> until it's been hit <N> times in total, then
+shows where it stopped and the registers. Stopping for anything else ends it early.
+
+continue &
+
+Continues in the background. Commands can always be entered while the process runs, this
+matters for batch mode where the next command would otherwise wait for the process to stop.
+Use interrupt to stop it.";
+
+const INTERRUPT_HELP: &str = "interrupt
+
+Stops the running process, the same as CTRL+C.";
+
+const INPUT_HELP: &str = "input [TEXT]
+
+Sends <TEXT> followed by a newline to the program's stdin, without text sends an empty line.
+The text is sent as typed including any spaces. Only programs rustybug launched can be given
+input, attached processes keep their own stdin. Continue the program for it to read it.
+
+Example: input hello world";
+
+const SIGNAL_HELP: &str = "signal <SIGNAL>
+
+Continues the process delivering <SIGNAL> instead of the signal it stopped with. Signals can be
+given by name with or without SIG, or by number. signal 0 continues without delivering any.
+
+Example: signal SIGUSR1";
+
+const HANDLE_HELP: &str = "handle <SIGNAL> pass|nopass
+
+Chooses whether the process gets a signal it stopped with when it's continued. Signals are
+passed on by default apart from SIGTRAP, SIGSTOP and SIGINT. This changes the nopass-signals
+setting.
+
+Example: handle SIGALRM nopass";
+
+const CATCH_HELP: &str = "catch syscall
+
+Toggles stopping the process on entry to and exit from every syscall when it's continued. Each
+stop is logged with the syscall's name, its six argument registers on entry and what it
+returned on exit, like strace. Continue to carry on to the next stop.";
+
+const WAIT_HELP: &str = "wait [SECONDS]
+
+Reports the process stopping without resuming it, e.g. after `continue &`. Without a timeout
+this only checks if it's already stopped, with one it waits up to that many seconds for a
+stop. Does nothing if the process isn't running.
+
+Example: wait 2.5";
+
+const STEP_HELP: &str = "step
+s
+
+Steps to the next source line, stopping at the start of any function called on the way that
+has line info. Functions without line info, like those in libc, are run without stopping.
+Returning from the current function stops in the caller. Where there's no line information
+for the current address this steps over a single instruction.
+
+The number of instructions single stepped is bounded by `max-step-instructions` the same as
+for `next`.";
+
+const STEPI_HELP: &str = "stepi
+si
+
+Executes a single instruction, stepping into calls.";
+
+const NEXT_HELP: &str = "next
+n
+
+Steps to the next source line, running any functions called on the way without stopping in
+them unless they hit a breakpoint. Where there's no line information for the current address
+this steps over a single instruction instead.
+
+Single stepping stops after `max-step-instructions` instructions and the rest of the line is
+run by continuing to a breakpoint at the end of it.";
+
+const FINISH_HELP: &str = "finish
+
+Continues until the current function returns to its caller, then shows where it returned to
+and the value left in rax. The return address is found by following the frame pointers like
+`bt`, or read from the top of the stack when stopped on the first instruction of a function.
+
+Fails in the outermost frame as there's nowhere to return to.";
+
+const STATUS_HELP: &str = "status
+
+Shows whether the process is running or stopped, and where it is stopped. Once it's gone shows
+the exit code or signal it ended with.";
+
+const RESTART_HELP: &str = "restart
+
+Kills the current process and launches the program (or attaches to the PID) rustybug was
+started with again.";
+
+const LIST_HELP: &str = "l, list [LOCATION]
+
+Lists all the breakpoints that are set. With a <LOCATION> shows the source around it instead,
+`set listsize` changes how many lines are shown. <LOCATION> is written the same as for
+`break`.
+
+Examples: list, list main, list main.c:20";
+
+const PRINT_HELP: &str = "print <EXPRESSION>
+
+Prints the value of an expression. Supported expressions:
+    registers    all the general purpose and floating point registers
+    <NAME>       a global or static variable, use set print pretty on to print structs
+                 with a line per field
+    <ARITHMETIC> integers, registers and integer variables combined with + - * / and
+                 brackets, *<EXPR> reads the 8 byte word at an address. Division is signed
+
+Examples: print registers, print rsp + 8 * 2, print *(rbp - 8)";
+
+const LOAD_HELP: &str = "load <PATH> [ARGS...]
+
+Launches the program at <PATH> and starts debugging it, the current process is stopped. Any
+arguments after the path are passed to the program, split on whitespace, and used again by
+`restart`. Without any the program gets no arguments.
+
+Example: load ./target/debug/my_program --verbose input.txt";
+
+const ENV_HELP: &str = "env [KEY=VALUE]
+
+Sets an environment variable for the program, it's used the next time the program is
+launched with `restart` or `load`. The program gets our environment with these on top, or
+only these if rustybug was started with --clean-env. Without an argument lists the variables
+set so far.
+
+Example: env RUST_LOG=debug";
+
+const CWD_HELP: &str = "cwd [DIR]
+
+Sets the directory the program starts in, it's used the next time the program is launched
+with `restart` or `load`. Relative paths are from rustybug's own working directory, which the
+program uses if this isn't set. The directory has to exist when the program is launched.
+Without an argument shows the directory the program will start in.
+
+Example: cwd tests/data";
+
+const DETACH_HELP: &str = "detach
+
+Removes all breakpoints and stops debugging the process, leaving it running. Unlike quitting
+this works for launched programs too, though they'll get a SIGPIPE if they write output once
+rustybug stops reading it. The process has to be stopped, use interrupt first if it's running.";
+
+const ATTACH_HELP: &str = "attach <PID>
+
+Attaches to an already running process. <PID> is a decimal process ID.
+
+Example: attach 1234";
+
+const BREAK_HELP: &str = "break <LOCATION> [if <CONDITION> | thread <TID>]
+
+Sets a breakpoint, <LOCATION> can be:
+    <ADDR>           an address either as 0x prefixed hexadecimal or decimal
+    <FUNCTION>       the name of a function, mangled or demangled
+    <FILE> <LINE>    a source file and line number, also written <FILE>:<LINE>
+
+An address in a known function is checked to be the start of an instruction, with a warning
+if it's in the middle of one.
+
+With a condition the process only stops when the condition holds. A condition compares a
+general purpose register, global variable or memory to a number with ==, !=, <, <=, > or >=.
+Variables can be integers, bools, pointers or floats. Memory is written *<ADDR> or *<REG> and
+compares the 8 bytes at that address.
+
+With a thread the process only stops when that thread hits the breakpoint, other threads are
+continued past it without counting a hit. <TID> is a thread ID as shown by `thread list`.
+
+Examples: break 0x401126, break main, break main.c 12, break main.c:12 if counter == 5,
+break greet if *rdi != 0, break thread_function thread 4321";
+
+const RUN_TO_HELP: &str = "run-to <LOCATION>
+
+Continues until <LOCATION> is reached using a one-off breakpoint, which is removed once the
+process stops there or anywhere else. <LOCATION> is written the same as for `break`.
+
+Example: run-to main.c:20";
+
+const TBREAK_HELP: &str = "tbreak <LOCATION>
+
+Sets a temporary breakpoint, which stops the process once and is then removed. <LOCATION>
+is written the same as for `break`. If there's already a breakpoint at the location it's
+left as it is.
+
+Example: tbreak main.c:20";
+
+const HBREAK_HELP: &str = "hbreak <LOCATION>
+
+Sets a hardware breakpoint, which uses one of the CPU's debug registers instead of writing an
+interrupt into the code. <LOCATION> is written the same as for `break`. Only 4 can be enabled
+at once, every thread stops at them.
+
+Example: hbreak main.c:20";
+
+const DELETE_HELP: &str = "delete <ID>
+
+Removes the breakpoint with the given ID, IDs are shown by `list`.
+
+Example: delete 2";
+
+const IGNORE_HELP: &str = "ignore <ID> <COUNT>
+
+Carries on past the next <COUNT> hits of the breakpoint with the given ID instead of
+stopping. Ignored hits still count towards the hits shown by `list`, an ignore count of 0
+stops at the next hit again.
+
+Example: ignore 1 3";
+
+const TRACE_HELP: &str = "trace start <FILE>, trace-record <FILE>, trace stop
+
+Single steps the program writing the address of every instruction executed to <FILE>, one
+per line followed by the function and source line it's in. With trace-registers set the
+general purpose registers are written too. Tracing stops when the program exits, stops for
+another reason, the instruction limit is reached, it's interrupted or `trace stop` is used.
+This is slow!
+
+Example: trace-record trace.txt";
+
+const SET_HELP: &str = "set <NAME> <VALUE>
+
+Changes a setting, boolean settings accept on/off. Use `show all` to see the settings.
+
+Example: set history-size 20";
+
+const SHOW_HELP: &str = "show <NAME>, show all
+
+Shows the value of a setting or of every setting.
+
+Example: show trace-limit";
+
+const RELOAD_SYMBOLS_HELP: &str = "reload-symbols
+
+Reads the debug information from the executable again without restarting the process, use
+this after rebuilding the program. Breakpoints aren't moved.";
+
+const DIFF_SYMBOLS_HELP: &str = "diff-symbols <FILE>
+
+Compares the functions in the program being debugged against the executable <FILE>, like
+another build of it, using their debug info. Lists functions only in one of them and ones
+whose code changed size. The program doesn't need to be running.
+
+Example: diff-symbols target/release/old-build";
+
+const THREAD_HELP: &str = "thread list
+
+Lists the threads in the process with their thread IDs and names.";
+
+const BACKTRACE_HELP: &str = "bt, backtrace
+
+Shows the stack of function calls leading to where the process is stopped, innermost first.
+This follows frame pointers so code compiled without them will have a short or broken
+backtrace. At most backtrace-limit frames are shown, change it with set backtrace-limit <N>.";
+
+const STOPS_HELP: &str = "stops [N], history stops [N]
+
+Lists the places the process has recently stopped, most recent first. With an index shows that
+stop in the source view without changing where the process is stopped.
+
+Example: stops 2";
+
+const DPRINTF_HELP: &str = "dprintf <LOCATION>, \"<FORMAT>\", <OPERAND>...
+
+Sets a breakpoint which logs a message each time it's hit and then continues. The format
+supports %d, %u, %x and %s (a pointer to a C string). Operands are general purpose registers
+or global variables.
+
+Example: dprintf main.c:12, \"name = %s, count = %d\\n\", rdi, count";
+
+const SNAPSHOT_HELP: &str = "snapshot save <NAME> [<ADDR> <LEN>]..., snapshot diff <NAME>
+
+Saves the registers and optionally some ranges of memory as <NAME>, then later shows which
+registers and bytes of memory have changed since it was saved.
+
+Example: snapshot save a 0x404028 16, snapshot diff a";
+
+const CALL_HELP: &str = "call <FUNCTION>(<ARG>, ...)
+
+Calls a function in the stopped process and shows the value it returned. Up to six integer or
+pointer arguments can be passed and the return value is read as an integer. Registers are
+restored afterwards but any memory the function changes stays changed. If the function hits a
+breakpoint or signal the call is abandoned.
+
+Example: call add(1, 0x10)";
+
+const INFO_HELP: &str = "info <WHAT>
+
+Shows information about the debugger or the process. <WHAT> can be:
+
+  version            rustybug's version, the target it was built for and optional features
+                     compiled in
+  registers [GROUP]  registers in the stopped process, GROUP is one of all (the default),
+                     general, segment or flags
+  inferior-args      arguments and environment the program was started with, read from its
+                     stack so only works while it's stopped at the entry point
+  line [LOCATION]    address ranges of the code for a source line, the line LOCATION is on or
+                     the line the process is stopped on
+  compiler           the compilers and languages the debug info came from, with how many
+                     compile units each produced
+  signals-state      the process's scheduler state and which signals are pending, blocked,
+                     ignored or have a handler, read from /proc/<pid>/status
+  display            the expressions added with `display` and their numbers
+  threads            the threads being traced with the PC of the stopped one, threads are only
+                     traced once they're created if stop-on-events is on";
+
+const DISPLAY_HELP: &str = "display [EXPR]
+display x <ADDR> [LEN]
+
+Shows <EXPR> every time the process stops, and straight away if it's stopped now. <EXPR> is
+anything `print` accepts, or an `examine` of memory. Each display is given a number to remove
+it with `undisplay`. Without an expression lists the displays, like `info display`.
+
+Examples: display counter, display rsp + 8, display x 0x404028 16";
+
+const UNDISPLAY_HELP: &str = "undisplay <N>
+
+Stops showing display number <N>, see `info display` for the numbers.";
+
+const EXAMINE_HELP: &str = "x, examine <ADDR> [LEN]
+
+Shows <LEN> bytes of memory starting at <ADDR> as hex and ASCII, 16 bytes a row. <ADDR> can be
+decimal, hex starting with 0x or a register with an optional offset like rsp+16 or rbp-0x8.
+<LEN> defaults to 64 bytes and is a count with an optional size, b for bytes, h for 2 bytes, w
+for 4 bytes or g for 8 bytes. If only some of the memory can be read what could be read is
+shown. Other commands taking addresses and lengths accept the same forms.
+
+Example: x rsp 4g";
+
+const DISASSEMBLE_HELP: &str = "disassemble, disas <FUNCTION>
+
+Decodes the whole of <FUNCTION> from the process's memory, one instruction per line with its
+address, offset into the function, bytes and Intel syntax text. Calls and jumps also name the
+function their target is in. Breakpoints are marked with b and show the bytes they replaced,
+the current instruction is marked with =>. Functions the compiler split up are shown a range
+at a time.
+
+Example: disassemble main";
+
+const ASM_HELP: &str = "asm <ADDR> <INSTRUCTION>
+
+Assembles an instruction and writes it into the process at <ADDR>, logging the bytes it
+replaced. Supports nop, ret, int3, ud2, hlt, leave, syscall, jmp/call/j<cc> <ADDR>,
+push/pop <REG> and mov <REG>, <IMM> with 64 bit registers. Jumps are relative to <ADDR>. It's
+an error for the instruction to run past the end of the function, if it overwrites the start
+of the next instruction a warning is shown.
+
+Example: asm 0x401000 \"jmp 0x401020\"";
+
 impl Command {
     pub fn store_in_history(&self) -> bool {
-        !matches!(self, Self::Null | Self::Help | Self::Quit)
+        !matches!(
+            self,
+            Self::Null | Self::Help | Self::Quit | Self::HelpFor(_)
+        )
+    }
+
+    /// Detailed help text for a command, looked up by any of the names it can be invoked by.
+    pub fn help_text(name: &str) -> Option<&'static str> {
+        let text = match name {
+            "q" | "quit" => QUIT_HELP,
+            "logs" => LOGS_HELP,
+            "output" => OUTPUT_HELP,
+            "breakpoints" => BREAKPOINTS_HELP,
+            "?" | "help" => HELP_HELP,
+            "continue" | "cont" | "c" => CONTINUE_HELP,
+            "interrupt" => INTERRUPT_HELP,
+            "input" => INPUT_HELP,
+            "signal" => SIGNAL_HELP,
+            "handle" => HANDLE_HELP,
+            "catch" => CATCH_HELP,
+            "wait" => WAIT_HELP,
+            "step" | "s" => STEP_HELP,
+            "stepi" | "si" => STEPI_HELP,
+            "next" | "n" => NEXT_HELP,
+            "finish" => FINISH_HELP,
+            "status" => STATUS_HELP,
+            "restart" => RESTART_HELP,
+            "list" | "l" => LIST_HELP,
+            "print" => PRINT_HELP,
+            "load" => LOAD_HELP,
+            "attach" => ATTACH_HELP,
+            "env" => ENV_HELP,
+            "cwd" => CWD_HELP,
+            "detach" => DETACH_HELP,
+            "break" => BREAK_HELP,
+            "run-to" => RUN_TO_HELP,
+            "tbreak" => TBREAK_HELP,
+            "hbreak" => HBREAK_HELP,
+            "delete" => DELETE_HELP,
+            "ignore" => IGNORE_HELP,
+            "trace" | "trace-record" => TRACE_HELP,
+            "set" => SET_HELP,
+            "show" => SHOW_HELP,
+            "reload-symbols" => RELOAD_SYMBOLS_HELP,
+            "diff-symbols" => DIFF_SYMBOLS_HELP,
+            "thread" => THREAD_HELP,
+            "bt" | "backtrace" => BACKTRACE_HELP,
+            "stops" | "history" => STOPS_HELP,
+            "dprintf" => DPRINTF_HELP,
+            "snapshot" => SNAPSHOT_HELP,
+            "asm" => ASM_HELP,
+            "disassemble" | "disas" => DISASSEMBLE_HELP,
+            "call" => CALL_HELP,
+            "info" => INFO_HELP,
+            "display" => DISPLAY_HELP,
+            "undisplay" => UNDISPLAY_HELP,
+            "x" | "examine" => EXAMINE_HELP,
+            _ => return None,
+        };
+        Some(text)
     }
 }
 
@@ -80,37 +743,482 @@ impl FromStr for Command {
     type Err = ParseError;
 
     fn from_str(command: &str) -> Result<Self, Self::Err> {
+        if let Some(name) = command.strip_suffix("--help") {
+            let name = name.split_whitespace().next().unwrap_or_default();
+            return match Self::help_text(name) {
+                Some(_) => Ok(Self::HelpFor(name.to_string())),
+                None => Err(ParseError::InvalidCommand(command.to_string())),
+            };
+        }
         match command {
             "q" | "quit" => Ok(Self::Quit),
             "logs" => Ok(Self::ToggleLogs),
+            "output" => Ok(Self::ToggleOutput),
+            "breakpoints" => Ok(Self::ToggleBreakpoints),
             "?" | "help" => Ok(Self::Help),
             "continue" | "cont" | "c" => Ok(Self::Continue),
-            "step" => Ok(Self::Step),
+            x if x
+                .strip_suffix('&')
+                .is_some_and(|x| ["continue", "cont", "c"].contains(&x.trim_end())) =>
+            {
+                Ok(Self::ContinueBackground)
+            }
+            "interrupt" => Ok(Self::Interrupt),
+            "input" => Ok(Self::Input(String::new())),
+            x if x.starts_with("signal ") => {
+                let name = x.trim_start_matches("signal ").trim();
+                if name == "0" {
+                    return Ok(Self::Signal(None));
+                }
+                let signal = parse_signal(name).map_err(|msg| ParseError::InvalidArgument {
+                    index: 0,
+                    arg: name.to_string(),
+                    msg,
+                })?;
+                Ok(Self::Signal(Some(signal)))
+            }
+            x if x.starts_with("handle ") => {
+                let args = x.split_whitespace().skip(1).collect::<Vec<_>>();
+                let invalid = |index, arg: &str, msg: String| ParseError::InvalidArgument {
+                    index,
+                    arg: arg.to_string(),
+                    msg,
+                };
+                let [name, action] = args[..] else {
+                    return Err(invalid(
+                        0,
+                        x,
+                        "expected handle <SIGNAL> pass|nopass".to_string(),
+                    ));
+                };
+                let signal = parse_signal(name).map_err(|msg| invalid(0, name, msg))?;
+                let pass = match action {
+                    "pass" => true,
+                    "nopass" => false,
+                    _ => return Err(invalid(1, action, "expected pass or nopass".to_string())),
+                };
+                Ok(Self::Handle { signal, pass })
+            }
+            "catch syscall" => Ok(Self::CatchSyscalls),
+            x if x == "catch" || x.starts_with("catch ") => Err(ParseError::InvalidArgument {
+                index: 0,
+                arg: x.trim_start_matches("catch").trim().to_string(),
+                msg: "only syscall can be caught".to_string(),
+            }),
+            x if x.starts_with("input ") => {
+                Ok(Self::Input(x.trim_start_matches("input ").to_string()))
+            }
+            "wait" => Ok(Self::Wait(None)),
+            x if x.starts_with("wait ") => {
+                let arg = x["wait ".len()..].trim();
+                arg.parse::<f64>()
+                    .ok()
+                    .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                    .map(|timeout| Self::Wait(Some(timeout)))
+                    .ok_or_else(|| ParseError::InvalidArgument {
+                        index: 1,
+                        arg: arg.to_string(),
+                        msg: "expected a timeout in seconds".to_string(),
+                    })
+            }
+            x if ["continue until ", "cont until ", "c until "]
+                .iter()
+                .any(|prefix| x.starts_with(prefix)) =>
+            {
+                let args = x.split_whitespace().skip(2).collect::<Vec<_>>();
+                let [id, "hits", hits] = args[..] else {
+                    return Err(ParseError::InvalidArgument {
+                        index: 0,
+                        arg: args.join(" "),
+                        msg: "expected continue until <ID> hits <N>".to_string(),
+                    });
+                };
+                let parse = |index, arg: &str| {
+                    arg.parse::<u64>().map_err(|e| ParseError::InvalidArgument {
+                        index,
+                        arg: arg.to_string(),
+                        msg: e.to_string(),
+                    })
+                };
+                Ok(Self::ContinueUntil {
+                    id: parse(0, id)?,
+                    hits: parse(2, hits)?,
+                })
+            }
+            "step" | "s" => Ok(Self::Step),
+            "stepi" | "si" => Ok(Self::StepInstruction),
+            "next" | "n" => Ok(Self::Next),
+            "finish" => Ok(Self::Finish),
             "status" => Ok(Self::Status),
             "restart" => Ok(Self::Restart),
             "list" | "l" => Ok(Self::ListBreakpoints),
+            "reload-symbols" => Ok(Self::ReloadSymbols),
+            "thread list" => Ok(Self::ListThreads),
+            "bt" | "backtrace" => Ok(Self::Backtrace),
+            "stops" | "history stops" => Ok(Self::StopHistory(None)),
+            "info" => Err(ParseError::InvalidArgument {
+                index: 0,
+                arg: String::new(),
+                msg: format!("expected one of: {}", INFO_KINDS),
+            }),
+            x if x.starts_with("info ") => Ok(Self::Info(x.trim_start_matches("info ").parse()?)),
+            "display" => Ok(Self::Info(InfoKind::Display)),
+            x if x.starts_with("display ") => {
+                let expr = x.trim_start_matches("display ").trim();
+                match Self::from_str(expr) {
+                    Ok(Self::Examine { addr, len }) => {
+                        Ok(Self::Display(DisplayExpr::Examine { addr, len }))
+                    }
+                    // Anything else is an expression, which could look like another command
+                    _ => {
+                        let expr =
+                            Expression::from_str(expr).map_err(ParseError::InvalidExpression)?;
+                        Ok(Self::Display(DisplayExpr::Print(expr)))
+                    }
+                }
+            }
+            x if x.starts_with("undisplay ") => {
+                let id = x.trim_start_matches("undisplay ").trim();
+                id.parse::<u64>()
+                    .map(Self::Undisplay)
+                    .map_err(|e| ParseError::InvalidArgument {
+                        index: 0,
+                        arg: id.to_string(),
+                        msg: e.to_string(),
+                    })
+            }
+            x if x.starts_with("stops ") || x.starts_with("history stops ") => {
+                let index_str = x
+                    .trim_start_matches("history ")
+                    .trim_start_matches("stops ")
+                    .trim();
+                match index_str.parse::<usize>() {
+                    Ok(index) => Ok(Self::StopHistory(Some(index))),
+                    Err(e) => Err(ParseError::InvalidArgument {
+                        index: 0,
+                        arg: index_str.to_string(),
+                        msg: e.to_string(),
+                    }),
+                }
+            }
             x if x.starts_with("print ") => {
                 let expr_str = x.trim_start_matches("print ");
                 let expr = Expression::from_str(expr_str).map_err(ParseError::InvalidExpression)?;
                 Ok(Self::Print(expr))
             }
             x if x.starts_with("load ") => {
-                let path = x.trim_start_matches("load ");
-                let path = PathBuf::from(path);
-                Ok(Self::Load(path))
+                let mut words = x.split_whitespace().skip(1);
+                let path =
+                    words
+                        .next()
+                        .map(PathBuf::from)
+                        .ok_or_else(|| ParseError::InvalidArgument {
+                            index: 0,
+                            arg: String::new(),
+                            msg: "expected a path".to_string(),
+                        })?;
+                Ok(Self::Load {
+                    path,
+                    args: words.map(str::to_string).collect(),
+                })
+            }
+            "env" => Ok(Self::ListEnv),
+            x if x.starts_with("env ") => {
+                let var = x.trim_start_matches("env ").trim();
+                let (key, value) =
+                    parse_env_var(var).map_err(|msg| ParseError::InvalidArgument {
+                        index: 0,
+                        arg: var.to_string(),
+                        msg,
+                    })?;
+                Ok(Self::SetEnv { key, value })
             }
+            "detach" => Ok(Self::Detach),
+            "cwd" => Ok(Self::Cwd(None)),
+            x if x.starts_with("cwd ") => Ok(Self::Cwd(Some(PathBuf::from(
+                x.trim_start_matches("cwd ").trim(),
+            )))),
             x if x.starts_with("attach ") => {
                 let pid_str = x.trim_start_matches("attach ");
                 let pid = pid_str.parse::<i32>();
+                let invalid = |msg: String| ParseError::InvalidArgument {
+                    index: 0,
+                    arg: pid_str.to_string(),
+                    msg,
+                };
                 match pid {
+                    Ok(pid) if pid <= 0 => {
+                        Err(invalid(format!("PID {} is not a valid process", pid)))
+                    }
+                    Ok(pid) if !Path::new(&format!("/proc/{}", pid)).exists() => {
+                        Err(invalid(format!("PID {} does not exist", pid)))
+                    }
                     Ok(pid) => Ok(Self::Attach(pid)),
+                    Err(e) => Err(invalid(e.to_string())),
+                }
+            }
+            "diff-symbols" => Err(ParseError::InvalidArgument {
+                index: 0,
+                arg: String::new(),
+                msg: "no executable to compare against provided".to_string(),
+            }),
+            x if x.starts_with("diff-symbols ") => Ok(Self::DiffSymbols(PathBuf::from(
+                x.trim_start_matches("diff-symbols ").trim(),
+            ))),
+            "trace stop" => Ok(Self::StopTrace),
+            x if x.starts_with("trace start ") || x.starts_with("trace-record ") => {
+                let path = x
+                    .trim_start_matches("trace start ")
+                    .trim_start_matches("trace-record ")
+                    .trim();
+                if path.is_empty() {
+                    Err(ParseError::InvalidArgument {
+                        index: 1,
+                        arg: String::new(),
+                        msg: "no trace file provided".to_string(),
+                    })
+                } else {
+                    Ok(Self::StartTrace(PathBuf::from(path)))
+                }
+            }
+            "show all" => Ok(Self::Show(None)),
+            x if x.starts_with("show ") => {
+                let name = x.trim_start_matches("show ").trim();
+                Ok(Self::Show(Some(name.to_string())))
+            }
+            x if x.starts_with("set ") => {
+                let args = x
+                    .trim_start_matches("set ")
+                    .split_whitespace()
+                    .collect::<Vec<_>>();
+                match args.split_last() {
+                    Some((value, name)) if !name.is_empty() => Ok(Self::Set {
+                        name: name.join(" "),
+                        value: value.to_string(),
+                    }),
+                    _ => Err(ParseError::InvalidArgument {
+                        index: 0,
+                        arg: args.join(" "),
+                        msg: "expected a setting name and value".to_string(),
+                    }),
+                }
+            }
+            x if x.starts_with("delete ") => {
+                let id_str = x.trim_start_matches("delete ");
+                match id_str.parse::<u64>() {
+                    Ok(id) => Ok(Self::Delete(id)),
                     Err(e) => Err(ParseError::InvalidArgument {
                         index: 0,
-                        arg: pid_str.to_string(),
+                        arg: id_str.to_string(),
                         msg: e.to_string(),
                     }),
                 }
             }
+            x if x.starts_with("ignore ") => {
+                let args = x.split_whitespace().skip(1).collect::<Vec<_>>();
+                let invalid = |index, arg: &str, msg: String| ParseError::InvalidArgument {
+                    index,
+                    arg: arg.to_string(),
+                    msg,
+                };
+                let [id, count] = args[..] else {
+                    return Err(invalid(0, x, "expected ignore <ID> <COUNT>".to_string()));
+                };
+                let id = id.parse().map_err(|e| invalid(0, id, format!("{}", e)))?;
+                let count = count
+                    .parse()
+                    .map_err(|e| invalid(1, count, format!("{}", e)))?;
+                Ok(Self::Ignore { id, count })
+            }
+            x if x.starts_with("dprintf ") => {
+                let args = x.trim_start_matches("dprintf ");
+                let Some((location, format)) = args.split_once(',') else {
+                    return Err(ParseError::InvalidArgument {
+                        index: 0,
+                        arg: args.to_string(),
+                        msg: "expected a location followed by a format".to_string(),
+                    });
+                };
+                let location = Location::from_str(location).map_err(ParseError::InvalidLocation)?;
+                let dprintf = Dprintf::from_str(format).map_err(ParseError::InvalidDprintf)?;
+                Ok(Self::Dprintf { location, dprintf })
+            }
+            x if x.starts_with("snapshot save ") => {
+                let args = x
+                    .trim_start_matches("snapshot save ")
+                    .split_whitespace()
+                    .collect::<Vec<_>>();
+                let Some((name, ranges)) = args.split_first() else {
+                    return Err(ParseError::InvalidArgument {
+                        index: 0,
+                        arg: String::new(),
+                        msg: "no snapshot name provided".to_string(),
+                    });
+                };
+                if ranges.len() % 2 != 0 {
+                    return Err(ParseError::InvalidArgument {
+                        index: args.len() - 1,
+                        arg: args[args.len() - 1].to_string(),
+                        msg: "memory ranges need an address and a length".to_string(),
+                    });
+                }
+                let ranges = ranges
+                    .chunks(2)
+                    .enumerate()
+                    .map(|(i, range)| {
+                        // The name is the first argument
+                        let invalid = |index: usize, msg: String| ParseError::InvalidArgument {
+                            index: 1 + 2 * i + index,
+                            arg: range[index].to_string(),
+                            msg,
+                        };
+                        let addr =
+                            parse_address(range[0]).map_err(|e| invalid(0, e.to_string()))?;
+                        let len = parse_length(range[1]).map_err(|e| invalid(1, e.to_string()))?;
+                        Ok((addr, len))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Self::SaveSnapshot {
+                    name: name.to_string(),
+                    ranges,
+                })
+            }
+            x if x.starts_with("snapshot diff ") => {
+                let name = x.trim_start_matches("snapshot diff ").trim();
+                Ok(Self::DiffSnapshot(name.to_string()))
+            }
+            x if x.starts_with("call ") => {
+                let call = x.trim_start_matches("call ").trim();
+                let invalid = |index, arg: &str, msg: &str| ParseError::InvalidArgument {
+                    index,
+                    arg: arg.to_string(),
+                    msg: msg.to_string(),
+                };
+                let Some((function, args)) =
+                    call.strip_suffix(')').and_then(|call| call.split_once('('))
+                else {
+                    return Err(invalid(0, call, "expected <FUNCTION>(<ARG>, ...)"));
+                };
+                let args = args
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|arg| !arg.is_empty())
+                    .enumerate()
+                    .map(|(i, arg)| {
+                        let value = match arg.strip_prefix('-') {
+                            Some(arg) => parse_number(arg).map(|v| (v as i64).wrapping_neg()),
+                            None => parse_number(arg).map(|v| v as i64),
+                        };
+                        value.map_err(|e| invalid(i + 1, arg, &e.to_string()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Self::Call {
+                    function: function.trim().to_string(),
+                    args,
+                })
+            }
+            x if x.starts_with("asm ") => {
+                let args = x.trim_start_matches("asm ").trim();
+                let (addr_str, instruction) =
+                    args.split_once(char::is_whitespace).unwrap_or((args, ""));
+                let addr = parse_address(addr_str).map_err(|e| ParseError::InvalidArgument {
+                    index: 0,
+                    arg: addr_str.to_string(),
+                    msg: e.to_string(),
+                })?;
+                let instruction = instruction.trim();
+                let instruction = instruction
+                    .strip_prefix('"')
+                    .and_then(|i| i.strip_suffix('"'))
+                    .unwrap_or(instruction);
+                let instruction =
+                    Instruction::from_str(instruction).map_err(ParseError::InvalidInstruction)?;
+                Ok(Self::Assemble { addr, instruction })
+            }
+            x if x.starts_with("examine ") || x.starts_with("x ") => {
+                let args = x.split_whitespace().skip(1).collect::<Vec<_>>();
+                let invalid = |index, arg: &str, msg: String| ParseError::InvalidArgument {
+                    index,
+                    arg: arg.to_string(),
+                    msg,
+                };
+                let (addr, len) = match args[..] {
+                    [addr] => (addr, None),
+                    [addr, len] => (addr, Some(len)),
+                    _ => return Err(invalid(0, x, "expected examine <ADDR> [LEN]".to_string())),
+                };
+                let addr = parse_address(addr).map_err(|e| invalid(0, addr, e.to_string()))?;
+                let len = match len {
+                    Some(len) => parse_length(len).map_err(|e| invalid(1, len, e.to_string()))?,
+                    None => DEFAULT_EXAMINE_LEN,
+                };
+                Ok(Self::Examine { addr, len })
+            }
+            x if x.starts_with("disassemble ") || x.starts_with("disas ") => {
+                let name = x.split_once(' ').map(|(_, name)| name.trim());
+                match name {
+                    Some(name) if !name.is_empty() => Ok(Self::Disassemble(name.to_string())),
+                    _ => Err(ParseError::InvalidArgument {
+                        index: 0,
+                        arg: String::new(),
+                        msg: "no function provided".to_string(),
+                    }),
+                }
+            }
+            x if x.starts_with("break ") && x.contains(" if ") => {
+                let args = x.trim_start_matches("break ");
+                let (location, condition) = args.split_once(" if ").unwrap();
+                let location = Location::from_str(location).map_err(ParseError::InvalidLocation)?;
+                let condition =
+                    Condition::from_str(condition).map_err(ParseError::InvalidCondition)?;
+                Ok(Self::ConditionalBreak {
+                    location,
+                    condition,
+                })
+            }
+            x if x.starts_with("break ") && x.contains(" thread ") => {
+                let args = x.trim_start_matches("break ");
+                let (location, thread) = args.rsplit_once(" thread ").unwrap();
+                let location = Location::from_str(location).map_err(ParseError::InvalidLocation)?;
+                let tid = thread
+                    .trim()
+                    .parse::<i32>()
+                    .ok()
+                    .filter(|tid| *tid > 0)
+                    .ok_or_else(|| ParseError::InvalidArgument {
+                        index: 1,
+                        arg: thread.to_string(),
+                        msg: "expected a thread ID".to_string(),
+                    })?;
+                Ok(Self::ThreadBreak {
+                    location,
+                    thread: Pid::from_raw(tid),
+                })
+            }
+            x if x.starts_with("run-to ") => {
+                let location_str = x.trim_start_matches("run-to ");
+                let location =
+                    Location::from_str(location_str).map_err(ParseError::InvalidLocation)?;
+                Ok(Self::RunTo(location))
+            }
+            x if x.starts_with("list ") || x.starts_with("l ") => {
+                let location_str = x.split_once(' ').map(|(_, loc)| loc).unwrap_or_default();
+                let location =
+                    Location::from_str(location_str).map_err(ParseError::InvalidLocation)?;
+                Ok(Self::ListSource(location))
+            }
+            x if x.starts_with("tbreak ") => {
+                let location_str = x.trim_start_matches("tbreak ");
+                let location =
+                    Location::from_str(location_str).map_err(ParseError::InvalidLocation)?;
+                Ok(Self::TempBreak(location))
+            }
+            x if x.starts_with("hbreak ") => {
+                let location_str = x.trim_start_matches("hbreak ");
+                let location =
+                    Location::from_str(location_str).map_err(ParseError::InvalidLocation)?;
+                Ok(Self::HardwareBreak(location))
+            }
             x if x.starts_with("break ") => {
                 let location_str = x.trim_start_matches("break ");
                 let location =
@@ -130,6 +1238,19 @@ impl FromStr for Location {
         let args = location.split_whitespace().collect::<Vec<&str>>();
         if args.len() == 1 {
             let addr = args[0];
+            // file:line, but not a path like foo::bar
+            if let Some((file, line)) = addr.rsplit_once(':') {
+                if !file.is_empty() && !file.ends_with(':') {
+                    let line = line.parse::<usize>().map_err(|e| {
+                        error!("Invalid line number: {}", e);
+                        LocationError::InvalidLineNumber
+                    })?;
+                    return Ok(Location::Line {
+                        file: PathBuf::from(file),
+                        line,
+                    });
+                }
+            }
             if addr.starts_with("0x") {
                 let hex_addr = addr.strip_prefix("0x").unwrap();
                 let addr = u64::from_str_radix(hex_addr, 16).map_err(|e| {
@@ -160,14 +1281,32 @@ impl FromStr for Location {
     }
 }
 
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Registers => write!(f, "registers"),
+            Self::Variable(name) => write!(f, "{}", name),
+            Self::Evaluate(expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
 impl FromStr for Expression {
     type Err = ExpressionError;
 
     fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let is_identifier = value
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
         if value == "registers" {
             Ok(Expression::Registers)
+        } else if is_identifier && !Registers::GENERAL_PURPOSE.contains(&value) {
+            // Printed with its type rather than as a number
+            Ok(Expression::Variable(value.to_string()))
         } else {
-            Err(ExpressionError::InvalidExpression)
+            Ok(Expression::Evaluate(Expr::from_str(value)?))
         }
     }
 }
@@ -181,26 +1320,202 @@ mod tests {
         assert_eq!(Command::from_str("quit").unwrap(), Command::Quit);
         assert_eq!(Command::from_str("q").unwrap(), Command::Quit);
         assert_eq!(Command::from_str("logs").unwrap(), Command::ToggleLogs);
+        assert_eq!(Command::from_str("output").unwrap(), Command::ToggleOutput);
+        assert_eq!(
+            Command::from_str("breakpoints").unwrap(),
+            Command::ToggleBreakpoints
+        );
         assert_eq!(Command::from_str("status").unwrap(), Command::Status);
         assert_eq!(Command::from_str("l").unwrap(), Command::ListBreakpoints);
+        assert_eq!(
+            Command::from_str("list main.c:4").unwrap(),
+            Command::ListSource(Location::Line {
+                file: "main.c".into(),
+                line: 4
+            })
+        );
+        assert_eq!(
+            Command::from_str("l main").unwrap(),
+            Command::ListSource(Location::Function("main".to_string()))
+        );
+        assert_eq!(
+            Command::from_str("reload-symbols").unwrap(),
+            Command::ReloadSymbols
+        );
+        assert_eq!(
+            Command::from_str("diff-symbols old/loop").unwrap(),
+            Command::DiffSymbols(PathBuf::from("old/loop"))
+        );
+        assert!(Command::from_str("diff-symbols").is_err());
+        assert_eq!(
+            Command::from_str("thread list").unwrap(),
+            Command::ListThreads
+        );
+        assert_eq!(Command::from_str("bt").unwrap(), Command::Backtrace);
+        assert_eq!(
+            Command::from_str("stops").unwrap(),
+            Command::StopHistory(None)
+        );
+        assert_eq!(
+            Command::from_str("history stops 3").unwrap(),
+            Command::StopHistory(Some(3))
+        );
+        assert!(Command::from_str("stops last").is_err());
         assert_eq!(Command::from_str("help").unwrap(), Command::Help);
         assert_eq!(Command::from_str("?").unwrap(), Command::Help);
         assert_eq!(Command::from_str("restart").unwrap(), Command::Restart);
         assert_eq!(
             Command::from_str("load help.rs").unwrap(),
-            Command::Load(PathBuf::from("help.rs"))
+            Command::Load {
+                path: PathBuf::from("help.rs"),
+                args: vec![]
+            }
+        );
+        assert_eq!(
+            Command::from_str("load ./prog foo  bar").unwrap(),
+            Command::Load {
+                path: PathBuf::from("./prog"),
+                args: vec!["foo".to_string(), "bar".to_string()]
+            }
+        );
+        assert!(Command::from_str("load  ").is_err());
+        assert_eq!(Command::from_str("env").unwrap(), Command::ListEnv);
+        assert_eq!(
+            Command::from_str("env GREETING=hello world").unwrap(),
+            Command::SetEnv {
+                key: "GREETING".to_string(),
+                value: "hello world".to_string()
+            }
         );
+        assert!(Command::from_str("env GREETING").is_err());
+        assert_eq!(Command::from_str("detach").unwrap(), Command::Detach);
+        assert_eq!(Command::from_str("cwd").unwrap(), Command::Cwd(None));
         assert_eq!(
-            Command::from_str("attach 546").unwrap(),
-            Command::Attach(546)
+            Command::from_str("cwd ../data").unwrap(),
+            Command::Cwd(Some(PathBuf::from("../data")))
+        );
+        let pid = std::process::id() as i32;
+        assert_eq!(
+            Command::from_str(&format!("attach {}", pid)).unwrap(),
+            Command::Attach(pid)
         );
         assert_eq!(Command::from_str("continue").unwrap(), Command::Continue);
+        assert_eq!(
+            Command::from_str("continue &").unwrap(),
+            Command::ContinueBackground
+        );
+        assert_eq!(
+            Command::from_str("c&").unwrap(),
+            Command::ContinueBackground
+        );
+        assert!(Command::from_str("cat &").is_err());
+        assert_eq!(Command::from_str("interrupt").unwrap(), Command::Interrupt);
+        assert_eq!(
+            Command::from_str("input").unwrap(),
+            Command::Input(String::new())
+        );
+        assert_eq!(
+            Command::from_str("signal usr1").unwrap(),
+            Command::Signal(Some(Signal::SIGUSR1))
+        );
+        assert_eq!(
+            Command::from_str("signal 15").unwrap(),
+            Command::Signal(Some(Signal::SIGTERM))
+        );
+        assert_eq!(
+            Command::from_str("signal 0").unwrap(),
+            Command::Signal(None)
+        );
+        assert!(Command::from_str("signal SIGNOPE").is_err());
+        assert_eq!(
+            Command::from_str("handle SIGALRM nopass").unwrap(),
+            Command::Handle {
+                signal: Signal::SIGALRM,
+                pass: false
+            }
+        );
+        assert!(matches!(
+            Command::from_str("handle SIGALRM maybe"),
+            Err(ParseError::InvalidArgument { index: 1, .. })
+        ));
+        assert!(Command::from_str("handle SIGALRM").is_err());
+        assert_eq!(
+            Command::from_str("catch syscall").unwrap(),
+            Command::CatchSyscalls
+        );
+        assert!(Command::from_str("catch fork").is_err());
+        assert_eq!(
+            Command::from_str("input  two  spaces ").unwrap(),
+            Command::Input(" two  spaces ".to_string())
+        );
+        assert_eq!(Command::from_str("wait").unwrap(), Command::Wait(None));
+        assert_eq!(
+            Command::from_str("wait 2.5").unwrap(),
+            Command::Wait(Some(Duration::from_millis(2500)))
+        );
+        assert!(Command::from_str("wait soon").is_err());
+        assert!(Command::from_str("wait -1").is_err());
         assert_eq!(Command::from_str("step").unwrap(), Command::Step);
+        assert_eq!(Command::from_str("s").unwrap(), Command::Step);
+        assert_eq!(
+            Command::from_str("stepi").unwrap(),
+            Command::StepInstruction
+        );
+        assert_eq!(Command::from_str("si").unwrap(), Command::StepInstruction);
+        assert_eq!(Command::from_str("next").unwrap(), Command::Next);
+        assert_eq!(Command::from_str("n").unwrap(), Command::Next);
+        assert_eq!(Command::from_str("finish").unwrap(), Command::Finish);
         assert_eq!(
             Command::from_str("print registers").unwrap(),
             Command::Print(Expression::Registers)
         );
+        assert_eq!(
+            Command::from_str("print counter").unwrap(),
+            Command::Print(Expression::Variable("counter".to_string()))
+        );
+        assert_eq!(
+            Command::from_str("print *counter").unwrap(),
+            Command::Print(Expression::Evaluate(Expr::from_str("*counter").unwrap()))
+        );
+        assert_eq!(
+            Command::from_str("print rsp").unwrap(),
+            Command::Print(Expression::Evaluate(Expr::Register("rsp".to_string())))
+        );
+        assert!(matches!(
+            Command::from_str("print counter %"),
+            Err(ParseError::InvalidExpression(ExpressionError::Eval(_)))
+        ));
         assert_eq!(Command::from_str("").unwrap(), Command::Null);
+        assert_eq!(
+            Command::from_str("trace start trace.txt").unwrap(),
+            Command::StartTrace(PathBuf::from("trace.txt"))
+        );
+        assert_eq!(
+            Command::from_str("trace-record out.trace").unwrap(),
+            Command::StartTrace(PathBuf::from("out.trace"))
+        );
+        assert_eq!(Command::from_str("trace stop").unwrap(), Command::StopTrace);
+        assert_eq!(Command::from_str("delete 2").unwrap(), Command::Delete(2));
+        assert_eq!(
+            Command::from_str("set history-size 20").unwrap(),
+            Command::Set {
+                name: "history-size".to_string(),
+                value: "20".to_string()
+            }
+        );
+        assert_eq!(Command::from_str("show all").unwrap(), Command::Show(None));
+        assert_eq!(
+            Command::from_str("show trace-limit").unwrap(),
+            Command::Show(Some("trace-limit".to_string()))
+        );
+        assert_eq!(
+            Command::from_str("break --help").unwrap(),
+            Command::HelpFor("break".to_string())
+        );
+        assert_eq!(
+            Command::from_str("trace start --help").unwrap(),
+            Command::HelpFor("trace".to_string())
+        );
         assert_eq!(
             Command::from_str("break main").unwrap(),
             Command::Break(Location::Function("main".to_string()))
@@ -213,6 +1528,43 @@ mod tests {
             Command::from_str("attach boop"),
             Err(ParseError::InvalidArgument { .. })
         ));
+        assert_eq!(
+            Command::from_str("attach 0"),
+            Err(ParseError::InvalidArgument {
+                index: 0,
+                arg: "0".to_string(),
+                msg: "PID 0 is not a valid process".to_string(),
+            })
+        );
+        assert!(matches!(
+            Command::from_str("attach -5"),
+            Err(ParseError::InvalidArgument { .. })
+        ));
+        let missing = i32::MAX;
+        assert_eq!(
+            Command::from_str(&format!("attach {}", missing)),
+            Err(ParseError::InvalidArgument {
+                index: 0,
+                arg: missing.to_string(),
+                msg: format!("PID {} does not exist", missing),
+            })
+        );
+        assert_eq!(
+            Command::from_str("dance --help"),
+            Err(ParseError::InvalidCommand("dance --help".to_string()))
+        );
+        assert!(matches!(
+            Command::from_str("set history-size"),
+            Err(ParseError::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            Command::from_str("delete one"),
+            Err(ParseError::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            Command::from_str("trace start "),
+            Err(ParseError::InvalidArgument { .. })
+        ));
         assert_eq!(
             Command::from_str("dance"),
             Err(ParseError::InvalidCommand("dance".to_string()))
@@ -239,6 +1591,359 @@ mod tests {
         );
     }
 
+    #[test]
+    fn dprintf_command_parsing() {
+        let command = Command::from_str(r#"dprintf loop.c:6, "counter = %d\n", counter"#).unwrap();
+        assert_eq!(
+            command,
+            Command::Dprintf {
+                location: Location::Line {
+                    file: PathBuf::from("loop.c"),
+                    line: 6
+                },
+                dprintf: Dprintf::from_str(r#""counter = %d\n", counter"#).unwrap(),
+            }
+        );
+        assert!(matches!(
+            Command::from_str("dprintf main"),
+            Err(ParseError::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            Command::from_str(r#"dprintf main, "%d""#),
+            Err(ParseError::InvalidDprintf(_))
+        ));
+    }
+
+    #[test]
+    fn snapshot_command_parsing() {
+        assert_eq!(
+            Command::from_str("snapshot save a").unwrap(),
+            Command::SaveSnapshot {
+                name: "a".to_string(),
+                ranges: vec![]
+            }
+        );
+        assert_eq!(
+            Command::from_str("snapshot save a 0x1000 16 4096 8").unwrap(),
+            Command::SaveSnapshot {
+                name: "a".to_string(),
+                ranges: vec![(0x1000.into(), 16), (4096.into(), 8)]
+            }
+        );
+        assert_eq!(
+            Command::from_str("snapshot save a rsp-0x10 2g").unwrap(),
+            Command::SaveSnapshot {
+                name: "a".to_string(),
+                ranges: vec![(
+                    Address::Register {
+                        name: "rsp".to_string(),
+                        offset: -16
+                    },
+                    16
+                )]
+            }
+        );
+        assert!(matches!(
+            Command::from_str("snapshot save a 0x1000 0"),
+            Err(ParseError::InvalidArgument { index: 2, .. })
+        ));
+        assert_eq!(
+            Command::from_str("snapshot diff a").unwrap(),
+            Command::DiffSnapshot("a".to_string())
+        );
+        assert!(matches!(
+            Command::from_str("snapshot save a 0x1000"),
+            Err(ParseError::InvalidArgument { index: 1, .. })
+        ));
+        assert!(matches!(
+            Command::from_str("snapshot save a 0x1000 lots"),
+            Err(ParseError::InvalidArgument { index: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn run_to_parsing() {
+        assert_eq!(
+            Command::from_str("run-to main").unwrap(),
+            Command::RunTo(Location::Function("main".to_string()))
+        );
+        assert_eq!(
+            Command::from_str("run-to main.c:20").unwrap(),
+            Command::RunTo(Location::Line {
+                file: "main.c".into(),
+                line: 20
+            })
+        );
+        assert!(matches!(
+            Command::from_str("run-to "),
+            Err(ParseError::InvalidLocation(_))
+        ));
+    }
+
+    #[test]
+    fn tbreak_and_hbreak_parsing() {
+        assert_eq!(
+            Command::from_str("tbreak 0x401000").unwrap(),
+            Command::TempBreak(Location::Address(0x401000))
+        );
+        assert_eq!(
+            Command::from_str("hbreak increment").unwrap(),
+            Command::HardwareBreak(Location::Function("increment".to_string()))
+        );
+        assert!(matches!(
+            Command::from_str("tbreak "),
+            Err(ParseError::InvalidLocation(_))
+        ));
+    }
+
+    #[test]
+    fn continue_until_parsing() {
+        assert_eq!(
+            Command::from_str("continue until 2 hits 5").unwrap(),
+            Command::ContinueUntil { id: 2, hits: 5 }
+        );
+        assert_eq!(
+            Command::from_str("c until 1 hits 10").unwrap(),
+            Command::ContinueUntil { id: 1, hits: 10 }
+        );
+        assert!(matches!(
+            Command::from_str("continue until 2"),
+            Err(ParseError::InvalidArgument { index: 0, .. })
+        ));
+        assert!(matches!(
+            Command::from_str("continue until 2 hits lots"),
+            Err(ParseError::InvalidArgument { index: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn info_command_parsing() {
+        assert_eq!(
+            Command::from_str("info version").unwrap(),
+            Command::Info(InfoKind::Version)
+        );
+        let missing = Command::from_str("info");
+        assert!(matches!(missing, Err(ParseError::InvalidArgument { .. })));
+        // Both list everything info can show
+        let message = |res: Result<Command, ParseError>| match res {
+            Err(ParseError::InvalidArgument { msg, .. }) => msg,
+            res => panic!("Unexpected {:?}", res),
+        };
+        assert_eq!(message(missing), message(Command::from_str("info nothing")));
+        assert!(message(Command::from_str("info")).contains("threads"));
+        assert_eq!(
+            Command::from_str("info registers").unwrap(),
+            Command::Info(InfoKind::Registers(RegisterGroup::All))
+        );
+        assert_eq!(
+            Command::from_str("info registers segment").unwrap(),
+            Command::Info(InfoKind::Registers(RegisterGroup::Segment))
+        );
+        assert_eq!(
+            Command::from_str("info registers flags").unwrap(),
+            Command::Info(InfoKind::Registers(RegisterGroup::Flags))
+        );
+        assert_eq!(
+            Command::from_str("info registers vector"),
+            Err(ParseError::InvalidArgument {
+                index: 1,
+                arg: "vector".to_string(),
+                msg: "expected one of: all, general, segment, flags".to_string(),
+            })
+        );
+        assert_eq!(
+            Command::from_str("info inferior-args").unwrap(),
+            Command::Info(InfoKind::InferiorArgs)
+        );
+        assert_eq!(
+            Command::from_str("info compiler").unwrap(),
+            Command::Info(InfoKind::Compiler)
+        );
+        assert_eq!(
+            Command::from_str("info signals-state").unwrap(),
+            Command::Info(InfoKind::SignalsState)
+        );
+        assert_eq!(
+            Command::from_str("info display").unwrap(),
+            Command::Info(InfoKind::Display)
+        );
+        assert_eq!(
+            Command::from_str("info threads").unwrap(),
+            Command::Info(InfoKind::Threads)
+        );
+        assert_eq!(
+            Command::from_str("display").unwrap(),
+            Command::Info(InfoKind::Display)
+        );
+        assert_eq!(
+            Command::from_str("display counter").unwrap(),
+            Command::Display(DisplayExpr::Print(Expression::Variable(
+                "counter".to_string()
+            )))
+        );
+        assert_eq!(
+            Command::from_str("display x 0x10 4").unwrap(),
+            Command::Display(DisplayExpr::Examine {
+                addr: 0x10.into(),
+                len: 4
+            })
+        );
+        assert!(matches!(
+            Command::from_str("display rsp + 8").unwrap(),
+            Command::Display(DisplayExpr::Print(Expression::Evaluate(_)))
+        ));
+        assert!(Command::from_str("display 1 +").is_err());
+        assert_eq!(
+            Command::from_str("undisplay 2").unwrap(),
+            Command::Undisplay(2)
+        );
+        assert!(Command::from_str("undisplay all").is_err());
+        assert_eq!(
+            Command::from_str("info line").unwrap(),
+            Command::Info(InfoKind::Line(None))
+        );
+        assert_eq!(
+            Command::from_str("info line main.c:9").unwrap(),
+            Command::Info(InfoKind::Line(Some(Location::Line {
+                file: "main.c".into(),
+                line: 9
+            })))
+        );
+        assert_eq!(
+            Command::from_str("info line increment").unwrap(),
+            Command::Info(InfoKind::Line(Some(Location::Function(
+                "increment".to_string()
+            ))))
+        );
+        assert!(matches!(
+            Command::from_str("info colours"),
+            Err(ParseError::InvalidArgument { .. })
+        ));
+        assert_eq!(
+            Command::from_str("info --help").unwrap(),
+            Command::HelpFor("info".to_string())
+        );
+    }
+
+    #[test]
+    fn call_command_parsing() {
+        assert_eq!(
+            Command::from_str("call add(1, -2, 0x10)").unwrap(),
+            Command::Call {
+                function: "add".to_string(),
+                args: vec![1, -2, 16]
+            }
+        );
+        assert_eq!(
+            Command::from_str("call tick()").unwrap(),
+            Command::Call {
+                function: "tick".to_string(),
+                args: vec![]
+            }
+        );
+        assert!(matches!(
+            Command::from_str("call add"),
+            Err(ParseError::InvalidArgument { index: 0, .. })
+        ));
+        assert!(matches!(
+            Command::from_str("call add(1, x)"),
+            Err(ParseError::InvalidArgument { index: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn disassemble_command_parsing() {
+        assert_eq!(
+            Command::from_str("disassemble main").unwrap(),
+            Command::Disassemble("main".to_string())
+        );
+        assert_eq!(
+            Command::from_str("disas increment").unwrap(),
+            Command::Disassemble("increment".to_string())
+        );
+        assert!(Command::from_str("disas  ").is_err());
+    }
+
+    #[test]
+    fn asm_command_parsing() {
+        assert_eq!(
+            Command::from_str("asm 0x401000 nop").unwrap(),
+            Command::Assemble {
+                addr: 0x401000.into(),
+                instruction: Instruction::Nop
+            }
+        );
+        assert_eq!(
+            Command::from_str("asm 0x401000 \"jmp 0x401020\"").unwrap(),
+            Command::Assemble {
+                addr: 0x401000.into(),
+                instruction: Instruction::Jmp(0x401020)
+            }
+        );
+        assert!(matches!(
+            Command::from_str("asm 0x401000"),
+            Err(ParseError::InvalidInstruction(AsmError::Empty))
+        ));
+        assert!(matches!(
+            Command::from_str("asm here nop"),
+            Err(ParseError::InvalidArgument { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn examine_command_parsing() {
+        assert_eq!(
+            Command::from_str("x 0x1000").unwrap(),
+            Command::Examine {
+                addr: 0x1000.into(),
+                len: DEFAULT_EXAMINE_LEN
+            }
+        );
+        assert_eq!(
+            Command::from_str("examine 4096 0x20").unwrap(),
+            Command::Examine {
+                addr: 0x1000.into(),
+                len: 32
+            }
+        );
+        assert_eq!(
+            Command::from_str("x rbp-8 4g").unwrap(),
+            Command::Examine {
+                addr: Address::Register {
+                    name: "rbp".to_string(),
+                    offset: -8
+                },
+                len: 32
+            }
+        );
+        assert!(matches!(
+            Command::from_str("x rsp 4q"),
+            Err(ParseError::InvalidArgument { index: 1, .. })
+        ));
+        assert!(matches!(
+            Command::from_str("x main"),
+            Err(ParseError::InvalidArgument { index: 0, .. })
+        ));
+        assert!(matches!(
+            Command::from_str("x 0x1000 lots"),
+            Err(ParseError::InvalidArgument { index: 1, .. })
+        ));
+        assert!(Command::from_str("x 0x1000 16 32").is_err());
+    }
+
+    #[test]
+    fn ignore_command_parsing() {
+        assert_eq!(
+            Command::from_str("ignore 2 10").unwrap(),
+            Command::Ignore { id: 2, count: 10 }
+        );
+        assert!(matches!(
+            Command::from_str("ignore 2 many"),
+            Err(ParseError::InvalidArgument { index: 1, .. })
+        ));
+        assert!(Command::from_str("ignore 2").is_err());
+    }
+
     #[test]
     fn break_command_parsing() {
         let b = Command::from_str("break main.rs 5").unwrap();
@@ -257,6 +1962,55 @@ mod tests {
             e => panic!("Invalid command parsed: {:?}", e),
         }
 
+        assert_eq!(
+            Command::from_str("break main.rs:5").unwrap(),
+            Command::Break(Location::Line {
+                file: PathBuf::from("main.rs"),
+                line: 5
+            })
+        );
+        assert_eq!(
+            Command::from_str("break foo::bar").unwrap(),
+            Command::Break(Location::Function("foo::bar".to_string()))
+        );
+
+        assert_eq!(
+            Command::from_str("break loop.c:6 if counter == 3").unwrap(),
+            Command::ConditionalBreak {
+                location: Location::Line {
+                    file: PathBuf::from("loop.c"),
+                    line: 6
+                },
+                condition: Condition::from_str("counter == 3").unwrap(),
+            }
+        );
+        assert!(matches!(
+            Command::from_str("break main if rax"),
+            Err(ParseError::InvalidCondition(_))
+        ));
+        assert_eq!(
+            Command::from_str("break thread_function thread 1234").unwrap(),
+            Command::ThreadBreak {
+                location: Location::Function("thread_function".to_string()),
+                thread: Pid::from_raw(1234),
+            }
+        );
+        assert_eq!(
+            Command::from_str("break threads.c 9 thread 1234").unwrap(),
+            Command::ThreadBreak {
+                location: Location::Line {
+                    file: PathBuf::from("threads.c"),
+                    line: 9
+                },
+                thread: Pid::from_raw(1234),
+            }
+        );
+        assert!(matches!(
+            Command::from_str("break main thread two"),
+            Err(ParseError::InvalidArgument { index: 1, .. })
+        ));
+        assert!(Command::from_str("break main thread -1").is_err());
+
         let b = Command::from_str("break 0x12AD6").unwrap();
         match b {
             Command::Break(location) => {
@@ -0,0 +1,404 @@
+//! Conditions for breakpoints, `break foo if counter == 5`. A condition compares a general
+//! purpose register, a global variable or a word of memory to a number. Variables have to be a
+//! scalar type: a signed or unsigned integer, bool, pointer or a float. Floats are compared as
+//! floats, anything else as integers.
+//!
+//! Memory is written `*<ADDR>` or `*<REG>` for the address in a register and is read as an
+//! unsigned 8 byte word. Like `print` the address is where it is in the running process, not the
+//! address in the executable.
+//!
+//! Variables are looked up in the debug info when the breakpoint is set, so an unknown variable
+//! is reported then rather than every time the breakpoint is hit.
+use crate::elf::{ExecutableFile, ValueKind};
+use crate::process::{Process, ProcessError, Registers};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum ConditionError {
+    #[error("expected a comparison (==, !=, <, <=, >, >=)")]
+    MissingComparison,
+    #[error("invalid operand \"{0}\", expected a register, variable name or *<ADDR>")]
+    InvalidOperand(String),
+    #[error("invalid number \"{0}\"")]
+    InvalidLiteral(String),
+    #[error("no global variable named {0}")]
+    UnknownVariable(String),
+    #[error("variable {0} isn't an integer, pointer or float")]
+    UnsupportedType(String),
+    #[error("no debug info loaded to find {0}")]
+    NoDebugInfo(String),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Two character operators come first so `<=` isn't taken as `<`.
+const COMPARISONS: &[(&str, Comparison)] = &[
+    ("==", Comparison::Eq),
+    ("!=", Comparison::Ne),
+    ("<=", Comparison::Le),
+    (">=", Comparison::Ge),
+    ("<", Comparison::Lt),
+    (">", Comparison::Gt),
+];
+
+impl Comparison {
+    fn as_str(&self) -> &'static str {
+        COMPARISONS
+            .iter()
+            .find(|(_, op)| op == self)
+            .map(|(s, _)| *s)
+            .unwrap_or_default()
+    }
+
+    fn compare<T: PartialOrd>(&self, lhs: T, rhs: T) -> bool {
+        match self {
+            Self::Eq => lhs == rhs,
+            Self::Ne => lhs != rhs,
+            Self::Lt => lhs < rhs,
+            Self::Le => lhs <= rhs,
+            Self::Gt => lhs > rhs,
+            Self::Ge => lhs >= rhs,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Operand {
+    Register(String),
+    Variable(String),
+    /// The word at an address, `*0x7ffc1000` or `*rdi`
+    Memory(Pointer),
+}
+
+/// Where a memory operand points.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Pointer {
+    Address(u64),
+    /// The address held in a general purpose register
+    Register(String),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Register(name) | Self::Variable(name) => f.write_str(name),
+            Self::Memory(Pointer::Address(addr)) => write!(f, "*0x{:x}", addr),
+            Self::Memory(Pointer::Register(name)) => write!(f, "*{}", name),
+        }
+    }
+}
+
+/// The number a value is compared to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Literal {
+    Int(i64),
+    /// Bits of an `f64` so the command can still be `Eq`
+    Float(u64),
+}
+
+impl Literal {
+    fn as_f64(&self) -> f64 {
+        match self {
+            Self::Int(i) => *i as f64,
+            Self::Float(bits) => f64::from_bits(*bits),
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Int(i) => write!(f, "{}", i),
+            Self::Float(bits) => write!(f, "{}", f64::from_bits(*bits)),
+        }
+    }
+}
+
+/// A condition as written by the user.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Condition {
+    pub lhs: Operand,
+    pub comparison: Comparison,
+    pub rhs: Literal,
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.lhs, self.comparison.as_str(), self.rhs)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = ConditionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, text, comparison) = COMPARISONS
+            .iter()
+            .filter_map(|(text, op)| s.find(text).map(|i| (i, text, *op)))
+            .min_by_key(|(i, _, _)| *i)
+            .ok_or(ConditionError::MissingComparison)?;
+        let lhs = s[..index].trim();
+        let rhs = s[index + text.len()..].trim();
+
+        Ok(Self {
+            lhs: parse_operand(lhs)?,
+            comparison,
+            rhs: parse_literal(rhs)?,
+        })
+    }
+}
+
+fn parse_operand(s: &str) -> Result<Operand, ConditionError> {
+    let invalid = || ConditionError::InvalidOperand(s.to_string());
+    if let Some(pointer) = s.strip_prefix('*') {
+        let pointer = pointer.trim();
+        let pointer = if Registers::GENERAL_PURPOSE.contains(&pointer) {
+            Pointer::Register(pointer.to_string())
+        } else {
+            match parse_literal(pointer) {
+                Ok(Literal::Int(addr)) if !pointer.starts_with('-') => {
+                    Pointer::Address(addr as u64)
+                }
+                _ => return Err(invalid()),
+            }
+        };
+        return Ok(Operand::Memory(pointer));
+    }
+
+    let is_identifier = s
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !is_identifier {
+        Err(invalid())
+    } else if Registers::GENERAL_PURPOSE.contains(&s) {
+        Ok(Operand::Register(s.to_string()))
+    } else {
+        Ok(Operand::Variable(s.to_string()))
+    }
+}
+
+fn parse_literal(s: &str) -> Result<Literal, ConditionError> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s),
+    };
+    let int = match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).map(|i| i as i64),
+        None => digits.parse::<i64>(),
+    };
+    match int {
+        Ok(i) if negative => Ok(Literal::Int(i.wrapping_neg())),
+        Ok(i) => Ok(Literal::Int(i)),
+        Err(_) => s
+            .parse::<f64>()
+            .map(|f| Literal::Float(f.to_bits()))
+            .map_err(|_| ConditionError::InvalidLiteral(s.to_string())),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Register(String),
+    /// Memory at the address held in a register, only known when the breakpoint is hit
+    RegisterPointer(String),
+    Memory {
+        addr: u64,
+        size: u64,
+        kind: ValueKind,
+    },
+}
+
+/// A condition with its variables resolved to addresses in the running process, ready to be
+/// checked when a breakpoint is hit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedCondition {
+    condition: Condition,
+    lhs: Value,
+}
+
+impl fmt::Display for ResolvedCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.condition.fmt(f)
+    }
+}
+
+impl Condition {
+    /// Looks up any variables in the condition, `addr_offset` is the load offset of the
+    /// executable in the process.
+    pub fn resolve(
+        &self,
+        elf: Option<&ExecutableFile>,
+        addr_offset: u64,
+    ) -> Result<ResolvedCondition, ConditionError> {
+        let lhs = match &self.lhs {
+            Operand::Register(name) => Value::Register(name.clone()),
+            Operand::Memory(Pointer::Register(name)) => Value::RegisterPointer(name.clone()),
+            Operand::Memory(Pointer::Address(addr)) => Value::Memory {
+                addr: *addr,
+                size: 8,
+                kind: ValueKind::Unsigned,
+            },
+            Operand::Variable(name) => {
+                let elf = elf.ok_or_else(|| ConditionError::NoDebugInfo(name.clone()))?;
+                let variable = elf
+                    .global_variable(name)
+                    .ok()
+                    .flatten()
+                    .ok_or_else(|| ConditionError::UnknownVariable(name.clone()))?;
+                if variable.kind == ValueKind::Other || !matches!(variable.size, 1 | 2 | 4 | 8) {
+                    return Err(ConditionError::UnsupportedType(name.clone()));
+                }
+                Value::Memory {
+                    addr: variable.address + addr_offset,
+                    size: variable.size,
+                    kind: variable.kind,
+                }
+            }
+        };
+        Ok(ResolvedCondition {
+            condition: self.clone(),
+            lhs,
+        })
+    }
+}
+
+impl ResolvedCondition {
+    /// Checks the condition against the current state of a stopped process.
+    pub fn evaluate(&self, process: &Process) -> Result<bool, ProcessError> {
+        let comparison = self.condition.comparison;
+        let rhs = self.condition.rhs;
+        let (raw, size, kind) = match &self.lhs {
+            Value::Register(name) => {
+                let registers = process.get_gp_registers()?;
+                (
+                    registers.get(name).unwrap_or_default(),
+                    8,
+                    ValueKind::Unsigned,
+                )
+            }
+            Value::RegisterPointer(name) => {
+                let addr = process.get_gp_registers()?.get(name).unwrap_or_default();
+                let data = process.read_memory(addr, 8)?;
+                (
+                    u64::from_le_bytes(data.try_into().unwrap_or_default()),
+                    8,
+                    ValueKind::Unsigned,
+                )
+            }
+            Value::Memory { addr, size, kind } => {
+                let data = process.read_memory(*addr, *size as usize)?;
+                let mut bytes = [0u8; 8];
+                bytes[..data.len()].copy_from_slice(&data);
+                (u64::from_le_bytes(bytes), *size, *kind)
+            }
+        };
+        let result = match kind {
+            ValueKind::Float => {
+                let value = if size == 4 {
+                    f32::from_bits(raw as u32) as f64
+                } else {
+                    f64::from_bits(raw)
+                };
+                comparison.compare(value, rhs.as_f64())
+            }
+            _ => {
+                // Compare integers as i128 so large unsigned values and negative literals work
+                let shift = 64 - 8 * size;
+                let value = if kind == ValueKind::Signed {
+                    (((raw << shift) as i64) >> shift) as i128
+                } else {
+                    raw as i128
+                };
+                match rhs {
+                    Literal::Int(i) if kind == ValueKind::Unsigned && size == 8 && i < 0 => {
+                        // Registers are unsigned but -1 should still match 0xffff_ffff_ffff_ffff
+                        comparison.compare(value, i as u64 as i128)
+                    }
+                    Literal::Int(i) => comparison.compare(value, i as i128),
+                    Literal::Float(_) => comparison.compare(value as f64, rhs.as_f64()),
+                }
+            }
+        };
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_conditions() {
+        assert_eq!(
+            Condition::from_str("rax == 0").unwrap(),
+            Condition {
+                lhs: Operand::Register("rax".to_string()),
+                comparison: Comparison::Eq,
+                rhs: Literal::Int(0),
+            }
+        );
+        assert_eq!(
+            Condition::from_str("counter<=-5").unwrap(),
+            Condition {
+                lhs: Operand::Variable("counter".to_string()),
+                comparison: Comparison::Le,
+                rhs: Literal::Int(-5),
+            }
+        );
+        assert_eq!(
+            Condition::from_str("ratio > 1.5").unwrap().rhs,
+            Literal::Float(1.5f64.to_bits())
+        );
+        assert_eq!(
+            Condition::from_str("rdi != 0x10").unwrap().rhs,
+            Literal::Int(16)
+        );
+        assert_eq!(
+            Condition::from_str("counter >= 3").unwrap().to_string(),
+            "counter >= 3"
+        );
+        assert_eq!(
+            Condition::from_str("*rdi == 1").unwrap().lhs,
+            Operand::Memory(Pointer::Register("rdi".to_string()))
+        );
+        let condition = Condition::from_str("* 0x4010 != 0").unwrap();
+        assert_eq!(condition.lhs, Operand::Memory(Pointer::Address(0x4010)));
+        assert_eq!(condition.to_string(), "*0x4010 != 0");
+    }
+
+    #[test]
+    fn invalid_conditions() {
+        assert_eq!(
+            Condition::from_str("rax"),
+            Err(ConditionError::MissingComparison)
+        );
+        assert_eq!(
+            Condition::from_str("rax + 1 == 1"),
+            Err(ConditionError::InvalidOperand("rax + 1".to_string()))
+        );
+        assert_eq!(
+            Condition::from_str("*counter == 1"),
+            Err(ConditionError::InvalidOperand("*counter".to_string()))
+        );
+        assert_eq!(
+            Condition::from_str("*-8 == 1"),
+            Err(ConditionError::InvalidOperand("*-8".to_string()))
+        );
+        assert_eq!(
+            Condition::from_str("rax == lots"),
+            Err(ConditionError::InvalidLiteral("lots".to_string()))
+        );
+    }
+}
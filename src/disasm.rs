@@ -0,0 +1,274 @@
+//! Decoding x86-64 machine code with [`iced_x86`]. Along with the instruction's text this
+//! works out how long each instruction is and whether it changes control flow, which is what
+//! stepping needs: `next` has to know whether the instruction at the program counter is a call
+//! and where the call returns to.
+//!
+//! The decode path is:
+//!
+//! 1. At most `MAX_INSTRUCTION_LEN` bytes are handed to an iced `Decoder` set up for 64-bit
+//!    code at the instruction's address, so relative branch targets come out as addresses in
+//!    the process. An encoding that needs more bytes than that is `TooLong` rather than
+//!    `Truncated`.
+//! 2. The decoder's `FlowControl` is mapped onto our `Flow`. iced counts `syscall` and
+//!    `sysenter` as calls, so they're picked out by mnemonic first.
+//! 3. The instruction is formatted with an `IntelFormatter` for listings.
+use iced_x86::{
+    Decoder, DecoderError, DecoderOptions, FlowControl, Formatter, Instruction, IntelFormatter,
+    Mnemonic,
+};
+use thiserror::Error;
+
+/// Longest an instruction can be, longer encodings fault.
+pub const MAX_INSTRUCTION_LEN: usize = 15;
+
+#[derive(Clone, Copy, Debug, Error, Eq, PartialEq)]
+pub enum DecodeError {
+    #[error("instruction runs past the end of the bytes read")]
+    Truncated,
+    #[error("invalid instruction starting with 0x{0:02x}")]
+    InvalidOpcode(u8),
+    #[error("instruction is longer than 15 bytes")]
+    TooLong,
+}
+
+/// How an instruction affects where execution goes next.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Flow {
+    /// Carries on to the next instruction
+    Sequential,
+    /// Calls a function, with the target if it's relative rather than through a register or
+    /// memory
+    Call(Option<u64>),
+    Return,
+    Jump(Option<u64>),
+    ConditionalJump(u64),
+    Syscall,
+    /// `int3`, `int n` and friends
+    Interrupt,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Decoded {
+    pub len: usize,
+    pub flow: Flow,
+    /// The instruction in Intel syntax, e.g. `mov rbp, rsp`
+    pub text: String,
+}
+
+impl Decoded {
+    pub fn is_call(&self) -> bool {
+        matches!(self.flow, Flow::Call(_))
+    }
+}
+
+/// One instruction out of a block of code decoded by `decode_all`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Listed {
+    pub addr: u64,
+    /// The instruction's bytes, a single byte if it couldn't be decoded
+    pub bytes: Vec<u8>,
+    pub decoded: Result<Decoded, DecodeError>,
+}
+
+/// Decodes every instruction in `bytes`, which start at `addr` in the process. Bytes that don't
+/// decode are skipped one at a time so decoding can get back in step with the instructions
+/// after them.
+pub fn decode_all(addr: u64, bytes: &[u8]) -> Vec<Listed> {
+    let mut result = vec![];
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let pc = addr + pos as u64;
+        let decoded = decode(pc, &bytes[pos..]);
+        let len = decoded.as_ref().map(|d| d.len).unwrap_or(1);
+        result.push(Listed {
+            addr: pc,
+            bytes: bytes[pos..pos + len].to_vec(),
+            decoded,
+        });
+        pos += len;
+    }
+    result
+}
+
+/// Where an instruction sends execution, from what iced says about it.
+fn flow(instruction: &Instruction) -> Flow {
+    if matches!(
+        instruction.mnemonic(),
+        Mnemonic::Syscall | Mnemonic::Sysenter
+    ) {
+        return Flow::Syscall;
+    }
+    match instruction.flow_control() {
+        FlowControl::Call if instruction.is_call_near() => {
+            Flow::Call(Some(instruction.near_branch_target()))
+        }
+        FlowControl::Call | FlowControl::IndirectCall => Flow::Call(None),
+        FlowControl::Return => Flow::Return,
+        FlowControl::UnconditionalBranch if instruction.is_jmp_short_or_near() => {
+            Flow::Jump(Some(instruction.near_branch_target()))
+        }
+        FlowControl::UnconditionalBranch | FlowControl::IndirectBranch => Flow::Jump(None),
+        FlowControl::ConditionalBranch => Flow::ConditionalJump(instruction.near_branch_target()),
+        FlowControl::Interrupt => Flow::Interrupt,
+        FlowControl::Next | FlowControl::XbeginXabortXend | FlowControl::Exception => {
+            Flow::Sequential
+        }
+    }
+}
+
+/// Whether `bytes` decode once they're padded out to a full length instruction.
+fn is_valid_padded(bytes: &[u8]) -> bool {
+    let mut padded = bytes.to_vec();
+    padded.resize(MAX_INSTRUCTION_LEN, 0);
+    let mut decoder = Decoder::new(64, &padded, DecoderOptions::NONE);
+    let _ = decoder.decode();
+    decoder.last_error() == DecoderError::None
+}
+
+/// Decodes the instruction at the start of `bytes`, which is at `addr` in the process.
+pub fn decode(addr: u64, bytes: &[u8]) -> Result<Decoded, DecodeError> {
+    let window = &bytes[..bytes.len().min(MAX_INSTRUCTION_LEN)];
+    let mut decoder = Decoder::with_ip(64, window, addr, DecoderOptions::NONE);
+    let instruction = decoder.decode();
+    match decoder.last_error() {
+        DecoderError::None => {}
+        DecoderError::NoMoreBytes if bytes.len() > MAX_INSTRUCTION_LEN => {
+            return Err(DecodeError::TooLong)
+        }
+        DecoderError::InvalidInstruction if instruction.len() >= MAX_INSTRUCTION_LEN => {
+            return Err(DecodeError::TooLong)
+        }
+        // iced runs out of bytes before it notices an opcode is invalid, so check with the
+        // gap filled in whether it's the opcode or only the length that's the problem
+        DecoderError::NoMoreBytes if bytes.is_empty() || is_valid_padded(window) => {
+            return Err(DecodeError::Truncated)
+        }
+        _ => return Err(DecodeError::InvalidOpcode(bytes[0])),
+    }
+    let mut formatter = IntelFormatter::new();
+    let options = formatter.options_mut();
+    options.set_space_after_operand_separator(true);
+    options.set_hex_prefix("0x");
+    options.set_hex_suffix("");
+    options.set_branch_leading_zeros(false);
+    let mut text = String::new();
+    formatter.format(&instruction, &mut text);
+    Ok(Decoded {
+        len: instruction.len(),
+        flow: flow(&instruction),
+        text,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn len(bytes: &[u8]) -> usize {
+        decode(0x1000, bytes).unwrap().len
+    }
+
+    #[test]
+    fn instruction_lengths() {
+        // push rbp; mov rbp, rsp; sub rsp, 0x10
+        assert_eq!(len(&[0x55]), 1);
+        assert_eq!(len(&[0x48, 0x89, 0xe5]), 3);
+        assert_eq!(len(&[0x48, 0x83, 0xec, 0x10]), 4);
+        // mov eax, [rip + 0x2ee6]
+        assert_eq!(len(&[0x8b, 0x05, 0xe6, 0x2e, 0x00, 0x00]), 6);
+        // mov dword [rbp - 4], 0
+        assert_eq!(len(&[0xc7, 0x45, 0xfc, 0, 0, 0, 0]), 7);
+        // mov rax, imm64
+        assert_eq!(len(&[0x48, 0xb8, 1, 2, 3, 4, 5, 6, 7, 8]), 10);
+        // mov ax, imm16
+        assert_eq!(len(&[0x66, 0xb8, 1, 2]), 4);
+        // lea rax, [rax + rbx*8 + 0x10]
+        assert_eq!(len(&[0x48, 0x8d, 0x44, 0xd8, 0x10]), 5);
+        // test byte [rax], 1 vs not byte [rax]
+        assert_eq!(len(&[0xf6, 0x00, 0x01]), 3);
+        assert_eq!(len(&[0xf6, 0x10]), 2);
+        // nop word cs:[rax + rax]
+        assert_eq!(len(&[0x66, 0x2e, 0x0f, 0x1f, 0x84, 0, 0, 0, 0, 0]), 10);
+        // endbr64
+        assert_eq!(len(&[0xf3, 0x0f, 0x1e, 0xfa]), 4);
+        // pshufd xmm0, xmm1, 0x1b
+        assert_eq!(len(&[0x66, 0x0f, 0x70, 0xc1, 0x1b]), 5);
+        // vpxor ymm0, ymm0, ymm0 and vzeroupper
+        assert_eq!(len(&[0xc5, 0xfd, 0xef, 0xc0]), 4);
+        assert_eq!(len(&[0xc5, 0xf8, 0x77]), 3);
+        // vpalignr ymm0, ymm1, ymm2, 4
+        assert_eq!(len(&[0xc4, 0xe3, 0x75, 0x0f, 0xc2, 0x04]), 6);
+        // vmovdqu64 zmm0, [rdi]
+        assert_eq!(len(&[0x62, 0xf1, 0xfe, 0x48, 0x6f, 0x07]), 6);
+    }
+
+    #[test]
+    fn instruction_text() {
+        let text = |addr, bytes: &[u8]| decode(addr, bytes).unwrap().text;
+        assert_eq!(text(0, &[0x55]), "push rbp");
+        assert_eq!(text(0, &[0x48, 0x89, 0xe5]), "mov rbp, rsp");
+        assert_eq!(text(0, &[0x48, 0x83, 0xec, 0x10]), "sub rsp, 0x10");
+        assert_eq!(
+            text(0, &[0xc7, 0x45, 0xfc, 0, 0, 0, 0]),
+            "mov dword ptr [rbp-4], 0"
+        );
+        assert_eq!(text(0x1000, &[0xe8, 0x10, 0, 0, 0]), "call 0x1015");
+        assert_eq!(text(0, &[0x0f, 0x05]), "syscall");
+    }
+
+    #[test]
+    fn control_flow() {
+        // call 0x1000 + 5 + 0x10
+        let call = decode(0x1000, &[0xe8, 0x10, 0, 0, 0]).unwrap();
+        assert_eq!(call.len, 5);
+        assert_eq!(call.flow, Flow::Call(Some(0x1015)));
+        assert!(call.is_call());
+        // call [rip + 0x2fe2] and call rax
+        let call = decode(0x1000, &[0xff, 0x15, 0xe2, 0x2f, 0, 0]).unwrap();
+        assert_eq!((call.len, call.flow), (6, Flow::Call(None)));
+        assert_eq!(decode(0, &[0xff, 0xd0]).unwrap().flow, Flow::Call(None));
+        // inc dword [rax] shares the opcode but isn't a call
+        assert_eq!(decode(0, &[0xff, 0x00]).unwrap().flow, Flow::Sequential);
+
+        assert_eq!(decode(0, &[0xc3]).unwrap().flow, Flow::Return);
+        assert_eq!(
+            decode(0x1000, &[0xeb, 0xfe]).unwrap().flow,
+            Flow::Jump(Some(0x1000))
+        );
+        assert_eq!(
+            decode(0x1000, &[0x0f, 0x85, 0x00, 0x01, 0, 0])
+                .unwrap()
+                .flow,
+            Flow::ConditionalJump(0x1106)
+        );
+        assert_eq!(
+            decode(0x1000, &[0x74, 0x02]).unwrap().flow,
+            Flow::ConditionalJump(0x1004)
+        );
+        assert_eq!(decode(0, &[0x0f, 0x05]).unwrap().flow, Flow::Syscall);
+        assert_eq!(decode(0, &[0xcc]).unwrap().flow, Flow::Interrupt);
+    }
+
+    #[test]
+    fn decode_block() {
+        // push rbp; mov rbp, rsp; an invalid byte; ret; and a call cut short
+        let code = [0x55, 0x48, 0x89, 0xe5, 0x06, 0xc3, 0xe8, 0x10];
+        let listed = decode_all(0x1000, &code);
+        let addrs = listed.iter().map(|l| l.addr).collect::<Vec<_>>();
+        assert_eq!(addrs, [0x1000, 0x1001, 0x1004, 0x1005, 0x1006, 0x1007]);
+        assert_eq!(listed[1].bytes, [0x48, 0x89, 0xe5]);
+        assert_eq!(listed[2].decoded, Err(DecodeError::InvalidOpcode(0x06)));
+        assert_eq!(listed[3].decoded.as_ref().unwrap().flow, Flow::Return);
+        assert_eq!(listed[4].decoded, Err(DecodeError::Truncated));
+        assert_eq!(listed[5].bytes, [0x10]);
+    }
+
+    #[test]
+    fn invalid_instructions() {
+        assert_eq!(decode(0, &[]), Err(DecodeError::Truncated));
+        assert_eq!(decode(0, &[0xe8, 0x10]), Err(DecodeError::Truncated));
+        assert_eq!(decode(0, &[0x48]), Err(DecodeError::Truncated));
+        assert_eq!(decode(0, &[0x06]), Err(DecodeError::InvalidOpcode(0x06)));
+        assert_eq!(decode(0, &[0x66; 16]), Err(DecodeError::TooLong));
+    }
+}
@@ -0,0 +1,297 @@
+//! Dynamic printf, a breakpoint which logs a formatted message each time it's hit and then lets
+//! the program carry on. Written like a C printf call minus the function name:
+//!
+//! ```text
+//! dprintf loop.c:6, "counter = %d\n", counter
+//! ```
+//!
+//! Operands are general purpose registers or global variables, the supported conversions are
+//! `%d`, `%u`, `%x` and `%s` (a pointer to a nul terminated string).
+use crate::elf::ExecutableFile;
+use crate::process::{Process, ProcessError, Registers};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Longest string `%s` will read before giving up on finding the terminator.
+const MAX_STRING_LEN: usize = 1024;
+
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum DprintfError {
+    #[error("format string must start with a \"")]
+    MissingFormat,
+    #[error("format string has no closing \"")]
+    UnterminatedFormat,
+    #[error("unsupported conversion %{0}")]
+    InvalidConversion(char),
+    #[error("unknown escape \\{0}")]
+    InvalidEscape(char),
+    #[error("format has {expected} conversions but {found} operands were given")]
+    OperandCount { expected: usize, found: usize },
+    #[error("invalid operand \"{0}\"")]
+    InvalidOperand(String),
+    #[error("no global variable named {0}")]
+    UnknownVariable(String),
+    #[error("variable {name} is {size} bytes, only up to 8 can be printed")]
+    UnsupportedSize { name: String, size: u64 },
+    #[error("no debug info loaded to find {0}")]
+    NoDebugInfo(String),
+    #[error(transparent)]
+    Process(#[from] ProcessError),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Conversion {
+    Signed,
+    Unsigned,
+    Hex,
+    Str,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Piece {
+    Literal(String),
+    Conversion(Conversion),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Operand {
+    Register(String),
+    Variable(String),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Dprintf {
+    pieces: Vec<Piece>,
+    operands: Vec<Operand>,
+}
+
+impl FromStr for Dprintf {
+    type Err = DprintfError;
+
+    /// Parses the format string and operands, `"x = %d\n", rax`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim_start();
+        let mut chars = s
+            .strip_prefix('"')
+            .ok_or(DprintfError::MissingFormat)?
+            .chars();
+        let mut pieces = vec![];
+        let mut literal = String::new();
+        loop {
+            match chars.next().ok_or(DprintfError::UnterminatedFormat)? {
+                '"' => break,
+                '\\' => match chars.next().ok_or(DprintfError::UnterminatedFormat)? {
+                    'n' => literal.push('\n'),
+                    't' => literal.push('\t'),
+                    '\\' => literal.push('\\'),
+                    '"' => literal.push('"'),
+                    c => return Err(DprintfError::InvalidEscape(c)),
+                },
+                '%' => {
+                    let conversion = match chars.next().ok_or(DprintfError::UnterminatedFormat)? {
+                        '%' => {
+                            literal.push('%');
+                            continue;
+                        }
+                        'd' | 'i' => Conversion::Signed,
+                        'u' => Conversion::Unsigned,
+                        'x' => Conversion::Hex,
+                        's' => Conversion::Str,
+                        c => return Err(DprintfError::InvalidConversion(c)),
+                    };
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(Piece::Conversion(conversion));
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+
+        let operands = chars
+            .as_str()
+            .split(',')
+            .map(str::trim)
+            .skip_while(|op| op.is_empty())
+            .map(parse_operand)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let expected = pieces
+            .iter()
+            .filter(|p| matches!(p, Piece::Conversion(_)))
+            .count();
+        if expected != operands.len() {
+            return Err(DprintfError::OperandCount {
+                expected,
+                found: operands.len(),
+            });
+        }
+        Ok(Self { pieces, operands })
+    }
+}
+
+fn parse_operand(operand: &str) -> Result<Operand, DprintfError> {
+    let is_identifier = operand
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && operand
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !is_identifier {
+        Err(DprintfError::InvalidOperand(operand.to_string()))
+    } else if Registers::GENERAL_PURPOSE.contains(&operand) {
+        Ok(Operand::Register(operand.to_string()))
+    } else {
+        Ok(Operand::Variable(operand.to_string()))
+    }
+}
+
+impl Dprintf {
+    /// Formats the message using the current state of a stopped process.
+    pub fn format(
+        &self,
+        process: &Process,
+        elf: Option<&ExecutableFile>,
+    ) -> Result<String, DprintfError> {
+        let registers = process.get_gp_registers()?;
+        let mut operands = self.operands.iter();
+        let mut message = String::new();
+        for piece in &self.pieces {
+            let conversion = match piece {
+                Piece::Literal(s) => {
+                    message.push_str(s);
+                    continue;
+                }
+                Piece::Conversion(c) => c,
+            };
+            // Parsing checked there's an operand for every conversion
+            let operand = operands.next().unwrap();
+            let (value, size) = match operand {
+                Operand::Register(name) => (registers.get(name).unwrap_or_default(), 8),
+                Operand::Variable(name) => read_variable(process, elf, name)?,
+            };
+            match conversion {
+                Conversion::Signed => {
+                    // Sign extend from the size of the operand
+                    let shift = 64 - 8 * size;
+                    message.push_str(&(((value << shift) as i64) >> shift).to_string());
+                }
+                Conversion::Unsigned => message.push_str(&value.to_string()),
+                Conversion::Hex => message.push_str(&format!("{:x}", value)),
+                Conversion::Str => message.push_str(&read_string(process, value)?),
+            }
+        }
+        Ok(message)
+    }
+}
+
+/// Reads a global variable returning the value and its size in bytes.
+fn read_variable(
+    process: &Process,
+    elf: Option<&ExecutableFile>,
+    name: &str,
+) -> Result<(u64, u64), DprintfError> {
+    let elf = elf.ok_or_else(|| DprintfError::NoDebugInfo(name.to_string()))?;
+    let variable = elf
+        .global_variable(name)
+        .ok()
+        .flatten()
+        .ok_or_else(|| DprintfError::UnknownVariable(name.to_string()))?;
+    if variable.size == 0 || variable.size > 8 {
+        return Err(DprintfError::UnsupportedSize {
+            name: name.to_string(),
+            size: variable.size,
+        });
+    }
+    let data = process.read_memory(
+        variable.address + process.addr_offset,
+        variable.size as usize,
+    )?;
+    let mut bytes = [0u8; 8];
+    bytes[..data.len()].copy_from_slice(&data);
+    Ok((u64::from_le_bytes(bytes), variable.size))
+}
+
+/// Reads a nul terminated string, a word at a time so we don't read past the end of the mapping
+/// the string is in.
+fn read_string(process: &Process, addr: u64) -> Result<String, DprintfError> {
+    let mut bytes = vec![];
+    let mut current = addr;
+    while bytes.len() < MAX_STRING_LEN {
+        let len = 8 - (current % 8) as usize;
+        let data = process.read_memory(current, len)?;
+        if let Some(end) = data.iter().position(|b| *b == 0) {
+            bytes.extend_from_slice(&data[..end]);
+            return Ok(String::from_utf8_lossy(&bytes).into_owned());
+        }
+        bytes.extend_from_slice(&data);
+        current += len as u64;
+    }
+    bytes.truncate(MAX_STRING_LEN);
+    Ok(format!("{}...", String::from_utf8_lossy(&bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_format() {
+        let dprintf = Dprintf::from_str(r#""x = %d, %x%%\n", rax, counter"#).unwrap();
+        assert_eq!(
+            dprintf.pieces,
+            vec![
+                Piece::Literal("x = ".to_string()),
+                Piece::Conversion(Conversion::Signed),
+                Piece::Literal(", ".to_string()),
+                Piece::Conversion(Conversion::Hex),
+                Piece::Literal("%\n".to_string()),
+            ]
+        );
+        assert_eq!(
+            dprintf.operands,
+            vec![
+                Operand::Register("rax".to_string()),
+                Operand::Variable("counter".to_string())
+            ]
+        );
+
+        let no_operands = Dprintf::from_str(r#""hit \"here\"""#).unwrap();
+        assert_eq!(
+            no_operands.pieces,
+            vec![Piece::Literal("hit \"here\"".to_string())]
+        );
+        assert!(no_operands.operands.is_empty());
+    }
+
+    #[test]
+    fn invalid_formats() {
+        assert_eq!(
+            Dprintf::from_str("x = %d, rax"),
+            Err(DprintfError::MissingFormat)
+        );
+        assert_eq!(
+            Dprintf::from_str(r#""x = %d"#),
+            Err(DprintfError::UnterminatedFormat)
+        );
+        assert_eq!(
+            Dprintf::from_str(r#""x = %f", rax"#),
+            Err(DprintfError::InvalidConversion('f'))
+        );
+        assert_eq!(
+            Dprintf::from_str(r#""x = %d %d", rax"#),
+            Err(DprintfError::OperandCount {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(
+            Dprintf::from_str(r#""x = %d", *rax"#),
+            Err(DprintfError::InvalidOperand("*rax".to_string()))
+        );
+    }
+}
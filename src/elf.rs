@@ -1,30 +1,29 @@
 use crate::commands::Location;
+use crate::value::{Member, Type};
 use gimli::{
-    AttributeValue, DebuggingInformationEntry, Dwarf, DwarfFileType, EndianSlice, RunTimeEndian,
-    Unit, UnitHeader, UnitOffset,
+    AttributeValue, DebuggingInformationEntry, Dwarf, DwarfFileType, EndianSlice,
+    LineProgramHeader, RunTimeEndian, Unit, UnitOffset,
 };
-use object::{
-    read::{ObjectSection, ReadCache, ReadRef},
-    Object, ObjectKind, ObjectSegment,
-};
-use rustc_demangle::demangle;
-use std::collections::{HashMap, HashSet};
+use object::elf;
+use object::read::elf::ProgramHeader;
+use object::{read::ObjectSection, Object, ObjectKind, ObjectSegment};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::fs;
 use std::io;
 use std::mem;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, LazyLock, RwLock};
 use thiserror::Error;
-use tracing::{debug, error, trace, warn};
-
-/// So I think if I:
-///
-/// 1. Mutate the vectors
-/// 2. Remove hashmap values
-///
-/// Then this could explode. But if I don't do those things... Maybe I'm fine?
+use tracing::{error, info, warn};
+
+/// Raw bytes of the ELF files we've loaded. The parsed `ExecutableFile` borrows from these with
+/// a faked `'static` lifetime, this is only sound because the vectors are never mutated and every
+/// `ExecutableFile` holds its own `Arc` to the bytes. So an entry can be removed (to reload a
+/// rebuilt file) without pulling the data out from under a file that's still in use.
 static LOADED_FILES: LazyLock<RwLock<HashMap<PathBuf, Arc<Vec<u8>>>>> =
-    LazyLock::new(|| Default::default());
+    LazyLock::new(Default::default);
 
 #[derive(Debug, Error)]
 pub enum ObjectError {
@@ -46,25 +45,161 @@ pub enum ObjectError {
     FailedToParseDieTree,
 }
 
-#[derive(Debug)]
+/// How many levels of nested types are described before giving up, so malformed debug info with
+/// a cycle doesn't recurse forever.
+const MAX_TYPE_DEPTH: usize = 32;
+
+/// A compilation unit and the offset of a function's debugging information entry within it.
+pub type FunctionEntry = (Unit<EndianSlice<'static, RunTimeEndian>>, UnitOffset);
+
+/// Loadable segments are mapped at page boundaries.
+const PAGE_SIZE: u64 = 0x1000;
+
+/// How an executable was linked, which decides where in memory it ends up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    /// Everything linked in and loaded at a fixed address
+    Static,
+    /// Everything linked in but relocates itself wherever it's loaded
+    StaticPie,
+    /// Uses shared libraries and is loaded at a fixed address
+    Dynamic,
+    /// Uses shared libraries and can be loaded anywhere, the default for most toolchains
+    DynamicPie,
+}
+
+impl fmt::Display for LinkKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Static => "static",
+            Self::StaticPie => "static PIE",
+            Self::Dynamic => "dynamically linked",
+            Self::DynamicPie => "dynamically linked PIE",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A global variable, the unit it's in and the offset of its type in that unit.
+type FoundVariable = (
+    Unit<EndianSlice<'static, RunTimeEndian>>,
+    Variable,
+    Option<UnitOffset>,
+);
+
+/// How the bytes of a value should be interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Signed,
+    /// Unsigned integers, booleans and pointers
+    Unsigned,
+    Float,
+    /// Structs, arrays and anything else that isn't a single number
+    Other,
+}
+
+/// A variable with a fixed address, like a global or static.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Variable {
+    /// Address not accounting for any load offset
+    pub address: u64,
+    /// Size of the variable's type in bytes
+    pub size: u64,
+    pub kind: ValueKind,
+}
+
+/// The compiler and language of some of the compile units in an executable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompilerInfo {
+    /// `DW_AT_producer`, usually the compiler's name, version and sometimes flags
+    pub producer: Option<String>,
+    /// `DW_AT_language` without the `DW_LANG_` prefix, e.g. `C11` or `Rust`
+    pub language: Option<String>,
+    /// Number of compile units with this producer and language
+    pub units: usize,
+}
+
+/// How two executables' functions differ, found by name. Sizes are in bytes, a function split
+/// into several ranges counts all of them and functions with the same name in different
+/// compile units (e.g. statics) are added together.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FunctionDiff {
+    /// Functions only in the first file with their sizes
+    pub removed: Vec<(String, u64)>,
+    /// Functions only in the second file with their sizes
+    pub added: Vec<(String, u64)>,
+    /// Functions in both with their sizes in the first then the second file
+    pub resized: Vec<(String, u64, u64)>,
+    /// Functions in both with the same size
+    pub unchanged: usize,
+}
+
+impl FunctionDiff {
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty() && self.resized.is_empty()
+    }
+}
+
+/// A line in a source file, as found in the DWARF line table.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceLocation {
+    pub file: PathBuf,
+    pub line: u64,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file.display(), self.line)
+    }
+}
+
 pub struct ExecutableFile {
     elf_file: object::File<'static, &'static [u8]>,
     dwarf: Dwarf<EndianSlice<'static, RunTimeEndian>>,
+    /// The bytes `elf_file` and `dwarf` borrow from. This has to be the last field so it's
+    /// dropped after them.
+    data: Arc<Vec<u8>>,
 }
 
-fn cache_file(path: &Path) -> io::Result<()> {
+impl fmt::Debug for ExecutableFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ExecutableFile")
+            .field("kind", &self.elf_file.kind())
+            .field("size", &self.data.len())
+            .finish()
+    }
+}
+
+fn cache_file(path: &Path) -> io::Result<Arc<Vec<u8>>> {
     let rw_lock = &*LOADED_FILES;
 
     let mut cache = rw_lock.write().unwrap();
-    if !cache.contains_key(path) {
-        let data = fs::read(path)?;
-        cache.insert(path.to_path_buf(), Arc::new(data));
+    if let Some(data) = cache.get(path) {
+        return Ok(Arc::clone(data));
+    }
+    let data = Arc::new(fs::read(path)?);
+    cache.insert(path.to_path_buf(), Arc::clone(&data));
+    Ok(data)
+}
+
+#[cfg(feature = "tokio")]
+async fn cache_file_async(path: &Path) -> io::Result<Arc<Vec<u8>>> {
+    if let Some(data) = LOADED_FILES.read().unwrap().get(path) {
+        return Ok(Arc::clone(data));
     }
-    Ok(())
+    let data = tokio::fs::read(path).await?;
+    let data = LOADED_FILES
+        .write()
+        .unwrap()
+        .entry(path.to_path_buf())
+        .or_insert_with(|| Arc::new(data))
+        .clone();
+    Ok(data)
 }
 
-fn get_bytes(path: &Path) -> Option<Arc<Vec<u8>>> {
-    (&*LOADED_FILES).read().unwrap().get(path).map(Arc::clone)
+/// Drops the cached bytes for a file so the next load reads it from disk again.
+fn invalidate_cached_file(path: &Path) {
+    LOADED_FILES.write().unwrap().remove(path);
 }
 
 fn try_get_file_section_reader(
@@ -99,18 +234,47 @@ fn get_file_section_reader(
 }
 
 impl ExecutableFile {
-    pub fn load(path: &Path) -> Result<Self, ObjectError> {
-        let file = cache_file(path).map_err(|e| {
+    /// Loads and parses the ELF file. This does blocking file IO and parsing so in an async
+    /// context use `load_async` (with the tokio feature enabled) instead.
+    pub fn load_blocking(path: &Path) -> Result<Self, ObjectError> {
+        let data = cache_file(path).map_err(|e| {
             error!("Couldn't open {}: {}", path.display(), e);
             ObjectError::CantOpenElf
         })?;
+        Self::parse(data)
+    }
+
+    /// Loads the ELF file again from disk even if it's been loaded before, for when the program
+    /// has been rebuilt.
+    pub fn reload_blocking(path: &Path) -> Result<Self, ObjectError> {
+        invalidate_cached_file(path);
+        Self::load_blocking(path)
+    }
 
-        let data = get_bytes(path).unwrap();
-        let elf_file = object::File::parse(unsafe { mem::transmute(data.as_ref().as_slice()) })
+    /// Loads the ELF file without blocking the runtime, the file is read asynchronously and the
+    /// parsing is done on the blocking thread pool.
+    #[cfg(feature = "tokio")]
+    pub async fn load_async(path: &Path) -> Result<Self, ObjectError> {
+        let data = cache_file_async(path).await.map_err(|e| {
+            error!("Couldn't open {}: {}", path.display(), e);
+            ObjectError::CantOpenElf
+        })?;
+        tokio::task::spawn_blocking(move || Self::parse(data))
+            .await
             .map_err(|e| {
-                error!("Couldn't parse elf file: {}", e);
+                error!("ELF parsing task failed: {}", e);
                 ObjectError::CouldntParse
-            })?;
+            })?
+    }
+
+    fn parse(data: Arc<Vec<u8>>) -> Result<Self, ObjectError> {
+        let elf_file = object::File::parse(unsafe {
+            mem::transmute::<&[u8], &'static [u8]>(data.as_ref().as_slice())
+        })
+        .map_err(|e| {
+            error!("Couldn't parse elf file: {}", e);
+            ObjectError::CouldntParse
+        })?;
 
         let endian = if elf_file.is_little_endian() {
             RunTimeEndian::Little
@@ -123,31 +287,90 @@ impl ExecutableFile {
         let mut dwarf = gimli::Dwarf::load(loader)?;
         dwarf.file_type = DwarfFileType::Main;
 
-        Ok(ExecutableFile { elf_file, dwarf })
+        Ok(ExecutableFile {
+            elf_file,
+            dwarf,
+            data,
+        })
+    }
+
+    /// Whether the executable is loaded by a dynamic linker, it has a `PT_INTERP` program header
+    /// naming the interpreter to use.
+    pub fn is_dynamic(&self) -> bool {
+        let interp = match &self.elf_file {
+            object::File::Elf64(elf) => elf
+                .elf_program_headers()
+                .iter()
+                .any(|ph| ph.p_type(elf.endian()) == elf::PT_INTERP),
+            object::File::Elf32(elf) => elf
+                .elf_program_headers()
+                .iter()
+                .any(|ph| ph.p_type(elf.endian()) == elf::PT_INTERP),
+            _ => false,
+        };
+        interp || self.elf_file.section_by_name(".interp").is_some()
+    }
+
+    /// Position independent executables are `ET_DYN` and can be loaded anywhere, everything else
+    /// is `ET_EXEC` and loaded at the addresses in the file.
+    pub fn is_position_independent(&self) -> bool {
+        self.elf_file.kind() == ObjectKind::Dynamic
     }
 
+    pub fn link_kind(&self) -> LinkKind {
+        match (self.is_dynamic(), self.is_position_independent()) {
+            (false, false) => LinkKind::Static,
+            (false, true) => LinkKind::StaticPie,
+            (true, false) => LinkKind::Dynamic,
+            (true, true) => LinkKind::DynamicPie,
+        }
+    }
+
+    /// Address execution starts at (not accounting for any load offset), usually `_start`.
+    pub fn entry_point(&self) -> u64 {
+        self.elf_file.entry()
+    }
+
+    /// Works out how far the executable has been moved from the addresses in the file, given the
+    /// address of its lowest mapping in the process.
     pub fn runtime_address_offset(&self, mapped_address: u64) -> u64 {
-        match self.elf_file.kind() {
-            ObjectKind::Dynamic => {
+        let kind = self.link_kind();
+        info!("Detected {} executable", kind);
+        match kind {
+            // Loaded at the addresses it was linked at
+            LinkKind::Static | LinkKind::Dynamic => 0,
+            // The kernel (or the dynamic linker when it's run directly) picks a base address, the
+            // lowest mapping is the first loadable segment rounded down to a page.
+            LinkKind::StaticPie | LinkKind::DynamicPie => {
                 let min_segment_address = self
                     .elf_file
                     .segments()
                     .filter(|segment| segment.size() > 0)
-                    .map(|segment| segment.address())
+                    .map(|segment| segment.address() & !(PAGE_SIZE - 1))
                     .min()
                     .unwrap_or_default();
 
-                mapped_address - min_segment_address
+                mapped_address.saturating_sub(min_segment_address)
             }
-            _ => 0,
         }
     }
 
+    /// Resolves a location to an address (not accounting for any load offset). A line resolves
+    /// to the lowest address generated for it and a function to the start of the first function
+    /// with that name.
     pub fn get_address(&self, location: Location) -> Result<u64, ObjectError> {
         match location {
             Location::Address(addr) => Ok(addr),
-            Location::Line { file, line } => todo!(),
-            Location::Function(fn_name) => todo!(),
+            Location::Line { file, line } => self
+                .addresses_for_line(&file, line as u64)?
+                .first()
+                .copied()
+                .ok_or(ObjectError::BadLocation),
+            Location::Function(name) => self
+                .find_functions(&name)?
+                .iter()
+                .find_map(|function| self.function_start(function))
+                .ok_or(ObjectError::BadLocation),
         }
     }
 
@@ -183,10 +406,208 @@ impl ExecutableFile {
         None
     }
 
-    fn function_containing_address<'a>(
+    /// Finds the source line the instruction at the given address (not accounting for any load
+    /// offset) was generated from.
+    pub fn line_for_address(&self, address: u64) -> Result<Option<SourceLocation>, ObjectError> {
+        let Some(unit) = self.compile_unit_containing_address(address) else {
+            return Ok(None);
+        };
+        let Some(program) = unit.line_program.clone() else {
+            return Ok(None);
+        };
+        let mut rows = program.rows();
+        // The row applies to every address up until the next row in the sequence
+        let mut previous: Option<(u64, u64, u64)> = None;
+        while let Some((header, row)) = rows
+            .next_row()
+            .map_err(|_| ObjectError::DwarfParsingFailed)?
+        {
+            if let Some((start, file, line)) = previous.take() {
+                if (start..row.address()).contains(&address) {
+                    let file = self.file_path(&unit, header, file);
+                    return Ok(file.map(|file| SourceLocation { file, line }));
+                }
+            }
+            if !row.end_sequence() {
+                if let Some(line) = row.line() {
+                    previous = Some((row.address(), row.file_index(), line.get()));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Finds the source file, line and the name of the function the instruction at the given
+    /// address (not accounting for any load offset) belongs to. The function name can be missing
+    /// for code without debug info for its function, like some compiler generated code.
+    pub fn location_for_address(&self, address: u64) -> Option<(PathBuf, usize, Option<String>)> {
+        let SourceLocation { file, line } = self.line_for_address(address).ok()??;
+        let function = self
+            .function_containing_address(address)
+            .ok()
+            .flatten()
+            .and_then(|function| self.function_name(&function));
+        Some((file, line as usize, function))
+    }
+
+    /// Addresses in `range` (not accounting for any load offset) where a row of the line table
+    /// starts. Every row starts on an instruction so these are known instruction boundaries.
+    pub fn line_rows_in(&self, range: Range<u64>) -> Result<Vec<u64>, ObjectError> {
+        let Some(unit) = self.compile_unit_containing_address(range.start) else {
+            return Ok(vec![]);
+        };
+        let Some(program) = unit.line_program.clone() else {
+            return Ok(vec![]);
+        };
+        let mut result = vec![];
+        let mut rows = program.rows();
+        while let Some((_, row)) = rows
+            .next_row()
+            .map_err(|_| ObjectError::DwarfParsingFailed)?
+        {
+            if !row.end_sequence() && range.contains(&row.address()) {
+                result.push(row.address());
+            }
+        }
+        result.sort_unstable();
+        result.dedup();
+        Ok(result)
+    }
+
+    /// Finds the addresses (not accounting for any load offset) of the statements generated for
+    /// a source line, lowest first. The file only has to match the end of the path in the debug
+    /// info so `main.c` will match `/home/me/project/src/main.c`.
+    pub fn addresses_for_line(&self, file: &Path, line: u64) -> Result<Vec<u64>, ObjectError> {
+        let mut result = vec![];
+        let mut units = self.dwarf.units();
+        while let Ok(Some(header)) = units.next() {
+            let Ok(unit) = self.dwarf.unit(header) else {
+                continue;
+            };
+            let Some(program) = unit.line_program.clone() else {
+                continue;
+            };
+            let mut file_matches = HashMap::new();
+            let mut rows = program.rows();
+            while let Some((header, row)) = rows
+                .next_row()
+                .map_err(|_| ObjectError::DwarfParsingFailed)?
+            {
+                if row.end_sequence() || !row.is_stmt() || row.line().map(|l| l.get()) != Some(line)
+                {
+                    continue;
+                }
+                let matches = *file_matches.entry(row.file_index()).or_insert_with(|| {
+                    self.file_path(&unit, header, row.file_index())
+                        .is_some_and(|path| path.ends_with(file))
+                });
+                if matches {
+                    result.push(row.address());
+                }
+            }
+        }
+        result.sort_unstable();
+        result.dedup();
+        Ok(result)
+    }
+
+    /// Finds the address ranges (not accounting for any load offset) covered by the rows of the
+    /// line table for a source line, lowest first. A line can have several rows and they aren't
+    /// always next to each other, e.g. the condition of a loop, rows that are next to each other
+    /// are merged into one range. Files match like `addresses_for_line`.
+    pub fn address_ranges_for_line(
+        &self,
+        file: &Path,
+        line: u64,
+    ) -> Result<Vec<Range<u64>>, ObjectError> {
+        let mut result: Vec<Range<u64>> = vec![];
+        let mut units = self.dwarf.units();
+        while let Ok(Some(header)) = units.next() {
+            let Ok(unit) = self.dwarf.unit(header) else {
+                continue;
+            };
+            let Some(program) = unit.line_program.clone() else {
+                continue;
+            };
+            let mut file_matches = HashMap::new();
+            let mut rows = program.rows();
+            // Start of the range for the previous row if it was for our line, a row covers the
+            // addresses up to the next row in its sequence
+            let mut start = None;
+            while let Some((header, row)) = rows
+                .next_row()
+                .map_err(|_| ObjectError::DwarfParsingFailed)?
+            {
+                if let Some(start) = start.take() {
+                    if start < row.address() {
+                        result.push(start..row.address());
+                    }
+                }
+                if row.end_sequence() || row.line().map(|l| l.get()) != Some(line) {
+                    continue;
+                }
+                let matches = *file_matches.entry(row.file_index()).or_insert_with(|| {
+                    self.file_path(&unit, header, row.file_index())
+                        .is_some_and(|path| path.ends_with(file))
+                });
+                if matches {
+                    start = Some(row.address());
+                }
+            }
+        }
+        result.sort_unstable_by_key(|range| range.start);
+        let mut merged: Vec<Range<u64>> = Vec::with_capacity(result.len());
+        for range in result {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        Ok(merged)
+    }
+
+    /// The range of addresses (not accounting for any load offset) starting at the first
+    /// statement of a line, going up to the first address that belongs to another line. See
+    /// `address_ranges_for_line` for all the code generated for a line.
+    pub fn address_range_for_line(&self, file: &Path, line: u64) -> Option<(u64, u64)> {
+        let start = *self.addresses_for_line(file, line).ok()?.first()?;
+        self.address_ranges_for_line(file, line)
+            .ok()?
+            .into_iter()
+            .find(|range| range.contains(&start))
+            .map(|range| (start, range.end))
+    }
+
+    /// Resolves a file in a line program header to a path, relative paths are joined onto the
+    /// include directory and compilation directory they're relative to.
+    fn file_path(
+        &self,
+        unit: &Unit<EndianSlice<'static, RunTimeEndian>>,
+        header: &LineProgramHeader<EndianSlice<'static, RunTimeEndian>>,
+        index: u64,
+    ) -> Option<PathBuf> {
+        let file = header.file(index)?;
+        let attr_path = |attr| {
+            self.dwarf
+                .attr_string(unit, attr)
+                .ok()
+                .and_then(|r| r.to_string().ok().map(PathBuf::from))
+        };
+        let mut path = unit
+            .comp_dir
+            .and_then(|dir| dir.to_string().ok().map(PathBuf::from))
+            .unwrap_or_default();
+        if let Some(dir) = file.directory(header).and_then(attr_path) {
+            path.push(dir);
+        }
+        path.push(attr_path(file.path_name())?);
+        Some(path)
+    }
+
+    pub fn function_containing_address(
         &self,
         address: u64,
-    ) -> Result<Option<(Unit<EndianSlice<'static, RunTimeEndian>>, UnitOffset)>, ObjectError> {
+    ) -> Result<Option<FunctionEntry>, ObjectError> {
         let cu = match self.compile_unit_containing_address(address) {
             Some(cu) => cu,
             None => return Ok(None),
@@ -194,25 +615,18 @@ impl ExecutableFile {
 
         let mut cursor = cu.entries();
 
-        while let Some((delta_depth, current)) = cursor
+        while let Some((_, current)) = cursor
             .next_dfs()
             .map_err(|_| ObjectError::FailedToParseDieTree)?
         {
             if current.tag() == gimli::DW_TAG_subprogram {
-                // I am a function!
-                let low_pc = current.attr_value(gimli::DW_AT_low_pc);
-                let high_pc = current.attr_value(gimli::DW_AT_high_pc);
-                let low_pc = match low_pc {
-                    Ok(Some(AttributeValue::Addr(x))) => x,
-                    _ => 0u64,
-                };
-                // High is an offset from the base pc, therefore is u64 data.
-                let high_pc = match high_pc {
-                    Ok(Some(AttributeValue::Udata(x))) => low_pc + x,
-                    Ok(Some(AttributeValue::Addr(x))) => x,
-                    _ => 0u64,
-                };
-                if (low_pc..high_pc).contains(&address) {
+                // I am a function! Possibly split into several ranges if the compiler moved cold
+                // code out of line.
+                if self
+                    .die_ranges(&cu, current)
+                    .iter()
+                    .any(|r| r.contains(&address))
+                {
                     let offset = current.offset();
                     return Ok(Some((cu, offset)));
                 }
@@ -221,34 +635,564 @@ impl ExecutableFile {
         Ok(None)
     }
 
-    pub fn find_functions(
+    /// The address ranges a function's code occupies (not accounting for any load offset). This
+    /// is a single range unless the compiler split the function up, in which case the range
+    /// containing the entry point comes first.
+    pub fn function_ranges(&self, function: &FunctionEntry) -> Vec<Range<u64>> {
+        let (unit, offset) = function;
+        match unit.entry(*offset) {
+            Ok(die) => self.die_ranges(unit, &die),
+            Err(_) => vec![],
+        }
+    }
+
+    /// Reads either the `DW_AT_low_pc`/`DW_AT_high_pc` pair or the `DW_AT_ranges` list of a DIE.
+    fn die_ranges(
+        &self,
+        unit: &Unit<EndianSlice<'static, RunTimeEndian>>,
+        die: &DebuggingInformationEntry<EndianSlice<'static, RunTimeEndian>>,
+    ) -> Vec<Range<u64>> {
+        let mut result = vec![];
+        match self.dwarf.die_ranges(unit, die) {
+            Ok(mut ranges) => {
+                while let Ok(Some(r)) = ranges.next() {
+                    if r.begin < r.end {
+                        result.push(r.begin..r.end);
+                    }
+                }
+            }
+            Err(e) => error!("Couldn't get ranges for DIE: {}", e),
+        }
+        result
+    }
+
+    /// Gets the address of the first instruction of a function (not accounting for any load
+    /// offset).
+    pub fn function_start(&self, function: &FunctionEntry) -> Option<u64> {
+        let (unit, offset) = function;
+        let die = unit.entry(*offset).ok()?;
+        match die.attr_value(gimli::DW_AT_entry_pc) {
+            Ok(Some(AttributeValue::Addr(x))) => return Some(x),
+            Ok(Some(AttributeValue::Udata(x))) => {
+                // DWARF 5 allows the entry point as an offset from the start of the function
+                return self.die_ranges(unit, &die).first().map(|r| r.start + x);
+            }
+            _ => {}
+        }
+        match die.attr_value(gimli::DW_AT_low_pc) {
+            Ok(Some(AttributeValue::Addr(x))) => Some(x),
+            // Split functions have ranges instead, GCC puts the one with the entry point first
+            _ => self.die_ranges(unit, &die).first().map(|r| r.start),
+        }
+    }
+
+    /// Gets the name of a function found via `find_functions` or `function_containing_address`.
+    pub fn function_name(&self, function: &FunctionEntry) -> Option<String> {
+        let (unit, offset) = function;
+        let die = unit.entry(*offset).ok()?;
+        self.die_name(unit, &die)
+    }
+
+    /// Formats a function's signature from its debug info, its name, parameters with their
+    /// names and types and return type, like `add(a: int, b: int) -> int`. Pointers are written
+    /// `*const T` or `*mut T` and a function returning void has no `->`.
+    pub fn function_signature(&self, function: &FunctionEntry) -> String {
+        let (unit, offset) = function;
+        let Ok(die) = unit.entry(*offset) else {
+            return "??".to_string();
+        };
+        let name = self
+            .die_name(unit, &die)
+            .unwrap_or_else(|| "??".to_string());
+        format!("{}{}", name, self.signature_tail(unit, &die, *offset, 0))
+    }
+
+    /// The `(params) -> ret` part of a signature, shared by functions and function pointer
+    /// types.
+    fn signature_tail(
+        &self,
+        unit: &Unit<EndianSlice<'static, RunTimeEndian>>,
+        die: &DebuggingInformationEntry<EndianSlice<'static, RunTimeEndian>>,
+        offset: UnitOffset,
+        depth: usize,
+    ) -> String {
+        let params = self
+            .children(unit, offset)
+            .iter()
+            .filter_map(|child| match child.tag() {
+                gimli::DW_TAG_formal_parameter => {
+                    let ty = self.attr_type_name(unit, child, depth + 1);
+                    Some(match self.die_name(unit, child) {
+                        Some(name) => format!("{}: {}", name, ty),
+                        None => ty,
+                    })
+                }
+                gimli::DW_TAG_unspecified_parameters => Some("...".to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        match die.attr_value(gimli::DW_AT_type) {
+            Ok(Some(AttributeValue::UnitRef(ret))) => format!(
+                "({}) -> {}",
+                params.join(", "),
+                self.type_name(unit, ret, depth + 1)
+            ),
+            _ => format!("({})", params.join(", ")),
+        }
+    }
+
+    /// The name of the type a DIE's `DW_AT_type` refers to, `void` if it doesn't have one.
+    fn attr_type_name(
+        &self,
+        unit: &Unit<EndianSlice<'static, RunTimeEndian>>,
+        die: &DebuggingInformationEntry<EndianSlice<'static, RunTimeEndian>>,
+        depth: usize,
+    ) -> String {
+        match die.attr_value(gimli::DW_AT_type) {
+            Ok(Some(AttributeValue::UnitRef(ty))) => self.type_name(unit, ty, depth),
+            _ => "void".to_string(),
+        }
+    }
+
+    /// A short name for the type at `offset` to show in signatures. Named types use their name,
+    /// so a typedef shows as itself rather than what it stands for.
+    fn type_name(
+        &self,
+        unit: &Unit<EndianSlice<'static, RunTimeEndian>>,
+        offset: UnitOffset,
+        depth: usize,
+    ) -> String {
+        if depth > MAX_TYPE_DEPTH {
+            return "...".to_string();
+        }
+        let Ok(die) = unit.entry(offset) else {
+            return "??".to_string();
+        };
+        let inner = || self.attr_type_name(unit, &die, depth + 1);
+        match die.tag() {
+            gimli::DW_TAG_const_type
+            | gimli::DW_TAG_volatile_type
+            | gimli::DW_TAG_restrict_type
+            | gimli::DW_TAG_atomic_type => inner(),
+            _ if die.attr_value(gimli::DW_AT_name).is_ok_and(|n| n.is_some()) => {
+                self.die_name(unit, &die).unwrap_or_default()
+            }
+            gimli::DW_TAG_pointer_type => {
+                let is_const = match die.attr_value(gimli::DW_AT_type) {
+                    Ok(Some(AttributeValue::UnitRef(pointee))) => unit
+                        .entry(pointee)
+                        .is_ok_and(|p| p.tag() == gimli::DW_TAG_const_type),
+                    _ => false,
+                };
+                format!("*{} {}", if is_const { "const" } else { "mut" }, inner())
+            }
+            gimli::DW_TAG_reference_type => format!("&{}", inner()),
+            gimli::DW_TAG_rvalue_reference_type => format!("&&{}", inner()),
+            gimli::DW_TAG_array_type => {
+                let count = self
+                    .children(unit, offset)
+                    .iter()
+                    .find(|child| child.tag() == gimli::DW_TAG_subrange_type)
+                    .and_then(|child| {
+                        match (
+                            child.attr_value(gimli::DW_AT_count),
+                            child.attr_value(gimli::DW_AT_upper_bound),
+                        ) {
+                            (Ok(Some(count)), _) => count.udata_value(),
+                            (_, Ok(Some(upper))) => upper.udata_value().map(|u| u + 1),
+                            _ => None,
+                        }
+                    });
+                match count {
+                    Some(count) => format!("[{}; {}]", inner(), count),
+                    None => format!("[{}]", inner()),
+                }
+            }
+            gimli::DW_TAG_subroutine_type => {
+                format!("fn{}", self.signature_tail(unit, &die, offset, depth))
+            }
+            tag => tag
+                .static_string()
+                .map(|s| s.trim_start_matches("DW_TAG_").to_string())
+                .unwrap_or_else(|| "??".to_string()),
+        }
+    }
+
+    fn die_name(
+        &self,
+        unit: &Unit<EndianSlice<'static, RunTimeEndian>>,
+        die: &DebuggingInformationEntry<EndianSlice<'static, RunTimeEndian>>,
+    ) -> Option<String> {
+        let name = die.attr_value(gimli::DW_AT_name).ok()??;
+        self.dwarf
+            .attr_string(unit, name)
+            .ok()
+            .and_then(|r| r.to_string().ok().map(|s| s.to_string()))
+    }
+
+    /// Finds a variable stored at a fixed address (a global or static) by name.
+    pub fn global_variable(&self, name: &str) -> Result<Option<Variable>, ObjectError> {
+        Ok(self
+            .find_global_variable(name)?
+            .map(|(_, variable, _)| variable))
+    }
+
+    /// Finds a global variable along with a description of its type for printing it.
+    pub fn global_variable_with_type(
         &self,
         name: &str,
-    ) -> Result<Vec<(Unit<EndianSlice<'static, RunTimeEndian>>, UnitOffset)>, ObjectError> {
+    ) -> Result<Option<(Variable, Type)>, ObjectError> {
+        let Some((unit, variable, ty)) = self.find_global_variable(name)? else {
+            return Ok(None);
+        };
+        let ty = match ty {
+            Some(offset) => self.describe_type(&unit, offset, 0),
+            None => Type::Unknown {
+                name: "unknown type".to_string(),
+                size: 0,
+            },
+        };
+        Ok(Some((variable, ty)))
+    }
+
+    /// Finds a variable with a fixed address returning the unit it's in and the offset of its
+    /// type.
+    fn find_global_variable(&self, name: &str) -> Result<Option<FoundVariable>, ObjectError> {
+        let mut units = self.dwarf.units();
+        while let Ok(Some(header)) = units.next() {
+            let Ok(unit) = self.dwarf.unit(header) else {
+                continue;
+            };
+            let mut cursor = unit.entries();
+            while let Some((_, current)) = cursor
+                .next_dfs()
+                .map_err(|_| ObjectError::FailedToParseDieTree)?
+            {
+                if current.tag() != gimli::DW_TAG_variable
+                    || self.die_name(&unit, current).as_deref() != Some(name)
+                {
+                    continue;
+                }
+                // Declarations and locals don't have a fixed address so keep looking
+                let Ok(Some(AttributeValue::Exprloc(expr))) =
+                    current.attr_value(gimli::DW_AT_location)
+                else {
+                    continue;
+                };
+                let mut ops = expr.operations(unit.encoding());
+                let Ok(Some(gimli::Operation::Address { address })) = ops.next() else {
+                    continue;
+                };
+                let ty = match current.attr_value(gimli::DW_AT_type) {
+                    Ok(Some(AttributeValue::UnitRef(offset))) => Some(offset),
+                    _ => None,
+                };
+                let (size, kind) = ty
+                    .and_then(|offset| self.type_info(&unit, offset))
+                    .unwrap_or((0, ValueKind::Other));
+                let variable = Variable {
+                    address,
+                    size,
+                    kind,
+                };
+                return Ok(Some((unit, variable, ty)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Builds a description of the type at `offset` for printing values of it. `depth` is how
+    /// deeply nested this type is, to stop on malformed or very deep types.
+    fn describe_type(
+        &self,
+        unit: &Unit<EndianSlice<'static, RunTimeEndian>>,
+        offset: UnitOffset,
+        depth: usize,
+    ) -> Type {
+        let unknown = |name: &str| Type::Unknown {
+            name: name.to_string(),
+            size: 0,
+        };
+        if depth > MAX_TYPE_DEPTH {
+            return unknown("too deeply nested");
+        }
+        let Ok(die) = unit.entry(offset) else {
+            return unknown("unknown type");
+        };
+        let name = self.die_name(unit, &die).unwrap_or_default();
+        let size = match die.attr_value(gimli::DW_AT_byte_size) {
+            Ok(Some(size)) => size.udata_value().unwrap_or_default(),
+            _ => 0,
+        };
+        let inner = || match die.attr_value(gimli::DW_AT_type) {
+            Ok(Some(AttributeValue::UnitRef(next))) => self.describe_type(unit, next, depth + 1),
+            _ => unknown("void"),
+        };
+        match die.tag() {
+            gimli::DW_TAG_base_type => {
+                let (size, kind) = self
+                    .type_info(unit, offset)
+                    .unwrap_or((size, ValueKind::Other));
+                Type::Base { name, size, kind }
+            }
+            gimli::DW_TAG_pointer_type
+            | gimli::DW_TAG_reference_type
+            | gimli::DW_TAG_rvalue_reference_type => Type::Pointer {
+                size: if size == 0 { 8 } else { size },
+            },
+            gimli::DW_TAG_typedef
+            | gimli::DW_TAG_const_type
+            | gimli::DW_TAG_volatile_type
+            | gimli::DW_TAG_restrict_type
+            | gimli::DW_TAG_atomic_type => inner(),
+            gimli::DW_TAG_enumeration_type => {
+                let variants = self
+                    .children(unit, offset)
+                    .iter()
+                    .filter(|child| child.tag() == gimli::DW_TAG_enumerator)
+                    .filter_map(|child| {
+                        let value = match child.attr_value(gimli::DW_AT_const_value) {
+                            Ok(Some(AttributeValue::Sdata(v))) => v,
+                            Ok(Some(v)) => v.udata_value()? as i64,
+                            _ => return None,
+                        };
+                        Some((value, self.die_name(unit, child)?))
+                    })
+                    .collect();
+                Type::Enum { size, variants }
+            }
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_class_type | gimli::DW_TAG_union_type => {
+                let members = self
+                    .children(unit, offset)
+                    .iter()
+                    .filter(|child| child.tag() == gimli::DW_TAG_member)
+                    .map(|child| {
+                        let offset = match child.attr_value(gimli::DW_AT_data_member_location) {
+                            Ok(Some(v)) => v.udata_value().unwrap_or_default(),
+                            _ => 0,
+                        };
+                        let ty = match (
+                            child.attr_value(gimli::DW_AT_bit_size),
+                            child.attr_value(gimli::DW_AT_type),
+                        ) {
+                            (Ok(Some(_)), _) => unknown("bitfield"),
+                            (_, Ok(Some(AttributeValue::UnitRef(ty)))) => {
+                                self.describe_type(unit, ty, depth + 1)
+                            }
+                            _ => unknown("unknown type"),
+                        };
+                        Member {
+                            name: self.die_name(unit, child).unwrap_or_default(),
+                            offset,
+                            ty,
+                        }
+                    })
+                    .collect();
+                Type::Struct { size, members }
+            }
+            gimli::DW_TAG_array_type => {
+                let counts = self
+                    .children(unit, offset)
+                    .iter()
+                    .filter(|child| child.tag() == gimli::DW_TAG_subrange_type)
+                    .map(|child| {
+                        match (
+                            child.attr_value(gimli::DW_AT_count),
+                            child.attr_value(gimli::DW_AT_upper_bound),
+                        ) {
+                            (Ok(Some(count)), _) => count.udata_value().unwrap_or_default(),
+                            (_, Ok(Some(upper))) => {
+                                upper.udata_value().map(|u| u + 1).unwrap_or_default()
+                            }
+                            // Flexible array members have no size
+                            _ => 0,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                // int x[2][3] is an array of 2 arrays of 3 ints so build from the inside out
+                counts
+                    .iter()
+                    .rev()
+                    .fold(inner(), |element, count| Type::Array {
+                        element: Box::new(element),
+                        count: *count,
+                    })
+            }
+            _ => Type::Unknown {
+                name: if name.is_empty() {
+                    die.tag()
+                        .static_string()
+                        .unwrap_or("unknown type")
+                        .to_string()
+                } else {
+                    name
+                },
+                size,
+            },
+        }
+    }
+
+    /// The direct children of a DIE.
+    fn children<'u>(
+        &self,
+        unit: &'u Unit<EndianSlice<'static, RunTimeEndian>>,
+        offset: UnitOffset,
+    ) -> Vec<DebuggingInformationEntry<'u, 'u, EndianSlice<'static, RunTimeEndian>>> {
+        let mut result = vec![];
+        let Ok(mut tree) = unit.entries_tree(Some(offset)) else {
+            return result;
+        };
+        let Ok(root) = tree.root() else {
+            return result;
+        };
+        let mut children = root.children();
+        while let Ok(Some(child)) = children.next() {
+            result.push(child.entry().clone());
+        }
+        result
+    }
+
+    /// Size in bytes and kind of a type, looking through typedefs and qualifiers to the
+    /// underlying type.
+    fn type_info(
+        &self,
+        unit: &Unit<EndianSlice<'static, RunTimeEndian>>,
+        mut offset: UnitOffset,
+    ) -> Option<(u64, ValueKind)> {
+        // Limit how far we follow the chain in case of a malformed cycle
+        for _ in 0..16 {
+            let die = unit.entry(offset).ok()?;
+            if let Ok(Some(size)) = die.attr_value(gimli::DW_AT_byte_size) {
+                let kind = match die.tag() {
+                    gimli::DW_TAG_base_type => match die.attr_value(gimli::DW_AT_encoding) {
+                        Ok(Some(AttributeValue::Encoding(encoding))) => match encoding {
+                            gimli::DW_ATE_float => ValueKind::Float,
+                            gimli::DW_ATE_signed | gimli::DW_ATE_signed_char => ValueKind::Signed,
+                            gimli::DW_ATE_unsigned
+                            | gimli::DW_ATE_unsigned_char
+                            | gimli::DW_ATE_boolean => ValueKind::Unsigned,
+                            _ => ValueKind::Other,
+                        },
+                        _ => ValueKind::Other,
+                    },
+                    gimli::DW_TAG_pointer_type => ValueKind::Unsigned,
+                    gimli::DW_TAG_enumeration_type => ValueKind::Signed,
+                    _ => ValueKind::Other,
+                };
+                return Some((size.udata_value()?, kind));
+            }
+            match die.attr_value(gimli::DW_AT_type) {
+                Ok(Some(AttributeValue::UnitRef(next))) => offset = next,
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Summarises which compilers produced the compile units and in which languages, with how
+    /// many units each combination produced, most units first.
+    pub fn compilers(&self) -> Vec<CompilerInfo> {
+        let mut result: Vec<CompilerInfo> = vec![];
+        let mut units = self.dwarf.units();
+        while let Ok(Some(header)) = units.next() {
+            let Ok(unit) = self.dwarf.unit(header) else {
+                continue;
+            };
+            let mut cursor = unit.entries();
+            let Ok(Some((_, root))) = cursor.next_dfs() else {
+                continue;
+            };
+            let producer = root
+                .attr_value(gimli::DW_AT_producer)
+                .ok()
+                .flatten()
+                .and_then(|attr| self.dwarf.attr_string(&unit, attr).ok())
+                .and_then(|r| r.to_string().ok().map(|s| s.to_string()));
+            let language = match root.attr_value(gimli::DW_AT_language) {
+                Ok(Some(AttributeValue::Language(lang))) => Some(match lang.static_string() {
+                    Some(name) => name.trim_start_matches("DW_LANG_").to_string(),
+                    None => format!("unknown (0x{:x})", lang.0),
+                }),
+                _ => None,
+            };
+            match result
+                .iter_mut()
+                .find(|c| c.producer == producer && c.language == language)
+            {
+                Some(existing) => existing.units += 1,
+                None => result.push(CompilerInfo {
+                    producer,
+                    language,
+                    units: 1,
+                }),
+            }
+        }
+        result.sort_by_key(|c| std::cmp::Reverse(c.units));
+        result
+    }
+
+    /// Every function with code in the executable by name, with the total size of its code in
+    /// bytes. Declarations and inlined-only functions have no code so aren't included.
+    pub fn function_sizes(&self) -> BTreeMap<String, u64> {
+        let mut result = BTreeMap::new();
+        let mut units = self.dwarf.units();
+        while let Ok(Some(header)) = units.next() {
+            let Ok(unit) = self.dwarf.unit(header) else {
+                continue;
+            };
+            let mut cursor = unit.entries();
+            while let Ok(Some((_, current))) = cursor.next_dfs() {
+                if current.tag() != gimli::DW_TAG_subprogram {
+                    continue;
+                }
+                let Some(name) = self.die_name(&unit, current) else {
+                    continue;
+                };
+                let size = self
+                    .die_ranges(&unit, current)
+                    .iter()
+                    .map(|r| r.end - r.start)
+                    .sum::<u64>();
+                if size > 0 {
+                    *result.entry(name).or_default() += size;
+                }
+            }
+        }
+        result
+    }
+
+    /// Compares the functions in this executable against another, e.g. a different build of
+    /// the same program.
+    pub fn diff_functions(&self, other: &ExecutableFile) -> FunctionDiff {
+        let ours = self.function_sizes();
+        let theirs = other.function_sizes();
+        let mut diff = FunctionDiff::default();
+        for (name, size) in &ours {
+            match theirs.get(name) {
+                None => diff.removed.push((name.clone(), *size)),
+                Some(new) if new != size => diff.resized.push((name.clone(), *size, *new)),
+                Some(_) => diff.unchanged += 1,
+            }
+        }
+        diff.added = theirs
+            .into_iter()
+            .filter(|(name, _)| !ours.contains_key(name))
+            .collect();
+        diff
+    }
+
+    pub fn find_functions(&self, name: &str) -> Result<Vec<FunctionEntry>, ObjectError> {
         let mut result = vec![];
         let mut units = self.dwarf.units();
         while let Ok(Some(header)) = units.next() {
             if let Ok(unit) = self.dwarf.unit(header) {
                 let mut cursor = unit.entries();
-                while let Some((delta_depth, current)) = cursor
+                while let Some((_, current)) = cursor
                     .next_dfs()
                     .map_err(|_| ObjectError::FailedToParseDieTree)?
                 {
                     if current.tag() == gimli::DW_TAG_subprogram {
-                        let fn_name = match current.attr_value(gimli::DW_AT_name) {
-                            Ok(name) => name,
-                            Err(_) => continue,
-                        };
-
-                        let fn_name: Option<String> = match fn_name {
-                            Some(AttributeValue::DebugStrRef(offset)) => self
-                                .dwarf
-                                .string(offset)
-                                .and_then(|r| r.to_string().map(|s| s.to_string()))
-                                .ok(),
-                            _ => None,
-                        };
-                        if let Some(fn_name) = fn_name {
+                        if let Some(fn_name) = self.die_name(&unit, current) {
                             if name_matches(name, &fn_name) {
                                 let offset = current.offset();
                                 result.push((self.dwarf.unit(header).unwrap(), offset));
@@ -272,22 +1216,75 @@ fn name_matches(name: &str, compiled_name: &str) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
     use std::env;
 
     #[test]
     fn doesnt_just_segfault() {
         let path = env::current_exe().unwrap();
 
-        let file = ExecutableFile::load(&path).unwrap();
+        let file = ExecutableFile::load_blocking(&path).unwrap();
 
         file.endianness();
         assert!(file.elf_file.symbols().count() > 0);
     }
 
+    #[inline(never)]
+    fn signature_target(count: u32, data: *const u8) -> bool {
+        count == 0 && data.is_null()
+    }
+
+    #[test]
+    fn function_signatures() {
+        assert!(signature_target(0, std::ptr::null()));
+        let path = env::current_exe().unwrap();
+        let file = ExecutableFile::load_blocking(&path).unwrap();
+
+        let function = &file.find_functions("signature_target").unwrap()[0];
+        assert_eq!(
+            file.function_signature(function),
+            "signature_target(count: u32, data: *const u8) -> bool"
+        );
+        let function = &file.find_functions("function_signatures").unwrap()[0];
+        assert_eq!(file.function_signature(function), "function_signatures()");
+    }
+
+    #[test]
+    fn function_sizes_match_ranges() {
+        let path = env::current_exe().unwrap();
+        let file = ExecutableFile::load_blocking(&path).unwrap();
+
+        let sizes = file.function_sizes();
+        let function = &file.find_functions("function_sizes_match_ranges").unwrap()[0];
+        let size = file
+            .function_ranges(function)
+            .iter()
+            .map(|r| r.end - r.start)
+            .sum::<u64>();
+        assert_eq!(sizes["function_sizes_match_ranges"], size);
+
+        let diff = file.diff_functions(&file);
+        assert!(diff.is_empty());
+        assert_eq!(diff.unchanged, sizes.len());
+    }
+
+    #[test]
+    fn test_binary_is_dynamic_pie() {
+        let path = env::current_exe().unwrap();
+        let file = ExecutableFile::load_blocking(&path).unwrap();
+
+        assert!(file.is_dynamic());
+        assert_eq!(file.link_kind(), LinkKind::DynamicPie);
+        assert_eq!(
+            file.runtime_address_offset(0x5555_5555_4000),
+            0x5555_5555_4000
+        );
+    }
+
     #[test]
     fn can_find_functions() {
         let path = env::current_exe().unwrap();
-        let file = ExecutableFile::load(&path).unwrap();
+        let file = ExecutableFile::load_blocking(&path).unwrap();
 
         let v = file.find_functions("can_find_functions").unwrap();
         assert!(!v.is_empty());
@@ -308,5 +1305,141 @@ mod tests {
 
         assert_eq!(unit_lookup.header, unit.header);
         assert_eq!(offset_lookup, *offset);
+        assert_eq!(
+            file.function_name(&(unit_lookup, offset_lookup)).as_deref(),
+            Some("can_find_functions")
+        );
+    }
+
+    #[test]
+    fn location_for_function_start() {
+        let path = env::current_exe().unwrap();
+        let file = ExecutableFile::load_blocking(&path).unwrap();
+
+        let functions = file.find_functions("location_for_function_start").unwrap();
+        let start = file.function_start(&functions[0]).unwrap();
+        let (path, line, function) = file.location_for_address(start).unwrap();
+        assert!(path.ends_with("src/elf.rs"), "{}", path.display());
+        assert!(line > 0);
+        assert_eq!(function.as_deref(), Some("location_for_function_start"));
+
+        assert_eq!(file.location_for_address(u64::MAX), None);
+    }
+
+    #[test]
+    fn get_address_resolves_locations() {
+        let path = env::current_exe().unwrap();
+        let file = ExecutableFile::load_blocking(&path).unwrap();
+
+        let function = Location::Function("get_address_resolves_locations".to_string());
+        let start = file.get_address(function).unwrap();
+        assert_ne!(start, 0);
+        let functions = file
+            .find_functions("get_address_resolves_locations")
+            .unwrap();
+        assert_eq!(file.function_start(&functions[0]), Some(start));
+
+        let (source, line, _) = file.location_for_address(start).unwrap();
+        let line_start = file
+            .get_address(Location::Line { file: source, line })
+            .unwrap();
+        assert_eq!(file.location_for_address(line_start).unwrap().1, line);
+
+        assert_eq!(file.get_address(Location::Address(0x1234)).unwrap(), 0x1234);
+        assert!(matches!(
+            file.get_address(Location::Function("not_a_real_function".to_string())),
+            Err(ObjectError::BadLocation)
+        ));
+        assert!(matches!(
+            file.get_address(Location::Line {
+                file: "not_a_real_file.rs".into(),
+                line: 1,
+            }),
+            Err(ObjectError::BadLocation)
+        ));
+    }
+
+    #[test]
+    fn address_range_for_function_line() {
+        let path = env::current_exe().unwrap();
+        let file = ExecutableFile::load_blocking(&path).unwrap();
+
+        let functions = file
+            .find_functions("address_range_for_function_line")
+            .unwrap();
+        let start = file.function_start(&functions[0]).unwrap();
+        let (source, line, _) = file.location_for_address(start).unwrap();
+        let (low, high) = file.address_range_for_line(&source, line as u64).unwrap();
+        assert!(low <= start, "0x{:x} 0x{:x}", low, start);
+
+        // The function's line is shared with the closure `#[test]` wraps it in, so the function
+        // start can be in a later range than the first
+        let ranges = file.address_ranges_for_line(&source, line as u64).unwrap();
+        assert_eq!(ranges.first(), Some(&(low..high)));
+        assert!(ranges.iter().any(|range| range.contains(&start)));
+        assert!(ranges.windows(2).all(|r| r[0].end < r[1].start));
+
+        assert_eq!(
+            file.address_range_for_line(Path::new("not_a_file.rs"), 1),
+            None
+        );
+    }
+
+    #[test]
+    fn compilers_include_rustc() {
+        let path = env::current_exe().unwrap();
+        let file = ExecutableFile::load_blocking(&path).unwrap();
+
+        let compilers = file.compilers();
+        let rust = compilers
+            .iter()
+            .find(|c| c.language.as_deref() == Some("Rust"))
+            .unwrap();
+        assert!(rust.producer.as_ref().unwrap().contains("rustc"));
+        assert!(compilers.windows(2).all(|c| c[0].units >= c[1].units));
+        let unique = compilers
+            .iter()
+            .map(|c| (&c.producer, &c.language))
+            .collect::<HashSet<_>>();
+        assert_eq!(unique.len(), compilers.len());
+    }
+
+    #[test]
+    fn reload_reads_file_again() {
+        let path = env::current_exe().unwrap();
+        let file = ExecutableFile::load_blocking(&path).unwrap();
+        let reloaded = ExecutableFile::reload_blocking(&path).unwrap();
+
+        assert!(!Arc::ptr_eq(&file.data, &reloaded.data));
+        assert!(Arc::ptr_eq(
+            &reloaded.data,
+            LOADED_FILES.read().unwrap().get(&path).unwrap()
+        ));
+        // The old file keeps its own copy of the data alive so is still usable
+        assert!(!file
+            .find_functions("reload_reads_file_again")
+            .unwrap()
+            .is_empty());
+        assert!(!reloaded
+            .find_functions("reload_reads_file_again")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn load_async() {
+        let path = env::current_exe().unwrap();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let file = rt.block_on(ExecutableFile::load_async(&path)).unwrap();
+        assert!(!file.find_functions("load_async").unwrap().is_empty());
+
+        let missing = rt.block_on(ExecutableFile::load_async(Path::new(
+            "i-am-not-a-real-program-you-cannot-load-me",
+        )));
+        assert!(matches!(missing, Err(ObjectError::CantOpenElf)));
     }
 }
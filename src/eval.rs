@@ -0,0 +1,441 @@
+//! Integer expressions for `print`, e.g. `print rsp + 8 * 2` or `print *(rbp - 8)`. Operands are
+//! integer literals, general purpose registers and global variables, combined with `+ - * /`,
+//! parentheses, unary minus and `*` to dereference, which reads the 8 byte word at the address.
+//!
+//! Everything is worked out as 64 bit integers with wrapping arithmetic, like the registers
+//! themselves. Variables have to be integers or pointers and are read when the expression is
+//! evaluated so they can't be stale.
+use crate::elf::{ExecutableFile, ValueKind};
+use crate::process::{Process, ProcessError, Registers};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// How deeply expressions can nest, this keeps the recursive parser from overflowing the stack
+/// on something silly like a thousand opening brackets.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum EvalError {
+    #[error("expected a number, register or variable")]
+    MissingOperand,
+    #[error("unexpected \"{0}\"")]
+    Unexpected(String),
+    #[error("missing closing bracket")]
+    UnclosedBracket,
+    #[error("invalid number \"{0}\"")]
+    InvalidLiteral(String),
+    #[error("expression nests more than {MAX_DEPTH} deep")]
+    TooDeep,
+    #[error("no global variable named {0}")]
+    UnknownVariable(String),
+    #[error("variable {0} isn't an integer or pointer")]
+    UnsupportedType(String),
+    #[error("no debug info loaded to find {0}")]
+    NoDebugInfo(String),
+    #[error("division by zero")]
+    DivideByZero,
+    #[error(transparent)]
+    Process(#[from] ProcessError),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinaryOp {
+    fn as_char(&self) -> char {
+        match self {
+            Self::Add => '+',
+            Self::Sub => '-',
+            Self::Mul => '*',
+            Self::Div => '/',
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Add | Self::Sub => 1,
+            Self::Mul | Self::Div => 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Expr {
+    Literal(u64),
+    Register(String),
+    Variable(String),
+    /// The 8 byte word at the address
+    Deref(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Literal(i) => write!(f, "{}", i),
+            Self::Register(name) | Self::Variable(name) => write!(f, "{}", name),
+            Self::Deref(expr) => write!(f, "*{}", Operand(expr)),
+            Self::Neg(expr) => write!(f, "-{}", Operand(expr)),
+            Self::Binary { op, lhs, rhs } => {
+                // Brackets are only needed where the precedence would otherwise change, the
+                // right hand side needs them for equal precedence too as a - (b - c) != a - b - c
+                let needs_brackets = |expr: &Expr, strict: bool| match expr {
+                    Self::Binary { op: inner, .. } if strict => {
+                        inner.precedence() <= op.precedence()
+                    }
+                    Self::Binary { op: inner, .. } => inner.precedence() < op.precedence(),
+                    _ => false,
+                };
+                let side = |f: &mut fmt::Formatter, expr: &Expr, strict| {
+                    if needs_brackets(expr, strict) {
+                        write!(f, "({})", expr)
+                    } else {
+                        write!(f, "{}", expr)
+                    }
+                };
+                side(f, lhs, false)?;
+                write!(f, " {} ", op.as_char())?;
+                side(f, rhs, true)
+            }
+        }
+    }
+}
+
+/// Shows the operand of a unary operator, bracketed if it's a binary expression.
+struct Operand<'a>(&'a Expr);
+
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Expr::Binary { .. } => write!(f, "({})", self.0),
+            expr => write!(f, "{}", expr),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Number(String),
+    Identifier(String),
+    Op(char),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, EvalError> {
+    let mut tokens = vec![];
+    let mut chars = s.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut take_while = |f: fn(char) -> bool| {
+            let mut end = start + c.len_utf8();
+            while let Some((i, c)) = chars.next_if(|(_, c)| f(*c)) {
+                end = i + c.len_utf8();
+            }
+            s[start..end].to_string()
+        };
+        match c {
+            c if c.is_whitespace() => {}
+            '+' | '-' | '*' | '/' | '(' | ')' => tokens.push(Token::Op(c)),
+            c if c.is_ascii_digit() => {
+                tokens.push(Token::Number(take_while(|c| c.is_ascii_alphanumeric())))
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                tokens.push(Token::Identifier(take_while(|c| {
+                    c.is_ascii_alphanumeric() || c == '_'
+                })))
+            }
+            c => return Err(EvalError::Unexpected(c.to_string())),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek_op(&self) -> Option<char> {
+        match self.tokens.get(self.position) {
+            Some(Token::Op(c)) => Some(*c),
+            _ => None,
+        }
+    }
+
+    fn nested<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, EvalError>,
+    ) -> Result<T, EvalError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(EvalError::TooDeep);
+        }
+        self.depth += 1;
+        let res = f(self);
+        self.depth -= 1;
+        res
+    }
+
+    /// expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Expr, EvalError> {
+        let mut lhs = self.term()?;
+        while let Some(op @ ('+' | '-')) = self.peek_op() {
+            self.position += 1;
+            let op = if op == '+' {
+                BinaryOp::Add
+            } else {
+                BinaryOp::Sub
+            };
+            let rhs = self.term()?;
+            lhs = Expr::Binary {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Expr, EvalError> {
+        let mut lhs = self.unary()?;
+        while let Some(op @ ('*' | '/')) = self.peek_op() {
+            self.position += 1;
+            let op = if op == '*' {
+                BinaryOp::Mul
+            } else {
+                BinaryOp::Div
+            };
+            let rhs = self.unary()?;
+            lhs = Expr::Binary {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// unary := ('*' | '-') unary | primary
+    fn unary(&mut self) -> Result<Expr, EvalError> {
+        match self.peek_op() {
+            Some('*') => {
+                self.position += 1;
+                let operand = self.nested(Self::unary)?;
+                Ok(Expr::Deref(Box::new(operand)))
+            }
+            Some('-') => {
+                self.position += 1;
+                let operand = self.nested(Self::unary)?;
+                Ok(Expr::Neg(Box::new(operand)))
+            }
+            _ => self.primary(),
+        }
+    }
+
+    /// primary := number | register | variable | '(' expr ')'
+    fn primary(&mut self) -> Result<Expr, EvalError> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or(EvalError::MissingOperand)?;
+        self.position += 1;
+        match token {
+            Token::Number(n) => {
+                let value = match n.strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16),
+                    None => n.parse::<u64>(),
+                };
+                value
+                    .map(Expr::Literal)
+                    .map_err(|_| EvalError::InvalidLiteral(n))
+            }
+            Token::Identifier(name) if Registers::GENERAL_PURPOSE.contains(&name.as_str()) => {
+                Ok(Expr::Register(name))
+            }
+            Token::Identifier(name) => Ok(Expr::Variable(name)),
+            Token::Op('(') => {
+                let expr = self.nested(Self::expr)?;
+                match self.peek_op() {
+                    Some(')') => {
+                        self.position += 1;
+                        Ok(expr)
+                    }
+                    _ => Err(EvalError::UnclosedBracket),
+                }
+            }
+            Token::Op(c) => Err(EvalError::Unexpected(c.to_string())),
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = EvalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            position: 0,
+            depth: 0,
+        };
+        let expr = parser.expr()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(expr),
+            Some(Token::Op(c)) => Err(EvalError::Unexpected(c.to_string())),
+            Some(Token::Number(s) | Token::Identifier(s)) => Err(EvalError::Unexpected(s.clone())),
+        }
+    }
+}
+
+impl Expr {
+    /// Evaluates the expression against a stopped process, variables are looked up in `elf`.
+    pub fn evaluate(
+        &self,
+        process: &Process,
+        elf: Option<&ExecutableFile>,
+    ) -> Result<u64, EvalError> {
+        match self {
+            Self::Literal(i) => Ok(*i),
+            Self::Register(name) => Ok(process.get_gp_registers()?.get(name).unwrap_or_default()),
+            Self::Variable(name) => {
+                let elf = elf.ok_or_else(|| EvalError::NoDebugInfo(name.clone()))?;
+                let variable = elf
+                    .global_variable(name)
+                    .ok()
+                    .flatten()
+                    .ok_or_else(|| EvalError::UnknownVariable(name.clone()))?;
+                if !matches!(variable.kind, ValueKind::Signed | ValueKind::Unsigned)
+                    || !matches!(variable.size, 1 | 2 | 4 | 8)
+                {
+                    return Err(EvalError::UnsupportedType(name.clone()));
+                }
+                let data = process.read_memory(
+                    variable.address + process.addr_offset,
+                    variable.size as usize,
+                )?;
+                let mut bytes = [0u8; 8];
+                bytes[..data.len()].copy_from_slice(&data);
+                let raw = u64::from_le_bytes(bytes);
+                let shift = 64 - 8 * variable.size;
+                if variable.kind == ValueKind::Signed {
+                    Ok((((raw << shift) as i64) >> shift) as u64)
+                } else {
+                    Ok(raw)
+                }
+            }
+            Self::Deref(expr) => {
+                let addr = expr.evaluate(process, elf)?;
+                let data = process.read_memory(addr, 8)?;
+                Ok(u64::from_le_bytes(data.try_into().unwrap_or_default()))
+            }
+            Self::Neg(expr) => Ok(expr.evaluate(process, elf)?.wrapping_neg()),
+            Self::Binary { op, lhs, rhs } => {
+                let lhs = lhs.evaluate(process, elf)?;
+                let rhs = rhs.evaluate(process, elf)?;
+                match op {
+                    BinaryOp::Add => Ok(lhs.wrapping_add(rhs)),
+                    BinaryOp::Sub => Ok(lhs.wrapping_sub(rhs)),
+                    BinaryOp::Mul => Ok(lhs.wrapping_mul(rhs)),
+                    // Signed like C would for the negative numbers people are likely to write
+                    BinaryOp::Div if rhs == 0 => Err(EvalError::DivideByZero),
+                    BinaryOp::Div => Ok((lhs as i64).wrapping_div(rhs as i64) as u64),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary(op: BinaryOp, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Binary {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    #[test]
+    fn parse_expressions() {
+        assert_eq!(
+            Expr::from_str("rsp + 8 * 2").unwrap(),
+            binary(
+                BinaryOp::Add,
+                Expr::Register("rsp".to_string()),
+                binary(BinaryOp::Mul, Expr::Literal(8), Expr::Literal(2))
+            )
+        );
+        assert_eq!(
+            Expr::from_str("*rbp").unwrap(),
+            Expr::Deref(Box::new(Expr::Register("rbp".to_string())))
+        );
+        assert_eq!(
+            Expr::from_str("*(counter-0x10)/-2").unwrap(),
+            binary(
+                BinaryOp::Div,
+                Expr::Deref(Box::new(binary(
+                    BinaryOp::Sub,
+                    Expr::Variable("counter".to_string()),
+                    Expr::Literal(16)
+                ))),
+                Expr::Neg(Box::new(Expr::Literal(2)))
+            )
+        );
+        // Left associative
+        assert_eq!(
+            Expr::from_str("1 - 2 - 3").unwrap().to_string(),
+            "1 - 2 - 3"
+        );
+        assert_eq!(
+            Expr::from_str("1 - (2 - 3)").unwrap().to_string(),
+            "1 - (2 - 3)"
+        );
+        assert_eq!(
+            Expr::from_str("(rsp+8)*2 + *(rbp)").unwrap().to_string(),
+            "(rsp + 8) * 2 + *rbp"
+        );
+        assert_eq!(
+            Expr::from_str("*(rbp - 8)").unwrap().to_string(),
+            "*(rbp - 8)"
+        );
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert_eq!(Expr::from_str(""), Err(EvalError::MissingOperand));
+        assert_eq!(Expr::from_str("rax +"), Err(EvalError::MissingOperand));
+        assert_eq!(Expr::from_str("(rax + 1"), Err(EvalError::UnclosedBracket));
+        assert_eq!(
+            Expr::from_str("rax + 1)"),
+            Err(EvalError::Unexpected(")".to_string()))
+        );
+        assert_eq!(
+            Expr::from_str("rax % 2"),
+            Err(EvalError::Unexpected("%".to_string()))
+        );
+        assert_eq!(
+            Expr::from_str("rax rbx"),
+            Err(EvalError::Unexpected("rbx".to_string()))
+        );
+        assert_eq!(
+            Expr::from_str("12abc"),
+            Err(EvalError::InvalidLiteral("12abc".to_string()))
+        );
+        let deep = format!("{}1{}", "(".repeat(100), ")".repeat(100));
+        assert_eq!(Expr::from_str(&deep), Err(EvalError::TooDeep));
+        assert_eq!(Expr::from_str(&"*".repeat(100)), Err(EvalError::TooDeep));
+    }
+}
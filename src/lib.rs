@@ -1,30 +1,131 @@
+use crate::asm::{Instruction, Patch};
+use crate::backtrace::Backtrace;
 use crate::commands::Location;
-use crate::elf::ExecutableFile;
-use crate::process::{Process, Registers, StopReason};
-use clap::Parser;
+use crate::condition::Condition;
+use crate::disasm::{Decoded, Flow, MAX_INSTRUCTION_LEN};
+use crate::dprintf::Dprintf;
+use crate::elf::{CompilerInfo, ExecutableFile, FunctionDiff, SourceLocation};
+use crate::eval::Expr;
+use crate::process::{
+    Event, Info, Process, ProcessError, RegisterGroup, Registers, StopReason, TrapType,
+};
+use crate::settings::Settings;
+use crate::snapshot::{Snapshot, SnapshotDiff};
+use crate::startup::StartupArgs;
+use crate::syscalls::SyscallStop;
+use crate::trace::InstructionTrace;
+use crate::vdso::Vdso;
+use clap::{ArgGroup, Parser};
+use nix::sys::signal::Signal;
 use nix::unistd::Pid;
-use std::path::PathBuf;
-use std::time::Duration;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tracing::{debug, info, warn};
 
 pub use crate::process::State;
 
+pub mod address;
+pub mod asm;
+pub mod backtrace;
 pub mod breakpoint;
+pub mod call;
 pub mod commands;
+pub mod condition;
+pub mod disasm;
+pub mod dprintf;
 pub mod elf;
+pub mod eval;
 pub mod linux;
+pub mod output;
 pub mod process;
 pub mod ptrace_control;
+pub mod settings;
+pub mod snapshot;
+pub mod startup;
+pub mod syscalls;
+pub mod trace;
+pub mod value;
+pub mod vdso;
+
+/// Number of instructions traced on each wait before handing back to the caller.
+const TRACE_BATCH_SIZE: usize = 10_000;
+/// How long to wait for a single step to complete before handing back to the caller, a step over
+/// a blocking syscall can take a while.
+const TRACE_STEP_TIMEOUT: Duration = Duration::from_millis(10);
+/// Number of stops remembered by the stop history, older stops are dropped as new ones come in.
+pub const STOP_HISTORY_LEN: usize = 32;
+/// Default for the `max-step-instructions` setting. A source line step single steps until it
+/// leaves the line, a tight loop on one line could take millions of steps.
+pub const DEFAULT_MAX_STEP_INSTRUCTIONS: usize = 10_000;
+
+/// How this build of rustybug was made, shown by `info version` and logged on startup so bug
+/// reports say what they were seen with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// Target triple rustybug was compiled for
+    pub target: &'static str,
+    /// Optional cargo features compiled in
+    pub features: &'static [&'static str],
+}
+
+pub const BUILD_INFO: BuildInfo = BuildInfo {
+    version: env!("CARGO_PKG_VERSION"),
+    target: env!("RUSTYBUG_TARGET"),
+    features: &[
+        #[cfg(feature = "tokio")]
+        "tokio",
+    ],
+};
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rustybug {} ({})", self.version, self.target)?;
+        if self.features.is_empty() {
+            write!(f, " with no optional features")
+        } else {
+            write!(f, " with features: {}", self.features.join(", "))
+        }
+    }
+}
 
 /// rustybug a moderately simple debugger written in rust. Not intended to be feature complete more
 /// a toy project and way to test some tarpaulin assumptions.
 #[derive(Clone, Debug, Default, Parser)]
+#[clap(version)]
+#[clap(group(ArgGroup::new("target").args(["input", "pid"])))]
 pub struct Args {
     /// Executable to debug
     pub input: Option<PathBuf>,
     /// PID of a running process to attach to
     #[clap(long, short)]
     pub pid: Option<i32>,
+    /// Arguments passed to the executable, everything after `--` so the program's own flags
+    /// aren't taken as ours
+    #[clap(last = true, conflicts_with = "pid")]
+    pub program_args: Vec<String>,
+    /// Environment variable to set for the program, can be given more than once
+    #[clap(long, value_name = "KEY=VALUE", value_parser = parse_env_var, conflicts_with = "pid")]
+    pub env: Vec<(String, String)>,
+    /// Start the program with only the variables from `--env` rather than our environment
+    #[clap(long, conflicts_with = "pid")]
+    pub clean_env: bool,
+    /// Directory the program is started in, ours if not given
+    #[clap(long, conflicts_with = "pid")]
+    pub cwd: Option<PathBuf>,
+    /// Run the program as soon as it's launched rather than stopping at the start, the same as
+    /// `set auto-continue on`
+    #[clap(long, short, conflicts_with = "pid")]
+    pub run: bool,
+    /// Run the commands in this file one per line without the UI, exiting once they're done
+    #[clap(long)]
+    pub batch: Option<PathBuf>,
+    /// Report how long each command in the batch took and a summary at the end
+    #[clap(long, requires = "batch")]
+    pub timings: bool,
 }
 
 impl Args {
@@ -38,6 +139,12 @@ impl Args {
         }
     }
 
+    /// Whether there's a program or process to debug, without one rustybug starts with no
+    /// process waiting for a `load` or `attach`.
+    pub fn has_target(&self) -> bool {
+        self.input.is_some() || self.pid.is_some()
+    }
+
     pub fn set_input(&mut self, input: PathBuf) {
         self.input = Some(input);
         self.pid = None;
@@ -46,68 +153,774 @@ impl Args {
     pub fn set_pid(&mut self, input: i32) {
         self.pid = Some(input);
         self.input = None;
+        self.program_args.clear();
+    }
+
+    /// Sets an environment variable for the program, replacing any earlier value.
+    pub fn set_env(&mut self, key: String, value: String) {
+        self.env.retain(|(k, _)| *k != key);
+        self.env.push((key, value));
+    }
+
+    /// Environment the program is launched with, our own unless `clean_env` is set with the
+    /// variables from `env` on top.
+    pub fn environment(&self) -> Vec<(String, String)> {
+        let mut environment = if self.clean_env {
+            vec![]
+        } else {
+            std::env::vars().collect::<Vec<_>>()
+        };
+        environment.retain(|(key, _)| !self.env.iter().any(|(k, _)| k == key));
+        environment.extend(self.env.iter().cloned());
+        environment
+    }
+}
+
+/// Splits a `KEY=VALUE` environment variable, the value can contain `=` but the key can't be
+/// empty.
+pub fn parse_env_var(var: &str) -> Result<(String, String), String> {
+    match var.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got \"{}\"", var)),
     }
 }
 
+/// An instruction in a function shown by `disassemble_function`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DisassembledInstruction {
+    pub addr: u64,
+    /// The instruction's bytes as compiled, a breakpoint's int3 is replaced by the byte it
+    /// overwrote
+    pub bytes: Vec<u8>,
+    /// The instruction in Intel syntax, `None` if it couldn't be decoded
+    pub text: Option<String>,
+    /// How it affects control flow, `None` if it couldn't be decoded
+    pub flow: Option<Flow>,
+    /// Whether there's an enabled breakpoint on it
+    pub breakpoint: bool,
+}
+
+/// A summary of a breakpoint for displaying to the user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BreakpointInfo {
+    pub id: u64,
+    pub addr: u64,
+    pub enabled: bool,
+    pub hit_count: u64,
+    /// Number of upcoming hits that won't stop the process
+    pub ignore_count: u64,
+    /// Removed once it's hit
+    pub temporary: bool,
+    pub source_loc: Option<SourceLocation>,
+    /// Condition that has to hold for the breakpoint to stop the process
+    pub condition: Option<String>,
+    /// The only thread the breakpoint stops
+    pub thread: Option<Pid>,
+    /// Set in a debug register rather than as an int3
+    pub hardware: bool,
+}
+
+/// A place the process stopped, with the location resolved when it stopped so it still reads
+/// right if symbols are reloaded later.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StopRecord {
+    pub pc: u64,
+    pub function: Option<String>,
+    pub source_loc: Option<SourceLocation>,
+    pub trap_reason: Option<TrapType>,
+}
+
+/// Progress of a `step` or `next` stepping through a source line.
 #[derive(Debug)]
+struct LineStep {
+    /// Addresses of the line being stepped through, empty if it's a single instruction
+    ranges: Vec<Range<u64>>,
+    /// Whether to stop in called functions, only those with line info are stopped in
+    into_calls: bool,
+    /// Return address of the call that was just stepped into
+    called_from: Option<u64>,
+    /// Instructions single stepped so far
+    steps: usize,
+    /// Breakpoint the step is continuing to as its address, ID and whether it was added for
+    /// the step
+    resume_at: Option<(u64, u64, bool)>,
+}
+
 pub struct DebuggerStateMachine {
     root: Process,
     elf: Option<ExecutableFile>,
+    /// Symbols of the vDSO, which isn't in `elf` or any file on disk
+    vdso: Option<Vdso>,
     args: Args,
+    trace: Option<InstructionTrace>,
+    settings: Settings,
+    /// Messages logged when a breakpoint is hit, keyed by breakpoint ID
+    dprintfs: HashMap<u64, Dprintf>,
+    snapshots: HashMap<String, Snapshot>,
+    /// Recent stops, most recent at the back
+    stop_history: VecDeque<StopRecord>,
+    /// Breakpoint ID and hit count a `continue until` is waiting for
+    continue_until: Option<(u64, u64)>,
+    /// Breakpoint a `run-to` is waiting for and whether it was added just for the `run-to`, if
+    /// so it's removed on the next stop
+    run_to: Option<(u64, bool)>,
+    /// A `step` or `next` that's waiting for a step to finish
+    line_step: Option<LineStep>,
+    /// Return address a `finish` is waiting for and the ID of the breakpoint on it, along with
+    /// whether the breakpoint was added just for the `finish`
+    finish: Option<(u64, u64, bool)>,
+    /// Whether continuing stops the process on entry to and exit from every syscall
+    catch_syscalls: bool,
+}
+
+/// Deriving this would dump the whole process and ELF file which is far too noisy for logs, so
+/// just show a summary of the state.
+impl fmt::Debug for DebuggerStateMachine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let elf = if self.elf.is_some() { "Some" } else { "None" };
+        f.debug_struct("DebuggerStateMachine")
+            .field("pid", &self.root.pid().as_raw())
+            .field("state", &self.root.state())
+            .field("breakpoints", &self.root.breakpoints().len())
+            .field("elf", &format_args!("{}", elf))
+            .finish()
+    }
 }
 
 impl DebuggerStateMachine {
     pub fn start(args: Args) -> anyhow::Result<Self> {
+        Self::start_with_settings(args, Settings::default())
+    }
+
+    pub fn start_with_settings(args: Args, settings: Settings) -> anyhow::Result<Self> {
         let (mut root, elf) = if let Some(input) = args.input.as_ref() {
-            let elf = match ExecutableFile::load(input) {
+            let elf = match ExecutableFile::load_blocking(input) {
                 Ok(elf) => Some(elf),
                 Err(e) => {
                     warn!("Failed to load elf file: {}", e);
                     None
                 }
             };
-            (Process::launch(input)?, elf)
+            (
+                Process::launch_with(
+                    input,
+                    &args.program_args,
+                    &args.environment(),
+                    args.cwd.as_deref(),
+                )?,
+                elf,
+            )
         } else if let Some(pid) = args.pid {
             let pid = Pid::from_raw(pid);
             (Process::attach(pid)?, None)
         } else {
-            panic!("You should provide an executable name or PID");
+            anyhow::bail!("You should provide an executable name or PID");
         };
 
         info!(pid=?root.pid(), "program launch.");
 
-        if let (Some(elf), Some(mapped_address)) = (elf.as_ref(), root.mapped_address()) {
-            root.addr_offset = elf.runtime_address_offset(mapped_address);
+        if let Some(offset) = elf
+            .as_ref()
+            .and_then(|elf| runtime_address_offset(&root, elf))
+        {
+            root.addr_offset = offset;
             info!("Using runtime address offset 0x{:x}", root.addr_offset);
         }
+        let vdso = read_vdso(&root);
+
+        if settings.stop_on_events {
+            root.stop_on_events();
+        }
+        root.set_verify_breakpoints(settings.verify_breakpoints);
+        root.set_nopass_signals(settings.nopass_signals.clone());
+        root.set_follow_fork(settings.follow_fork);
+
+        if settings.auto_continue && args.input.is_some() {
+            info!("Continuing launched program, auto-continue is on");
+            root.resume()?;
+        }
 
         debug!(process=?root);
 
-        Ok(Self { root, elf, args })
+        Ok(Self {
+            root,
+            elf,
+            vdso,
+            args,
+            trace: None,
+            settings,
+            dprintfs: HashMap::new(),
+            snapshots: HashMap::new(),
+            stop_history: VecDeque::with_capacity(STOP_HISTORY_LEN),
+            continue_until: None,
+            run_to: None,
+            line_step: None,
+            finish: None,
+            catch_syscalls: false,
+        })
     }
 
     pub fn blocking_wait(&mut self, duration: Duration) -> anyhow::Result<StopReason> {
-        Ok(self.root.blocking_wait_on_signal(duration)?)
+        let deadline = Instant::now() + duration;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let stop = self.root.blocking_wait_on_signal(remaining)?;
+            if !self.handle_stop(&stop)? {
+                return Ok(stop);
+            }
+        }
     }
 
     pub fn wait(&mut self) -> anyhow::Result<Option<StopReason>> {
-        Ok(self.root.wait_on_signal()?)
+        let mut stop = self.root.wait_on_signal()?;
+        let mut traced = 0;
+        while let (Some(reason), true) = (stop.as_ref(), self.is_tracing()) {
+            if reason.reason != State::Stopped || reason.trap_reason != Some(TrapType::SingleStep) {
+                info!(
+                    "Stopping trace, process stopped for another reason: {:?}",
+                    reason
+                );
+                self.stop_trace()?;
+                break;
+            }
+            // Just our own step so keep the trace going and don't bother the user with it
+            self.trace_step()?;
+            traced += 1;
+            if traced >= TRACE_BATCH_SIZE || !self.is_tracing() {
+                // Give control back so the UI stays responsive, the next wait picks up the step
+                return Ok(None);
+            }
+            stop = match self.root.blocking_wait_on_signal(TRACE_STEP_TIMEOUT) {
+                Ok(reason) => Some(reason),
+                Err(ProcessError::Timeout) => None,
+                Err(e) => return Err(e.into()),
+            };
+        }
+        if let Some(reason) = stop.as_ref() {
+            if self.handle_stop(reason)? {
+                return Ok(None);
+            }
+        }
+        Ok(stop)
     }
 
-    pub fn cont(&mut self) -> anyhow::Result<()> {
-        if self.root.state() == State::Stopped {
-            self.root.resume()?;
+    /// Does anything that should happen automatically when the process stops. Returns true if
+    /// the process was continued so the stop shouldn't be reported.
+    fn handle_stop(&mut self, stop: &StopReason) -> anyhow::Result<bool> {
+        if stop.event == Some(Event::SyscallStop) {
+            self.log_syscall();
+        }
+        if stop.trap_reason == Some(TrapType::Spurious) && self.settings.skip_spurious_traps {
+            debug!("Continuing past spurious SIGTRAP: {:?}", stop);
+            self.resume()?;
+            return Ok(true);
+        }
+        if self.continue_line_step(stop)? {
+            return Ok(true);
+        }
+        if self.run_breakpoint_actions(stop)? {
+            return Ok(true);
+        }
+        self.finish_run_to(stop)?;
+        self.report_finish(stop)?;
+        self.check_continue_until(stop)
+    }
+
+    /// Reports whether a `run-to` reached its location and removes its breakpoint.
+    fn finish_run_to(&mut self, stop: &StopReason) -> anyhow::Result<()> {
+        let Some((id, temporary)) = self.run_to.take() else {
+            return Ok(());
+        };
+        let target = self
+            .root
+            .breakpoints()
+            .into_iter()
+            .find(|bp| bp.id == id)
+            .map(|bp| bp.pc);
+        let pc = self.root.pc().ok();
+        if stop.reason.is_closed() {
+            info!(
+                "Process ended before reaching the run-to location: {:?}",
+                stop.info
+            );
+            return Ok(());
+        }
+        if stop.trap_reason == Some(TrapType::SoftwareBreak) && pc.is_some() && pc == target {
+            info!("Reached run-to location 0x{:x}", pc.unwrap_or_default());
+        } else {
+            info!("Stopped before reaching the run-to location: {:?}", stop);
+        }
+        if temporary {
+            self.delete_breakpoint(id)?;
         }
         Ok(())
     }
 
-    pub fn step(&mut self) -> anyhow::Result<()> {
-        if self.root.state() == State::Stopped {
+    /// Reports where a `finish` returned to, removing its breakpoint if it stopped somewhere else
+    /// first. A breakpoint added for the `finish` is temporary so it's already gone if it was hit.
+    fn report_finish(&mut self, stop: &StopReason) -> anyhow::Result<()> {
+        let Some((addr, id, added)) = self.finish.take() else {
+            return Ok(());
+        };
+        if stop.reason.is_closed() {
+            info!(
+                "Process ended before the function returned: {:?}",
+                stop.info
+            );
+            return Ok(());
+        }
+        let pc = self.root.pc().ok();
+        if stop.trap_reason == Some(TrapType::SoftwareBreak) && pc == Some(addr) {
+            let function = self
+                .function_location(addr)
+                .map(|f| format!(" in {}", f))
+                .unwrap_or_default();
+            let source = self
+                .source_location(addr)
+                .map(|loc| format!(" at {}", loc))
+                .unwrap_or_default();
+            let rax = self.root.get_gp_registers()?.regs.rax;
+            info!(
+                "Returned to 0x{:x}{}{}, rax = {} (0x{:x})",
+                addr, function, source, rax as i64, rax
+            );
+        } else {
+            info!("Stopped before the function returned: {:?}", stop);
+        }
+        if added && self.root.breakpoints().iter().any(|bp| bp.id == id) {
+            self.delete_breakpoint(id)?;
+        }
+        Ok(())
+    }
+
+    /// Continues past the breakpoint a `continue until` is waiting for if it hasn't been hit
+    /// enough times yet. Any other stop ends the `continue until`.
+    fn check_continue_until(&mut self, stop: &StopReason) -> anyhow::Result<bool> {
+        let Some((id, hits)) = self.continue_until else {
+            return Ok(false);
+        };
+        let pc = self.root.pc().ok();
+        let hit_count = pc
+            .and_then(|pc| self.root.breakpoint_at(pc))
+            .filter(|bp| {
+                matches!(
+                    stop.trap_reason,
+                    Some(TrapType::SoftwareBreak | TrapType::HardwareBreak)
+                ) && bp.id == id
+            })
+            .map(|bp| bp.hit_count());
+        match hit_count {
+            Some(count) if count < hits => {
+                debug!(
+                    "Breakpoint {} hit {} of {} times, continuing",
+                    id, count, hits
+                );
+                self.resume()?;
+                return Ok(true);
+            }
+            Some(count) => {
+                info!(
+                    "Breakpoint {} hit {} times, stopped at 0x{:x}",
+                    id,
+                    count,
+                    pc.unwrap_or_default()
+                );
+                for line in self
+                    .root
+                    .get_gp_registers()?
+                    .format_group(RegisterGroup::General)
+                {
+                    info!("{}", line);
+                }
+            }
+            None => info!(
+                "Stopped before breakpoint {} was hit {} times: {:?}",
+                id, hits, stop
+            ),
+        }
+        self.continue_until = None;
+        Ok(false)
+    }
+
+    /// Logs the syscall the process stopped at, with its arguments on entry or the value it
+    /// returned on exit, decoding errors into their errno.
+    fn log_syscall(&self) {
+        if let Some(stop) = self.syscall_stop() {
+            info!("syscall {}", stop);
+        }
+    }
+
+    /// Decodes the syscall the process is stopped at, only meaningful when the last stop was a
+    /// `SyscallStop` event.
+    pub fn syscall_stop(&self) -> Option<SyscallStop> {
+        let registers = self.root.get_gp_registers().ok()?;
+        Some(SyscallStop::from_registers(&registers.regs))
+    }
+
+    /// Runs the dprintf attached to the breakpoint the process stopped at if there is one and
+    /// continues the process. Returns true if the process was continued.
+    fn run_breakpoint_actions(&mut self, stop: &StopReason) -> anyhow::Result<bool> {
+        if stop.trap_reason != Some(TrapType::SoftwareBreak) {
+            return Ok(false);
+        }
+        let Some(dprintf) = self
+            .root
+            .pc()
+            .ok()
+            .and_then(|pc| self.root.breakpoint_at(pc))
+            .and_then(|bp| self.dprintfs.get(&bp.id))
+        else {
+            return Ok(false);
+        };
+        match dprintf.format(&self.root, self.elf.as_ref()) {
+            Ok(message) => info!("{}", message.trim_end()),
+            Err(e) => warn!("dprintf failed: {}", e),
+        }
+        self.resume()?;
+        Ok(true)
+    }
+
+    /// Starts single stepping the process recording every instruction address to a file, with the
+    /// function and source line it's in and the registers if `trace-registers` is set. Stepping
+    /// continues as the process is waited on until it exits, stops for another reason, hits the
+    /// trace limit or `stop_trace` is called.
+    pub fn start_trace(&mut self, path: &Path) -> anyhow::Result<()> {
+        if self.root.state() != State::Stopped {
+            anyhow::bail!("Process must be stopped to start a trace");
+        }
+        if self.is_tracing() {
+            self.stop_trace()?;
+        }
+        info!("Tracing instructions to {}", path.display());
+        warn!(
+            "Tracing single steps every instruction so the program will run very slowly, \
+             interrupt or `trace stop` to end it"
+        );
+        self.trace = Some(InstructionTrace::create(path, self.settings.trace_limit)?);
+        self.trace_step()
+    }
+
+    pub fn stop_trace(&mut self) -> anyhow::Result<()> {
+        if let Some(trace) = self.trace.take() {
+            info!(
+                "Recorded {} instructions to {}",
+                trace.recorded(),
+                trace.path().display()
+            );
+            trace.finish()?;
+        }
+        Ok(())
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.trace.is_some()
+    }
+
+    fn trace_step(&mut self) -> anyhow::Result<()> {
+        let Some(trace) = self.trace.as_ref() else {
+            return Ok(());
+        };
+        let pc = self.root.pc()?;
+        let location = (!trace.has_location(pc)).then(|| {
+            let function = self.function_location(pc).unwrap_or_default();
+            let line = self
+                .source_location(pc)
+                .map(|loc| loc.to_string())
+                .unwrap_or_default();
+            format!("{}\t{}", function, line)
+        });
+        let registers = if self.settings.trace_registers {
+            let regs = self.root.get_gp_registers()?;
+            let values = Registers::GENERAL_PURPOSE
+                .iter()
+                .filter_map(|name| Some(format!("{}=0x{:x}", name, regs.get(name)?)))
+                .collect::<Vec<_>>();
+            Some(values.join(" "))
+        } else {
+            None
+        };
+        let recorded = match self.trace.as_mut() {
+            Some(trace) => trace.record(pc, location, registers.as_deref())?,
+            None => return Ok(()),
+        };
+        if recorded {
             self.root.step()?;
+        } else {
+            warn!(
+                "Trace limit of {} instructions reached, stopping trace",
+                self.settings.trace_limit
+            );
+            self.stop_trace()?;
+        }
+        Ok(())
+    }
+
+    /// Removes all breakpoints and lets the process carry on running without the debugger.
+    /// Anything waiting on a stop, like a `finish` or `step`, is abandoned.
+    pub fn detach(&mut self) -> anyhow::Result<()> {
+        if self.root.state() != State::Stopped {
+            anyhow::bail!(
+                "Process must be stopped to detach, interrupt it first: {:?}",
+                self.root.state()
+            );
         }
+        self.stop_trace()?;
+        self.continue_until = None;
+        self.run_to = None;
+        self.line_step = None;
+        self.finish = None;
+        self.dprintfs.clear();
+        self.root.detach()?;
         Ok(())
     }
 
+    /// How the root process ended once it has exited or been killed, so the exit code or signal
+    /// can still be checked after the stop reporting it.
+    pub fn last_exit_status(&self) -> Option<Info> {
+        self.root.exit_status()
+    }
+
+    pub fn cont(&mut self) -> anyhow::Result<()> {
+        Ok(self.resume()?)
+    }
+
+    /// Continues delivering `signal` instead of whatever signal the process stopped with, `None`
+    /// delivers nothing.
+    pub fn cont_with_signal(&mut self, signal: Option<Signal>) -> anyhow::Result<()> {
+        if self.catch_syscalls {
+            Ok(self.root.resume_to_syscall_with_signal(signal)?)
+        } else {
+            Ok(self.root.resume_with_signal(signal)?)
+        }
+    }
+
+    /// Continues the process, stopping at the next syscall if syscalls are being caught.
+    fn resume(&mut self) -> Result<(), ProcessError> {
+        if self.catch_syscalls {
+            self.root.resume_to_syscall()
+        } else {
+            self.root.resume()
+        }
+    }
+
+    /// Sets whether continuing stops the process on entry to and exit from every syscall. Takes
+    /// effect the next time the process is continued.
+    pub fn set_catch_syscalls(&mut self, catch: bool) {
+        self.catch_syscalls = catch;
+    }
+
+    pub fn catching_syscalls(&self) -> bool {
+        self.catch_syscalls
+    }
+
+    /// Continues until breakpoint `id` has been hit `hits` times in total, stops at it before
+    /// then are skipped. Stopping for anything else ends it early.
+    pub fn continue_until(&mut self, id: u64, hits: u64) -> anyhow::Result<()> {
+        let Some(bp) = self.root.breakpoints().into_iter().find(|bp| bp.id == id) else {
+            anyhow::bail!("No breakpoint with ID {}", id);
+        };
+        if bp.hit_count() >= hits {
+            anyhow::bail!(
+                "Breakpoint {} has already been hit {} times",
+                id,
+                bp.hit_count()
+            );
+        }
+        self.continue_until = Some((id, hits));
+        self.cont()
+    }
+
+    /// Continues to a location with a one-off breakpoint that's removed when the process next
+    /// stops, whether or not it got there. An existing breakpoint at the location is kept.
+    pub fn continue_to(&mut self, location: &Location) -> anyhow::Result<()> {
+        if self.root.state() != State::Stopped {
+            anyhow::bail!("Process must be stopped to run to a location");
+        }
+        let existing = self
+            .root
+            .breakpoints()
+            .iter()
+            .map(|bp| bp.id)
+            .collect::<Vec<_>>();
+        let id = self.set_break(location)?;
+        self.run_to = Some((id, !existing.contains(&id)));
+        self.cont()
+    }
+
+    pub fn step(&mut self) -> anyhow::Result<()> {
+        Ok(self.root.step()?)
+    }
+
+    /// Steps over the current source line, running any functions it calls without stopping in
+    /// them unless they hit a breakpoint. Instructions are single stepped until the program
+    /// counter leaves the line, calls are run by continuing to a temporary breakpoint on the
+    /// return address. Without a line for the current address this steps over one instruction.
+    ///
+    /// After `max-step-instructions` single steps the rest of the line is run by continuing to
+    /// a breakpoint at the end of it instead, so a tight loop doesn't take forever.
+    pub fn step_over(&mut self) -> anyhow::Result<()> {
+        self.start_line_step(false)
+    }
+
+    /// Steps to the next source line, stopping at the start of any function it calls that has
+    /// line info. Calls to functions without any, like those in libc, are stepped over. Leaving
+    /// the current function stops in the caller, part way through its line.
+    ///
+    /// This is bounded by `max-step-instructions` the same as `step_over`.
+    pub fn step_line(&mut self) -> anyhow::Result<()> {
+        self.start_line_step(true)
+    }
+
+    fn start_line_step(&mut self, into_calls: bool) -> anyhow::Result<()> {
+        if self.root.state() != State::Stopped {
+            anyhow::bail!("Process must be stopped to step to the next line");
+        }
+        let ranges = match self.line_ranges(None) {
+            Ok((_, _, ranges)) => ranges,
+            Err(e) => {
+                debug!("Stepping one instruction: {}", e);
+                vec![]
+            }
+        };
+        self.advance_line_step(LineStep {
+            ranges,
+            into_calls,
+            called_from: None,
+            steps: 0,
+            resume_at: None,
+        })
+    }
+
+    /// Runs the next instruction of a line step, continuing over it if it's a call that's being
+    /// stepped over.
+    fn advance_line_step(&mut self, mut step: LineStep) -> anyhow::Result<()> {
+        let pc = self.root.pc()?;
+        if step.steps >= self.settings.max_step_instructions {
+            if let Some(end) = step.ranges.iter().find(|r| r.contains(&pc)).map(|r| r.end) {
+                warn!(
+                    "Stepped {} instructions without leaving the line, continuing to 0x{:x}",
+                    step.steps, end
+                );
+                step.resume_at = Some(self.add_step_breakpoint(end)?);
+                self.line_step = Some(step);
+                return self.cont();
+            }
+        }
+        step.steps += 1;
+        let code = self.root.read_code(pc, MAX_INSTRUCTION_LEN).data;
+        match disasm::decode(pc, &code) {
+            Ok(instruction) if instruction.is_call() && step.into_calls => {
+                step.called_from = Some(pc + instruction.len as u64);
+                self.line_step = Some(step);
+                self.step()
+            }
+            Ok(instruction) if instruction.is_call() => {
+                let return_address = pc + instruction.len as u64;
+                step.resume_at = Some(self.add_step_breakpoint(return_address)?);
+                self.line_step = Some(step);
+                self.cont()
+            }
+            decoded => {
+                if let Err(e) = decoded {
+                    warn!("Couldn't decode the instruction at 0x{:x}: {}", pc, e);
+                }
+                self.line_step = Some(step);
+                self.step()
+            }
+        }
+    }
+
+    /// Sets a temporary breakpoint for a line step to continue to, returning the address, ID and
+    /// whether it was added rather than already being there.
+    fn add_step_breakpoint(&mut self, addr: u64) -> anyhow::Result<(u64, u64, bool)> {
+        let added = self.root.breakpoint_at(addr).is_none();
+        let id = self.root.set_temporary_breakpoint(addr)?;
+        Ok((addr, id, added))
+    }
+
+    /// Carries on a line step once the step or continue it did has stopped. Returns true if the
+    /// process was stepped again so the stop shouldn't be reported. Anything other than the
+    /// step finishing, like hitting a breakpoint in a called function, ends the line step.
+    fn continue_line_step(&mut self, stop: &StopReason) -> anyhow::Result<bool> {
+        let Some(mut step) = self.line_step.take() else {
+            return Ok(false);
+        };
+        if stop.reason.is_closed() {
+            return Ok(false);
+        }
+        let pc = self.root.pc()?;
+        let finished_step = match step.resume_at.take() {
+            Some((addr, id, added)) => {
+                // Temporary breakpoints remove themselves when hit, this one needs removing if
+                // something else stopped the process first
+                if added && self.root.breakpoints().iter().any(|bp| bp.id == id) {
+                    self.delete_breakpoint(id)?;
+                }
+                added && stop.trap_reason == Some(TrapType::SoftwareBreak) && pc == addr
+            }
+            None => stop.trap_reason == Some(TrapType::SingleStep),
+        };
+        if !finished_step {
+            return Ok(false);
+        }
+        if let Some(return_address) = step.called_from.take() {
+            if self.source_location(pc).is_some() {
+                return Ok(false);
+            }
+            debug!("No line info for 0x{:x}, stepping over the call", pc);
+            step.resume_at = Some(self.add_step_breakpoint(return_address)?);
+            self.line_step = Some(step);
+            self.cont()?;
+            return Ok(true);
+        }
+        if !step.ranges.iter().any(|r| r.contains(&pc)) {
+            return Ok(false);
+        }
+        self.advance_line_step(step)?;
+        Ok(true)
+    }
+
+    /// Continues until the current function returns, using a temporary breakpoint on the return
+    /// address. Returns the return address. A recursive call returning to the same address
+    /// first stops there too.
+    pub fn finish(&mut self) -> anyhow::Result<u64> {
+        if self.root.state() != State::Stopped {
+            anyhow::bail!("Process must be stopped to finish a function");
+        }
+        let addr = self.return_address()?;
+        let existing = self.root.breakpoint_at(addr).is_some();
+        let id = self.root.set_temporary_breakpoint(addr)?;
+        self.finish = Some((addr, id, !existing));
+        self.cont()?;
+        Ok(addr)
+    }
+
+    /// Finds where the current function returns to. Before the function's prologue has pushed
+    /// the frame pointer the return address is on top of the stack, after that it's found by
+    /// walking the frame pointers.
+    fn return_address(&self) -> anyhow::Result<u64> {
+        let regs = self.root.get_gp_registers()?.regs;
+        let at_entry = self.elf.as_ref().is_some_and(|elf| {
+            let Some(elf_pc) = regs.rip.checked_sub(self.root.addr_offset) else {
+                return false;
+            };
+            elf.function_containing_address(elf_pc)
+                .ok()
+                .flatten()
+                .and_then(|f| elf.function_start(&f))
+                == Some(elf_pc)
+        });
+        if at_entry {
+            let data = self.root.read_memory(regs.rsp, 8)?;
+            return Ok(u64::from_le_bytes(data.try_into().unwrap_or_default()));
+        }
+        let backtrace = backtrace::walk(&self.root, 2)?;
+        match backtrace.frames.get(1) {
+            Some(caller) => Ok(caller.pc),
+            None => anyhow::bail!("Already in the outermost frame, there's nothing to return to"),
+        }
+    }
+
     pub fn get_registers(&self) -> anyhow::Result<Registers> {
         if self.root.state() != State::Stopped {
             anyhow::bail!(
@@ -119,28 +932,172 @@ impl DebuggerStateMachine {
         Ok(regs)
     }
 
+    /// Walks the stack of the stopped process, up to the `backtrace-limit` setting's frames.
+    pub fn backtrace(&self) -> anyhow::Result<Backtrace> {
+        if self.root.state() != State::Stopped {
+            anyhow::bail!(
+                "Process must be stopped to get a backtrace: {:?}",
+                self.root.state()
+            );
+        }
+        Ok(backtrace::walk(&self.root, self.settings.backtrace_limit)?)
+    }
+
+    /// Reads the arguments and environment the process was started with, see the `startup`
+    /// module for why the process has to be at its entry point.
+    pub fn startup_args(&self) -> anyhow::Result<StartupArgs> {
+        if self.root.state() != State::Stopped {
+            anyhow::bail!(
+                "Process must be stopped to read its arguments: {:?}",
+                self.root.state()
+            );
+        }
+        Ok(startup::read(&self.root)?)
+    }
+
+    /// Saves the registers and the given `(address, length)` memory ranges under a name,
+    /// replacing any snapshot already saved with that name.
+    pub fn save_snapshot(&mut self, name: &str, ranges: &[(u64, usize)]) -> anyhow::Result<()> {
+        if self.root.state() != State::Stopped {
+            anyhow::bail!(
+                "Process must be stopped to take a snapshot: {:?}",
+                self.root.state()
+            );
+        }
+        let snapshot = Snapshot::capture(&self.root, ranges)?;
+        self.snapshots.insert(name.to_string(), snapshot);
+        Ok(())
+    }
+
+    /// Compares the current registers and memory to a saved snapshot.
+    pub fn diff_snapshot(&self, name: &str) -> anyhow::Result<SnapshotDiff> {
+        if self.root.state() != State::Stopped {
+            anyhow::bail!(
+                "Process must be stopped to diff a snapshot: {:?}",
+                self.root.state()
+            );
+        }
+        let Some(snapshot) = self.snapshots.get(name) else {
+            anyhow::bail!("No snapshot named {}", name);
+        };
+        Ok(snapshot.diff(&self.root)?)
+    }
+
     pub fn set_break(&mut self, location: &Location) -> anyhow::Result<u64> {
-        match location {
-            Location::Address(addr) => {
-                let id = self.root.set_breakpoint(*addr)?;
-                Ok(id)
+        let addr = self.breakpoint_address(location)?;
+        self.check_instruction_boundary(location, addr);
+        Ok(self.root.set_breakpoint(addr)?)
+    }
+
+    /// Sets a breakpoint that's removed the first time it's hit. An existing breakpoint at the
+    /// location is kept as it is.
+    pub fn set_temp_break(&mut self, location: &Location) -> anyhow::Result<u64> {
+        let addr = self.breakpoint_address(location)?;
+        self.check_instruction_boundary(location, addr);
+        Ok(self.root.set_temporary_breakpoint(addr)?)
+    }
+
+    /// Sets a breakpoint in a debug register. There's no instruction boundary check as one in
+    /// the middle of an instruction never fires rather than corrupting it.
+    pub fn set_hardware_break(&mut self, location: &Location) -> anyhow::Result<u64> {
+        let addr = self.breakpoint_address(location)?;
+        Ok(self.root.set_hardware_breakpoint(addr)?)
+    }
+
+    /// Warns if a breakpoint at a raw address would land in the middle of an instruction, where
+    /// the interrupt corrupts it instead of trapping. Functions and lines always start on an
+    /// instruction so only addresses are checked.
+    fn check_instruction_boundary(&self, location: &Location, addr: u64) {
+        if !matches!(location, Location::Address(_)) {
+            return;
+        }
+        if let Some((start, instruction)) = self.instruction_containing(addr) {
+            warn!(
+                "0x{:x} is in the middle of the instruction at 0x{:x} ({} in {}), a breakpoint \
+                 there will corrupt it",
+                addr,
+                start,
+                instruction.text,
+                self.function_location(start)
+                    .unwrap_or_else(|| "unknown function".to_string())
+            );
+        }
+    }
+
+    /// Finds the instruction `addr` is part of and where it starts, if it isn't the start of one.
+    /// The instructions are decoded forward from the start of the function containing `addr`, if
+    /// there's no known function or the code doesn't decode this gives up and returns `None`.
+    fn instruction_containing(&self, addr: u64) -> Option<(u64, Decoded)> {
+        let start = match self
+            .vdso
+            .as_ref()
+            .and_then(|vdso| vdso.function_containing(addr))
+        {
+            Some((_, offset)) => addr - offset,
+            None => {
+                let elf = self.elf.as_ref()?;
+                let offset = self.root.addr_offset;
+                let elf_addr = addr.checked_sub(offset)?;
+                let function = elf.function_containing_address(elf_addr).ok()??;
+                // Each range of a split function starts on an instruction
+                elf.function_ranges(&function)
+                    .iter()
+                    .find(|range| range.contains(&elf_addr))?
+                    .start
+                    + offset
+            }
+        };
+        let len = (addr - start) as usize + MAX_INSTRUCTION_LEN;
+        let code = self.root.read_code(start, len).data;
+        let mut pc = start;
+        while pc < addr {
+            let offset = (pc - start) as usize;
+            match disasm::decode(pc, code.get(offset..)?) {
+                Ok(instruction) if pc + instruction.len as u64 > addr => {
+                    return Some((pc, instruction))
+                }
+                Ok(instruction) => pc += instruction.len as u64,
+                Err(e) => {
+                    debug!("Couldn't check 0x{:x} is on an instruction: {}", addr, e);
+                    return None;
+                }
             }
-            Location::Line { .. } => {
-                anyhow::bail!("Need to implement file+line breakpoint setting")
+        }
+        None
+    }
+
+    /// Resolves a location to the source line its code was compiled from.
+    pub fn source_location_of(&self, location: &Location) -> anyhow::Result<SourceLocation> {
+        let addr = self.breakpoint_address(location)?;
+        match self.source_location(addr) {
+            Some(loc) => Ok(loc),
+            None => anyhow::bail!("No source line for 0x{:x}", addr),
+        }
+    }
+
+    /// Address in the running process a breakpoint at the location goes.
+    fn breakpoint_address(&self, location: &Location) -> anyhow::Result<u64> {
+        match location {
+            Location::Address(addr) => Ok(*addr),
+            Location::Line { file, line } => {
+                let Some(elf) = self.elf.as_ref() else {
+                    anyhow::bail!("No elf file loaded");
+                };
+                let Some(addr) = elf.addresses_for_line(file, *line as u64)?.first().copied()
+                else {
+                    anyhow::bail!("No code found for {}:{}", file.display(), line);
+                };
+                Ok(addr + self.root.addr_offset)
             }
             Location::Function(fn_name) => {
                 if let Some(elf) = self.elf.as_ref() {
-                    let functions = elf.find_functions(&fn_name)?;
-
-                    for (unit, offset) in &functions {
-                        let die = unit.entry(*offset)?;
-                        let low_pc = die.attr_value(gimli::DW_AT_low_pc);
-                        let low_pc = match low_pc {
-                            Ok(Some(gimli::AttributeValue::Addr(x))) => x,
-                            _ => continue,
+                    let functions = elf.find_functions(fn_name)?;
+
+                    for function in &functions {
+                        let Some(low_pc) = elf.function_start(function) else {
+                            continue;
                         };
-                        let id = self.root.set_breakpoint(low_pc + self.root.addr_offset)?;
-                        return Ok(id);
+                        return Ok(low_pc + self.root.addr_offset);
                     }
                     anyhow::bail!("No function found we could attach a breakpoint to");
                 } else {
@@ -150,23 +1107,420 @@ impl DebuggerStateMachine {
         }
     }
 
-    pub fn list_breakpoints(&self) {
-        info!("Breakpoints: {:?}", self.root.breakpoints());
+    /// Decodes the whole of a function from the process's memory. Functions the compiler split
+    /// up are decoded a range at a time, the range with the entry point first.
+    pub fn disassemble_function(
+        &self,
+        name: &str,
+    ) -> anyhow::Result<Vec<(Range<u64>, Vec<DisassembledInstruction>)>> {
+        if self.root.state() != State::Stopped {
+            anyhow::bail!(
+                "Process must be stopped to read its code: {:?}",
+                self.root.state()
+            );
+        }
+        let Some(elf) = self.elf.as_ref() else {
+            anyhow::bail!("No elf file loaded");
+        };
+        let Some(ranges) = elf
+            .find_functions(name)?
+            .iter()
+            .map(|function| elf.function_ranges(function))
+            .find(|ranges| !ranges.is_empty())
+        else {
+            anyhow::bail!("No code found for function {}", name);
+        };
+        let offset = self.root.addr_offset;
+        let mut result = vec![];
+        for range in ranges {
+            let range = range.start + offset..range.end + offset;
+            let code = self
+                .root
+                .read_code(range.start, (range.end - range.start) as usize);
+            if let Some(fault) = code.fault {
+                warn!("Couldn't read code past 0x{:x}", fault);
+            }
+            let instructions = disasm::decode_all(range.start, &code.data)
+                .into_iter()
+                .map(|listed| {
+                    let decoded = listed.decoded.ok();
+                    DisassembledInstruction {
+                        addr: listed.addr,
+                        bytes: listed.bytes,
+                        flow: decoded.as_ref().map(|decoded| decoded.flow),
+                        text: decoded.map(|decoded| decoded.text),
+                        breakpoint: self
+                            .root
+                            .breakpoint_at(listed.addr)
+                            .is_some_and(|bp| bp.is_enabled()),
+                    }
+                })
+                .collect();
+            result.push((range, instructions));
+        }
+        Ok(result)
+    }
+
+    /// Parses the executable again, for when it's been rebuilt while debugging. For an attached
+    /// process this reads the executable the process is running from.
+    pub fn reload_symbols(&mut self) -> anyhow::Result<()> {
+        let path = if let Some(input) = self.args.input.as_ref() {
+            input.clone()
+        } else if let Some(pid) = self.args.pid {
+            PathBuf::from(format!("/proc/{}/exe", pid))
+        } else {
+            anyhow::bail!("No executable to reload symbols from");
+        };
+        let elf = ExecutableFile::reload_blocking(&path)?;
+        if let Some(offset) = runtime_address_offset(&self.root, &elf) {
+            self.root.addr_offset = offset;
+            info!("Using runtime address offset 0x{:x}", self.root.addr_offset);
+        }
+        if !self.root.breakpoints().is_empty() {
+            warn!("Existing breakpoints are left at their old addresses");
+        }
+        self.elf = Some(elf);
+        self.vdso = read_vdso(&self.root);
+        Ok(())
+    }
+
+    /// Sets a breakpoint which only stops the process when the condition holds. If there's
+    /// already a breakpoint at the location its condition is replaced.
+    pub fn set_conditional_break(
+        &mut self,
+        location: &Location,
+        condition: &Condition,
+    ) -> anyhow::Result<u64> {
+        // Resolve first so a bad condition doesn't leave a breakpoint behind
+        let condition = condition.resolve(self.elf.as_ref(), self.root.addr_offset)?;
+        let id = self.set_break(location)?;
+        if let Some(bp) = self.root.breakpoint_mut(id) {
+            bp.set_condition(Some(condition));
+        }
+        Ok(id)
+    }
+
+    /// Sets a breakpoint which only stops `thread`, other threads that hit it are continued.
+    pub fn set_thread_break(&mut self, location: &Location, thread: Pid) -> anyhow::Result<u64> {
+        if !self.root.threads()?.iter().any(|t| t.tid == thread) {
+            anyhow::bail!("No thread {} in the process", thread);
+        }
+        let id = self.set_break(location)?;
+        if let Some(bp) = self.root.breakpoint_mut(id) {
+            bp.set_thread(Some(thread));
+        }
+        Ok(id)
+    }
+
+    /// Sets a breakpoint which logs a message and continues each time it's hit.
+    pub fn set_dprintf(&mut self, location: &Location, dprintf: Dprintf) -> anyhow::Result<u64> {
+        let id = self.set_break(location)?;
+        self.dprintfs.insert(id, dprintf);
+        Ok(id)
+    }
+
+    pub fn delete_breakpoint(&mut self, id: u64) -> anyhow::Result<()> {
+        self.root.remove_breakpoint(id)?;
+        self.dprintfs.remove(&id);
+        Ok(())
+    }
+
+    /// Carries on past the next `count` hits of a breakpoint instead of stopping.
+    pub fn ignore_breakpoint(&mut self, id: u64, count: u64) -> anyhow::Result<()> {
+        Ok(self.root.ignore_breakpoint(id, count)?)
+    }
+
+    /// Flips whether a breakpoint is enabled, returning the new state.
+    pub fn toggle_breakpoint(&mut self, id: u64) -> anyhow::Result<bool> {
+        Ok(self.root.toggle_breakpoint(id)?)
+    }
+
+    /// Resolves an address in the running process to the function containing it, formatted
+    /// like `main+0x4`. Functions in the vDSO are found too.
+    pub fn function_location(&self, addr: u64) -> Option<String> {
+        if let Some((name, offset)) = self
+            .vdso
+            .as_ref()
+            .and_then(|vdso| vdso.function_containing(addr))
+        {
+            return match offset {
+                0 => Some(name.to_string()),
+                offset => Some(format!("{}+0x{:x}", name, offset)),
+            };
+        }
+        let elf = self.elf.as_ref()?;
+        let elf_addr = addr.checked_sub(self.root.addr_offset)?;
+        let function = elf.function_containing_address(elf_addr).ok()??;
+        let name = elf.function_name(&function)?;
+        match elf.function_start(&function) {
+            Some(start) if start < elf_addr => Some(format!("{}+0x{:x}", name, elf_addr - start)),
+            _ => Some(name),
+        }
+    }
+
+    /// The signature of the function starting at an address in the running process, like
+    /// `add(a: int, b: int) -> int`. `None` unless `addr` is the start of a function with debug
+    /// info, so only breakpoints on functions get one.
+    pub fn function_signature(&self, addr: u64) -> Option<String> {
+        let elf = self.elf.as_ref()?;
+        let elf_addr = addr.checked_sub(self.root.addr_offset)?;
+        let function = elf.function_containing_address(elf_addr).ok()??;
+        (elf.function_start(&function)? == elf_addr).then(|| elf.function_signature(&function))
+    }
+
+    /// Resolves an address in the running process to the source line it was compiled from.
+    pub fn source_location(&self, addr: u64) -> Option<SourceLocation> {
+        let elf = self.elf.as_ref()?;
+        let elf_addr = addr.checked_sub(self.root.addr_offset)?;
+        elf.line_for_address(elf_addr).ok()?
+    }
+
+    /// Adds a stop to the stop history, stops where the process has exited or the PC can't be
+    /// read are ignored.
+    pub fn record_stop(&mut self, stop: &StopReason) {
+        if stop.reason != State::Stopped {
+            return;
+        }
+        let Ok(pc) = self.root.pc() else {
+            return;
+        };
+        if self.stop_history.len() == STOP_HISTORY_LEN {
+            self.stop_history.pop_front();
+        }
+        let record = StopRecord {
+            pc,
+            function: self.function_location(pc),
+            source_loc: self.source_location(pc),
+            trap_reason: stop.trap_reason,
+        };
+        self.stop_history.push_back(record);
+    }
+
+    /// Recent stops, most recent first.
+    pub fn stop_history(&self) -> impl Iterator<Item = &StopRecord> {
+        self.stop_history.iter().rev()
+    }
+
+    /// Calls a function in the process with integer arguments and returns what it returned in
+    /// `rax`. The process is left stopped where it was, see the `call` module for the details.
+    pub fn call_function(&mut self, name: &str, args: &[u64]) -> anyhow::Result<u64> {
+        let Some(elf) = self.elf.as_ref() else {
+            anyhow::bail!("No elf file loaded");
+        };
+        let Some(start) = elf
+            .find_functions(name)?
+            .iter()
+            .find_map(|f| elf.function_start(f))
+        else {
+            anyhow::bail!("No function named {}", name);
+        };
+        let offset = self.root.addr_offset;
+        let return_address = elf.entry_point() + offset;
+        Ok(call::call(
+            &mut self.root,
+            start + offset,
+            return_address,
+            args,
+            self.settings.unwind_on_signal,
+        )?)
+    }
+
+    /// Assembles an instruction and writes it into the process at `addr`. Fails if the
+    /// instruction would run past the end of the function `addr` is in, and warns if it
+    /// overwrites the start of another instruction. Addresses outside the executable, like in a
+    /// library, aren't checked.
+    pub fn assemble(&mut self, addr: u64, instruction: &Instruction) -> anyhow::Result<Patch> {
+        let bytes = instruction.encode(addr)?;
+        let Some(end) = addr.checked_add(bytes.len() as u64) else {
+            anyhow::bail!(
+                "{} byte instruction at 0x{:x} is past the end of memory",
+                bytes.len(),
+                addr
+            );
+        };
+        let offset = self.root.addr_offset;
+        let elf_range = addr.checked_sub(offset).zip(end.checked_sub(offset));
+        if let (Some(elf), Some((elf_addr, elf_end))) = (self.elf.as_ref(), elf_range) {
+            let range = elf.function_containing_address(elf_addr)?.and_then(|f| {
+                let name = elf.function_name(&f).unwrap_or_default();
+                elf.function_ranges(&f)
+                    .into_iter()
+                    .find(|r| r.contains(&elf_addr))
+                    .map(|r| (name, r))
+            });
+            if let Some((name, range)) = range {
+                if elf_end > range.end {
+                    anyhow::bail!(
+                        "{} byte instruction at 0x{:x} runs past the end of {} at 0x{:x}",
+                        bytes.len(),
+                        addr,
+                        name,
+                        range.end + offset
+                    );
+                }
+            }
+            let clobbered = elf.line_rows_in(elf_addr + 1..elf_end)?;
+            if let Some(next) = clobbered.first() {
+                warn!(
+                    "Writing {} bytes at 0x{:x} overwrites the instruction at 0x{:x}",
+                    bytes.len(),
+                    addr,
+                    next + offset
+                );
+            }
+        }
+        let old = self.root.read_memory(addr, bytes.len())?;
+        self.root.write_memory(addr, &bytes)?;
+        Ok(Patch {
+            addr,
+            old,
+            new: bytes,
+        })
+    }
+
+    /// Reads a global variable and formats it using its type from the debug info, structs are
+    /// spread across lines if the print pretty setting is on.
+    pub fn print_variable(&self, name: &str) -> anyhow::Result<String> {
+        let Some(elf) = self.elf.as_ref() else {
+            anyhow::bail!("No elf file loaded");
+        };
+        let Some((variable, ty)) = elf.global_variable_with_type(name)? else {
+            anyhow::bail!("No global variable named {}", name);
+        };
+        // Only as much of a long array as is printed is read
+        let data = self.root.read_memory(
+            variable.address + self.root.addr_offset,
+            ty.printed_size() as usize,
+        )?;
+        Ok(value::format_value(&ty, &data, self.settings.print_pretty))
+    }
+
+    /// Which compilers and languages produced the debug info.
+    pub fn compilers(&self) -> anyhow::Result<Vec<CompilerInfo>> {
+        let Some(elf) = self.elf.as_ref() else {
+            anyhow::bail!("No elf file loaded");
+        };
+        Ok(elf.compilers())
+    }
+
+    /// Compares the functions in the program against another executable.
+    pub fn diff_symbols(&self, other: &Path) -> anyhow::Result<FunctionDiff> {
+        let Some(elf) = self.elf.as_ref() else {
+            anyhow::bail!("No elf file loaded");
+        };
+        Ok(elf.diff_functions(&ExecutableFile::load_blocking(other)?))
+    }
+
+    /// Works out the value of an expression in the stopped process.
+    pub fn evaluate(&self, expr: &Expr) -> anyhow::Result<u64> {
+        if self.root.state() != State::Stopped {
+            anyhow::bail!(
+                "Process must be stopped to evaluate an expression: {:?}",
+                self.root.state()
+            );
+        }
+        Ok(expr.evaluate(&self.root, self.elf.as_ref())?)
+    }
+
+    /// The breakpoints in the order they were added.
+    pub fn list_breakpoints(&self) -> Vec<BreakpointInfo> {
+        self.root
+            .breakpoints()
+            .into_iter()
+            .map(|bp| BreakpointInfo {
+                id: bp.id,
+                addr: bp.pc,
+                enabled: bp.is_enabled(),
+                hit_count: bp.hit_count(),
+                ignore_count: bp.ignore_count(),
+                temporary: bp.is_temporary(),
+                source_loc: self.source_location(bp.pc),
+                condition: bp.condition().map(|c| c.to_string()),
+                thread: bp.thread(),
+                hardware: bp.is_hardware(),
+            })
+            .collect()
+    }
+
+    /// Finds the address ranges of the code for a source line, as the line a location is on or
+    /// where the process is stopped if there's no location. Returns the file and line with the
+    /// ranges as addresses in the running process.
+    pub fn line_ranges(
+        &self,
+        location: Option<&Location>,
+    ) -> anyhow::Result<(PathBuf, usize, Vec<Range<u64>>)> {
+        let Some(elf) = self.elf.as_ref() else {
+            anyhow::bail!("No elf file loaded");
+        };
+        let offset = self.root.addr_offset;
+        let (file, line) = match location {
+            Some(Location::Line { file, line }) => (file.clone(), *line),
+            Some(Location::Function(name)) => {
+                let addr = elf.get_address(Location::Function(name.clone()))?;
+                self.line_at(elf, addr)?
+            }
+            Some(Location::Address(addr)) => {
+                let Some(addr) = addr.checked_sub(offset) else {
+                    anyhow::bail!("0x{:x} isn't in the executable", addr);
+                };
+                self.line_at(elf, addr)?
+            }
+            None => self.line_at(elf, self.root.pc()?.saturating_sub(offset))?,
+        };
+        let ranges = elf
+            .address_ranges_for_line(&file, line as u64)?
+            .into_iter()
+            .map(|range| range.start + offset..range.end + offset)
+            .collect();
+        Ok((file, line, ranges))
+    }
+
+    fn line_at(&self, elf: &ExecutableFile, addr: u64) -> anyhow::Result<(PathBuf, usize)> {
+        match elf.location_for_address(addr) {
+            Some((file, line, _)) => Ok((file, line)),
+            None => anyhow::bail!("No source line for 0x{:x}", addr + self.root.addr_offset),
+        }
     }
 
     pub fn log_status(&self) {
         let state = self.root.state();
         if state == State::Stopped {
             if let Ok(addr) = self.root.pc() {
-                info!("Root process is stopped at {:x}", addr);
+                let location = self.elf.as_ref().and_then(|elf| {
+                    elf.location_for_address(addr.checked_sub(self.root.addr_offset)?)
+                });
+                match location {
+                    Some((file, line, Some(function))) => info!(
+                        "Root process is stopped at {}:{} in {} (0x{:x})",
+                        file.display(),
+                        line,
+                        function,
+                        addr
+                    ),
+                    Some((file, line, None)) => info!(
+                        "Root process is stopped at {}:{} (0x{:x})",
+                        file.display(),
+                        line,
+                        addr
+                    ),
+                    None => info!("Root process is stopped at {:x}", addr),
+                }
             } else {
                 info!("Root process is stopped at an unknown place");
             }
+            if let Some(signal) = self.root.current_signal() {
+                info!("Pending signal: {}", signal);
+            }
         } else {
             info!("Root process is {:?}", state);
         }
     }
 
+    /// The vDSO mapped into the process, if it has one and it could be read.
+    pub fn vdso(&self) -> Option<&Vdso> {
+        self.vdso.as_ref()
+    }
+
     pub fn root_process(&self) -> &Process {
         &self.root
     }
@@ -178,6 +1532,67 @@ impl DebuggerStateMachine {
     pub fn has_elf_file(&self) -> bool {
         self.elf.is_some()
     }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        &mut self.settings
+    }
+
+    /// Replaces the settings, passing on the ones the process itself uses. Settings only used
+    /// when a process is started don't take effect until the next one.
+    pub fn apply_settings(&mut self, settings: Settings) {
+        self.root
+            .set_verify_breakpoints(settings.verify_breakpoints);
+        self.root
+            .set_nopass_signals(settings.nopass_signals.clone());
+        self.root.set_follow_fork(settings.follow_fork);
+        self.settings = settings;
+    }
+}
+
+/// Works out how far the executable was moved when it was loaded, from its mappings in `/proc`.
+/// If those can't be read it's worked out from the entry point instead, see
+/// `offset_from_entry`.
+fn runtime_address_offset(process: &Process, elf: &ExecutableFile) -> Option<u64> {
+    if let Some(mapped_address) = process.mapped_address() {
+        return Some(elf.runtime_address_offset(mapped_address));
+    }
+    if process.proc_accessible() {
+        // Readable but the executable isn't mapped under its own path
+        return None;
+    }
+    let offset = offset_from_entry(process, elf);
+    match offset {
+        Some(_) => info!("Worked out the load address from the entry point instead of /proc"),
+        None => warn!(
+            "Couldn't work out the load address from the entry point either, the process needs \
+             to be at its entry point"
+        ),
+    }
+    offset
+}
+
+/// Works out how far the executable was moved when it was loaded from the entry point the
+/// kernel passes in the auxv. This doesn't need `/proc` but the auxv can only be found while
+/// the process is stopped at its entry point, like straight after it's launched.
+pub fn offset_from_entry(process: &Process, elf: &ExecutableFile) -> Option<u64> {
+    let entry = startup::read(process).ok()?.entry()?;
+    Some(entry.wrapping_sub(elf.entry_point()))
+}
+
+/// Reads the vDSO's symbols, a process without them can still be debugged so failures are only
+/// logged.
+fn read_vdso(process: &Process) -> Option<Vdso> {
+    match Vdso::read(process) {
+        Ok(vdso) => vdso,
+        Err(e) => {
+            warn!("Couldn't read the vDSO: {}", e);
+            None
+        }
+    }
 }
 
 #[cfg(test)]
@@ -189,14 +1604,130 @@ mod tests {
         let args = Args {
             input: Some("i-am-not-a-real-program-you-cannot-run-me".into()),
             pid: None,
+            ..Default::default()
         };
         let sm = DebuggerStateMachine::start(args);
         assert!(sm.is_err());
     }
 
     #[test]
-    #[should_panic]
-    fn panic_if_starting_nothing() {
-        let _ = DebuggerStateMachine::start(Args::default());
+    fn load_address_from_entry_point_without_proc() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            ..Default::default()
+        };
+        let expected = DebuggerStateMachine::start(args.clone())
+            .unwrap()
+            .root
+            .addr_offset;
+        assert_ne!(expected, 0);
+
+        process::FAIL_PROC_READS.with(|fail| fail.set(true));
+        let sm = DebuggerStateMachine::start(args);
+        let mapped = sm.as_ref().ok().and_then(|sm| sm.root.mapped_address());
+        process::FAIL_PROC_READS.with(|fail| fail.set(false));
+        let sm = sm.unwrap();
+        assert_eq!(mapped, None);
+        assert_eq!(sm.root.addr_offset, expected);
+    }
+
+    #[test]
+    fn error_if_starting_nothing() {
+        let sm = DebuggerStateMachine::start(Args::default());
+        assert!(sm.is_err());
+    }
+
+    #[test]
+    fn input_and_pid_are_exclusive() {
+        let err = Args::try_parse_from(["rustybug", "./my_program", "--pid", "5"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+
+        let args = Args::try_parse_from(["rustybug"]).unwrap();
+        assert!(!args.has_target());
+
+        let args = Args::try_parse_from(["rustybug", "./my_program"]).unwrap();
+        assert!(args.has_target());
+        assert_eq!(args.input, Some(PathBuf::from("./my_program")));
+        assert_eq!(args.pid, None);
+
+        let args = Args::try_parse_from(["rustybug", "-p", "5"]).unwrap();
+        assert_eq!(args.input, None);
+        assert_eq!(args.pid, Some(5));
+    }
+
+    #[test]
+    fn build_info() {
+        assert_eq!(BUILD_INFO.version, env!("CARGO_PKG_VERSION"));
+        assert!(BUILD_INFO.target.contains("linux"), "{}", BUILD_INFO.target);
+        let banner = BUILD_INFO.to_string();
+        assert!(banner.starts_with(&format!("rustybug {} (", BUILD_INFO.version)));
+        assert_eq!(
+            BUILD_INFO.features.contains(&"tokio"),
+            cfg!(feature = "tokio")
+        );
+
+        let err = Args::try_parse_from(["rustybug", "--version"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::DisplayVersion);
+    }
+
+    #[test]
+    fn trailing_program_args() {
+        let args =
+            Args::try_parse_from(["rustybug", "./my_program", "--", "-v", "--pid", "3", "--"])
+                .unwrap();
+        assert_eq!(args.input, Some(PathBuf::from("./my_program")));
+        assert_eq!(args.pid, None);
+        assert_eq!(args.program_args, ["-v", "--pid", "3", "--"]);
+
+        let args = Args::try_parse_from(["rustybug", "./my_program"]).unwrap();
+        assert!(args.program_args.is_empty());
+
+        // Without the `--` the program's flags are ours
+        let err = Args::try_parse_from(["rustybug", "./my_program", "-v"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::UnknownArgument);
+
+        // Arguments only make sense for a program we launch
+        let err = Args::try_parse_from(["rustybug", "-p", "5", "--", "-v"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+
+        let args = Args::try_parse_from(["rustybug", "--run", "./a", "--", "-r"]).unwrap();
+        assert!(args.run);
+        assert_eq!(args.program_args, ["-r"]);
+
+        let mut args = Args::try_parse_from(["rustybug", "./a", "--", "x"]).unwrap();
+        args.set_input("./b".into());
+        assert_eq!(args.program_args, ["x"]);
+        args.set_pid(5);
+        assert!(args.program_args.is_empty());
+    }
+
+    #[test]
+    fn environment_variables() {
+        let mut args = Args::try_parse_from([
+            "rustybug",
+            "--env",
+            "A=1",
+            "--env=B=x=y",
+            "--clean-env",
+            "./a",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.environment(),
+            [
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "x=y".to_string())
+            ]
+        );
+        args.set_env("A".to_string(), "2".to_string());
+        assert_eq!(args.environment()[1], ("A".to_string(), "2".to_string()));
+        assert_eq!(args.environment().len(), 2);
+
+        let err = Args::try_parse_from(["rustybug", "--env", "=1", "./a"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+
+        // Without --clean-env our environment is passed on
+        let args = Args::try_parse_from(["rustybug", "./a"]).unwrap();
+        assert_eq!(args.environment().len(), std::env::vars().count());
     }
 }
@@ -1,51 +1,93 @@
 use crate::ptrace_control::*;
 use nix::errno::Errno;
-use nix::fcntl::OFlag;
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
 use nix::sys::personality;
 use nix::unistd::*;
 use std::ffi::{CStr, CString};
-use std::io;
-use std::os::fd::OwnedFd;
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
 use std::path::Path;
-use std::process::Command;
 use tracing::warn;
 
 pub struct LaunchedProcess {
     pub pid: Pid,
+    pub stdin_writer: Option<OwnedFd>,
     pub stdout_reader: Option<OwnedFd>,
+    pub stderr_reader: Option<OwnedFd>,
 }
 
-/// This is in nix but not yet released on crates.io so should be able to remove it in 0.30.0
+/// Makes `target` a copy of `fd`. nix 0.30 has `dup2_stdin`, `dup2_stdout` and `dup2_stderr`
+/// for this so it can go once we're on that.
 #[inline]
-pub fn dup2_stdout<Fd: std::os::fd::AsFd>(fd: Fd) -> Result<(), Errno> {
-    use libc::STDOUT_FILENO;
-    use std::os::fd::AsRawFd;
-
-    let res = unsafe { libc::dup2(fd.as_fd().as_raw_fd(), STDOUT_FILENO) };
+pub fn dup2_fd<Fd: std::os::fd::AsFd>(fd: Fd, target: RawFd) -> Result<(), Errno> {
+    let res = unsafe { libc::dup2(fd.as_fd().as_raw_fd(), target) };
     Errno::result(res).map(drop)
 }
 
+/// Pipe for capturing one of the child's outputs. Both ends are closed on exec, dup2 doesn't
+/// copy the flag so the child's end survives once it's been dup'd. Only our end is
+/// non-blocking so polling for output doesn't hang, the child gets a normal blocking file.
+fn output_pipe() -> nix::Result<(OwnedFd, OwnedFd)> {
+    let (read, write) = pipe2(OFlag::O_CLOEXEC)?;
+    fcntl(read.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+    Ok((read, write))
+}
+
 /// Returns the coverage statistics for a test executable in the given workspace
-pub fn launch_program(exe: &Path) -> anyhow::Result<Option<LaunchedProcess>> {
+///
+/// `args` are the arguments after the program name, `argv[0]` is the path to the program. `env`
+/// is the program's whole environment and `cwd` the directory it starts in.
+pub fn launch_program(
+    exe: &Path,
+    args: &[String],
+    env: &[(String, String)],
+    cwd: Option<&Path>,
+) -> anyhow::Result<Option<LaunchedProcess>> {
     if !exe.exists() {
         warn!("Test at {} doesn't exist", exe.display());
         return Ok(None);
     }
+    // A relative path would be looked up from the new working directory after the chdir
+    let program = match cwd {
+        Some(_) => exe.canonicalize()?,
+        None => exe.to_path_buf(),
+    };
 
-    let (read, write) = pipe2(OFlag::O_CLOEXEC)?;
+    // Unlike the output pipes our end blocks, writes only wait if the child isn't reading
+    let (in_read, in_write) = pipe2(OFlag::O_CLOEXEC)?;
+    let (read, write) = output_pipe()?;
+    let (err_read, err_write) = output_pipe()?;
 
     unsafe {
         match fork() {
-            Ok(ForkResult::Parent { child }) => Ok(Some(LaunchedProcess {
-                pid: child,
-                stdout_reader: Some(read),
-            })),
+            Ok(ForkResult::Parent { child }) => {
+                // Otherwise we'd hold the pipes open and never see EOF when the child exits
+                std::mem::drop(in_read);
+                std::mem::drop(write);
+                std::mem::drop(err_write);
+                Ok(Some(LaunchedProcess {
+                    pid: child,
+                    stdin_writer: Some(in_write),
+                    stdout_reader: Some(read),
+                    stderr_reader: Some(err_read),
+                }))
+            }
             Ok(ForkResult::Child) => {
+                std::mem::drop(in_write);
                 std::mem::drop(read);
-                /*if let Err(e) = dup2_stdout(&write) {
-                    warn!("Failed to redirect stdout");
-                }*/
-                execute(exe, &[], &[])?;
+                std::mem::drop(err_read);
+                if let Err(e) = dup2_fd(&in_read, libc::STDIN_FILENO) {
+                    warn!("Failed to redirect stdin: {}", e);
+                }
+                if let Err(e) = dup2_fd(&write, libc::STDOUT_FILENO) {
+                    warn!("Failed to redirect stdout: {}", e);
+                }
+                if let Err(e) = dup2_fd(&err_write, libc::STDERR_FILENO) {
+                    warn!("Failed to redirect stderr: {}", e);
+                }
+                let argv = std::iter::once(exe.display().to_string())
+                    .chain(args.iter().cloned())
+                    .collect::<Vec<_>>();
+                execute(&program, &argv, env, cwd)?;
                 Ok(None)
             }
             Err(err) => anyhow::bail!("Failed to run test {}, Error: {}", exe.display(), err),
@@ -65,7 +107,12 @@ fn is_aslr_enabled() -> bool {
 }
 
 #[cfg(not(tarpaulin_include))]
-pub fn execute(test: &Path, argv: &[String], envar: &[(String, String)]) -> anyhow::Result<Pid> {
+pub fn execute(
+    test: &Path,
+    argv: &[String],
+    envar: &[(String, String)],
+    cwd: Option<&Path>,
+) -> anyhow::Result<Pid> {
     let program = CString::new(test.display().to_string()).unwrap_or_default();
     if let Err(e) = setpgid(Pid::from_raw(0), Pid::from_raw(0)) {
         warn!("Failed to set pgid: {}", e);
@@ -91,6 +138,9 @@ pub fn execute(test: &Path, argv: &[String], envar: &[(String, String)]) -> anyh
     // TODO if I wanted to be _extra_ try hard for tarpaulin I'd see if I could manually trigger a
     // saving of a profraw file here before execve!
 
+    if let Some(cwd) = cwd {
+        chdir(cwd)?;
+    }
     execve(&program, &arg_ref, &env_ref)?;
 
     unreachable!();
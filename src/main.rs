@@ -1,66 +1,213 @@
 use anyhow::Result;
 use clap::Parser;
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+    KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use crossterm::execute;
 use ratatui::{
     buffer::Buffer,
-    layout::{Constraint, Flex, Layout, Rect},
+    layout::{Constraint, Flex, Layout, Position, Rect},
     style::{Style, Stylize},
     symbols::border,
     text::{Line, Span, Text},
-    widgets::{Block, Clear, Paragraph, Widget},
+    widgets::{Block, Clear, Paragraph, Row, Table, Widget},
     DefaultTerminal, Frame,
 };
 use rustybug::{
-    commands::{Command, Expression},
-    Args, DebuggerStateMachine,
+    address::AddressError,
+    backtrace::Truncated,
+    commands::{Command, DisplayExpr, Expression, InfoKind, Location},
+    disasm::Flow,
+    elf::{ExecutableFile, SourceLocation},
+    output::{OutputBuffer, Stream},
+    process::{Info, ProcessError, StopReason, TrapType},
+    settings::Settings,
+    Args, DebuggerStateMachine, State, BUILD_INFO,
 };
-use std::collections::VecDeque;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io::stdout;
+use std::ops::Range;
+use std::panic;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use tracing::{error, info, warn};
-use tracing_subscriber::{self, layer::SubscriberExt, util::SubscriberInitExt};
-use tui_logger::{TuiLoggerLevelOutput, TuiLoggerWidget};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::{self, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tui_logger::{TuiLoggerLevelOutput, TuiLoggerWidget, TuiWidgetEvent, TuiWidgetState};
 
 const HELP_TEXT: &str = "Rustybug
 This is a simple debugger mainly for playing with ptrace. But being a debugger there are
 some commands to learn:
 
 attach <PID>       Attach to the given PID for debugging
-load <PATH>        Loads the given program and starts debugging it. TODO args
+detach             Remove breakpoints and leave the process running without the debugger
+load <PATH> [ARGS...]
+                   Loads the given program and starts debugging it, passing it ARGS
 restart            Restart the program/attached pid you launched rustybug with
-print              Print a given expression (currently only accepts 'registers')
+env KEY=VALUE      Set an environment variable for the next launch, env alone lists them
+cwd [DIR]          Set the directory the program is launched in, cwd alone shows it
+print <EXPR>       Print 'registers', a global variable or arithmetic like rsp + 8 or *rbp
+display <EXPR>     Print an expression, or x <ADDR> [LEN], every time the process stops
+undisplay <N>      Stop showing display <N>, info display lists them
 break <LOCATION>   Add a breakpoint at a given location - either an <ADDR> or <FILE> <LINE>
+break <LOCATION> if <CONDITION>
+                   Add a breakpoint that only stops when the condition holds e.g. rax == 0
+break <LOCATION> thread <TID>
+                   Add a breakpoint that only stops the given thread
+run-to <LOCATION>  Continue to a location with a breakpoint that's removed afterwards
+tbreak <LOCATION>  Add a breakpoint that's removed after it's hit once
+hbreak <LOCATION>  Add a breakpoint using a debug register, up to 4 at once
 l list             List all breakpoints
+list <LOCATION>    Show listsize lines of source around a location
+continue until <ID> hits <N>
+                   Continue until breakpoint <ID> has been hit <N> times
+finish             Continue until the current function returns
+n next             Step over the current source line
+s step             Step to the next source line, into called functions
+si stepi           Step a single instruction
+continue &         Continue in the background, batch commands don't wait for it to stop
+interrupt          Stop the running process, the same as CTRL+C
+input [TEXT]       Send a line of text to the program's stdin
+signal <SIGNAL>    Continue delivering a signal, signal 0 continues without one
+handle <SIGNAL> pass|nopass
+                   Choose whether a signal the process stops with is delivered to it
+catch syscall      Toggle stopping at every syscall entry and exit, logging it like strace
+wait [SECONDS]     Report the process stopping without resuming it
+delete <ID>        Remove the breakpoint with the given ID
+ignore <ID> <N>    Don't stop at the breakpoint for its next <N> hits
+trace start <FILE> Single step the program writing every instruction and its line to <FILE>
+trace-record <FILE>
+                   Same as trace start
+trace stop         Stop an instruction trace
+set <NAME> <VALUE> Change a setting
+show <NAME>        Show a setting, or all settings with show all
+reload-symbols     Re-read the debug info after rebuilding the program
+diff-symbols <FILE>
+                   List functions added, removed or resized compared to another executable
+thread list        List the threads in the process
+bt backtrace       Show the call stack, up to backtrace-limit frames
+stops [N]          List recent stops, or show stop N in the source view
+dprintf <LOCATION>, \"<FORMAT>\", <OPERAND>...
+                   Log a formatted message every time <LOCATION> is reached
+snapshot save <NAME> [<ADDR> <LEN>]...
+                   Save the registers and some memory to compare against later
+snapshot diff <NAME>
+                   Show what's changed since a snapshot was saved
+call <FN>(<ARGS>)  Call a function in the process with integer arguments
+x <ADDR> [LEN]     Show LEN bytes of memory (64 by default) as hex and ASCII
+disas <FUNCTION>   Decode every instruction in a function, also disassemble <FUNCTION>
+asm <ADDR> <INSTR> Assemble an instruction and write it at <ADDR> e.g. asm 0x401000 nop
+info version       Show the rustybug version, target and features for bug reports
+info registers [GROUP]
+                   Show all registers or a group: general, segment or flags
+info inferior-args Show the program's arguments and environment, when stopped at the start
+info line [LOC]    Show the address ranges of the code for a line
+info compiler      Show the compilers and languages the debug info came from
 logs               Show the debug logs
+breakpoints        Show the breakpoints panel
+output             Show the program output panel
 q quit             Quit rustybuy
 ? help             Show this message
 
+Use <COMMAND> --help for more detail on a command. Scroll the source and logs with the mouse
+wheel, click a source line to add or remove a breakpoint on it and click a breakpoint in the
+breakpoints panel to enable/disable it.
+
 Press any key to dismiss this message.
 ";
 
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How often a batch checks whether the process has stopped yet
+const BATCH_POLL: Duration = Duration::from_millis(5);
+/// How long a batch waits for the process to stop before running the next command anyway
+const BATCH_STOP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Lines moved per mouse wheel tick in the source view
+const SCROLL_LINES: usize = 3;
+
 fn main() -> anyhow::Result<()> {
-    init_logging()?;
     let args = Args::parse();
+    init_logging(args.batch.is_some())?;
+    info!("{}", BUILD_INFO);
+
+    if let Some(script) = args.batch.clone() {
+        let timings = args.timings;
+        let settings = Settings {
+            auto_continue: args.run,
+            // Nobody is there to answer
+            confirm: false,
+            pagination: false,
+            ..Default::default()
+        };
+        let mut app = App {
+            args,
+            settings,
+            ..Default::default()
+        };
+        let report = app.run_batch(&script)?;
+        if timings {
+            print!("{}", report);
+        }
+        return Ok(());
+    }
 
+    install_panic_hook();
     let mut terminal = ratatui::init();
     let _ = terminal.hide_cursor();
+    if let Err(e) = execute!(stdout(), EnableMouseCapture) {
+        warn!("Mouse support unavailable: {}", e);
+    }
 
+    let settings = Settings {
+        auto_continue: args.run,
+        ..Default::default()
+    };
     let mut app = App {
         args,
+        settings,
         show_logs: true,
-        history_len: 10,
         ..Default::default()
     };
     if let Err(e) = app.run(&mut terminal) {
-        ratatui::restore();
+        restore_terminal();
         eprintln!("{}", e);
     } else {
-        ratatui::restore()
+        restore_terminal();
     }
 
     Ok(())
 }
 
-fn init_logging() -> Result<()> {
+/// A panic in the middle of a session would otherwise leave the terminal in raw mode with the
+/// panic message mangled, so restore it before the default hook prints the message.
+fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_hook(info);
+    }));
+}
+
+/// Puts the terminal back how we found it, mouse capture isn't undone by ratatui so without
+/// disabling it the shell would be flooded with escape codes on mouse movement.
+fn restore_terminal() {
+    let _ = execute!(stdout(), DisableMouseCapture);
+    ratatui::restore();
+}
+
+fn init_logging(batch: bool) -> Result<()> {
+    if batch {
+        // No UI to show the logs in so they go to stderr, leaving stdout for the timings
+        tracing_subscriber::registry()
+            .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+            .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+            .init();
+        return Ok(());
+    }
     tracing_subscriber::registry()
         .with(tui_logger::tracing_subscriber_layer())
         .init();
@@ -68,50 +215,514 @@ fn init_logging() -> Result<()> {
     Ok(())
 }
 
-#[derive(Debug, Default)]
+#[derive(Default)]
 pub struct App {
     args: Args,
     exit: bool,
     show_help: bool,
     show_logs: bool,
+    show_breakpoints: bool,
+    show_output: bool,
+    /// Breakpoint table rows, refreshed after commands and stops rather than on every draw as
+    /// resolving the locations isn't free
+    breakpoint_rows: Vec<[String; 5]>,
+    /// Source file for where the process is stopped
+    source: Option<SourceView>,
+    /// Lines in the source view with a breakpoint on them
+    source_breakpoints: HashSet<u64>,
     current_command: String,
-    history_len: usize,
-    current_stdout: String,
+    settings: Settings,
+    /// What the program has written, shown in the output panel
+    output: OutputBuffer,
+    /// Lines the output panel is scrolled up from the newest output
+    output_scroll: usize,
     debugger: Option<DebuggerStateMachine>,
     command_history: VecDeque<String>,
     history_index: Option<usize>,
+    /// Scroll state of the logs panel
+    log_state: TuiWidgetState,
+    /// Size of the last frame drawn, used to work out which panel a mouse event landed in
+    last_area: Cell<Rect>,
+    /// Command that needs confirming, it runs if it's entered again straight away
+    pending_confirmation: Option<Command>,
+    /// The process was continued with `continue &` so batch commands don't wait for it to stop
+    background: bool,
+    /// Expressions shown on every stop keyed by their number
+    displays: BTreeMap<u64, DisplayExpr>,
+    /// Number given to the next display
+    next_display: u64,
+    /// Long command output being shown a page at a time
+    pager: Option<Pager>,
+    /// How the last process ended, kept from its state machine as that's dropped once it's gone
+    exit_status: Option<Info>,
+}
+
+/// Command output too long for one screen, shown over the main view a page at a time like
+/// `more`.
+struct Pager {
+    title: String,
+    lines: Vec<String>,
+    /// Index of the first line shown
+    top: usize,
+}
+
+impl Pager {
+    /// Moves on a page, returning false if the last page was already showing.
+    fn next_page(&mut self, height: usize) -> bool {
+        if self.top + height >= self.lines.len() {
+            return false;
+        }
+        self.top += height;
+        true
+    }
+
+    fn scroll(&mut self, lines: isize, height: usize) {
+        let last = self.lines.len().saturating_sub(height);
+        self.top = self.top.saturating_add_signed(lines).min(last);
+    }
+}
+
+/// A source file shown in the main view.
+struct SourceView {
+    path: PathBuf,
+    lines: Vec<String>,
+    /// Line the process is stopped on, starting from 1 like the line table
+    current_line: Option<u64>,
+    /// Index of the first line drawn
+    scroll: usize,
+}
+
+/// How long each command in a batch took.
+#[derive(Debug, Default)]
+struct TimingReport {
+    commands: Vec<(String, Duration)>,
+}
+
+impl TimingReport {
+    fn push(&mut self, command: &str, elapsed: Duration) {
+        self.commands.push((command.to_string(), elapsed));
+    }
+
+    fn total(&self) -> Duration {
+        self.commands.iter().map(|(_, elapsed)| *elapsed).sum()
+    }
+}
+
+impl fmt::Display for TimingReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (command, elapsed) in &self.commands {
+            writeln!(f, "{:>12.3?}  {}", elapsed, command)?;
+        }
+        write!(
+            f,
+            "{} commands in {:.3?}",
+            self.commands.len(),
+            self.total()
+        )?;
+        if let Some((command, elapsed)) = self.commands.iter().max_by_key(|(_, elapsed)| elapsed) {
+            write!(f, ", slowest: {} ({:.3?})", command, elapsed)?;
+        }
+        writeln!(f)
+    }
+}
+
+/// Indices of the `size` lines to list around the line at `index`, out of `total` lines. The
+/// line is centred unless that would run off either end of the file.
+fn list_window(index: usize, size: usize, total: usize) -> Range<usize> {
+    let end = (index.saturating_sub(size / 2) + size).min(total);
+    end.saturating_sub(size)..end
+}
+
+/// Formats a `print` expression, pretty printed values are split into lines so the logs panel
+/// shows them.
+fn print_lines(sm: &DebuggerStateMachine, expr: &Expression) -> Result<Vec<String>> {
+    match expr {
+        Expression::Registers => Ok(vec![format!("{:?}", sm.get_registers()?)]),
+        Expression::Variable(name) => {
+            let value = format!("{} = {}", name, sm.print_variable(name)?);
+            Ok(value.lines().map(str::to_string).collect())
+        }
+        Expression::Evaluate(expr) => {
+            let value = sm.evaluate(expr)?;
+            Ok(vec![format!("{} = {} (0x{:x})", expr, value as i64, value)])
+        }
+    }
+}
+
+/// Lists a function's instructions for `disassemble`, with the source line before the first
+/// instruction of each line.
+fn disassemble_lines(sm: &DebuggerStateMachine, name: &str) -> Result<Vec<String>> {
+    let ranges = sm.disassemble_function(name)?;
+    let pc = sm.root_process().pc().ok();
+    let mut lines = vec![];
+    let mut last_line = None;
+    for (range, instructions) in &ranges {
+        if ranges.len() > 1 {
+            lines.push(format!("Range 0x{:x}-0x{:x}:", range.start, range.end));
+        }
+        for instruction in instructions {
+            if let Some(line) = sm.source_location(instruction.addr) {
+                if last_line.as_ref() != Some(&line) {
+                    lines.push(line.to_string());
+                    last_line = Some(line);
+                }
+            }
+            let marker = if Some(instruction.addr) == pc {
+                "=>"
+            } else if instruction.breakpoint {
+                "b "
+            } else {
+                "  "
+            };
+            let location = sm
+                .function_location(instruction.addr)
+                .unwrap_or_else(|| name.to_string());
+            let bytes = instruction
+                .bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let decoded = instruction.text.as_deref().unwrap_or("(bad)");
+            let target = instruction
+                .flow
+                .and_then(|flow| branch_target(sm, flow))
+                .unwrap_or_default();
+            let text = format!(
+                "{} 0x{:x} <{}>: {:<30} {}{}",
+                marker, instruction.addr, location, bytes, decoded, target
+            );
+            lines.push(text.trim_end().to_string());
+        }
+    }
+    Ok(lines)
+}
+
+/// ` <function+offset>` for where a branch or call goes, to follow the instruction's text.
+fn branch_target(sm: &DebuggerStateMachine, flow: Flow) -> Option<String> {
+    let addr = match flow {
+        Flow::Call(Some(addr)) | Flow::Jump(Some(addr)) | Flow::ConditionalJump(addr) => addr,
+        _ => return None,
+    };
+    sm.function_location(addr)
+        .map(|function| format!(" <{}>", function))
+}
+
+/// ` in <signature>` for a breakpoint at the start of a function, so the messages about it show
+/// what the function takes.
+fn in_function(sm: &DebuggerStateMachine, id: u64) -> String {
+    sm.list_breakpoints()
+        .iter()
+        .find(|bp| bp.id == id)
+        .and_then(|bp| sm.function_signature(bp.addr))
+        .map(|signature| format!(" in {}", signature))
+        .unwrap_or_default()
+}
+
+/// Dumps memory for `examine`, along with why it stopped short if it couldn't all be read.
+fn examine_lines(
+    sm: &DebuggerStateMachine,
+    addr: u64,
+    len: usize,
+) -> (Vec<String>, Option<String>) {
+    let read = sm.root_process().read_memory_partial(addr, len);
+    let fault = read.fault.map(|fault| {
+        format!(
+            "Couldn't read memory at 0x{:x}, showing {} of {} bytes",
+            fault,
+            read.data.len(),
+            len
+        )
+    });
+    (hexdump(addr, &read.data), fault)
+}
+
+/// Formats memory like `hexdump -C`, each row is the address, 16 bytes in hex split into two
+/// groups of 8 and the bytes as ASCII with anything unprintable as a `.`.
+fn hexdump(addr: u64, bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let mut hex = String::new();
+            for i in 0..16 {
+                if i == 8 {
+                    hex.push(' ');
+                }
+                match chunk.get(i) {
+                    Some(b) => hex.push_str(&format!("{:02x} ", b)),
+                    None => hex.push_str("   "),
+                }
+            }
+            let ascii = chunk
+                .iter()
+                .map(|b| match b {
+                    0x20..=0x7e => *b as char,
+                    _ => '.',
+                })
+                .collect::<String>();
+            format!("{:016x}  {} |{}|", addr + row as u64 * 16, hex, ascii)
+        })
+        .collect()
+}
+
+/// Where each panel is drawn for a given frame size.
+struct AppLayout {
+    view: Rect,
+    logs: Option<Rect>,
+    breakpoints: Option<Rect>,
+    output: Option<Rect>,
+    prompt: Rect,
 }
 
 impl App {
     fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
-        self.debugger = Some(DebuggerStateMachine::start(self.args.clone())?);
+        if self.args.has_target() {
+            self.start_debugger()?;
+        } else {
+            info!("No program or process given, use load or attach to start debugging");
+        }
         while !self.exit {
             terminal.draw(|frame| self.draw(frame))?;
             self.handle_events()?;
+            self.poll_debugger()?;
+        }
+        info!("Exiting");
+        Ok(())
+    }
+
+    /// Collects any output from the process and handles it stopping, returns whether it stopped.
+    fn poll_debugger(&mut self) -> Result<bool> {
+        let Some(sm) = self.debugger.as_mut() else {
+            return Ok(false);
+        };
+        if let Some(stdout) = sm.root_process_mut().read_stdout_bytes() {
+            let stdout = self.settings.charset.decode(&stdout);
+            debug!("Got stdout: {}", stdout);
+            self.output.push(Stream::Stdout, &stdout);
+        }
+        if let Some(stderr) = sm.root_process_mut().read_stderr_bytes() {
+            let stderr = self.settings.charset.decode(&stderr);
+            debug!("Got stderr: {}", stderr);
+            self.output.push(Stream::Stderr, &stderr);
+        }
+
+        let Some(stop) = sm.wait()? else {
+            return Ok(false);
+        };
+        self.report_stop(&stop);
+        Ok(true)
+    }
+
+    /// Records and logs a stop returned by the debugger.
+    fn report_stop(&mut self, stop: &StopReason) {
+        let Some(sm) = self.debugger.as_mut() else {
+            return;
+        };
+        sm.record_stop(stop);
+        if stop.reason.is_closed() {
+            self.exit_status = sm.last_exit_status();
+            self.debugger = None;
+            match stop.info {
+                Info::Return(code) => info!("Process exited with code {}", code),
+                Info::Signalled(signal) => info!(
+                    "Process terminated by signal {}{}",
+                    signal,
+                    if stop.core_dumped {
+                        " (core dumped)"
+                    } else {
+                        ""
+                    }
+                ),
+            }
+        } else if let Some(status) = stop.exit_status {
+            match status {
+                Info::Return(code) => info!("Process is about to exit with code {}", code),
+                Info::Signalled(signal) => info!("Process is about to be killed by {}", signal),
+            }
+        } else if stop.trap_reason == Some(TrapType::Spurious) {
+            info!(
+                "Stopped by a SIGTRAP that wasn't from a breakpoint or step, `set \
+                 skip-spurious-traps on` to continue past these"
+            );
+        } else {
+            if matches!(
+                stop.trap_reason,
+                Some(TrapType::SoftwareBreak | TrapType::HardwareBreak)
+            ) {
+                if let Some(signature) = sm
+                    .root_process()
+                    .pc()
+                    .ok()
+                    .and_then(|pc| sm.function_signature(pc))
+                {
+                    info!("Breakpoint hit in {}", signature);
+                }
+            }
+            info!("Stopped: {:?}", stop);
+        }
+        if !stop.reason.is_closed() {
+            self.show_displays();
+        }
+        self.refresh_panels();
+    }
 
-            if let Some(sm) = self.debugger.as_mut() {
-                if let Some(stdout) = sm.root_process_mut().read_stdout() {
-                    info!("Got stdout: {}", stdout);
-                    self.current_stdout.push_str(&stdout);
+    /// Works out what each display shows, a display that can't be shown gives the reason.
+    fn display_lines(&self) -> Vec<String> {
+        let Some(sm) = self.debugger.as_ref() else {
+            return vec![];
+        };
+        let mut lines = vec![];
+        for (id, expr) in &self.displays {
+            let shown = match expr {
+                DisplayExpr::Print(expr) => print_lines(sm, expr),
+                DisplayExpr::Examine { addr, len } => addr
+                    .resolve_range(sm.root_process(), *len)
+                    .map(|addr| {
+                        let (mut shown, fault) = examine_lines(sm, addr, *len);
+                        shown.extend(fault);
+                        shown
+                    })
+                    .map_err(Into::into),
+            };
+            match shown {
+                Ok(shown) if matches!(expr, DisplayExpr::Examine { .. }) => {
+                    lines.push(format!("{}: {}", id, expr));
+                    lines.extend(shown);
                 }
+                Ok(shown) => {
+                    let mut shown = shown.into_iter();
+                    lines.push(format!("{}: {}", id, shown.next().unwrap_or_default()));
+                    lines.extend(shown);
+                }
+                Err(e) => lines.push(format!("{}: {} couldn't be shown: {}", id, expr, e)),
+            }
+        }
+        lines
+    }
+
+    fn show_displays(&self) {
+        for line in self.display_lines() {
+            info!("{}", line);
+        }
+    }
 
-                let stop = sm.wait()?;
+    /// Reports the process stopping for the `wait` command, checking once without a timeout.
+    fn wait_for_process(&mut self, timeout: Option<Duration>) -> Result<()> {
+        let Some(sm) = self.debugger.as_mut() else {
+            warn!("Not debugging nothing to wait for");
+            return Ok(());
+        };
+        if sm.root_process().state() != State::Running {
+            info!(
+                "Process isn't running, nothing to wait for: {:?}",
+                sm.root_process().state()
+            );
+            return Ok(());
+        }
+        let stop = match timeout {
+            None => sm.wait()?,
+            Some(timeout) => match sm.blocking_wait(timeout) {
+                Ok(stop) => Some(stop),
+                Err(e) if matches!(e.downcast_ref(), Some(ProcessError::Timeout)) => None,
+                Err(e) => return Err(e),
+            },
+        };
+        match stop {
+            Some(stop) => self.report_stop(&stop),
+            None => info!("Process is still running"),
+        }
+        Ok(())
+    }
 
-                if let Some(stop) = stop {
-                    if stop.reason.is_closed() {
-                        self.debugger = None;
-                        info!("Done");
+    /// Runs the commands in a script one at a time, waiting for the process to stop after each
+    /// one so the next command sees it stopped like it would when typed in, unless it was
+    /// continued with `continue &`. Blank lines and lines starting with `#` are skipped, a
+    /// command that fails is logged and the script carries on.
+    ///
+    /// Returns a report of how long each command took.
+    fn run_batch(&mut self, script: &Path) -> Result<TimingReport> {
+        let script = fs::read_to_string(script)?;
+        if self.args.has_target() {
+            self.start_debugger()?;
+        }
+        let mut report = TimingReport::default();
+        for line in script.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            info!("Running: {}", line);
+            let start = Instant::now();
+            match Command::from_str(line) {
+                Ok(command) => {
+                    if let Err(e) = self.run_command(&command) {
+                        error!("Failed to run command: {}", e);
+                    }
+                    match command {
+                        Command::ContinueBackground => self.background = true,
+                        Command::Interrupt => self.background = false,
+                        _ => {}
+                    }
+                    if self.background {
+                        // Still pick up the stop if it's happened by now
+                        if let Err(e) = self.poll_debugger() {
+                            error!("Failed waiting for the process to stop: {}", e);
+                        }
+                        self.background = self
+                            .debugger
+                            .as_ref()
+                            .is_some_and(|sm| sm.root_process().state() == State::Running);
                     } else {
-                        info!("Stopped: {:?}", stop);
+                        self.wait_for_stop();
                     }
                 }
+                Err(e) => error!("Invalid command: {}", e),
+            }
+            report.push(line, start.elapsed());
+            if self.exit {
+                break;
             }
         }
-        info!("Exiting");
+        Ok(report)
+    }
+
+    /// Waits for a running process to stop so batch commands don't race it.
+    fn wait_for_stop(&mut self) {
+        let start = Instant::now();
+        while self
+            .debugger
+            .as_ref()
+            .is_some_and(|sm| sm.root_process().state() == State::Running)
+        {
+            match self.poll_debugger() {
+                Ok(true) => {}
+                Ok(false) if start.elapsed() < BATCH_STOP_TIMEOUT => thread::sleep(BATCH_POLL),
+                Ok(false) => {
+                    warn!(
+                        "Process still running after {:?}, moving on to the next command",
+                        BATCH_STOP_TIMEOUT
+                    );
+                    return;
+                }
+                Err(e) => {
+                    error!("Failed waiting for the process to stop: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn start_debugger(&mut self) -> Result<()> {
+        self.exit_status = None;
+        self.debugger = Some(DebuggerStateMachine::start_with_settings(
+            self.args.clone(),
+            self.settings.clone(),
+        )?);
         Ok(())
     }
 
     fn draw(&self, frame: &mut Frame) {
+        self.last_area.set(frame.area());
         frame.render_widget(self, frame.area());
         if self.show_help {
             let area = frame.area();
@@ -124,9 +735,92 @@ impl App {
 
             frame.render_widget(paragraph, area);
         }
+        if let Some(pager) = self.pager.as_ref() {
+            self.render_pager(pager, frame.area(), frame.buffer_mut());
+        }
+    }
+
+    /// Where the pager is drawn.
+    fn pager_area(&self, area: Rect) -> Rect {
+        popup_area(area, 90, 80)
+    }
+
+    /// Lines of output on a page, 0 before anything has been drawn.
+    fn page_height(&self) -> usize {
+        let area = self.pager_area(self.last_area.get());
+        area.height.saturating_sub(2) as usize
+    }
+
+    /// Shows a command's output, in the pager if pagination is on and it doesn't fit on one
+    /// page otherwise logged a line at a time.
+    fn show_lines(&mut self, title: &str, lines: Vec<String>) {
+        let height = self.page_height();
+        if self.settings.pagination && height > 0 && lines.len() > height {
+            info!(
+                "{}: {} lines shown in the pager, <Space> for more and <Q> to close",
+                title,
+                lines.len()
+            );
+            self.pager = Some(Pager {
+                title: title.to_string(),
+                lines,
+                top: 0,
+            });
+        } else {
+            for line in lines {
+                info!("{}", line);
+            }
+        }
+    }
+
+    fn render_pager(&self, pager: &Pager, area: Rect, buf: &mut Buffer) {
+        let area = self.pager_area(area);
+        let height = area.height.saturating_sub(2) as usize;
+        let end = (pager.top + height).min(pager.lines.len());
+        let status = if end < pager.lines.len() {
+            format!(
+                " --More-- ({}/{}) <Space> next page, <Q> close ",
+                end,
+                pager.lines.len()
+            )
+        } else {
+            format!(" --End-- ({} lines) <Q> close ", pager.lines.len())
+        };
+        let block = Block::bordered()
+            .title(format!(" {} ", pager.title))
+            .title_bottom(Line::from(status).reversed())
+            .border_set(border::THICK);
+        let lines = pager.lines[pager.top..end]
+            .iter()
+            .map(|line| Line::raw(line.as_str()))
+            .collect::<Vec<_>>();
+        Clear.render(area, buf);
+        Paragraph::new(lines).block(block).render(area, buf);
+    }
+
+    /// Keys while the pager is open, Space or Enter move on a page closing it after the last
+    /// one, the arrows and page keys scroll and Q or Esc close it.
+    fn handle_pager_key(&mut self, key: KeyCode) {
+        let height = self.page_height().max(1);
+        let Some(pager) = self.pager.as_mut() else {
+            return;
+        };
+        match key {
+            KeyCode::Char(' ') | KeyCode::Enter if !pager.next_page(height) => self.pager = None,
+            KeyCode::Down => pager.scroll(1, height),
+            KeyCode::Up => pager.scroll(-1, height),
+            KeyCode::PageDown => pager.scroll(height as isize, height),
+            KeyCode::PageUp => pager.scroll(-(height as isize), height),
+            KeyCode::Char('q') | KeyCode::Esc => self.pager = None,
+            _ => {}
+        }
     }
 
     fn handle_events(&mut self) -> Result<()> {
+        // Don't block forever on input, the process may stop or be traced in the meantime
+        if !event::poll(POLL_INTERVAL)? {
+            return Ok(());
+        }
         match event::read()? {
             // it's important to check that the event is a key press event as
             // crossterm also emits key release and repeat events on Windows.
@@ -136,34 +830,314 @@ impl App {
             Event::Key(key_event) if key_event.kind == KeyEventKind::Release => {
                 self.handle_key_event(key_event)
             }
+            Event::Mouse(mouse_event) => self.handle_mouse_event(mouse_event),
             _ => Ok(()),
         }
     }
 
+    fn handle_mouse_event(&mut self, mouse_event: MouseEvent) -> Result<()> {
+        let position = Position::new(mouse_event.column, mouse_event.row);
+        let layout = self.layout(self.last_area.get());
+        let in_logs = layout.logs.is_some_and(|logs| logs.contains(position));
+        let in_output = layout
+            .output
+            .is_some_and(|output| output.contains(position));
+        let in_view = layout.view.contains(position);
+        match mouse_event.kind {
+            MouseEventKind::Down(MouseButton::Left) if self.show_help => {
+                self.show_help = false;
+            }
+            MouseEventKind::ScrollUp if in_logs => {
+                self.log_state.transition(TuiWidgetEvent::PrevPageKey);
+            }
+            MouseEventKind::ScrollDown if in_logs => {
+                self.log_state.transition(TuiWidgetEvent::NextPageKey);
+            }
+            MouseEventKind::ScrollUp if in_output => {
+                self.output_scroll =
+                    (self.output_scroll + SCROLL_LINES).min(self.output.len().saturating_sub(1));
+            }
+            MouseEventKind::ScrollDown if in_output => {
+                self.output_scroll = self.output_scroll.saturating_sub(SCROLL_LINES);
+            }
+            MouseEventKind::ScrollUp if in_view => {
+                if let Some(source) = self.source.as_mut() {
+                    source.scroll = source.scroll.saturating_sub(SCROLL_LINES);
+                }
+            }
+            MouseEventKind::ScrollDown if in_view => {
+                if let Some(source) = self.source.as_mut() {
+                    source.scroll =
+                        (source.scroll + SCROLL_LINES).min(source.lines.len().saturating_sub(1));
+                }
+            }
+            MouseEventKind::Down(MouseButton::Left) if in_view => {
+                self.click_source(layout.view, position);
+            }
+            MouseEventKind::Down(MouseButton::Left) => {
+                if let Some(breakpoints) = layout.breakpoints {
+                    if breakpoints.contains(position) {
+                        self.click_breakpoint(breakpoints, position);
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Adds a breakpoint to the clicked source line, or removes the ones already there.
+    fn click_source(&mut self, view: Rect, position: Position) {
+        let (Some(source), Some(sm)) = (self.source.as_ref(), self.debugger.as_mut()) else {
+            return;
+        };
+        // Skip the top border
+        let Some(row) = position.y.checked_sub(view.y + 1) else {
+            return;
+        };
+        let index = source.scroll + row as usize;
+        if index >= source.lines.len() {
+            return;
+        }
+        let line = index as u64 + 1;
+        if self.source_breakpoints.contains(&line) {
+            let ids = sm
+                .list_breakpoints()
+                .into_iter()
+                .filter(|bp| {
+                    bp.source_loc
+                        .as_ref()
+                        .is_some_and(|loc| loc.file == source.path && loc.line == line)
+                })
+                .map(|bp| bp.id);
+            for id in ids {
+                match sm.delete_breakpoint(id) {
+                    Ok(()) => info!(id, "Removed breakpoint"),
+                    Err(e) => error!("Failed to remove breakpoint: {}", e),
+                }
+            }
+        } else {
+            let location = Location::Line {
+                file: source.path.clone(),
+                line: line as usize,
+            };
+            match sm.set_break(&location) {
+                Ok(id) => info!(id, "Added breakpoint"),
+                Err(e) => error!("Failed to set breakpoint: {}", e),
+            }
+        }
+        self.refresh_breakpoints();
+    }
+
+    /// Toggles the breakpoint in the row of the breakpoints panel that was clicked.
+    fn click_breakpoint(&mut self, panel: Rect, position: Position) {
+        // Skip the top border and the header row
+        let Some(row) = position.y.checked_sub(panel.y + 2) else {
+            return;
+        };
+        let Some(id) = self
+            .breakpoint_rows
+            .get(row as usize)
+            .and_then(|row| row[0].parse::<u64>().ok())
+        else {
+            return;
+        };
+        if let Some(sm) = self.debugger.as_mut() {
+            match sm.toggle_breakpoint(id) {
+                Ok(true) => info!(id, "Enabled breakpoint"),
+                Ok(false) => info!(id, "Disabled breakpoint"),
+                Err(e) => error!("Failed to toggle breakpoint: {}", e),
+            }
+        }
+        self.refresh_breakpoints();
+    }
+
+    /// Returns why a command needs confirming before it runs if it does.
+    fn confirmation_needed(&self, command: &Command) -> Option<&'static str> {
+        let live = self
+            .debugger
+            .as_ref()
+            .is_some_and(|sm| !sm.root_process().state().is_closed());
+        if !self.settings.confirm || !live {
+            return None;
+        }
+        match command {
+            Command::Quit => Some("The process is still being debugged"),
+            Command::Restart | Command::Load { .. } | Command::Attach(_) => {
+                Some("This ends the process currently being debugged")
+            }
+            _ => None,
+        }
+    }
+
     fn run_command(&mut self, command: &Command) -> Result<()> {
+        let pending = self.pending_confirmation.take();
+        if let Some(reason) = self.confirmation_needed(command) {
+            if pending.as_ref() != Some(command) {
+                warn!(
+                    "{}, enter the command again to confirm or `set confirm off` to stop asking",
+                    reason
+                );
+                self.pending_confirmation = Some(command.clone());
+                return Ok(());
+            }
+        }
         match command {
             Command::Quit => self.exit(),
             Command::ToggleLogs => self.toggle_logs(),
+            Command::ToggleBreakpoints => self.show_breakpoints = !self.show_breakpoints,
+            Command::ToggleOutput => self.show_output = !self.show_output,
             Command::Help => {
                 self.show_help = true;
             }
+            Command::HelpFor(name) => match Command::help_text(name) {
+                Some(text) => info!("{}", text),
+                None => warn!("No help for {}", name),
+            },
             Command::Restart => {
-                self.debugger = Some(DebuggerStateMachine::start(self.args.clone())?);
+                self.start_debugger()?;
             }
-            Command::Load(path) => {
+            Command::Load { path, args } => {
                 self.args.set_input(path.clone());
-                self.debugger = Some(DebuggerStateMachine::start(self.args.clone())?);
+                self.args.program_args = args.clone();
+                self.start_debugger()?;
+            }
+            Command::SetEnv { key, value } => {
+                self.args.set_env(key.clone(), value.clone());
+                info!(
+                    "Set {}={}, this takes effect when the program is next launched",
+                    key, value
+                );
             }
+            Command::ListEnv => {
+                if self.args.env.is_empty() {
+                    info!("No environment variables set");
+                }
+                for (key, value) in &self.args.env {
+                    info!("{}={}", key, value);
+                }
+            }
+            Command::Cwd(Some(dir)) => {
+                if !dir.is_dir() {
+                    warn!("{} isn't a directory, launching will fail", dir.display());
+                }
+                self.args.cwd = Some(dir.clone());
+                info!(
+                    "Working directory set to {}, this takes effect when the program is next \
+                     launched",
+                    dir.display()
+                );
+            }
+            Command::Cwd(None) => match self.args.cwd.as_ref() {
+                Some(dir) => info!("Working directory: {}", dir.display()),
+                None => match std::env::current_dir() {
+                    Ok(dir) => info!("Working directory: {} (ours)", dir.display()),
+                    Err(e) => warn!("Couldn't get the working directory: {}", e),
+                },
+            },
             Command::Attach(pid) => {
                 self.args.set_pid(*pid);
-                self.debugger = Some(DebuggerStateMachine::start(self.args.clone())?);
+                self.start_debugger()?;
+            }
+            Command::Detach => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    sm.detach()?;
+                    self.debugger = None;
+                    self.refresh_panels();
+                } else {
+                    warn!("Not debugging nothing to detach from");
+                }
             }
-            Command::Continue => {
+            Command::Continue | Command::ContinueBackground => {
                 if let Some(proc) = self.debugger.as_mut() {
                     proc.cont()?;
                 }
             }
+            Command::Interrupt => match self.debugger.as_ref() {
+                Some(sm) if sm.is_tracing() || sm.root_process().state() == State::Running => {
+                    self.stop_process()
+                }
+                Some(_) => warn!("Process isn't running"),
+                None => warn!("Not debugging nothing to interrupt"),
+            },
+            Command::Signal(signal) => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    sm.cont_with_signal(*signal)?;
+                } else {
+                    warn!("Not debugging nothing to signal");
+                }
+            }
+            Command::Handle { signal, pass } => {
+                self.settings.nopass_signals.retain(|s| s != signal);
+                if !pass {
+                    self.settings.nopass_signals.push(*signal);
+                }
+                if let Some(sm) = self.debugger.as_mut() {
+                    sm.apply_settings(self.settings.clone());
+                }
+                info!(
+                    "{} is {}",
+                    signal,
+                    if *pass {
+                        "passed on to the process"
+                    } else {
+                        "not passed on to the process"
+                    }
+                );
+            }
+            Command::CatchSyscalls => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    let catch = !sm.catching_syscalls();
+                    sm.set_catch_syscalls(catch);
+                    if catch {
+                        info!("Stopping at every syscall the next time the process continues");
+                    } else {
+                        info!("No longer stopping at syscalls");
+                    }
+                } else {
+                    warn!("Not debugging no syscalls to catch");
+                }
+            }
+            Command::Input(text) => match self.debugger.as_mut() {
+                Some(sm) => sm
+                    .root_process_mut()
+                    .write_stdin(format!("{}\n", text).as_bytes())?,
+                None => warn!("Not debugging nothing to send input to"),
+            },
+            Command::Wait(timeout) => self.wait_for_process(*timeout)?,
+            Command::RunTo(location) => {
+                if let Some(proc) = self.debugger.as_mut() {
+                    proc.continue_to(location)?;
+                }
+            }
+            Command::ContinueUntil { id, hits } => {
+                if let Some(proc) = self.debugger.as_mut() {
+                    proc.continue_until(*id, *hits)?;
+                }
+            }
+            Command::Finish => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    let addr = sm.finish()?;
+                    info!("Running until return to 0x{:x}", addr);
+                } else {
+                    warn!("Not debugging nothing to finish");
+                }
+            }
+            Command::Next => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    sm.step_over()?;
+                } else {
+                    warn!("Not debugging nothing to step");
+                }
+            }
             Command::Step => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    sm.step_line()?;
+                } else {
+                    warn!("Not debugging nothing to step");
+                }
+            }
+            Command::StepInstruction => {
                 if let Some(proc) = self.debugger.as_mut() {
                     proc.step()?;
                 }
@@ -172,36 +1146,540 @@ impl App {
                 if let Some(poc) = self.debugger.as_ref() {
                     poc.log_status();
                 } else {
-                    info!("No process running");
+                    match self.exit_status {
+                        Some(Info::Return(code)) => {
+                            info!("No process running, the last one exited with code {}", code)
+                        }
+                        Some(Info::Signalled(signal)) => info!(
+                            "No process running, the last one was terminated by signal {}",
+                            signal
+                        ),
+                        None => info!("No process running"),
+                    }
                 }
             }
+            Command::ListSource(location) => {
+                let Some(sm) = self.debugger.as_ref() else {
+                    warn!("Not debugging no debug info to find {:?}", location);
+                    return Ok(());
+                };
+                let loc = sm.source_location_of(location)?;
+                let contents = fs::read_to_string(&loc.file)?;
+                let lines = contents.lines().collect::<Vec<_>>();
+                let index = loc.line.saturating_sub(1) as usize;
+                info!("{}", loc);
+                let listing = list_window(index, self.settings.list_size, lines.len())
+                    .map(|i| {
+                        let marker = if i == index { '>' } else { ' ' };
+                        format!("{}{:>5} {}", marker, i + 1, lines[i])
+                    })
+                    .collect();
+                self.show_lines(&loc.to_string(), listing);
+            }
             Command::ListBreakpoints => {
-                if let Some(poc) = self.debugger.as_ref() {
-                    poc.list_breakpoints();
+                let breakpoints = self
+                    .debugger
+                    .as_ref()
+                    .map(|sm| sm.list_breakpoints())
+                    .unwrap_or_default();
+                if breakpoints.is_empty() {
+                    info!("No breakpoints");
+                }
+                for bp in &breakpoints {
+                    let location = bp
+                        .source_loc
+                        .as_ref()
+                        .map(|loc| format!(" at {}", loc))
+                        .unwrap_or_default();
+                    let condition = bp
+                        .condition
+                        .as_ref()
+                        .map(|c| format!(" if {}", c))
+                        .unwrap_or_default();
+                    let thread = bp
+                        .thread
+                        .map(|t| format!(" thread {}", t))
+                        .unwrap_or_default();
+                    let ignore = match bp.ignore_count {
+                        0 => String::new(),
+                        n => format!(", ignoring next {}", n),
+                    };
+                    let kind = match (bp.temporary, bp.hardware) {
+                        (true, _) => "Temporary breakpoint",
+                        (false, true) => "Hardware breakpoint",
+                        (false, false) => "Breakpoint",
+                    };
+                    info!(
+                        "{} {}: 0x{:x}{}{}{} (enabled: {}, hits: {}{})",
+                        kind,
+                        bp.id,
+                        bp.addr,
+                        location,
+                        condition,
+                        thread,
+                        bp.enabled,
+                        bp.hit_count,
+                        ignore
+                    );
+                }
+            }
+            Command::Print(expr) => {
+                if let Some(sm) = self.debugger.as_ref() {
+                    for line in print_lines(sm, expr)? {
+                        info!("{}", line);
+                    }
                 } else {
-                    info!("Breakpoints []");
+                    warn!("Not debugging can't print {}", expr);
+                }
+            }
+            Command::Display(expr) => {
+                let id = self.next_display + 1;
+                self.next_display = id;
+                self.displays.insert(id, expr.clone());
+                info!("Added display {}: {}", id, expr);
+                if self
+                    .debugger
+                    .as_ref()
+                    .is_some_and(|sm| sm.root_process().state() == State::Stopped)
+                {
+                    self.show_displays();
                 }
             }
-            Command::Print(expr) => match expr {
-                Expression::Registers => {
-                    if let Some(sm) = self.debugger.as_ref() {
-                        match sm.get_registers() {
-                            Ok(regs) => info!("{:?}", regs),
-                            Err(e) => error!("Couldn't print registers: {}", e),
+            Command::Undisplay(id) => match self.displays.remove(id) {
+                Some(expr) => info!("Removed display {}: {}", id, expr),
+                None => warn!("No display {}", id),
+            },
+            Command::Info(InfoKind::Display) => {
+                if self.displays.is_empty() {
+                    info!("No displays");
+                }
+                for (id, expr) in &self.displays {
+                    info!("{}: {}", id, expr);
+                }
+            }
+            Command::Info(InfoKind::Threads) => {
+                if let Some(sm) = self.debugger.as_ref() {
+                    let process = sm.root_process();
+                    for (tid, pc) in process.thread_pcs() {
+                        let marker = if tid == process.stopped_thread() {
+                            "*"
+                        } else {
+                            " "
+                        };
+                        match pc {
+                            Some(pc) => info!(
+                                "{} Thread {}: 0x{:x} in {}",
+                                marker,
+                                tid,
+                                pc,
+                                sm.function_location(pc).as_deref().unwrap_or("??")
+                            ),
+                            None => info!("{} Thread {}: running", marker, tid),
                         }
-                    } else {
-                        warn!("Not debugging can't print registers");
                     }
+                } else {
+                    warn!("Not debugging no threads to list");
                 }
-            },
+            }
             Command::Break(loc) => {
                 if let Some(proc) = self.debugger.as_mut() {
                     match proc.set_break(loc) {
-                        Ok(s) => info!(id = s, "Added breakpoint"),
+                        Ok(s) => info!(id = s, "Added breakpoint{}", in_function(proc, s)),
                         Err(e) => error!("Failed to set breakpoint: {}", e),
                     }
                 }
             }
+            Command::TempBreak(location) => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    match sm.set_temp_break(location) {
+                        Ok(id) => info!(id, "Added temporary breakpoint{}", in_function(sm, id)),
+                        Err(e) => error!("Failed to set breakpoint: {}", e),
+                    }
+                }
+            }
+            Command::HardwareBreak(location) => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    match sm.set_hardware_break(location) {
+                        Ok(id) => info!(id, "Added hardware breakpoint{}", in_function(sm, id)),
+                        Err(e) => error!("Failed to set breakpoint: {}", e),
+                    }
+                }
+            }
+            Command::ConditionalBreak {
+                location,
+                condition,
+            } => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    match sm.set_conditional_break(location, condition) {
+                        Ok(id) => info!(
+                            id,
+                            "Added breakpoint{} if {}",
+                            in_function(sm, id),
+                            condition
+                        ),
+                        Err(e) => error!("Failed to set breakpoint: {}", e),
+                    }
+                }
+            }
+            Command::ThreadBreak { location, thread } => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    match sm.set_thread_break(location, *thread) {
+                        Ok(id) => info!(
+                            id,
+                            "Added breakpoint{} for thread {}",
+                            in_function(sm, id),
+                            thread
+                        ),
+                        Err(e) => error!("Failed to set breakpoint: {}", e),
+                    }
+                }
+            }
+            Command::Delete(id) => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    sm.delete_breakpoint(*id)?;
+                    info!(id, "Removed breakpoint");
+                } else {
+                    warn!("Not debugging no breakpoints to delete");
+                }
+            }
+            Command::Ignore { id, count } => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    sm.ignore_breakpoint(*id, *count)?;
+                    info!(id, "Ignoring the next {} hits", count);
+                } else {
+                    warn!("Not debugging no breakpoints to ignore");
+                }
+            }
+            Command::StartTrace(path) => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    sm.start_trace(path)?;
+                } else {
+                    warn!("Not debugging can't start a trace");
+                }
+            }
+            Command::StopTrace => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    sm.stop_trace()?;
+                }
+            }
+            Command::Set { name, value } => {
+                self.settings.set(name, value)?;
+                self.output.set_capacity(self.settings.output_lines);
+                if let Some(sm) = self.debugger.as_mut() {
+                    sm.apply_settings(self.settings.clone());
+                }
+                info!("{}", self.settings.show(name)?);
+            }
+            Command::Show(Some(name)) => info!("{}", self.settings.show(name)?),
+            Command::Show(None) => {
+                let settings = self.settings.show_all();
+                self.show_lines("show", settings);
+            }
+            Command::ReloadSymbols => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    sm.reload_symbols()?;
+                    info!("Reloaded symbols");
+                } else {
+                    warn!("Not debugging no symbols to reload");
+                }
+            }
+            Command::DiffSymbols(other) => {
+                let diff = match (self.debugger.as_ref(), self.args.input.as_ref()) {
+                    (Some(sm), _) => sm.diff_symbols(other)?,
+                    (None, Some(input)) => {
+                        let ours = ExecutableFile::load_blocking(input)?;
+                        ours.diff_functions(&ExecutableFile::load_blocking(other)?)
+                    }
+                    (None, None) => {
+                        warn!("No program loaded to compare against");
+                        return Ok(());
+                    }
+                };
+                info!(
+                    "{} only in ours, {} only in {}, {} resized, {} unchanged",
+                    diff.removed.len(),
+                    diff.added.len(),
+                    other.display(),
+                    diff.resized.len(),
+                    diff.unchanged
+                );
+                for (name, size) in &diff.removed {
+                    info!("- {} ({} bytes)", name, size);
+                }
+                for (name, size) in &diff.added {
+                    info!("+ {} ({} bytes)", name, size);
+                }
+                for (name, old, new) in &diff.resized {
+                    info!(
+                        "~ {} {} -> {} bytes ({:+})",
+                        name,
+                        old,
+                        new,
+                        *new as i64 - *old as i64
+                    );
+                }
+            }
+            Command::ListThreads => {
+                if let Some(sm) = self.debugger.as_ref() {
+                    for thread in sm.root_process().threads()? {
+                        info!("Thread {}: {}", thread.tid, thread.name);
+                    }
+                } else {
+                    warn!("Not debugging no threads to list");
+                }
+            }
+            Command::Dprintf { location, dprintf } => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    let id = sm.set_dprintf(location, dprintf.clone())?;
+                    info!(id, "Added dprintf");
+                } else {
+                    warn!("Not debugging can't add a dprintf");
+                }
+            }
+            Command::SaveSnapshot { name, ranges } => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    let process = sm.root_process();
+                    let ranges = ranges
+                        .iter()
+                        .map(|(addr, len)| Ok((addr.resolve_range(process, *len)?, *len)))
+                        .collect::<Result<Vec<_>, AddressError>>()?;
+                    sm.save_snapshot(name, &ranges)?;
+                    info!("Saved snapshot {}", name);
+                } else {
+                    warn!("Not debugging can't take a snapshot");
+                }
+            }
+            Command::DiffSnapshot(name) => {
+                if let Some(sm) = self.debugger.as_ref() {
+                    let diff = sm.diff_snapshot(name)?;
+                    if diff.is_empty() {
+                        info!("Nothing has changed since snapshot {}", name);
+                    }
+                    for change in &diff.registers {
+                        info!(
+                            "{}: 0x{:x} -> 0x{:x}",
+                            change.name, change.before, change.after
+                        );
+                    }
+                    for change in &diff.memory {
+                        info!(
+                            "0x{:x}: {:02x} -> {:02x}",
+                            change.addr, change.before, change.after
+                        );
+                    }
+                } else {
+                    warn!("Not debugging can't diff a snapshot");
+                }
+            }
+            Command::Backtrace => {
+                if let Some(sm) = self.debugger.as_ref() {
+                    let backtrace = sm.backtrace()?;
+                    for (i, frame) in backtrace.frames.iter().enumerate() {
+                        let function = sm
+                            .function_location(frame.lookup_address())
+                            .map(|f| format!(" in {}", f))
+                            .unwrap_or_default();
+                        let source = sm
+                            .source_location(frame.lookup_address())
+                            .map(|loc| format!(" at {}", loc))
+                            .unwrap_or_default();
+                        info!("#{} 0x{:x}{}{}", i, frame.pc, function, source);
+                    }
+                    match backtrace.truncated {
+                        Some(Truncated::Limit(limit)) => info!(
+                            "Backtrace stopped after {} frames, use set backtrace-limit to see \
+                             more",
+                            limit
+                        ),
+                        Some(Truncated::Cycle {
+                            frame_pointer,
+                            saved,
+                        }) => warn!(
+                            "Backtrace stopped: saved frame pointer 0x{:x} doesn't move up the \
+                             stack from 0x{:x}, the stack may be corrupt",
+                            saved, frame_pointer
+                        ),
+                        Some(Truncated::Unreadable(addr)) => {
+                            warn!("Backtrace stopped: couldn't read frame at 0x{:x}", addr)
+                        }
+                        None => {}
+                    }
+                } else {
+                    warn!("Not debugging no backtrace");
+                }
+            }
+            Command::StopHistory(index) => {
+                let Some(sm) = self.debugger.as_ref() else {
+                    warn!("Not debugging no stops to list");
+                    return Ok(());
+                };
+                match index {
+                    None => {
+                        for (i, stop) in sm.stop_history().enumerate() {
+                            let function = stop
+                                .function
+                                .as_ref()
+                                .map(|f| format!(" in {}", f))
+                                .unwrap_or_default();
+                            let source = stop
+                                .source_loc
+                                .as_ref()
+                                .map(|loc| format!(" at {}", loc))
+                                .unwrap_or_default();
+                            let reason = stop
+                                .trap_reason
+                                .map(|t| format!(" ({:?})", t))
+                                .unwrap_or_default();
+                            info!("{}: 0x{:x}{}{}{}", i, stop.pc, function, source, reason);
+                        }
+                    }
+                    Some(index) => {
+                        let Some(stop) = sm.stop_history().nth(*index) else {
+                            warn!("No stop {} in the history", index);
+                            return Ok(());
+                        };
+                        match stop.source_loc.clone() {
+                            Some(location) => {
+                                info!("Showing stop {} at {}", index, location);
+                                self.scroll_to_location(&location);
+                            }
+                            None => warn!("No source for stop {} at 0x{:x}", index, stop.pc),
+                        }
+                    }
+                }
+            }
+            Command::Info(InfoKind::Version) => info!("{}", BUILD_INFO),
+            Command::Info(InfoKind::Registers(group)) => {
+                if let Some(sm) = self.debugger.as_ref() {
+                    for line in sm.get_registers()?.format_group(*group) {
+                        info!("{}", line);
+                    }
+                } else {
+                    warn!("Not debugging can't show registers");
+                }
+            }
+            Command::Info(InfoKind::InferiorArgs) => {
+                if let Some(sm) = self.debugger.as_ref() {
+                    let args = sm.startup_args()?;
+                    info!("argc = {}", args.argv.len());
+                    for (i, arg) in args.argv.iter().enumerate() {
+                        info!("argv[{}] = {:?}", i, arg);
+                    }
+                    for (i, var) in args.envp.iter().enumerate() {
+                        info!("envp[{}] = {:?}", i, var);
+                    }
+                } else {
+                    warn!("Not debugging can't show the program's arguments");
+                }
+            }
+            Command::Info(InfoKind::Compiler) => {
+                if let Some(sm) = self.debugger.as_ref() {
+                    for compiler in sm.compilers()? {
+                        info!(
+                            "{} ({}): {} compile unit{}",
+                            compiler.producer.as_deref().unwrap_or("unknown producer"),
+                            compiler.language.as_deref().unwrap_or("unknown language"),
+                            compiler.units,
+                            if compiler.units == 1 { "" } else { "s" }
+                        );
+                    }
+                } else {
+                    warn!("Not debugging no debug info to look at");
+                }
+            }
+            Command::Info(InfoKind::SignalsState) => {
+                if let Some(sm) = self.debugger.as_ref() {
+                    let state = sm.root_process().signal_state()?;
+                    let names = |signals: &[String]| match signals {
+                        [] => "none".to_string(),
+                        signals => signals.join(", "),
+                    };
+                    info!("State: {}", state.state);
+                    info!("Pending: {}", names(&state.pending));
+                    info!("Blocked: {}", names(&state.blocked));
+                    info!("Ignored: {}", names(&state.ignored));
+                    info!("Caught: {}", names(&state.caught));
+                } else {
+                    warn!("Not debugging no process to look at");
+                }
+            }
+            Command::Info(InfoKind::Line(location)) => {
+                if let Some(sm) = self.debugger.as_ref() {
+                    let (file, line, ranges) = sm.line_ranges(location.as_ref())?;
+                    if ranges.is_empty() {
+                        info!("{}:{} has no code", file.display(), line);
+                    }
+                    for range in ranges {
+                        info!(
+                            "{}:{} => [0x{:x}, 0x{:x})",
+                            file.display(),
+                            line,
+                            range.start,
+                            range.end
+                        );
+                    }
+                } else {
+                    warn!("Not debugging can't look up lines");
+                }
+            }
+            Command::Call { function, args } => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    let raw_args = args.iter().map(|a| *a as u64).collect::<Vec<_>>();
+                    let result = sm.call_function(function, &raw_args)?;
+                    let args = args
+                        .iter()
+                        .map(|a| a.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    info!(
+                        "{}({}) = {} (0x{:x})",
+                        function, args, result as i64, result
+                    );
+                } else {
+                    warn!("Not debugging can't call a function");
+                }
+            }
+            Command::Assemble { addr, instruction } => {
+                if let Some(sm) = self.debugger.as_mut() {
+                    let addr = addr.resolve(sm.root_process())?;
+                    let patch = sm.assemble(addr, instruction)?;
+                    let hex = |bytes: &[u8]| {
+                        bytes
+                            .iter()
+                            .map(|b| format!("{:02x}", b))
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    };
+                    info!(
+                        "Wrote {} at 0x{:x}, replacing {}",
+                        hex(&patch.new),
+                        patch.addr,
+                        hex(&patch.old)
+                    );
+                } else {
+                    warn!("Not debugging can't patch instructions");
+                }
+            }
+            Command::Disassemble(name) => {
+                if let Some(sm) = self.debugger.as_ref() {
+                    let lines = disassemble_lines(sm, name)?;
+                    self.show_lines(&format!("disassemble {}", name), lines);
+                } else {
+                    warn!("Not debugging no code to disassemble");
+                }
+            }
+            Command::Examine { addr, len } => {
+                if let Some(sm) = self.debugger.as_ref() {
+                    let addr = addr.resolve_range(sm.root_process(), *len)?;
+                    let (lines, fault) = examine_lines(sm, addr, *len);
+                    self.show_lines(&format!("x 0x{:x} {}", addr, len), lines);
+                    if let Some(fault) = fault {
+                        warn!("{}", fault);
+                    }
+                } else {
+                    warn!("Not debugging can't examine memory");
+                }
+            }
             Command::Null => {}
         }
         Ok(())
@@ -212,12 +1690,15 @@ impl App {
             self.show_help = false;
             return Ok(());
         }
+        if self.pager.is_some() && key_event.modifiers != KeyModifiers::CONTROL {
+            if key_event.kind == KeyEventKind::Press {
+                self.handle_pager_key(key_event.code);
+            }
+            return Ok(());
+        }
         if key_event.modifiers == KeyModifiers::CONTROL {
             if key_event.code == KeyCode::Char('c') {
-                if let Some(debugger) = self.debugger.as_ref() {
-                    info!("Sending stop to child process");
-                    let _ = debugger.root_process().stop();
-                }
+                self.stop_process();
             }
         } else {
             match key_event.code {
@@ -261,17 +1742,25 @@ impl App {
                             return Ok(());
                         }
                     };
-                    if let Err(e) = self.run_command(&command) {
+                    let result = self.run_command(&command);
+                    if matches!(command, Command::StopHistory(Some(_))) {
+                        // Keep showing the old stop rather than jumping back to the current line
+                        self.refresh_breakpoints();
+                    } else {
+                        self.refresh_panels();
+                    }
+                    if let Err(e) = result {
                         error!("Failed to run command: {}", e);
-                    } else if command.store_in_history() && self.history_len > 0 {
-                        if self.command_history.back() != Some(&command_str) {
-                            while self.command_history.len() >= self.history_len.saturating_sub(1) {
-                                self.command_history.pop_front();
-                            }
-                            // So this will put nonsense onto the history we should actually parse into proper
-                            // commands
-                            self.command_history.push_back(command_str);
+                    } else if command.store_in_history()
+                        && self.settings.history_size > 0
+                        && self.command_history.back() != Some(&command_str)
+                    {
+                        while self.command_history.len() >= self.settings.history_size {
+                            self.command_history.pop_front();
                         }
+                        // So this will put nonsense onto the history we should actually parse
+                        // into proper commands
+                        self.command_history.push_back(command_str);
                     }
                 }
                 KeyCode::Esc => self.current_command.clear(),
@@ -288,9 +1777,155 @@ impl App {
         self.exit = true;
     }
 
+    /// Interrupts the debuggee (what CTRL+C does), the stop is picked up by the next wait. A
+    /// running trace is ended instead, the process is already stopped between its steps.
+    fn stop_process(&mut self) {
+        if let Some(debugger) = self.debugger.as_mut() {
+            if debugger.is_tracing() {
+                if let Err(e) = debugger.stop_trace() {
+                    error!("Failed to stop trace: {}", e);
+                }
+                return;
+            }
+            info!("Sending stop to child process");
+            if let Err(e) = debugger.root_process().stop() {
+                error!("Failed to stop process: {}", e);
+            }
+        }
+    }
+
+    fn refresh_panels(&mut self) {
+        self.refresh_source();
+        self.refresh_breakpoints();
+    }
+
+    /// Shows the source for where the process is stopped, keeping the current line in view.
+    fn refresh_source(&mut self) {
+        let Some(sm) = self.debugger.as_ref() else {
+            self.source = None;
+            return;
+        };
+        if sm.root_process().state() != State::Stopped {
+            return;
+        }
+        let Some(location) = sm
+            .root_process()
+            .pc()
+            .ok()
+            .and_then(|pc| sm.source_location(pc))
+        else {
+            if let Some(source) = self.source.as_mut() {
+                source.current_line = None;
+            }
+            return;
+        };
+        if self.scroll_to_location(&location) {
+            if let Some(source) = self.source.as_mut() {
+                source.current_line = Some(location.line);
+            }
+        }
+    }
+
+    /// Shows the file containing `location` in the source view with the line visible. Returns
+    /// false if the file couldn't be read.
+    fn scroll_to_location(&mut self, location: &SourceLocation) -> bool {
+        if self.source.as_ref().map(|s| &s.path) != Some(&location.file) {
+            match fs::read_to_string(&location.file) {
+                Ok(contents) => {
+                    self.source = Some(SourceView {
+                        path: location.file.clone(),
+                        lines: contents.lines().map(str::to_string).collect(),
+                        current_line: None,
+                        scroll: 0,
+                    });
+                }
+                Err(e) => {
+                    warn!("Couldn't read {}: {}", location.file.display(), e);
+                    self.source = None;
+                    return false;
+                }
+            }
+        }
+        // Minus the borders
+        let height = self
+            .layout(self.last_area.get())
+            .view
+            .height
+            .saturating_sub(2) as usize;
+        // Keep up to half of listsize lines either side of the line in view
+        let context = (self.settings.list_size / 2).min(height.saturating_sub(1) / 2);
+        if let Some(source) = self.source.as_mut() {
+            let index = location.line.saturating_sub(1) as usize;
+            if index < source.scroll + context || index + context >= source.scroll + height {
+                source.scroll = index.saturating_sub(height / 2);
+            }
+        }
+        true
+    }
+
+    fn refresh_breakpoints(&mut self) {
+        self.breakpoint_rows.clear();
+        self.source_breakpoints.clear();
+        let Some(sm) = self.debugger.as_ref() else {
+            return;
+        };
+        for bp in sm.list_breakpoints() {
+            if let (Some(loc), Some(source)) = (bp.source_loc.as_ref(), self.source.as_ref()) {
+                if loc.file == source.path {
+                    self.source_breakpoints.insert(loc.line);
+                }
+            }
+            let location = sm.function_location(bp.addr).unwrap_or_default();
+            let enabled = if bp.enabled { "yes" } else { "no" };
+            self.breakpoint_rows.push([
+                bp.id.to_string(),
+                format!("0x{:x}", bp.addr),
+                location,
+                enabled.to_string(),
+                bp.hit_count.to_string(),
+            ]);
+        }
+    }
+
     fn toggle_logs(&mut self) {
         self.show_logs = !self.show_logs;
     }
+
+    fn layout(&self, area: Rect) -> AppLayout {
+        let (view, bottom, prompt) = if self.show_logs || self.show_output {
+            let [view, bottom, prompt] =
+                Layout::vertical([Constraint::Fill(5), Constraint::Fill(3), Constraint::Max(1)])
+                    .areas(area);
+            (view, Some(bottom), prompt)
+        } else {
+            let [view, prompt] =
+                Layout::vertical([Constraint::Fill(8), Constraint::Max(1)]).areas(area);
+            (view, None, prompt)
+        };
+        let (logs, output) = match bottom {
+            Some(bottom) if self.show_logs && self.show_output => {
+                let [logs, output] =
+                    Layout::horizontal([Constraint::Fill(3), Constraint::Fill(2)]).areas(bottom);
+                (Some(logs), Some(output))
+            }
+            Some(bottom) if self.show_logs => (Some(bottom), None),
+            bottom => (None, bottom),
+        };
+        let (view, breakpoints) = if self.show_breakpoints {
+            let [view, breakpoints] =
+                Layout::horizontal([Constraint::Fill(3), Constraint::Fill(2)]).areas(view);
+            (view, Some(breakpoints))
+        } else {
+            (view, None)
+        };
+        AppLayout {
+            view,
+            logs,
+            breakpoints,
+            output,
+            prompt,
+        }
+    }
 }
 
 impl Widget for &App {
@@ -303,11 +1938,15 @@ impl Widget for &App {
             "<?> ".blue().bold(),
         ]);
 
-        let [view, prompt] = if self.show_logs {
-            let [view, logs, prompt] =
-                Layout::vertical([Constraint::Fill(5), Constraint::Fill(3), Constraint::Max(1)])
-                    .areas(area);
+        let AppLayout {
+            view,
+            logs,
+            breakpoints,
+            output,
+            prompt,
+        } = self.layout(area);
 
+        if let Some(logs) = logs {
             let block = Block::bordered().border_set(border::THICK);
 
             TuiLoggerWidget::default()
@@ -318,23 +1957,45 @@ impl Widget for &App {
                 .output_file(true)
                 .output_line(true)
                 .block(block)
+                .state(&self.log_state)
                 .render(logs, buf);
-            [view, prompt]
-        } else {
-            Layout::vertical([Constraint::Fill(8), Constraint::Max(1)]).areas(area)
-        };
+        }
 
-        let block = Block::bordered()
+        if let Some(breakpoints) = breakpoints {
+            self.render_breakpoints(breakpoints, buf);
+        }
+
+        if let Some(output) = output {
+            self.render_output(output, buf);
+        }
+
+        let mut block = Block::bordered()
             .title(title.centered())
             .title_bottom(instructions.centered())
             .border_set(border::THICK);
+        if let Some(signal) = self
+            .debugger
+            .as_ref()
+            .and_then(|sm| sm.root_process().current_signal())
+        {
+            block = block.title_bottom(
+                Line::from(format!(" pending signal: {} ", signal))
+                    .red()
+                    .right_aligned(),
+            );
+        }
 
-        let view_window = Text::from(vec![Line::from(self.args.name())]);
+        if let Some(source) = self.source.as_ref() {
+            let block = block.title(format!(" {} ", source.path.display()));
+            self.render_source(source, block, view, buf);
+        } else {
+            let view_window = Text::from(vec![Line::from(self.args.name())]);
 
-        Paragraph::new(view_window)
-            .centered()
-            .block(block)
-            .render(view, buf);
+            Paragraph::new(view_window)
+                .centered()
+                .block(block)
+                .render(view, buf);
+        }
 
         Line::from(vec![
             Span::styled("rb> ", Style::new().blue()),
@@ -345,6 +2006,81 @@ impl Widget for &App {
     }
 }
 
+impl App {
+    fn render_output(&self, area: Rect, buf: &mut Buffer) {
+        let mut title = format!(
+            " Output ({}/{}) ",
+            self.output.len(),
+            self.output.capacity()
+        );
+        if self.output.dropped() > 0 {
+            title = format!("{}{} dropped ", title, self.output.dropped());
+        }
+        let block = Block::bordered().title(title).border_set(border::THICK);
+        // Show the newest lines that fit, or older ones if scrolled up
+        let height = block.inner(area).height as usize;
+        let mut lines = self
+            .output
+            .lines_with_stream()
+            .rev()
+            .skip(self.output_scroll)
+            .take(height)
+            .map(|(stream, line)| match stream {
+                Stream::Stdout => Line::raw(line),
+                Stream::Stderr => Line::styled(line, Style::new().red()),
+            })
+            .collect::<Vec<_>>();
+        lines.reverse();
+        Paragraph::new(lines).block(block).render(area, buf);
+    }
+
+    fn render_source(&self, source: &SourceView, block: Block, area: Rect, buf: &mut Buffer) {
+        let width = source.lines.len().to_string().len();
+        let lines = source
+            .lines
+            .iter()
+            .enumerate()
+            .skip(source.scroll)
+            .map(|(index, text)| {
+                let line = index as u64 + 1;
+                let marker = if self.source_breakpoints.contains(&line) {
+                    Span::styled("● ", Style::new().red())
+                } else {
+                    Span::raw("  ")
+                };
+                let number = Span::styled(format!("{:>width$} ", line), Style::new().dark_gray());
+                let text = if source.current_line == Some(line) {
+                    Span::styled(text.as_str(), Style::new().black().on_yellow())
+                } else {
+                    Span::raw(text.as_str())
+                };
+                Line::from(vec![marker, number, text])
+            })
+            .collect::<Vec<_>>();
+        Paragraph::new(lines).block(block).render(area, buf);
+    }
+
+    fn render_breakpoints(&self, area: Rect, buf: &mut Buffer) {
+        let header = Row::new(["ID", "ADDRESS", "LOCATION", "ENABLED", "HITS"]).bold();
+        let rows = self.breakpoint_rows.iter().cloned().map(Row::new);
+        let widths = [
+            Constraint::Length(4),
+            Constraint::Length(18),
+            Constraint::Fill(1),
+            Constraint::Length(7),
+            Constraint::Length(6),
+        ];
+        Table::new(rows, widths)
+            .header(header)
+            .block(
+                Block::bordered()
+                    .title(" Breakpoints ")
+                    .border_set(border::THICK),
+            )
+            .render(area, buf);
+    }
+}
+
 /// helper function to create a centered rect using up certain percentage of the available rect `r`
 fn popup_area(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
     let vertical = Layout::vertical([Constraint::Percentage(percent_y)]).flex(Flex::Center);
@@ -353,3 +2089,453 @@ fn popup_area(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
     let [area] = horizontal.areas(area);
     area
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::sys::signal::Signal;
+    use rusty_fork::rusty_fork_test;
+    use rustybug::process::Info;
+
+    #[test]
+    fn list_window_stays_in_file() {
+        assert_eq!(list_window(20, 10, 100), 15..25);
+        assert_eq!(list_window(2, 10, 100), 0..10);
+        assert_eq!(list_window(98, 10, 100), 90..100);
+        assert_eq!(list_window(3, 10, 5), 0..5);
+        assert_eq!(list_window(0, 1, 5), 0..1);
+    }
+
+    #[test]
+    fn hexdump_rows() {
+        let bytes = b"\x7fELF\x02\x01\x01\x00 hello world!\n";
+        let rows = hexdump(0x1000, bytes);
+        assert_eq!(
+            rows,
+            [
+                "0000000000001000  7f 45 4c 46 02 01 01 00  20 68 65 6c 6c 6f 20 77  |.ELF.... hello w|",
+                "0000000000001010  6f 72 6c 64 21 0a                                 |orld!.|",
+            ]
+        );
+        assert!(hexdump(0x1000, &[]).is_empty());
+    }
+
+    #[test]
+    fn timing_report_summary() {
+        let mut report = TimingReport::default();
+        report.push("break main", Duration::from_millis(2));
+        report.push("continue", Duration::from_millis(30));
+        report.push("print x", Duration::from_millis(1));
+        assert_eq!(report.total(), Duration::from_millis(33));
+        let text = report.to_string();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].trim_start().starts_with("30.000ms  continue"));
+        assert_eq!(
+            lines[3],
+            "3 commands in 33.000ms, slowest: continue (30.000ms)"
+        );
+    }
+
+    #[test]
+    fn output_panel_shows_newest_lines() {
+        let mut app = App {
+            show_output: true,
+            ..Default::default()
+        };
+        for i in 0..100 {
+            app.output.push_str(&format!("line {}\n", i));
+        }
+        let area = Rect::new(0, 0, 80, 30);
+        app.last_area.set(area);
+        let layout = app.layout(area);
+        assert!(layout.logs.is_none());
+        let panel = layout.output.unwrap();
+
+        let panel_text = |app: &App| {
+            let mut buf = Buffer::empty(area);
+            app.render(area, &mut buf);
+            (panel.y + 1..panel.bottom() - 1)
+                .map(|y| {
+                    (panel.x + 1..panel.right() - 1)
+                        .map(|x| buf[(x, y)].symbol())
+                        .collect::<String>()
+                        .trim_end()
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+        };
+        let text = panel_text(&app);
+        assert_eq!(text.last().unwrap(), "line 99");
+        assert!(!text.contains(&"line 0".to_string()));
+
+        let scroll = MouseEvent {
+            kind: MouseEventKind::ScrollUp,
+            column: panel.x + 2,
+            row: panel.y + 2,
+            modifiers: KeyModifiers::NONE,
+        };
+        app.handle_mouse_event(scroll).unwrap();
+        let text = panel_text(&app);
+        assert_eq!(text.last().unwrap(), &format!("line {}", 99 - SCROLL_LINES));
+    }
+
+    #[test]
+    fn long_output_is_paged() {
+        let mut app = App::default();
+        let area = Rect::new(0, 0, 80, 30);
+        app.last_area.set(area);
+        let height = app.page_height();
+        assert!(height > 0);
+
+        // Output that fits on a page isn't paged
+        app.show_lines("short", vec!["only line".to_string()]);
+        assert!(app.pager.is_none());
+
+        let lines = (0..height * 2 + 1)
+            .map(|i| format!("line {}", i))
+            .collect::<Vec<_>>();
+        app.show_lines("long", lines.clone());
+        let pager_text = |app: &App| {
+            let mut buf = Buffer::empty(area);
+            app.render_pager(app.pager.as_ref().unwrap(), area, &mut buf);
+            (0..area.height)
+                .map(|y| {
+                    (0..area.width)
+                        .map(|x| buf[(x, y)].symbol())
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let text = pager_text(&app);
+        assert!(text.contains("line 0"));
+        assert!(!text.contains(&format!("line {}", height)));
+        assert!(text.contains(&format!("--More-- ({}/{})", height, lines.len())));
+
+        let space = KeyEvent::new(KeyCode::Char(' '), KeyModifiers::NONE);
+        app.handle_key_event(space).unwrap();
+        assert!(pager_text(&app).contains(&format!("line {}", height)));
+        // Typing doesn't go to the prompt while paging
+        assert!(app.current_command.is_empty());
+
+        app.handle_key_event(space).unwrap();
+        assert!(pager_text(&app).contains("--End--"));
+        app.handle_key_event(space).unwrap();
+        assert!(app.pager.is_none());
+
+        app.show_lines("long", lines.clone());
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE))
+            .unwrap();
+        assert!(app.pager.is_none());
+
+        app.settings.pagination = false;
+        app.show_lines("long", lines);
+        assert!(app.pager.is_none());
+    }
+
+    #[test]
+    fn history_keeps_history_size_commands() {
+        for size in [1, 2] {
+            let mut app = App::default();
+            app.settings.history_size = size;
+            for listsize in 1..=3 {
+                for c in format!("set listsize {}", listsize).chars() {
+                    app.handle_key_event(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+                        .unwrap();
+                }
+                app.handle_key_event(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+                    .unwrap();
+            }
+            let expected = (4 - size..=3)
+                .map(|listsize| format!("set listsize {}", listsize))
+                .collect::<Vec<_>>();
+            assert_eq!(Vec::from(app.command_history.clone()), expected);
+        }
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn ctrl_c_stops_process() {
+            let args = Args {
+                input: Some("tests/data/apps/build/dont_stop".into()),
+                pid: None,
+                ..Default::default()
+            };
+            let mut app = App {
+                debugger: Some(DebuggerStateMachine::start(args).unwrap()),
+                ..Default::default()
+            };
+
+            let sm = app.debugger.as_mut().unwrap();
+            sm.cont().unwrap();
+            assert_eq!(sm.root_process().state(), State::Running);
+
+            app.handle_key_event(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL))
+                .unwrap();
+
+            let sm = app.debugger.as_mut().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(1)).unwrap();
+            assert_eq!(reason.reason, State::Stopped);
+            assert_eq!(reason.info, Info::Signalled(Signal::SIGSTOP));
+            assert_eq!(sm.root_process().state(), State::Stopped);
+        }
+
+        #[test]
+        fn quitting_needs_confirming() {
+            let args = Args {
+                input: Some("tests/data/apps/build/loop".into()),
+                pid: None,
+                ..Default::default()
+            };
+            let mut app = App {
+                debugger: Some(DebuggerStateMachine::start(args).unwrap()),
+                settings: Settings::default(),
+                ..Default::default()
+            };
+            app.run_command(&Command::Quit).unwrap();
+            assert!(!app.exit);
+            // Anything else in between means asking again
+            app.run_command(&Command::Status).unwrap();
+            app.run_command(&Command::Quit).unwrap();
+            assert!(!app.exit);
+            app.run_command(&Command::Quit).unwrap();
+            assert!(app.exit);
+
+            app.exit = false;
+            app.settings.confirm = false;
+            app.run_command(&Command::Quit).unwrap();
+            assert!(app.exit);
+
+            // Nothing to lose once the process is gone
+            app.exit = false;
+            app.settings.confirm = true;
+            app.debugger = None;
+            app.run_command(&Command::Quit).unwrap();
+            assert!(app.exit);
+        }
+
+        #[test]
+        fn clicking_breakpoint_toggles_it() {
+            let args = Args {
+                input: Some("tests/data/apps/build/test_project".into()),
+                pid: None,
+                ..Default::default()
+            };
+            let mut app = App {
+                debugger: Some(DebuggerStateMachine::start(args).unwrap()),
+                show_breakpoints: true,
+                ..Default::default()
+            };
+            app.last_area.set(Rect::new(0, 0, 100, 40));
+
+            let sm = app.debugger.as_mut().unwrap();
+            let pc = sm.root_process().pc().unwrap();
+            sm.root_process_mut().set_breakpoint(pc).unwrap();
+            app.refresh_breakpoints();
+
+            let panel = app.layout(app.last_area.get()).breakpoints.unwrap();
+            let click = MouseEvent {
+                kind: MouseEventKind::Down(MouseButton::Left),
+                column: panel.x + 2,
+                // First row after the border and header
+                row: panel.y + 2,
+                modifiers: KeyModifiers::NONE,
+            };
+            app.handle_mouse_event(click).unwrap();
+            assert_eq!(app.breakpoint_rows[0][3], "no");
+            app.handle_mouse_event(click).unwrap();
+            assert_eq!(app.breakpoint_rows[0][3], "yes");
+        }
+
+        #[test]
+        fn clicking_source_line_toggles_breakpoint() {
+            let args = Args {
+                input: Some("tests/data/apps/build/loop".into()),
+                pid: None,
+                ..Default::default()
+            };
+            let mut app = App {
+                debugger: Some(DebuggerStateMachine::start(args).unwrap()),
+                ..Default::default()
+            };
+            app.last_area.set(Rect::new(0, 0, 100, 40));
+
+            let sm = app.debugger.as_mut().unwrap();
+            sm.set_break(&Location::Function("main".to_string())).unwrap();
+            sm.cont().unwrap();
+            sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            app.refresh_panels();
+
+            let source = app.source.as_ref().unwrap();
+            assert!(source.path.ends_with("loop.c"));
+            assert_eq!(source.current_line, Some(9));
+            assert!(app.source_breakpoints.contains(&9));
+
+            let view = app.layout(app.last_area.get()).view;
+            let click = MouseEvent {
+                kind: MouseEventKind::Down(MouseButton::Left),
+                column: view.x + 4,
+                // Line 6 when scrolled to the top, after the border
+                row: view.y + 6,
+                modifiers: KeyModifiers::NONE,
+            };
+            app.handle_mouse_event(click).unwrap();
+            assert!(app.source_breakpoints.contains(&6));
+            assert_eq!(app.breakpoint_rows.len(), 2);
+
+            app.handle_mouse_event(click).unwrap();
+            assert!(!app.source_breakpoints.contains(&6));
+            assert_eq!(app.breakpoint_rows.len(), 1);
+        }
+
+        #[test]
+        fn batch_runs_each_command() {
+            let script = std::env::temp_dir().join(format!("rustybug-batch-{}", std::process::id()));
+            fs::write(
+                &script,
+                "# stop in increment twice\nbreak increment\n\ncontinue\nnot-a-command\ncontinue\n",
+            )
+            .unwrap();
+            let mut app = App {
+                args: Args {
+                    input: Some("tests/data/apps/build/loop".into()),
+                    ..Default::default()
+                },
+                settings: Settings {
+                    confirm: false,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let report = app.run_batch(&script).unwrap();
+            fs::remove_file(&script).unwrap();
+
+            let commands = report.commands.iter().map(|(c, _)| c.as_str()).collect::<Vec<_>>();
+            assert_eq!(commands, ["break increment", "continue", "not-a-command", "continue"]);
+            let sm = app.debugger.as_ref().unwrap();
+            // Each continue waited for the stop before the next one ran
+            assert_eq!(sm.root_process().state(), State::Stopped);
+            assert_eq!(app.breakpoint_rows[0][4], "2");
+
+            // Quitting ends the batch early
+            let script = std::env::temp_dir().join(format!("rustybug-quit-{}", std::process::id()));
+            fs::write(&script, "quit\ncontinue\n").unwrap();
+            let report = app.run_batch(&script).unwrap();
+            fs::remove_file(&script).unwrap();
+            assert_eq!(report.commands.len(), 1);
+            assert!(app.exit);
+        }
+
+        #[test]
+        fn exit_status_kept_after_exit() {
+            let script =
+                std::env::temp_dir().join(format!("rustybug-exit-{}", std::process::id()));
+            fs::write(&script, "continue\nstatus\n").unwrap();
+            let mut app = App {
+                args: Args {
+                    input: Some("tests/data/apps/build/loop".into()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            app.run_batch(&script).unwrap();
+            fs::remove_file(&script).unwrap();
+
+            assert!(app.debugger.is_none());
+            assert_eq!(app.exit_status, Some(Info::Return(0)));
+
+            // Starting again forgets how the last one ended
+            app.start_debugger().unwrap();
+            assert_eq!(app.exit_status, None);
+        }
+
+        #[test]
+        fn batch_continue_in_background() {
+            let script =
+                std::env::temp_dir().join(format!("rustybug-background-{}", std::process::id()));
+            fs::write(&script, "continue &\ninfo version\ninterrupt\n").unwrap();
+            let mut app = App {
+                args: Args {
+                    input: Some("tests/data/apps/build/sleeper".into()),
+                    ..Default::default()
+                },
+                settings: Settings {
+                    confirm: false,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let report = app.run_batch(&script).unwrap();
+            fs::remove_file(&script).unwrap();
+
+            // The sleep is 500ms, neither command waited for it
+            assert_eq!(report.commands.len(), 3);
+            assert!(report.total() < Duration::from_millis(400), "{}", report);
+            let sm = app.debugger.as_ref().unwrap();
+            assert_eq!(sm.root_process().state(), State::Stopped);
+            assert!(!app.background);
+        }
+
+        #[test]
+        fn displays_shown_on_stop() {
+            let script =
+                std::env::temp_dir().join(format!("rustybug-display-{}", std::process::id()));
+            fs::write(
+                &script,
+                "display counter\nbreak loop.c:6\ncontinue\ndisplay rax + 1\ndisplay missing\nundisplay 3\n",
+            )
+            .unwrap();
+            let mut app = App {
+                args: Args {
+                    input: Some("tests/data/apps/build/loop".into()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            app.run_batch(&script).unwrap();
+            fs::remove_file(&script).unwrap();
+            assert_eq!(app.displays.len(), 2);
+
+            let rax = app.debugger.as_ref().unwrap().get_registers().unwrap().regs.rax;
+            let lines = app.display_lines();
+            assert_eq!(lines[0], "1: counter = 0");
+            assert!(lines[1].starts_with(&format!("2: rax + 1 = {}", rax + 1)));
+
+            app.run_command(&Command::Continue).unwrap();
+            app.wait_for_stop();
+            assert_eq!(app.display_lines()[0], "1: counter = 1");
+
+            app.run_command(&Command::from_str("display x 0x0 8").unwrap())
+                .unwrap();
+            let lines = app.display_lines();
+            assert_eq!(lines[2], "4: x 0x0 8");
+            assert!(lines[3].starts_with("Couldn't read memory at 0x0"));
+        }
+
+        #[test]
+        fn batch_wait_for_background_stop() {
+            let script =
+                std::env::temp_dir().join(format!("rustybug-wait-{}", std::process::id()));
+            fs::write(&script, "break sleeper.c:6\ncontinue &\nwait\nwait 5\nwait\n").unwrap();
+            let mut app = App {
+                args: Args {
+                    input: Some("tests/data/apps/build/sleeper".into()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let report = app.run_batch(&script).unwrap();
+            fs::remove_file(&script).unwrap();
+
+            // Checking without a timeout doesn't wait for the sleep
+            assert!(report.commands[2].1 < Duration::from_millis(400), "{}", report);
+            assert!(report.commands[3].1 >= Duration::from_millis(400), "{}", report);
+            let sm = app.debugger.as_ref().unwrap();
+            assert_eq!(sm.root_process().state(), State::Stopped);
+            let pc = sm.root_process().pc().unwrap();
+            assert_eq!(sm.source_location(pc).unwrap().line, 6);
+            assert!(!app.background);
+        }
+    }
+}
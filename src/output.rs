@@ -0,0 +1,193 @@
+//! Output written by the program being debugged, kept as lines so it can be scrolled through in
+//! the output panel. Only the most recent lines are kept so a chatty program can't use up all our
+//! memory. Lines remember whether they came from stdout or stderr so they can be shown
+//! differently, the two are interleaved in the order they were read.
+use std::collections::VecDeque;
+
+/// Lines of output kept by default.
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+/// Which of the program's outputs some text was written to.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Stream {
+    #[default]
+    Stdout,
+    Stderr,
+}
+
+#[derive(Clone, Debug)]
+pub struct OutputBuffer {
+    lines: VecDeque<(Stream, String)>,
+    /// Output after the last newline of each stream, shown as the last lines until the rest of
+    /// it arrives
+    partial_stdout: String,
+    partial_stderr: String,
+    capacity: usize,
+    /// Number of lines dropped to stay within the capacity
+    dropped: usize,
+}
+
+impl Default for OutputBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl OutputBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            partial_stdout: String::new(),
+            partial_stderr: String::new(),
+            capacity: capacity.max(1),
+            dropped: 0,
+        }
+    }
+
+    /// Adds stdout output to the buffer, see `push`.
+    pub fn push_str(&mut self, text: &str) {
+        self.push(Stream::Stdout, text);
+    }
+
+    /// Adds output to the buffer. Output doesn't have to be split on line boundaries, a line
+    /// read in several pieces is joined back up.
+    pub fn push(&mut self, stream: Stream, text: &str) {
+        for piece in text.split_inclusive('\n') {
+            match piece.strip_suffix('\n') {
+                Some(end) => {
+                    let mut line = std::mem::take(self.partial_mut(stream));
+                    line.push_str(end);
+                    self.lines.push_back((stream, line));
+                }
+                None => self.partial_mut(stream).push_str(piece),
+            }
+        }
+        self.truncate();
+    }
+
+    fn partial_mut(&mut self, stream: Stream) -> &mut String {
+        match stream {
+            Stream::Stdout => &mut self.partial_stdout,
+            Stream::Stderr => &mut self.partial_stderr,
+        }
+    }
+
+    /// Changes how many lines are kept, dropping the oldest if there are now too many.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.truncate();
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of lines dropped because the buffer was full.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+            + usize::from(!self.partial_stdout.is_empty())
+            + usize::from(!self.partial_stderr.is_empty())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The lines in the buffer oldest first, including unfinished last lines.
+    pub fn lines(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.lines_with_stream().map(|(_, line)| line)
+    }
+
+    /// The lines in the buffer oldest first along with the stream they came from.
+    pub fn lines_with_stream(&self) -> impl DoubleEndedIterator<Item = (Stream, &str)> {
+        let partials = [
+            (Stream::Stdout, self.partial_stdout.as_str()),
+            (Stream::Stderr, self.partial_stderr.as_str()),
+        ];
+        self.lines
+            .iter()
+            .map(|(stream, line)| (*stream, line.as_str()))
+            .chain(partials.into_iter().filter(|(_, line)| !line.is_empty()))
+    }
+
+    fn truncate(&mut self) {
+        while self.len() > self.capacity {
+            if self.lines.pop_front().is_none() {
+                // Only partial lines are left and they don't fit so they have to go too
+                if self.partial_stdout.is_empty() {
+                    self.partial_stderr.clear();
+                } else {
+                    self.partial_stdout.clear();
+                }
+            }
+            self.dropped += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_partial_lines() {
+        let mut output = OutputBuffer::default();
+        assert!(output.is_empty());
+        output.push_str("hello ");
+        assert_eq!(output.lines().collect::<Vec<_>>(), ["hello "]);
+        output.push_str("world\nsecond\nthi");
+        output.push_str("rd\n");
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            ["hello world", "second", "third"]
+        );
+        output.push_str("\n");
+        assert_eq!(output.len(), 4);
+        assert_eq!(output.lines().last(), Some(""));
+    }
+
+    #[test]
+    fn drops_oldest_lines() {
+        let mut output = OutputBuffer::new(3);
+        for i in 0..5 {
+            output.push_str(&format!("line {}\n", i));
+        }
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            ["line 2", "line 3", "line 4"]
+        );
+        assert_eq!(output.dropped(), 2);
+
+        output.push_str("partial");
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            ["line 3", "line 4", "partial"]
+        );
+
+        output.set_capacity(1);
+        assert_eq!(output.lines().collect::<Vec<_>>(), ["partial"]);
+        assert_eq!(output.dropped(), 5);
+        assert_eq!(output.capacity(), 1);
+    }
+
+    #[test]
+    fn keeps_streams_apart() {
+        let mut output = OutputBuffer::default();
+        output.push(Stream::Stdout, "out ");
+        output.push(Stream::Stderr, "error\nerr ");
+        output.push(Stream::Stdout, "line\n");
+        assert_eq!(
+            output.lines_with_stream().collect::<Vec<_>>(),
+            [
+                (Stream::Stderr, "error"),
+                (Stream::Stdout, "out line"),
+                (Stream::Stderr, "err ")
+            ]
+        );
+        assert_eq!(output.len(), 3);
+    }
+}
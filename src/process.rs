@@ -1,30 +1,203 @@
+use crate::address::MAX_LENGTH;
 use crate::breakpoint::*;
 use crate::linux::launch_program;
 use crate::ptrace_control::*;
+use crate::syscalls::{self, SyscallReturn};
 use libc::{c_int, user_fpregs_struct, user_regs_struct};
 use nix::errno::Errno;
 use nix::sys::ptrace::{self, regset};
 use nix::sys::signal::{kill, Signal};
 use nix::sys::wait::*;
-use nix::unistd::Pid;
+use nix::unistd::{read, write, Pid};
 use procfs::process::{MMapPath, Process as PfsProcess};
+#[cfg(test)]
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
 use std::os::fd::{AsRawFd, OwnedFd};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 use thiserror::Error;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
+
+/// Maximum amount of process output read in one go.
+const OUTPUT_BUFFER_SIZE: usize = 65536;
+
+/// Encoding of the `syscall` instruction.
+const SYSCALL: [u8; 2] = [0x0f, 0x05];
+
+/// Set once we've warned that `/proc` can't be read, so it's only said once however many
+/// processes are debugged.
+static PROC_WARNING_SHOWN: AtomicBool = AtomicBool::new(false);
+
+#[cfg(test)]
+thread_local! {
+    /// Makes reads of `/proc` on this thread fail, to test what happens when it isn't mounted.
+    pub(crate) static FAIL_PROC_READS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Opens `/proc/<pid>`, everything read from `/proc` goes through here.
+pub(crate) fn proc_entry(pid: Pid) -> procfs::ProcResult<PfsProcess> {
+    #[cfg(test)]
+    if FAIL_PROC_READS.with(|fail| fail.get()) {
+        return Err(procfs::ProcError::NotFound(None));
+    }
+    PfsProcess::new(pid.as_raw())
+}
+
+/// How long to wait for the process to stop after it's launched or attached to.
+const INITIAL_STOP_TIMEOUT: Duration = Duration::from_secs(15);
 
 #[derive(Clone, Debug)]
 pub struct Registers {
     pub regs: user_regs_struct,
-    pub fpregs: user_fpregs_struct,
+    /// Floating point and vector registers, `None` if they weren't read or can't be read on this
+    /// system
+    pub fpregs: Option<user_fpregs_struct>,
+}
+
+/// The x86 debug registers of a thread. DR4 and DR5 are aliases of DR6 and DR7 so aren't
+/// included.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DebugRegisters {
+    /// Addresses watched by the four hardware breakpoint slots
+    pub dr0: u64,
+    pub dr1: u64,
+    pub dr2: u64,
+    pub dr3: u64,
+    /// Status, which slots matched at the last debug exception
+    pub dr6: u64,
+    /// Control, which slots are enabled and what each one watches for
+    pub dr7: u64,
+}
+
+/// Subsets of the registers shown by `info registers <GROUP>`, like gdb's register groups.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum RegisterGroup {
+    #[default]
+    All,
+    /// Integer registers, the instruction pointer and the syscall number
+    General,
+    /// Segment selectors and the bases of the fs and gs segments
+    Segment,
+    Flags,
+}
+
+/// Bits of `eflags` shown when the flags register is printed.
+const EFLAGS: &[(u64, &str)] = &[
+    (1 << 0, "CF"),
+    (1 << 2, "PF"),
+    (1 << 4, "AF"),
+    (1 << 6, "ZF"),
+    (1 << 7, "SF"),
+    (1 << 8, "TF"),
+    (1 << 9, "IF"),
+    (1 << 10, "DF"),
+    (1 << 11, "OF"),
+];
+
+impl RegisterGroup {
+    pub const NAMES: &'static [(&'static str, RegisterGroup)] = &[
+        ("all", Self::All),
+        ("general", Self::General),
+        ("segment", Self::Segment),
+        ("flags", Self::Flags),
+    ];
+
+    /// Names of the registers in the group, in the order they're shown.
+    pub fn registers(&self) -> &'static [&'static str] {
+        match self {
+            Self::All => Registers::GENERAL_PURPOSE,
+            Self::General => &[
+                "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11",
+                "r12", "r13", "r14", "r15", "rip", "orig_rax",
+            ],
+            Self::Segment => &["cs", "ss", "ds", "es", "fs", "gs", "fs_base", "gs_base"],
+            Self::Flags => &["eflags"],
+        }
+    }
+}
+
+impl Registers {
+    /// Formats the registers in a group, one per line with the value in hex and decimal. The
+    /// flags register shows which flags are set instead of the decimal value.
+    pub fn format_group(&self, group: RegisterGroup) -> Vec<String> {
+        group
+            .registers()
+            .iter()
+            .filter_map(|name| Some((*name, self.get(name)?)))
+            .map(|(name, value)| {
+                if name == "eflags" {
+                    let flags = EFLAGS
+                        .iter()
+                        .filter(|(bit, _)| value & bit != 0)
+                        .map(|(_, flag)| *flag)
+                        .collect::<Vec<_>>();
+                    format!("{:<10}0x{:<18x}[ {} ]", name, value, flags.join(" "))
+                } else {
+                    format!("{:<10}0x{:<18x}{}", name, value, value as i64)
+                }
+            })
+            .collect()
+    }
+
+    /// Names of the general purpose registers that can be looked up with `get`.
+    pub const GENERAL_PURPOSE: &'static [&'static str] = &[
+        "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12",
+        "r13", "r14", "r15", "rip", "eflags", "cs", "ss", "ds", "es", "fs", "gs", "fs_base",
+        "gs_base", "orig_rax",
+    ];
+
+    /// Gets a general purpose register by name.
+    pub fn get(&self, name: &str) -> Option<u64> {
+        let regs = &self.regs;
+        let value = match name {
+            "rax" => regs.rax,
+            "rbx" => regs.rbx,
+            "rcx" => regs.rcx,
+            "rdx" => regs.rdx,
+            "rsi" => regs.rsi,
+            "rdi" => regs.rdi,
+            "rbp" => regs.rbp,
+            "rsp" => regs.rsp,
+            "r8" => regs.r8,
+            "r9" => regs.r9,
+            "r10" => regs.r10,
+            "r11" => regs.r11,
+            "r12" => regs.r12,
+            "r13" => regs.r13,
+            "r14" => regs.r14,
+            "r15" => regs.r15,
+            "rip" => regs.rip,
+            "eflags" => regs.eflags,
+            "cs" => regs.cs,
+            "ss" => regs.ss,
+            "ds" => regs.ds,
+            "es" => regs.es,
+            "fs" => regs.fs,
+            "gs" => regs.gs,
+            "fs_base" => regs.fs_base,
+            "gs_base" => regs.gs_base,
+            "orig_rax" => regs.orig_rax,
+            _ => return None,
+        };
+        Some(value)
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum TrapType {
     SingleStep,
     SoftwareBreak,
+    /// A debug register matched. Execution breakpoints stop before the instruction runs so the
+    /// PC doesn't need rewinding, and the kernel sets the resume flag so continuing doesn't
+    /// immediately trap again
     HardwareBreak,
+    /// A SIGTRAP that didn't come from one of our breakpoints or a step we asked for, like an
+    /// `int3` compiled into the program or a SIGTRAP sent by another process
+    Spurious,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -34,6 +207,11 @@ pub enum Event {
     Fork,
     Vfork,
     Spawn,
+    /// A seccomp filter returned `SECCOMP_RET_TRACE` for a syscall
+    Seccomp,
+    /// Entry to or exit from a syscall after `PTRACE_SYSCALL`, this isn't a ptrace event but a
+    /// stop with `SIGTRAP | 0x80` as the signal
+    SyscallStop,
 }
 
 impl TryFrom<i32> for Event {
@@ -49,6 +227,7 @@ impl TryFrom<i32> for Event {
             PTRACE_EVENT_CLONE => Ok(Self::Spawn),
             PTRACE_EVENT_EXEC => Ok(Self::Exec),
             PTRACE_EVENT_EXIT => Ok(Self::Exit),
+            PTRACE_EVENT_SECCOMP => Ok(Self::Seccomp),
             _ => Err(Errno::UnknownErrno),
         }
     }
@@ -60,6 +239,11 @@ pub struct StopReason {
     pub info: Info,
     pub event: Option<Event>,
     pub trap_reason: Option<TrapType>,
+    /// How the process is going to exit when stopped at `Event::Exit`. The process is still
+    /// there at this point so its registers and memory can be looked at one last time.
+    pub exit_status: Option<Info>,
+    /// Whether the signal that killed the process left a core dump
+    pub core_dumped: bool,
 }
 
 impl StopReason {
@@ -69,6 +253,8 @@ impl StopReason {
             info,
             event: None,
             trap_reason: None,
+            exit_status: None,
+            core_dumped: false,
         }
     }
 }
@@ -85,11 +271,13 @@ pub enum State {
     Running,
     Exited,
     Terminated,
+    /// We've detached and left the process running on its own
+    Detached,
 }
 
 impl State {
     pub fn is_closed(&self) -> bool {
-        matches!(self, Self::Exited | Self::Terminated)
+        matches!(self, Self::Exited | Self::Terminated | Self::Detached)
     }
 }
 
@@ -99,6 +287,8 @@ pub enum ProcessError {
     NoPid,
     #[error("failed to launch process")]
     LaunchFailed,
+    #[error("working directory doesn't exist")]
+    NoWorkingDirectory,
     #[error("failed to attach to process")]
     AttachFailed,
     #[error("failed to wait on the process pid")]
@@ -111,33 +301,178 @@ pub enum ProcessError {
     Timeout,
     #[error("failed to write data")]
     WriteFailed,
+    #[error("process stdin isn't connected to the debugger")]
+    NoStdin,
     #[error("couldn't read user registers")]
     RegisterReadFailed,
-    #[error("couldn't read user fp registers")]
-    FpRegisterReadFailed,
     #[error("couldn't write user registers")]
     RegisterWriteFailed,
     #[error("couldn't write user fp registers")]
     FpRegisterWriteFailed,
     #[error("couldn't add breakpoint")]
     BreakpointSetFailed,
+    #[error(
+        "all {} hardware breakpoint slots are in use",
+        HARDWARE_BREAKPOINT_SLOTS
+    )]
+    NoFreeDebugRegister,
+    #[error("no breakpoint with id {0}")]
+    NoBreakpoint(u64),
+    #[error("process is not stopped, it's {0:?}")]
+    NotStopped(State),
+    #[error("process no longer exists")]
+    Gone,
+    #[error("detached from the process")]
+    Detached,
     #[error("couldn't use kill syscall on process")]
     KillFailed,
+    #[error("couldn't read process information from /proc")]
+    ProcReadFailed,
+    #[error("couldn't read memory at 0x{0:x}")]
+    ReadFailed(u64),
+    #[error(
+        "stopped in forked child {0}, breakpoints can only be changed while the process being \
+         debugged is stopped"
+    )]
+    InForkedChild(Pid),
+}
+
+/// The result of a read which may have stopped partway through the requested range.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MemoryRead {
+    /// The bytes read, starting from the requested address
+    pub data: Vec<u8>,
+    /// The first address that couldn't be read, `None` if the whole range was read
+    pub fault: Option<u64>,
+}
+
+/// Scheduler state and signal masks from `/proc/<pid>/status`, signals are given by name with
+/// real-time signals that have no name as `SIG<N>`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SignalState {
+    /// State letter and description, e.g. `t (tracing stop)`
+    pub state: String,
+    /// Pending for the thread (`SigPnd`) or the whole process (`ShdPnd`)
+    pub pending: Vec<String>,
+    pub blocked: Vec<String>,
+    pub ignored: Vec<String>,
+    /// Signals with a handler installed
+    pub caught: Vec<String>,
+}
+
+/// Names the signals in a mask from `/proc`, where bit `n - 1` is signal `n`.
+fn signal_names(mask: u64) -> Vec<String> {
+    (1..=64)
+        .filter(|signal| mask & (1 << (signal - 1)) != 0)
+        .map(|signal| match Signal::try_from(signal) {
+            Ok(signal) => signal.as_str().to_string(),
+            Err(_) => format!("SIG{}", signal),
+        })
+        .collect()
+}
+
+/// Which process is debugged after the process forks.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum FollowFork {
+    /// Keep debugging the parent, the child is let go with our breakpoints removed from it
+    #[default]
+    Parent,
+    /// Trace the child as well, its stops are reported like those of a thread. Breakpoints
+    /// can't be changed while it's the child that's stopped
+    Child,
+}
+
+/// Signals not delivered to the process by default when it continues after stopping with them.
+/// These are our own interrupts and traps that aren't breakpoints, passing them on would stop
+/// the process again or kill it.
+pub const DEFAULT_NOPASS_SIGNALS: &[Signal] = &[Signal::SIGTRAP, Signal::SIGSTOP, Signal::SIGINT];
+
+/// Parses a signal given by name with or without the `SIG` prefix in any case, or by number.
+pub fn parse_signal(name: &str) -> Result<Signal, String> {
+    let name = name.trim().to_ascii_uppercase();
+    if let Ok(number) = name.parse::<i32>() {
+        return Signal::try_from(number).map_err(|_| format!("no signal number {}", number));
+    }
+    let full = if name.starts_with("SIG") {
+        name.clone()
+    } else {
+        format!("SIG{}", name)
+    };
+    full.parse::<Signal>()
+        .map_err(|_| format!("unknown signal \"{}\"", name))
+}
+
+/// A thread in the process.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ThreadInfo {
+    pub tid: Pid,
+    /// Name from `/proc/<pid>/task/<tid>/comm`, threads inherit the name of the thread that
+    /// created them unless they set their own
+    pub name: String,
 }
 
 #[derive(Debug)]
 pub struct Process {
     pid: Pid,
+    stdin_writer: Option<OwnedFd>,
     stdout_reader: Option<OwnedFd>,
+    stderr_reader: Option<OwnedFd>,
     pub addr_offset: u64,
     terminate_on_end: bool,
     state: State,
-    breakpoints: Vec<Breakpoint>,
+    /// Breakpoints keyed by their address in the running process
+    breakpoints: HashMap<u64, Breakpoint>,
+    /// Breakpoint disabled to step over it which should be put back on the next stop
+    reenable_after_stop: Option<u64>,
+    /// Stop that happened while we were internally waiting, returned on the next wait
+    pending_stop: Option<StopReason>,
+    /// A single step was started and hasn't been waited on yet
+    step_pending: bool,
+    /// Signal the process stopped with if it was stopped by one, other than our own traps
+    current_signal: Option<Signal>,
+    /// Check a breakpoint's interrupt is still in place before restoring the original byte
+    verify_breakpoints: bool,
+    /// Signals the process stopped with that are dropped rather than delivered when it's
+    /// continued
+    nopass_signals: Vec<Signal>,
+    /// Whether `PTRACE_O_TRACESYSGOOD` is set, without it syscall stops look like a SIGTRAP
+    marks_syscall_stops: bool,
+    /// How the process ended, the exit code or the signal that killed it
+    exit_status: Option<Info>,
+    /// Threads being traced, the main thread first. New threads are only traced once
+    /// `stop_on_events` has been called
+    threads: Vec<Pid>,
+    /// Thread the last stop was for, ptrace requests about registers and execution go to it
+    current_thread: Pid,
+    /// What happens to children the process forks, only seen with `stop_on_events`
+    follow_fork: FollowFork,
+    /// Forked children being traced with `FollowFork::Child`, waited on like threads so they're
+    /// reaped when they exit
+    forks: Vec<Pid>,
+    /// Threads sent a SIGSTOP so their debug registers can be updated, they're continued once
+    /// the stop is seen
+    debug_register_syncs: Vec<Pid>,
 }
 
 impl Process {
+    /// Launches the program with no arguments and an empty environment.
     pub fn launch(path: &Path) -> Result<Self, ProcessError> {
-        let handle = launch_program(path)
+        Self::launch_with(path, &[], &[], None)
+    }
+
+    /// Launches the program passing it `args`, which don't include the program name, with
+    /// `env` as its whole environment. It runs in `cwd` if given otherwise our working directory.
+    pub fn launch_with(
+        path: &Path,
+        args: &[String],
+        env: &[(String, String)],
+        cwd: Option<&Path>,
+    ) -> Result<Self, ProcessError> {
+        if let Some(cwd) = cwd.filter(|cwd| !cwd.is_dir()) {
+            error!("Working directory {} isn't a directory", cwd.display());
+            return Err(ProcessError::NoWorkingDirectory);
+        }
+        let handle = launch_program(path, args, env, cwd)
             .map_err(|e| {
                 error!("Failed to launch: {}", e);
                 ProcessError::LaunchFailed
@@ -145,23 +480,42 @@ impl Process {
             .ok_or(ProcessError::NoPid)?;
 
         let pid = handle.pid;
+        let stdin_writer = handle.stdin_writer;
         let stdout_reader = handle.stdout_reader;
+        let stderr_reader = handle.stderr_reader;
 
         if stdout_reader.is_none() {
             info!("No handle to process stdout returned");
         }
+        if stderr_reader.is_none() {
+            info!("No handle to process stderr returned");
+        }
 
         let mut ret = Self {
             pid,
+            stdin_writer,
             stdout_reader,
+            stderr_reader,
             addr_offset: 0,
             terminate_on_end: true,
             state: State::Stopped,
-            breakpoints: vec![],
+            breakpoints: HashMap::new(),
+            reenable_after_stop: None,
+            pending_stop: None,
+            step_pending: false,
+            current_signal: None,
+            verify_breakpoints: true,
+            nopass_signals: DEFAULT_NOPASS_SIGNALS.to_vec(),
+            marks_syscall_stops: false,
+            exit_status: None,
+            threads: vec![pid],
+            current_thread: pid,
+            follow_fork: FollowFork::default(),
+            forks: vec![],
+            debug_register_syncs: vec![],
         };
 
-        let timeout = Duration::from_secs(15);
-        ret.blocking_wait_on_signal(timeout)?;
+        ret.wait_for_initial_stop()?;
 
         Ok(ret)
     }
@@ -174,28 +528,219 @@ impl Process {
 
         let mut ret = Self {
             pid,
+            stdin_writer: None,
             stdout_reader: None,
+            stderr_reader: None,
             addr_offset: 0,
             terminate_on_end: false,
             state: State::Stopped,
-            breakpoints: vec![],
+            breakpoints: HashMap::new(),
+            reenable_after_stop: None,
+            pending_stop: None,
+            step_pending: false,
+            current_signal: None,
+            verify_breakpoints: true,
+            nopass_signals: DEFAULT_NOPASS_SIGNALS.to_vec(),
+            marks_syscall_stops: false,
+            exit_status: None,
+            threads: vec![pid],
+            current_thread: pid,
+            follow_fork: FollowFork::default(),
+            forks: vec![],
+            debug_register_syncs: vec![],
         };
 
-        let timeout = Duration::from_secs(15);
-        ret.blocking_wait_on_signal(timeout)?;
+        ret.wait_for_initial_stop()?;
 
         Ok(ret)
     }
 
+    /// Waits for the stop after launching or attaching. If this times out the process is
+    /// assumed to be running, the stop will still be picked up by a later wait or the user can
+    /// interrupt it.
+    fn wait_for_initial_stop(&mut self) -> Result<(), ProcessError> {
+        match self.blocking_wait_on_signal(INITIAL_STOP_TIMEOUT) {
+            Ok(_) => Ok(()),
+            Err(ProcessError::Timeout) => {
+                warn!(
+                    pid=%self.pid,
+                    "Timed out waiting for the process to stop after {:?}, treating it as running",
+                    INITIAL_STOP_TIMEOUT
+                );
+                self.state = State::Running;
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn stop(&self) -> Result<(), ProcessError> {
-        kill(self.pid, Signal::SIGSTOP).map_err(|e| {
+        self.ensure_alive()?;
+        // Sent to the main thread, a SIGSTOP any thread could take might be taken by one whose
+        // stops are continued without being reported
+        stop_thread(self.pid, self.pid).map_err(|e| {
             error!("Couldn't stop process: {}", e);
             ProcessError::KillFailed
         })
     }
 
+    /// Removes all breakpoints, putting the original code back, and stops tracing the process
+    /// so it carries on running without us. A signal it was stopped by is delivered as it's
+    /// let go. The process has to be stopped so the breakpoints can be removed safely.
+    ///
+    /// Every traced thread and forked child is let go as well, they're stopped first as ptrace
+    /// can only detach from a stopped tracee.
+    pub fn detach(&mut self) -> Result<(), ProcessError> {
+        self.ensure_stopped()?;
+        if self.step_pending {
+            return Err(ProcessError::NotStopped(State::Running));
+        }
+        let others = self
+            .threads
+            .iter()
+            .chain(&self.forks)
+            .copied()
+            .filter(|tid| *tid != self.current_thread)
+            .collect::<Vec<_>>();
+        let mut stopped = vec![];
+        for tid in others {
+            if let Some(signal) = self.stop_for_detach(tid) {
+                stopped.push((tid, signal));
+            }
+        }
+        // Forked children have their own copy of the breakpoints
+        let children = stopped
+            .iter()
+            .map(|(tid, _)| *tid)
+            .chain(std::iter::once(self.current_thread))
+            .filter(|tid| self.forks.contains(tid));
+        for child in children {
+            for bp in self.breakpoints.values() {
+                if let Err(e) = bp.remove_from(child) {
+                    warn!("Couldn't remove breakpoint {} from {}: {}", bp.id, child, e);
+                }
+            }
+        }
+        // Removing them all means there's nothing to put back either
+        self.reenable_after_stop = None;
+        let ids = self
+            .breakpoints
+            .values()
+            .map(|bp| bp.id)
+            .collect::<Vec<_>>();
+        // If a forked child is the one stopped the main thread was stopped above
+        let memory = if self.forks.contains(&self.current_thread) {
+            self.pid
+        } else {
+            self.current_thread
+        };
+        for id in ids {
+            self.remove_breakpoint_from(id, memory)?;
+        }
+        // With the breakpoints gone this clears the debug registers of the other threads
+        self.debug_register_syncs.clear();
+        for (tid, _) in stopped.iter().filter(|(tid, _)| self.threads.contains(tid)) {
+            self.sync_debug_registers(*tid);
+        }
+        if self.terminate_on_end {
+            warn!(
+                "Output of {} is no longer read, if it writes more it'll get a SIGPIPE",
+                self.pid
+            );
+        }
+        ptrace::detach(self.current_thread, self.current_signal.take()).map_err(|e| {
+            error!("Failed to detach from {}: {}", self.current_thread, e);
+            ProcessError::ContinueFailed
+        })?;
+        for (tid, signal) in stopped {
+            if let Err(e) = ptrace::detach(tid, signal) {
+                warn!("Couldn't detach from {}: {}", tid, e);
+            }
+        }
+        // In case it was in a group stop from an interrupt, this also throws away the SIGSTOPs
+        // of threads that stopped for something else first
+        for pid in std::iter::once(self.pid).chain(self.forks.iter().copied()) {
+            if let Err(e) = kill(pid, Signal::SIGCONT) {
+                warn!("Couldn't continue {} after detach: {}", pid, e);
+            }
+        }
+        info!("Detached from {}", self.pid);
+        self.mark_gone();
+        self.state = State::Detached;
+        Ok(())
+    }
+
+    /// Stops a running thread or forked child so it can be detached from. Returns the signal to
+    /// deliver as it's let go, or `None` if it's gone.
+    fn stop_for_detach(&self, tid: Pid) -> Option<Option<Signal>> {
+        let tgid = if self.forks.contains(&tid) {
+            tid
+        } else {
+            self.pid
+        };
+        if let Err(e) = stop_thread(tgid, tid) {
+            warn!("Couldn't stop {} to detach from it: {}", tid, e);
+            return None;
+        }
+        match waitpid(tid, Some(WaitPidFlag::__WALL)) {
+            Ok(WaitStatus::Stopped(_, Signal::SIGSTOP)) => Some(None),
+            Ok(WaitStatus::Stopped(_, Signal::SIGTRAP)) => {
+                // It hit a breakpoint before the SIGSTOP got to it, once the breakpoint's
+                // removed the instruction it's part way through has to run again
+                let pc = current_instruction_pointer(tid).ok()? as u64;
+                let hit = pc.checked_sub(1).filter(|addr| {
+                    self.breakpoints
+                        .get(addr)
+                        .is_some_and(|bp| bp.is_enabled() && !bp.is_hardware())
+                });
+                if let Some(addr) = hit {
+                    if let Err(e) = set_instruction_pointer(tid, addr) {
+                        warn!("Couldn't move {} back to the breakpoint: {}", tid, e);
+                    }
+                }
+                Some(None)
+            }
+            Ok(WaitStatus::Stopped(_, signal)) => Some(Some(signal)),
+            Ok(WaitStatus::PtraceEvent(_, _, event)) => {
+                // A thread or child that's just been created starts out traced too
+                let created = [
+                    libc::PTRACE_EVENT_CLONE,
+                    libc::PTRACE_EVENT_FORK,
+                    libc::PTRACE_EVENT_VFORK,
+                ];
+                if created.contains(&event) {
+                    if let Ok(child) = get_event_data(tid) {
+                        let child = Pid::from_raw(child as i32);
+                        let _ = waitpid(child, Some(WaitPidFlag::__WALL));
+                        if event == libc::PTRACE_EVENT_FORK {
+                            for bp in self.breakpoints.values() {
+                                if let Err(e) = bp.remove_from(child) {
+                                    warn!(
+                                        "Couldn't remove breakpoint {} from {}: {}",
+                                        bp.id, child, e
+                                    );
+                                }
+                            }
+                        }
+                        if let Err(e) = ptrace::detach(child, None) {
+                            warn!("Couldn't detach from {}: {}", child, e);
+                        }
+                    }
+                }
+                Some(None)
+            }
+            Ok(WaitStatus::PtraceSyscall(_)) => Some(None),
+            Ok(_) => None,
+            Err(e) => {
+                warn!("Couldn't wait for {} to stop: {}", tid, e);
+                None
+            }
+        }
+    }
+
     pub fn pc(&self) -> Result<u64, ProcessError> {
-        current_instruction_pointer(self.pid)
+        self.ensure_alive()?;
+        current_instruction_pointer(self.current_thread)
             .map(|x| x as u64)
             .map_err(|e| {
                 error!("Couldn't read PC register: {}", e);
@@ -203,64 +748,267 @@ impl Process {
             })
     }
 
-    pub fn stop_on_events(&self) {
-        if let Err(e) = trace_children(self.pid) {
-            error!("Won't stop when a child forks/clones/execs: {}", e);
+    pub fn stop_on_events(&mut self) {
+        match trace_children(self.pid) {
+            Ok(()) => self.marks_syscall_stops = true,
+            Err(e) => error!("Won't stop when a child forks/clones/execs: {}", e),
         }
     }
 
+    /// Signal the process was stopped by, if the last stop was for a signal rather than one of
+    /// our breakpoints, steps or a ptrace event. Cleared when the process is continued.
+    pub fn current_signal(&self) -> Option<Signal> {
+        self.current_signal
+    }
+
+    /// Forgets the signal the process stopped with so it isn't delivered when it's continued.
+    pub fn discard_signal(&mut self) {
+        self.current_signal = None;
+    }
+
+    /// Continues the process, delivering the signal it stopped with unless it's one of the
+    /// signals set to not be passed on.
     pub fn resume(&mut self) -> Result<(), ProcessError> {
+        let signal = self.passed_signal();
+        self.resume_with_signal(signal)
+    }
+
+    /// Continues the process like `resume` until the next entry to or exit from a syscall, which
+    /// stops it with a `SyscallStop` event.
+    pub fn resume_to_syscall(&mut self) -> Result<(), ProcessError> {
+        let signal = self.passed_signal();
+        self.resume_to_syscall_with_signal(signal)
+    }
+
+    /// Continues the process delivering `signal`, or no signal at all, in place of the one it
+    /// stopped with.
+    pub fn resume_with_signal(&mut self, signal: Option<Signal>) -> Result<(), ProcessError> {
+        self.continue_with(signal, false)
+    }
+
+    /// Continues the process to the next syscall stop delivering `signal` in place of the one it
+    /// stopped with.
+    pub fn resume_to_syscall_with_signal(
+        &mut self,
+        signal: Option<Signal>,
+    ) -> Result<(), ProcessError> {
+        self.continue_with(signal, true)
+    }
+
+    /// The signal the process stopped with if it should be passed on when it's continued.
+    fn passed_signal(&self) -> Option<Signal> {
+        self.current_signal.filter(|signal| {
+            let pass = !self.nopass_signals.contains(signal);
+            if !pass {
+                debug!("Not passing {} on to the process", signal);
+            }
+            pass
+        })
+    }
+
+    fn continue_with(
+        &mut self,
+        signal: Option<Signal>,
+        to_syscall: bool,
+    ) -> Result<(), ProcessError> {
+        self.ensure_stopped()?;
+        if to_syscall && !self.marks_syscall_stops {
+            mark_syscall_stops(self.current_thread).map_err(|_| ProcessError::ContinueFailed)?;
+            self.marks_syscall_stops = true;
+        }
         info!(pid=%self.pid, "Continuing process");
-        let mut bps: Vec<&mut Breakpoint> = self
-            .breakpoints
-            .iter_mut()
-            .filter(|bp| bp.has_hit(self.pid).unwrap_or_default())
-            .collect();
-        if bps.len() > 1 {
-            error!("breakpoint clashes: {:?}", bps);
-        }
-        if bps.is_empty() {
-            continue_exec(self.pid, None).map_err(|_| ProcessError::ContinueFailed)?;
+        self.current_signal = None;
+        if let Some(signal) = signal {
+            info!("Passing {} on to the process", signal);
+        }
+        // Only int3 breakpoints need stepping over. Debug registers are per thread and left
+        // alone by PTRACE_CONT and PTRACE_SINGLESTEP, so anything set in them stays armed
+        if let Some(addr) = self.hit_software_breakpoint() {
+            if !self.step_over_breakpoint(addr)? {
+                // Stopped for another reason while stepping over so we shouldn't continue
+                return Ok(());
+            }
+        }
+        if to_syscall {
+            continue_to_syscall(self.current_thread, signal)
         } else {
-            bps[0]
-                .jump_to(self.pid)
-                .map_err(|_| ProcessError::ContinueFailed)?;
-            bps[0]
-                .process(self.pid, true)
-                .map_err(|_| ProcessError::ContinueFailed)?;
-            continue_exec(self.pid, None).map_err(|_| ProcessError::ContinueFailed)?;
+            continue_exec(self.current_thread, signal)
         }
+        .map_err(|_| ProcessError::ContinueFailed)?;
         self.state = State::Running;
         Ok(())
     }
 
+    /// If the process was stopped by a signal part way through a syscall which the kernel will
+    /// restart, returns the number of the syscall that runs when it's resumed. See the
+    /// `syscalls` module for how restarts look to a debugger.
+    pub fn interrupted_syscall(&self) -> Option<u64> {
+        let regs = self.get_gp_registers().ok()?.regs;
+        let SyscallReturn::Error(errno) = SyscallReturn::from_rax(regs.rax) else {
+            return None;
+        };
+        let after_syscall = regs.rip >= 2 && self.read_memory(regs.rip - 2, 2).ok()? == SYSCALL;
+        (syscalls::is_restart(errno) && after_syscall)
+            .then(|| syscalls::restarted_as(regs.orig_rax, errno))
+    }
+
     pub fn step(&mut self) -> Result<(), ProcessError> {
-        let mut bps: Vec<&mut Breakpoint> = self
-            .breakpoints
-            .iter_mut()
-            .filter(|bp| bp.has_hit(self.pid).unwrap_or_default())
-            .collect();
-        if bps.len() > 1 {
-            error!("breakpoint clashes: {:?}", bps);
-        }
-        if bps.is_empty() {
-            single_step(self.pid).map_err(|_| ProcessError::SingleStepFailed)?;
-        } else {
-            bps[0]
-                .process(self.pid, true)
-                .map_err(|_| ProcessError::ContinueFailed)?;
-            single_step(self.pid).map_err(|_| ProcessError::SingleStepFailed)?;
+        self.ensure_stopped()?;
+        if let Some(syscall) = self.interrupted_syscall() {
+            info!(
+                "Syscall was interrupted and will be restarted as {}, the step finishes once it \
+                 returns",
+                syscalls::syscall_name(syscall).unwrap_or("an unknown syscall")
+            );
+        }
+        self.current_signal = None;
+        if let Some(addr) = self.hit_software_breakpoint() {
+            // Execute the original instruction and put the breakpoint back when the step stops
+            self.disable_breakpoint_at(addr)?;
+            self.reenable_after_stop = Some(addr);
         }
+        single_step(self.current_thread).map_err(|_| ProcessError::SingleStepFailed)?;
+        self.step_pending = true;
         self.state = State::Stopped;
         Ok(())
     }
 
+    /// ptrace requests on a process that isn't stopped fail with ESRCH which reads like the
+    /// process is gone, so check first to give a clearer error.
+    fn ensure_stopped(&self) -> Result<(), ProcessError> {
+        self.ensure_alive()?;
+        match self.state {
+            State::Stopped => Ok(()),
+            state => Err(ProcessError::NotStopped(state)),
+        }
+    }
+
+    /// Once the process has exited or been killed its pid is forgotten, as it could be reused by
+    /// an unrelated process, so anything that needs it fails here instead.
+    fn ensure_alive(&self) -> Result<(), ProcessError> {
+        match self.state {
+            State::Detached => Err(ProcessError::Detached),
+            state if state.is_closed() => Err(ProcessError::Gone),
+            _ => Ok(()),
+        }
+    }
+
+    /// The thread the last stop was for. Without `stop_on_events` only the main thread is
+    /// traced so this is always the process's own pid. Only this thread is stopped, the others
+    /// carry on running.
+    pub fn stopped_thread(&self) -> Pid {
+        self.current_thread
+    }
+
+    /// The threads being traced, the main thread first.
+    pub fn traced_threads(&self) -> &[Pid] {
+        &self.threads
+    }
+
+    /// The program counter of each traced thread, `None` for the ones that are running as
+    /// their registers can't be read.
+    pub fn thread_pcs(&self) -> Vec<(Pid, Option<u64>)> {
+        self.threads
+            .iter()
+            .map(|tid| {
+                let stopped = *tid == self.current_thread && self.state == State::Stopped;
+                let pc = stopped
+                    .then(|| current_instruction_pointer(*tid).ok())
+                    .flatten();
+                (*tid, pc.map(|pc| pc as u64))
+            })
+            .collect()
+    }
+
+    /// How the process ended, `None` while it's still running or if we detached from it.
+    pub fn exit_status(&self) -> Option<Info> {
+        self.exit_status
+    }
+
+    /// Forgets about the process after seeing it's gone, there's nothing left to detach from or
+    /// kill when we're dropped.
+    fn mark_gone(&mut self) {
+        // The other threads are gone too but have to be reaped
+        for tid in self.threads.drain(..).filter(|tid| *tid != self.pid) {
+            let _ = waitpid(tid, Some(WaitPidFlag::WNOHANG | WaitPidFlag::__WALL));
+        }
+        // Forked children carry on, they're only let go of when we exit
+        self.forks.clear();
+        self.debug_register_syncs.clear();
+        self.pid = Pid::from_raw(0);
+        self.current_thread = self.pid;
+        self.reenable_after_stop = None;
+        self.pending_stop = None;
+        self.step_pending = false;
+        self.current_signal = None;
+    }
+
+    /// Returns the address of the enabled breakpoint the process is stopped at if there is one.
+    fn hit_breakpoint(&self) -> Option<u64> {
+        let pc = self.pc().ok()?;
+        self.breakpoint_at(pc)
+            .filter(|bp| bp.is_enabled())
+            .map(|bp| bp.pc)
+    }
+
+    /// Like `hit_breakpoint` but only for int3 breakpoints, the ones that have to be taken out
+    /// to run the instruction underneath.
+    fn hit_software_breakpoint(&self) -> Option<u64> {
+        self.hit_breakpoint().filter(|addr| {
+            self.breakpoints
+                .get(addr)
+                .is_some_and(|bp| !bp.is_hardware())
+        })
+    }
+
+    fn disable_breakpoint_at(&mut self, addr: u64) -> Result<(), ProcessError> {
+        if let Some(bp) = self.breakpoints.get_mut(&addr) {
+            bp.disable(self.current_thread, self.verify_breakpoints)
+                .map_err(|e| {
+                    error!("Failed to disable breakpoint: {}", e);
+                    ProcessError::WriteFailed
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Single steps over the instruction a breakpoint replaced and then restores the breakpoint.
+    /// Returns false if the process stopped for something other than the step, this stop is
+    /// then returned by the next wait.
+    fn step_over_breakpoint(&mut self, addr: u64) -> Result<bool, ProcessError> {
+        self.disable_breakpoint_at(addr)?;
+        self.reenable_after_stop = Some(addr);
+        let status = loop {
+            single_step(self.current_thread).map_err(|_| ProcessError::SingleStepFailed)?;
+            let status = waitpid(self.current_thread, Some(WaitPidFlag::__WALL))
+                .map_err(|_| ProcessError::WaitFailed)?;
+            // A thread that was already at a breakpoint gets its debug register update here,
+            // before the instruction ran, so it's stepped again
+            if !self.take_debug_register_sync(self.current_thread, &status) {
+                break status;
+            }
+        };
+        self.step_pending = true;
+        match self.handle_wait_status(status) {
+            Some(stop)
+                if stop.reason == State::Stopped
+                    && stop.trap_reason == Some(TrapType::SingleStep) =>
+            {
+                Ok(true)
+            }
+            stop => {
+                self.pending_stop = stop;
+                Ok(false)
+            }
+        }
+    }
+
     pub fn pid(&self) -> Pid {
         self.pid
     }
 
     pub fn mapped_address(&self) -> Option<u64> {
-        let proc = PfsProcess::new(self.pid.as_raw()).ok()?;
+        let proc = proc_entry(self.pid).ok()?;
         let exe = proc.exe().ok()?;
         let maps = proc.maps().ok()?;
         maps.iter()
@@ -268,24 +1016,327 @@ impl Process {
             .map(|map| map.address.0)
     }
 
+    /// Checks `/proc/<pid>` can be read. The first time it can't a warning is logged explaining
+    /// what won't work, as otherwise the features relying on it quietly go wrong.
+    pub fn proc_accessible(&self) -> bool {
+        let accessible = proc_entry(self.pid).and_then(|proc| proc.maps()).is_ok();
+        if !accessible && !PROC_WARNING_SHOWN.swap(true, Ordering::Relaxed) {
+            warn!(
+                "/proc/{} can't be read, /proc may not be mounted or is hidden from us. Where the \
+                 program is loaded, its threads, the vDSO and signal state can't be found so \
+                 breakpoints may land in the wrong place",
+                self.pid
+            );
+        }
+        accessible
+    }
+
+    /// Reads the scheduler state and signal masks of the process.
+    pub fn signal_state(&self) -> Result<SignalState, ProcessError> {
+        self.ensure_alive()?;
+        let status = proc_entry(self.pid)
+            .and_then(|proc| proc.status())
+            .map_err(|e| {
+                error!("Couldn't read /proc status for {}: {}", self.pid, e);
+                ProcessError::ProcReadFailed
+            })?;
+        Ok(SignalState {
+            state: status.state,
+            pending: signal_names(status.sigpnd | status.shdpnd),
+            blocked: signal_names(status.sigblk),
+            ignored: signal_names(status.sigign),
+            caught: signal_names(status.sigcgt),
+        })
+    }
+
+    /// Lists the threads in the process. Threads can exit while we're looking at them, those
+    /// are left out.
+    pub fn threads(&self) -> Result<Vec<ThreadInfo>, ProcessError> {
+        self.ensure_alive()?;
+        let proc = proc_entry(self.pid).map_err(|e| {
+            error!("Couldn't read /proc for {}: {}", self.pid, e);
+            ProcessError::ProcReadFailed
+        })?;
+        let tasks = proc.tasks().map_err(|e| {
+            error!("Couldn't list threads: {}", e);
+            ProcessError::ProcReadFailed
+        })?;
+        let mut threads = vec![];
+        for task in tasks.flatten() {
+            let comm = format!("/proc/{}/task/{}/comm", self.pid, task.tid);
+            match fs::read_to_string(comm) {
+                Ok(name) => threads.push(ThreadInfo {
+                    tid: Pid::from_raw(task.tid),
+                    name: name.trim_end().to_string(),
+                }),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => warn!("Couldn't read name of thread {}: {}", task.tid, e),
+            }
+        }
+        threads.sort_by_key(|t| t.tid);
+        Ok(threads)
+    }
+
     pub fn state(&self) -> State {
         self.state
     }
 
+    /// Sets what happens to children the process forks from now on, see `FollowFork`.
+    pub fn set_follow_fork(&mut self, follow_fork: FollowFork) {
+        self.follow_fork = follow_fork;
+    }
+
+    /// Forked children being traced, see `FollowFork::Child`.
+    pub fn forked_children(&self) -> &[Pid] {
+        &self.forks
+    }
+
+    /// Sets the signals `resume` drops instead of delivering.
+    pub fn set_nopass_signals(&mut self, signals: Vec<Signal>) {
+        self.nopass_signals = signals;
+    }
+
+    /// Sets whether removing a breakpoint checks its interrupt hasn't been overwritten before
+    /// restoring the original byte, see `Breakpoint::disable`.
+    pub fn set_verify_breakpoints(&mut self, verify: bool) {
+        self.verify_breakpoints = verify;
+    }
+
+    /// The stopped thread to change breakpoints through. Breakpoints live in the memory of the
+    /// process being debugged, a forked child has its own copy of it so changing them while
+    /// it's the one stopped would write to the wrong process.
+    fn breakpoint_thread(&self) -> Result<Pid, ProcessError> {
+        if self.forks.contains(&self.current_thread) {
+            Err(ProcessError::InForkedChild(self.current_thread))
+        } else {
+            Ok(self.current_thread)
+        }
+    }
+
     pub fn set_breakpoint(&mut self, addr: u64) -> Result<u64, ProcessError> {
+        self.ensure_alive()?;
+        if let Some(bp) = self.breakpoints.get(&addr) {
+            info!("Breakpoint {} already set at 0x{:x}", bp.id, addr);
+            return Ok(bp.id);
+        }
+        let pid = self.breakpoint_thread()?;
         info!("Setting breakpoint at 0x{:x}", addr);
-        let bp = Breakpoint::new(self.pid, addr).map_err(|e| {
+        let bp = Breakpoint::new(pid, addr).map_err(|e| {
             error!("Failed to set breakpoint: {}", e);
             ProcessError::BreakpointSetFailed
         })?;
 
         let id = bp.id;
-        self.breakpoints.push(bp);
+        self.breakpoints.insert(addr, bp);
+        Ok(id)
+    }
+
+    /// Sets a breakpoint using a debug register rather than an int3, so the code isn't modified.
+    /// Every thread gets it, see `sync_all_debug_registers`. If there's already a breakpoint at
+    /// the address its ID is returned.
+    pub fn set_hardware_breakpoint(&mut self, addr: u64) -> Result<u64, ProcessError> {
+        self.ensure_stopped()?;
+        if let Some(bp) = self.breakpoints.get(&addr) {
+            info!("Breakpoint {} already set at 0x{:x}", bp.id, addr);
+            return Ok(bp.id);
+        }
+        let pid = self.breakpoint_thread()?;
+        let slot = self.free_debug_slot()?;
+        info!("Setting hardware breakpoint at 0x{:x}", addr);
+        let bp = Breakpoint::new_hardware(pid, addr, slot).map_err(|e| {
+            error!("Failed to set hardware breakpoint: {}", e);
+            ProcessError::BreakpointSetFailed
+        })?;
+        let id = bp.id;
+        self.breakpoints.insert(addr, bp);
+        self.sync_all_debug_registers();
+        Ok(id)
+    }
+
+    /// The first debug address register that isn't used by an enabled hardware breakpoint.
+    /// They're handed out for the whole process rather than per thread, so each breakpoint is in
+    /// the same register in every thread.
+    fn free_debug_slot(&self) -> Result<usize, ProcessError> {
+        (0..HARDWARE_BREAKPOINT_SLOTS)
+            .find(|slot| !self.breakpoints.values().any(|bp| bp.slot() == Some(*slot)))
+            .ok_or(ProcessError::NoFreeDebugRegister)
+    }
+
+    /// Makes a stopped thread's debug registers match the enabled hardware breakpoints.
+    fn sync_debug_registers(&self, tid: Pid) {
+        for slot in 0..HARDWARE_BREAKPOINT_SLOTS {
+            let res = match self.breakpoints.values().find(|bp| bp.slot() == Some(slot)) {
+                Some(bp) => bp.arm(tid),
+                None => disarm(tid, slot),
+            };
+            if let Err(e) = res {
+                warn!("Couldn't update debug register {} of {}: {}", slot, tid, e);
+            }
+        }
+    }
+
+    /// Updates every thread after the hardware breakpoints change. Debug registers can only be
+    /// written while a thread is stopped, so the threads other than the stopped one are sent a
+    /// SIGSTOP and updated when it's seen, before they're continued.
+    fn sync_all_debug_registers(&mut self) {
+        self.sync_debug_registers(self.current_thread);
+        let running = self
+            .threads
+            .iter()
+            .copied()
+            .filter(|tid| *tid != self.current_thread && !self.debug_register_syncs.contains(tid))
+            .collect::<Vec<_>>();
+        for tid in running {
+            match stop_thread(self.pid, tid) {
+                Ok(()) => self.debug_register_syncs.push(tid),
+                Err(e) => warn!("Couldn't stop {} to update its debug registers: {}", tid, e),
+            }
+        }
+    }
+
+    /// Finishes updating the debug registers of a thread stopped by `sync_all_debug_registers`
+    /// and sets it going again. Returns false if it's some other stop.
+    fn finish_debug_register_sync(&mut self, tid: Pid, status: &WaitStatus) -> bool {
+        if !self.take_debug_register_sync(tid, status) {
+            return false;
+        }
+        if self.step_pending && tid == self.current_thread {
+            // The step hasn't happened yet
+            if let Err(e) = single_step(tid) {
+                warn!("Couldn't step thread {}: {}", tid, e);
+            }
+        } else {
+            self.continue_thread(tid);
+        }
+        true
+    }
+
+    /// Updates the debug registers of a thread if this is the stop `sync_all_debug_registers`
+    /// asked for, leaving it stopped.
+    fn take_debug_register_sync(&mut self, tid: Pid, status: &WaitStatus) -> bool {
+        if !matches!(status, WaitStatus::Stopped(_, Signal::SIGSTOP)) {
+            return false;
+        }
+        let Some(index) = self.debug_register_syncs.iter().position(|t| *t == tid) else {
+            return false;
+        };
+        self.debug_register_syncs.remove(index);
+        self.sync_debug_registers(tid);
+        true
+    }
+
+    /// Sets a breakpoint that's removed after it's hit. If there's already a breakpoint at the
+    /// address it's left as it is and its ID returned.
+    pub fn set_temporary_breakpoint(&mut self, addr: u64) -> Result<u64, ProcessError> {
+        let existing = self.breakpoints.contains_key(&addr);
+        let id = self.set_breakpoint(addr)?;
+        if !existing {
+            if let Some(bp) = self.breakpoints.get_mut(&addr) {
+                bp.set_temporary(true);
+            }
+        }
         Ok(id)
     }
 
-    pub fn breakpoints(&self) -> &[Breakpoint] {
-        self.breakpoints.as_slice()
+    /// Removes the breakpoint with the given ID restoring the original instruction.
+    pub fn remove_breakpoint(&mut self, id: u64) -> Result<(), ProcessError> {
+        self.ensure_alive()?;
+        let pid = self.breakpoint_thread()?;
+        let hardware = self.breakpoint_mut(id).is_some_and(|bp| bp.is_hardware());
+        self.remove_breakpoint_from(id, pid)?;
+        if hardware {
+            self.sync_all_debug_registers();
+        }
+        Ok(())
+    }
+
+    fn remove_breakpoint_from(&mut self, id: u64, pid: Pid) -> Result<(), ProcessError> {
+        let addr = self
+            .breakpoints
+            .values()
+            .find(|bp| bp.id == id)
+            .map(|bp| bp.pc)
+            .ok_or(ProcessError::NoBreakpoint(id))?;
+        if let Some(mut bp) = self.breakpoints.remove(&addr) {
+            bp.disable(pid, self.verify_breakpoints).map_err(|e| {
+                error!("Failed to remove breakpoint: {}", e);
+                ProcessError::WriteFailed
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Makes the breakpoint carry on past its next `count` hits.
+    pub fn ignore_breakpoint(&mut self, id: u64, count: u64) -> Result<(), ProcessError> {
+        let bp = self
+            .breakpoints
+            .values_mut()
+            .find(|bp| bp.id == id)
+            .ok_or(ProcessError::NoBreakpoint(id))?;
+        bp.set_ignore_count(count);
+        Ok(())
+    }
+
+    /// Enables a disabled breakpoint or disables an enabled one, returning whether it's now
+    /// enabled. Disabled breakpoints are kept so they can be turned back on later.
+    pub fn toggle_breakpoint(&mut self, id: u64) -> Result<bool, ProcessError> {
+        self.ensure_alive()?;
+        let pid = self.breakpoint_thread()?;
+        let verify = self.verify_breakpoints;
+        let bp = self
+            .breakpoints
+            .values()
+            .find(|bp| bp.id == id)
+            .ok_or(ProcessError::NoBreakpoint(id))?;
+        let slot = if bp.is_hardware() && !bp.is_enabled() {
+            Some(self.free_debug_slot()?)
+        } else {
+            None
+        };
+        let bp = self
+            .breakpoint_mut(id)
+            .ok_or(ProcessError::NoBreakpoint(id))?;
+        let res = if bp.is_enabled() {
+            bp.disable(pid, verify)
+        } else if let Some(slot) = slot {
+            bp.enable_hardware(pid, slot)
+        } else {
+            bp.enable(pid)
+        };
+        res.map_err(|e| {
+            error!("Failed to toggle breakpoint: {}", e);
+            ProcessError::WriteFailed
+        })?;
+        let (enabled, hardware, addr) = (bp.is_enabled(), bp.is_hardware(), bp.pc);
+        if !enabled && self.reenable_after_stop == Some(addr) {
+            // Don't let a pending step put back a breakpoint the user just turned off
+            self.reenable_after_stop = None;
+        }
+        if hardware {
+            self.sync_all_debug_registers();
+        }
+        Ok(enabled)
+    }
+
+    /// Breakpoints in the order they were added.
+    pub fn breakpoints(&self) -> Vec<&Breakpoint> {
+        let mut bps = self.breakpoints.values().collect::<Vec<_>>();
+        bps.sort_by_key(|bp| bp.id);
+        bps
+    }
+
+    /// Returns the breakpoint set at the given address if there is one.
+    pub fn breakpoint_at(&self, addr: u64) -> Option<&Breakpoint> {
+        self.breakpoints.get(&addr)
+    }
+
+    pub fn breakpoint_at_mut(&mut self, addr: u64) -> Option<&mut Breakpoint> {
+        self.breakpoints.get_mut(&addr)
+    }
+
+    /// Returns the breakpoint with the given ID if there is one.
+    pub fn breakpoint_mut(&mut self, id: u64) -> Option<&mut Breakpoint> {
+        self.breakpoints.values_mut().find(|bp| bp.id == id)
     }
 
     pub fn blocking_wait_on_signal(
@@ -302,30 +1353,273 @@ impl Process {
     }
 
     pub fn wait_on_signal(&mut self) -> Result<Option<StopReason>, ProcessError> {
-        let mut ret = None;
-        let state = match waitpid(self.pid, Some(WaitPidFlag::WNOHANG))
-            .map_err(|_| ProcessError::WaitFailed)?
+        // Waiting on pid 0 would wait on any child in our process group
+        self.ensure_alive()?;
+        let stop = match self.pending_stop.take() {
+            Some(stop) => Some(stop),
+            None => self.wait_on_threads()?,
+        };
+        match stop {
+            Some(stop)
+                if matches!(
+                    stop.trap_reason,
+                    Some(TrapType::SoftwareBreak | TrapType::HardwareBreak)
+                ) && !self.record_breakpoint_hit() =>
+            {
+                // The breakpoint's condition doesn't hold so the user doesn't want to stop here
+                self.resume()?;
+                Ok(None)
+            }
+            stop => Ok(stop),
+        }
+    }
+
+    /// Polls the traced threads for a stop, only the stopped thread if the process is stopped.
+    /// Stops of other threads that are only bookkeeping, like a new thread starting or one
+    /// exiting, are dealt with here and not returned.
+    fn wait_on_threads(&mut self) -> Result<Option<StopReason>, ProcessError> {
+        let threads = match self.state {
+            State::Stopped => vec![self.current_thread],
+            _ => self.threads.iter().chain(&self.forks).copied().collect(),
+        };
+        let flags = WaitPidFlag::WNOHANG | WaitPidFlag::__WALL;
+        for tid in threads {
+            if tid == self.pid {
+                let status = waitpid(tid, Some(flags)).map_err(|_| ProcessError::WaitFailed)?;
+                if self.finish_debug_register_sync(tid, &status) {
+                    continue;
+                }
+                if status != WaitStatus::StillAlive {
+                    self.current_thread = tid;
+                    return Ok(self.handle_wait_status(status));
+                }
+                continue;
+            }
+            match waitpid(tid, Some(flags)) {
+                Ok(WaitStatus::StillAlive) => {}
+                Ok(status) if self.finish_debug_register_sync(tid, &status) => {}
+                Ok(status) => {
+                    if let Some(stop) = self.handle_thread_status(tid, status) {
+                        return Ok(Some(stop));
+                    }
+                }
+                Err(e) => {
+                    debug!("Couldn't wait on thread {}, forgetting it: {}", tid, e);
+                    self.remove_thread(tid);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Handles a wait status for a thread other than the main one, returning the stop if it's
+    /// one to report.
+    fn handle_thread_status(&mut self, tid: Pid, status: WaitStatus) -> Option<StopReason> {
+        match status {
+            WaitStatus::Exited(_, code) if self.forks.contains(&tid) => {
+                info!("Forked child {} exited with code {}", tid, code);
+                self.remove_thread(tid);
+                None
+            }
+            WaitStatus::Signaled(_, signal, _) if self.forks.contains(&tid) => {
+                info!("Forked child {} was killed by {}", tid, signal);
+                self.remove_thread(tid);
+                None
+            }
+            WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                debug!("Thread {} exited", tid);
+                self.remove_thread(tid);
+                None
+            }
+            // The main thread being interrupted, new threads' first stops are taken by
+            // `finish_debug_register_sync`
+            WaitStatus::Stopped(_, Signal::SIGSTOP) => {
+                self.continue_thread(tid);
+                None
+            }
+            WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_EXIT) => {
+                self.continue_thread(tid);
+                None
+            }
+            WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE) => {
+                self.add_cloned_thread(tid);
+                self.continue_thread(tid);
+                None
+            }
+            status => {
+                self.current_thread = tid;
+                self.handle_wait_status(status)
+            }
+        }
+    }
+
+    fn continue_thread(&self, tid: Pid) {
+        if let Err(e) = continue_exec(tid, None) {
+            warn!("Couldn't continue thread {}: {}", tid, e);
+        }
+    }
+
+    /// Starts tracking the thread `parent` just created, at its `PTRACE_EVENT_CLONE` stop.
+    fn add_cloned_thread(&mut self, parent: Pid) {
+        match get_event_data(parent) {
+            Ok(tid) => {
+                let tid = Pid::from_raw(tid as i32);
+                info!("New thread {}", tid);
+                if !self.threads.contains(&tid) {
+                    self.threads.push(tid);
+                    // New threads start with empty debug registers, they're filled in at the
+                    // thread's first stop
+                    self.debug_register_syncs.push(tid);
+                }
+            }
+            Err(e) => warn!("Couldn't get the new thread's ID: {}", e),
+        }
+    }
+
+    /// Deals with the child `parent` just forked, at its `PTRACE_EVENT_FORK` or
+    /// `PTRACE_EVENT_VFORK` stop. The child starts out traced and stopped.
+    fn add_forked_child(&mut self, parent: Pid, vfork: bool) {
+        let child = match get_event_data(parent) {
+            Ok(child) => Pid::from_raw(child as i32),
+            Err(e) => {
+                warn!("Couldn't get the forked child's pid: {}", e);
+                return;
+            }
+        };
+        match self.follow_fork {
+            FollowFork::Child => {
+                info!("Tracing forked child {}", child);
+                self.forks.push(child);
+            }
+            FollowFork::Parent => {
+                // Wait for its first stop so it can be detached from
+                if let Err(e) = waitpid(child, Some(WaitPidFlag::__WALL)) {
+                    warn!("Couldn't wait on forked child {}: {}", child, e);
+                    return;
+                }
+                // A vfork child shares the parent's memory, so removing them would remove the
+                // parent's too. It's meant to exec or exit straight away anyway
+                if !vfork {
+                    for bp in self.breakpoints.values() {
+                        if let Err(e) = bp.remove_from(child) {
+                            warn!("Couldn't remove breakpoint {} from {}: {}", bp.id, child, e);
+                        }
+                    }
+                }
+                match ptrace::detach(child, None) {
+                    Ok(()) => info!("Detached from forked child {}", child),
+                    Err(e) => warn!("Couldn't detach from forked child {}: {}", child, e),
+                }
+            }
+        }
+    }
+
+    fn remove_thread(&mut self, tid: Pid) {
+        self.debug_register_syncs.retain(|t| *t != tid);
+        self.forks.retain(|t| *t != tid);
+        self.threads.retain(|t| *t != tid);
+        if self.current_thread == tid {
+            self.current_thread = self.pid;
+        }
+    }
+
+    /// Checks the thread and condition of the breakpoint the process is stopped at, counting
+    /// the hit if they match. Returns false if the stop shouldn't be reported, either because
+    /// another thread hit it, the condition doesn't hold or the hit is being ignored. A temporary
+    /// breakpoint is removed once a hit is reported.
+    fn record_breakpoint_hit(&mut self) -> bool {
+        let Some(addr) = self.hit_breakpoint() else {
+            return true;
+        };
+        let Some(bp) = self.breakpoints.get(&addr) else {
+            return true;
+        };
+        if let Some(thread) = bp
+            .thread()
+            .filter(|thread| *thread != self.stopped_thread())
         {
+            debug!(
+                id = bp.id,
+                "Breakpoint is for thread {}, continuing {}",
+                thread,
+                self.stopped_thread()
+            );
+            return false;
+        }
+        let condition_met = match bp.condition() {
+            Some(condition) => condition.evaluate(self).unwrap_or_else(|e| {
+                warn!("Couldn't evaluate `{}`, stopping: {}", condition, e);
+                true
+            }),
+            None => true,
+        };
+        if !condition_met {
+            return false;
+        }
+        let Some(bp) = self.breakpoints.get_mut(&addr) else {
+            return true;
+        };
+        let stop = bp.record_hit();
+        if !stop {
+            debug!(id = bp.id, "Ignoring breakpoint hit");
+        } else if bp.is_temporary() {
+            let id = bp.id;
+            info!(id, "Removing temporary breakpoint");
+            if let Err(e) = self.remove_breakpoint(id) {
+                warn!("Couldn't remove temporary breakpoint {}: {}", id, e);
+            }
+        }
+        stop
+    }
+
+    /// At `PTRACE_EVENT_EXIT` the event message is the status `waitpid` is going to give when
+    /// the process is gone.
+    fn pending_exit_status(&self) -> Option<Info> {
+        let status = get_event_data(self.current_thread)
+            .map_err(|e| warn!("Couldn't get the exit status: {}", e))
+            .ok()? as i32;
+        let signal = status & 0x7f;
+        if signal == 0 {
+            Some(Info::Return(((status >> 8) & 0xff) as u8))
+        } else {
+            Signal::try_from(signal).ok().map(Info::Signalled)
+        }
+    }
+
+    fn handle_wait_status(&mut self, status: WaitStatus) -> Option<StopReason> {
+        let mut ret = None;
+        let state = match status {
             WaitStatus::StillAlive => self.state,
-            sig @ WaitStatus::Exited(child, ret_code) => {
+            WaitStatus::Exited(child, ret_code) => {
                 ret = Some(StopReason::new(State::Exited, Info::Return(ret_code as u8)));
                 if child == self.pid {
                     info!("Process {:?} exited with exit code {}", child, ret_code);
-                    self.pid = Pid::from_raw(0);
+                    self.exit_status = Some(Info::Return(ret_code as u8));
+                    self.mark_gone();
                     State::Exited
                 } else {
                     State::Running
                 }
             }
-            WaitStatus::Stopped(child, signal) => {
+            WaitStatus::Stopped(_, signal) => {
                 ret = Some(StopReason::new(State::Stopped, Info::Signalled(signal)));
                 State::Stopped
             }
-            WaitStatus::Signaled(pid, signal, has_coredump) => {
-                ret = Some(StopReason::new(State::Terminated, Info::Signalled(signal)));
+            WaitStatus::Signaled(child, signal, core_dumped) => {
+                let mut reason = StopReason::new(State::Terminated, Info::Signalled(signal));
+                reason.core_dumped = core_dumped;
+                ret = Some(reason);
+                info!(
+                    "Process {:?} was killed by {}{}",
+                    child,
+                    signal,
+                    if core_dumped { " (core dumped)" } else { "" }
+                );
+                self.exit_status = Some(Info::Signalled(signal));
+                self.mark_gone();
                 State::Terminated
             }
-            WaitStatus::PtraceEvent(pid, signal, event) => {
+            WaitStatus::PtraceEvent(_, signal, event) => {
                 let event = match Event::try_from(event) {
                     Ok(e) => Some(e),
                     Err(e) => {
@@ -335,19 +1629,36 @@ impl Process {
                 };
                 let mut reason = StopReason::new(State::Stopped, Info::Signalled(signal));
                 reason.event = event;
+                if event == Some(Event::Exit) {
+                    reason.exit_status = self.pending_exit_status();
+                } else if event == Some(Event::Spawn) {
+                    self.add_cloned_thread(self.current_thread);
+                } else if matches!(event, Some(Event::Fork | Event::Vfork)) {
+                    self.add_forked_child(self.current_thread, event == Some(Event::Vfork));
+                }
+                ret = Some(reason);
+                State::Stopped
+            }
+            WaitStatus::PtraceSyscall(_) => {
+                let mut reason = StopReason::new(State::Stopped, Info::Signalled(Signal::SIGTRAP));
+                reason.event = Some(Event::SyscallStop);
                 ret = Some(reason);
                 State::Stopped
             }
             sig => unimplemented!("{:?}", sig),
         };
-        if let Some(ret) = ret.as_mut() {
-            match ptrace::getsiginfo(self.pid) {
+        // Nothing more to find out about a process that's gone
+        if let Some(ret) = ret.as_mut().filter(|_| !state.is_closed()) {
+            match ptrace::getsiginfo(self.current_thread) {
                 Ok(sig_info) => {
+                    pub const TRAP_BRKPT: c_int = 1;
                     pub const TRAP_TRACE: c_int = 2;
                     pub const TRAP_HWBKPT: c_int = 4;
                     pub const SI_KERNEL: c_int = 0x80;
                     ret.trap_reason = match sig_info.si_code {
-                        TRAP_TRACE => Some(TrapType::SingleStep),
+                        // On x86 single stepping over a syscall instruction is reported as
+                        // TRAP_BRKPT rather than TRAP_TRACE
+                        TRAP_TRACE | TRAP_BRKPT => Some(TrapType::SingleStep),
                         SI_KERNEL => Some(TrapType::SoftwareBreak),
                         TRAP_HWBKPT => Some(TrapType::HardwareBreak),
                         _ => None,
@@ -357,63 +1668,317 @@ impl Process {
                     warn!("Couldn't get sig info: {}", e);
                 }
             }
+            if let Some(addr) = self.reenable_after_stop.take() {
+                if !state.is_closed() {
+                    if let Some(bp) = self.breakpoints.get_mut(&addr) {
+                        if let Err(e) = bp.enable(self.current_thread) {
+                            error!("Failed to restore breakpoint at 0x{:x}: {}", addr, e);
+                        }
+                    }
+                }
+            }
+            let stepping = std::mem::take(&mut self.step_pending);
+            let expected = match ret.trap_reason {
+                Some(TrapType::SoftwareBreak) => self.rewind_breakpoint_hit(),
+                Some(TrapType::SingleStep) => stepping,
+                Some(TrapType::HardwareBreak) => true,
+                _ => false,
+            };
+            if !expected
+                && state == State::Stopped
+                && ret.event.is_none()
+                && ret.info == Info::Signalled(Signal::SIGTRAP)
+            {
+                debug!("SIGTRAP wasn't from a breakpoint or step: {:?}", ret);
+                ret.trap_reason = Some(TrapType::Spurious);
+            }
+            self.current_signal = match ret.info {
+                Info::Signalled(signal)
+                    if state == State::Stopped
+                        && ret.event.is_none()
+                        && !(signal == Signal::SIGTRAP && expected) =>
+                {
+                    Some(signal)
+                }
+                _ => None,
+            };
         }
         self.state = state;
-        Ok(ret)
+        ret
+    }
+
+    /// After an INT3 the program counter is one past the breakpoint, move it back so the
+    /// instruction the breakpoint replaced is executed when we continue. Returns false if there's
+    /// no breakpoint of ours there.
+    fn rewind_breakpoint_hit(&mut self) -> bool {
+        let Ok(pc) = self.pc() else {
+            return false;
+        };
+        let addr = pc.wrapping_sub(1);
+        if self
+            .breakpoints
+            .get(&addr)
+            .is_some_and(|bp| bp.is_enabled() && !bp.is_hardware())
+        {
+            if let Err(e) = set_instruction_pointer(self.current_thread, addr) {
+                error!("Couldn't rewind program counter to breakpoint: {}", e);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Reads `len` bytes of the process memory starting at `addr`, which doesn't need to be
+    /// aligned. Fails if any of the range can't be read, use `read_memory_partial` to get what
+    /// could be read.
+    pub fn read_memory(&self, addr: u64, len: usize) -> Result<Vec<u8>, ProcessError> {
+        self.ensure_alive()?;
+        // Try the whole range in one go, reading a word at a time instead gets pages that can
+        // only be read with ptrace and finds where the read fails
+        if len <= MAX_LENGTH {
+            if let Ok(data) = read_memory_range(self.current_thread, addr, len) {
+                return Ok(data);
+            }
+        }
+        let read = self.read_memory_partial(addr, len);
+        match read.fault {
+            Some(fault) => Err(ProcessError::ReadFailed(fault)),
+            None => Ok(read.data),
+        }
+    }
+
+    /// Reads up to `len` bytes of the process memory starting at `addr`, stopping at the first
+    /// address that can't be read such as the start of an unmapped page.
+    pub fn read_memory_partial(&self, addr: u64, len: usize) -> MemoryRead {
+        let Some(end) = addr
+            .checked_add(len as u64)
+            .filter(|_| !self.state.is_closed())
+        else {
+            return MemoryRead {
+                data: vec![],
+                fault: Some(addr),
+            };
+        };
+        // The length can be far more than is mapped, so the buffer grows as words are read
+        let mut data = Vec::with_capacity(len.min(MAX_LENGTH));
+        let mut word_addr = align_address(addr);
+        while word_addr < end {
+            let word = match read_address(self.current_thread, word_addr) {
+                Ok(word) => word,
+                Err(e) => {
+                    let fault = word_addr.max(addr);
+                    warn!("Failed to read memory at 0x{:x}: {}", fault, e);
+                    return MemoryRead {
+                        data,
+                        fault: Some(fault),
+                    };
+                }
+            };
+            let bytes = word.to_ne_bytes();
+            let start = addr.saturating_sub(word_addr) as usize;
+            let stop = (end - word_addr).min(bytes.len() as u64) as usize;
+            data.extend_from_slice(&bytes[start..stop]);
+            word_addr += bytes.len() as u64;
+        }
+        MemoryRead { data, fault: None }
+    }
+
+    /// Reads code from the process with our breakpoints replaced by the bytes they overwrote, so
+    /// the instructions can be decoded.
+    pub fn read_code(&self, addr: u64, len: usize) -> MemoryRead {
+        let mut read = self.read_memory_partial(addr, len);
+        let end = addr.saturating_add(read.data.len() as u64);
+        for bp in self
+            .breakpoints
+            .values()
+            .filter(|bp| bp.is_enabled() && !bp.is_hardware() && (addr..end).contains(&bp.pc))
+        {
+            read.data[(bp.pc - addr) as usize] = bp.original_byte();
+        }
+        read
+    }
+
+    /// Writes `data` into the process memory starting at `addr`, which doesn't need to be
+    /// aligned. Breakpoints in the range stay set and restore the new bytes when removed.
+    pub fn write_memory(&mut self, addr: u64, data: &[u8]) -> Result<(), ProcessError> {
+        self.ensure_alive()?;
+        let end = addr
+            .checked_add(data.len() as u64)
+            .ok_or(ProcessError::WriteFailed)?;
+        let mut data = data.to_vec();
+        for bp in self
+            .breakpoints
+            .values_mut()
+            .filter(|bp| (addr..end).contains(&bp.pc))
+        {
+            let index = (bp.pc - addr) as usize;
+            data[index] = bp.overwrite(data[index]);
+        }
+        write_memory_range(self.current_thread, addr, &data).map_err(|e| {
+            error!("Failed to write memory at 0x{:x}: {}", addr, e);
+            ProcessError::WriteFailed
+        })
     }
 
     pub fn write_user_area(&self, offset: u64, data: i64) -> Result<(), ProcessError> {
-        write_to_address(self.pid, offset, data).map_err(|e| {
+        self.ensure_alive()?;
+        write_to_address(self.current_thread, offset, data).map_err(|e| {
             error!("Failed to write to register offset({}): {}", offset, e);
             ProcessError::WriteFailed
         })
     }
 
-    pub fn get_all_registers(&self) -> Result<Registers, ProcessError> {
-        let regs = ptrace::getregs(self.pid).map_err(|e| {
+    /// Reads only the general purpose registers, cheaper than `get_all_registers` when the
+    /// floating point state isn't needed.
+    pub fn get_gp_registers(&self) -> Result<Registers, ProcessError> {
+        self.ensure_alive()?;
+        let regs = ptrace::getregs(self.current_thread).map_err(|e| {
             error!("Failed to read registers: {}", e);
             ProcessError::RegisterReadFailed
         })?;
+        Ok(Registers { regs, fpregs: None })
+    }
 
-        let fpregs = ptrace::getregset::<regset::NT_PRFPREG>(self.pid).map_err(|e| {
-            error!("Failed to read fp registers: {}", e);
-            ProcessError::FpRegisterReadFailed
-        })?;
+    /// Reads the general purpose and floating point registers. If the floating point registers
+    /// can't be read only the general purpose ones are returned.
+    pub fn get_all_registers(&self) -> Result<Registers, ProcessError> {
+        let Registers { regs, .. } = self.get_gp_registers()?;
 
-        // In the book they do the debug registers but they aren't in the nix crate so I'll save
-        // them for now (maybe PR nix crate at some point or raise an issue when I understand them
-        // more).
+        let fpregs = match ptrace::getregset::<regset::NT_PRFPREG>(self.current_thread) {
+            Ok(fpregs) => Some(fpregs),
+            Err(e) => {
+                warn!(
+                    "Couldn't read fp registers, only general purpose registers available: {}",
+                    e
+                );
+                None
+            }
+        };
+
+        // The debug registers aren't in a regset, see `get_debug_registers`
 
         Ok(Registers { regs, fpregs })
     }
 
+    /// Reads the debug registers of the stopped thread. Each thread has its own so a hardware
+    /// breakpoint set in one only fires in that thread.
+    pub fn get_debug_registers(&self) -> Result<DebugRegisters, ProcessError> {
+        self.ensure_alive()?;
+        let read = |index| {
+            read_debug_register(self.current_thread, index).map_err(|e| {
+                error!("Failed to read DR{}: {}", index, e);
+                ProcessError::RegisterReadFailed
+            })
+        };
+        Ok(DebugRegisters {
+            dr0: read(0)?,
+            dr1: read(1)?,
+            dr2: read(2)?,
+            dr3: read(3)?,
+            dr6: read(6)?,
+            dr7: read(7)?,
+        })
+    }
+
+    /// Writes the debug registers of the stopped thread. DR7 goes last as the kernel checks the
+    /// slots it enables have valid addresses.
+    pub fn set_debug_registers(&mut self, regs: DebugRegisters) -> Result<(), ProcessError> {
+        self.ensure_alive()?;
+        let values = [
+            (0, regs.dr0),
+            (1, regs.dr1),
+            (2, regs.dr2),
+            (3, regs.dr3),
+            (6, regs.dr6),
+            (7, regs.dr7),
+        ];
+        for (index, value) in values {
+            write_debug_register(self.current_thread, index, value).map_err(|e| {
+                error!("Failed to write DR{}: {}", index, e);
+                ProcessError::RegisterWriteFailed
+            })?;
+        }
+        Ok(())
+    }
+
     pub fn write_all_registers(&mut self, registers: Registers) -> Result<(), ProcessError> {
         self.write_gp_registers(registers.regs)?;
-        self.write_fp_registers(registers.fpregs)
+        match registers.fpregs {
+            Some(fpregs) => self.write_fp_registers(fpregs),
+            None => Ok(()),
+        }
     }
 
     pub fn write_gp_registers(&mut self, regs: user_regs_struct) -> Result<(), ProcessError> {
-        ptrace::setregs(self.pid, regs).map_err(|e| {
+        self.ensure_alive()?;
+        ptrace::setregs(self.current_thread, regs).map_err(|e| {
             error!("Failed to write registers: {}", e);
             ProcessError::RegisterWriteFailed
         })
     }
 
     pub fn write_fp_registers(&mut self, fpregs: user_fpregs_struct) -> Result<(), ProcessError> {
-        ptrace::setregset::<regset::NT_PRFPREG>(self.pid, fpregs).map_err(|e| {
+        self.ensure_alive()?;
+        ptrace::setregset::<regset::NT_PRFPREG>(self.current_thread, fpregs).map_err(|e| {
             error!("Failed to write fp registers: {}", e);
             ProcessError::FpRegisterWriteFailed
         })
     }
 
+    /// Writes to the process's stdin, this blocks if the pipe is full until the process reads
+    /// enough of it. Attached processes keep their own stdin so can't be written to.
+    pub fn write_stdin(&mut self, data: &[u8]) -> Result<(), ProcessError> {
+        self.ensure_alive()?;
+        let writer = self.stdin_writer.as_ref().ok_or(ProcessError::NoStdin)?;
+        let mut written = 0;
+        while written < data.len() {
+            match write(writer, &data[written..]) {
+                Ok(len) => written += len,
+                Err(Errno::EINTR) => {}
+                Err(e) => {
+                    error!("Failed to write to process stdin: {}", e);
+                    return Err(ProcessError::WriteFailed);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads what the process has written to stdout, invalid UTF-8 is replaced.
     pub fn read_stdout(&mut self) -> Option<String> {
-        let reader = self.stdout_reader.as_ref()?;
-        let mut buf = [0u8; 1024];
-        let len = unsafe { libc::read(reader.as_raw_fd(), std::mem::transmute(&mut buf), 1024) };
-        if len > 0 {
-            let string = String::from_utf8_lossy(&buf[..(len as usize)]).into_owned();
-            Some(string)
-        } else {
+        self.read_stdout_bytes()
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Reads the raw bytes the process has written to stdout.
+    pub fn read_stdout_bytes(&mut self) -> Option<Vec<u8>> {
+        read_output(self.stdout_reader.as_ref()?, "stdout")
+    }
+
+    /// Reads what the process has written to stderr, invalid UTF-8 is replaced.
+    pub fn read_stderr(&mut self) -> Option<String> {
+        self.read_stderr_bytes()
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Reads the raw bytes the process has written to stderr.
+    pub fn read_stderr_bytes(&mut self) -> Option<Vec<u8>> {
+        read_output(self.stderr_reader.as_ref()?, "stderr")
+    }
+}
+
+/// Reads whatever is waiting in one of the output pipes, returning `None` if there's nothing.
+fn read_output(reader: &OwnedFd, name: &str) -> Option<Vec<u8>> {
+    let mut buf = vec![0u8; OUTPUT_BUFFER_SIZE];
+    match read(reader.as_raw_fd(), &mut buf) {
+        Ok(len) if len > 0 => {
+            buf.truncate(len);
+            Some(buf)
+        }
+        Ok(_) | Err(Errno::EAGAIN) => None,
+        Err(e) => {
+            warn!("Failed to read process {}: {}", name, e);
             None
         }
     }
@@ -421,32 +1986,65 @@ impl Process {
 
 impl Drop for Process {
     fn drop(&mut self) {
-        if self.pid.as_raw() != 0 {
+        if self.pid.as_raw() == 0 {
+            return;
+        }
+        if !self.terminate_on_end {
+            // Let it carry on without us the same way `detach` does, which needs it stopped
             if self.state == State::Running {
-                if let Err(e) = kill(self.pid, Signal::SIGSTOP) {
-                    warn!("Sending sigstop to process on teardown failed: {}", e);
+                match self.stop_for_detach(self.pid) {
+                    Some(signal) => {
+                        self.current_thread = self.pid;
+                        self.current_signal = signal;
+                        self.state = State::Stopped;
+                    }
+                    None => warn!("Couldn't stop {} on teardown", self.pid),
                 }
-                if let Err(e) = waitpid(self.pid, None) {
-                    warn!("Couldn't wait on receiving stop: {}", e);
+            } else if self.step_pending {
+                if let Err(e) = waitpid(self.current_thread, Some(WaitPidFlag::__WALL)) {
+                    warn!("Couldn't wait for the step to finish on teardown: {}", e);
                 }
+                self.step_pending = false;
             }
-
-            // For detach to work we need to be stopped! Hence the stop and wait before
-            if let Err(e) = detach_child(self.pid) {
+            if let Err(e) = self.detach() {
                 warn!("Failed to detach on teardown: {}", e);
             }
-            if let Err(e) = kill(self.pid, Signal::SIGCONT) {
-                warn!("Couldn't continue after detach: {}", e);
+            return;
+        }
+
+        if self.state == State::Running {
+            if let Err(e) = kill(self.pid, Signal::SIGSTOP) {
+                warn!("Sending sigstop to process on teardown failed: {}", e);
+            }
+            if let Err(e) = waitpid(self.pid, None) {
+                warn!("Couldn't wait on receiving stop: {}", e);
             }
+        }
 
-            if self.terminate_on_end {
-                if let Err(e) = kill(self.pid, Signal::SIGKILL) {
-                    warn!("Couldn't issue sigkill on teardown: {}", e);
-                }
-                if let Err(e) = waitpid(self.pid, None) {
-                    warn!("Wait after sigkill failed: {}", e);
-                }
+        // For detach to work we need to be stopped! Hence the stop and wait before
+        if let Err(e) = detach_child(self.pid) {
+            warn!("Failed to detach on teardown: {}", e);
+        }
+        if let Err(e) = kill(self.pid, Signal::SIGCONT) {
+            warn!("Couldn't continue after detach: {}", e);
+        }
+
+        if let Err(e) = kill(self.pid, Signal::SIGKILL) {
+            warn!("Couldn't issue sigkill on teardown: {}", e);
+        }
+        // Followed forks would otherwise outlive us, still traced
+        for child in &self.forks {
+            if let Err(e) = kill(*child, Signal::SIGKILL) {
+                warn!("Couldn't sigkill forked child {} on teardown: {}", child, e);
             }
+            let _ = waitpid(*child, Some(WaitPidFlag::__WALL));
+        }
+        // The main thread isn't reaped until the other traced threads are
+        for tid in self.threads.iter().filter(|tid| **tid != self.pid) {
+            let _ = waitpid(*tid, Some(WaitPidFlag::__WALL));
+        }
+        if let Err(e) = waitpid(self.pid, None) {
+            warn!("Wait after sigkill failed: {}", e);
         }
     }
 }
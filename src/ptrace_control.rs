@@ -18,10 +18,30 @@ pub fn trace_children(pid: Pid) -> Result<()> {
         | Options::PTRACE_O_TRACEEXIT
         | Options::PTRACE_O_TRACECLONE
         | Options::PTRACE_O_TRACEFORK
-        | Options::PTRACE_O_TRACEVFORK;
+        | Options::PTRACE_O_TRACEVFORK
+        | Options::PTRACE_O_TRACESECCOMP;
     setoptions(pid, options)
 }
 
+/// Sets only `PTRACE_O_TRACESYSGOOD`, so syscall stops can be told apart from a SIGTRAP, without
+/// stopping on forks, clones or execs.
+pub fn mark_syscall_stops(pid: Pid) -> Result<()> {
+    setoptions(pid, Options::PTRACE_O_TRACESYSGOOD)
+}
+
+/// Sends SIGSTOP to thread `tid` of process `pid` rather than any thread of the process.
+pub fn stop_thread(pid: Pid, tid: Pid) -> Result<()> {
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_tgkill,
+            pid.as_raw(),
+            tid.as_raw(),
+            Signal::SIGSTOP as libc::c_int,
+        )
+    };
+    Errno::result(ret).map(drop)
+}
+
 pub fn detach_child(pid: Pid) -> Result<()> {
     detach(pid, None)
 }
@@ -30,6 +50,10 @@ pub fn continue_exec(pid: Pid, sig: Option<Signal>) -> Result<()> {
     cont(pid, sig)
 }
 
+pub fn continue_to_syscall(pid: Pid, sig: Option<Signal>) -> Result<()> {
+    syscall(pid, sig)
+}
+
 #[allow(deprecated)]
 pub fn single_step(pid: Pid) -> Result<()> {
     step(pid, None)
@@ -39,6 +63,58 @@ pub fn read_address(pid: Pid, address: u64) -> Result<c_long> {
     read(pid, address as AddressType)
 }
 
+/// Reads `len` bytes at `address` with a single `process_vm_readv`, much quicker than a
+/// `PTRACE_PEEKDATA` per word for anything but a short read. Fails unless the whole range is
+/// read, this includes pages that aren't readable which ptrace can still read.
+pub fn read_memory_range(pid: Pid, address: u64, len: usize) -> Result<Vec<u8>> {
+    let mut data = vec![0u8; len];
+    let local = libc::iovec {
+        iov_base: data.as_mut_ptr().cast(),
+        iov_len: len,
+    };
+    let remote = libc::iovec {
+        iov_base: address as *mut c_void,
+        iov_len: len,
+    };
+    let read = unsafe { libc::process_vm_readv(pid.as_raw(), &local, 1, &remote, 1, 0) };
+    match Errno::result(read)? as usize {
+        read if read == len => Ok(data),
+        _ => Err(Errno::EFAULT),
+    }
+}
+
+/// Writes `data` at `address` a word at a time with `PTRACE_POKEDATA`, which can write to code
+/// that isn't writable. The address doesn't need to be aligned, words only partly covered by
+/// `data` are read first so the rest of them is kept.
+pub fn write_memory_range(pid: Pid, address: u64, data: &[u8]) -> Result<()> {
+    const WORD: u64 = std::mem::size_of::<c_long>() as u64;
+    let end = address
+        .checked_add(data.len() as u64)
+        .ok_or(Errno::EFAULT)?;
+    let mut word_addr = address & !(WORD - 1);
+    while word_addr < end {
+        let start = address.saturating_sub(word_addr) as usize;
+        let stop = (end - word_addr).min(WORD) as usize;
+        // Only need what's there already if part of the word is being kept
+        let mut bytes = if start == 0 && stop == WORD as usize {
+            [0u8; WORD as usize]
+        } else {
+            read_address(pid, word_addr)?.to_ne_bytes()
+        };
+        let offset = (word_addr + start as u64 - address) as usize;
+        bytes[start..stop].copy_from_slice(&data[offset..offset + stop - start]);
+        write_to_address(pid, word_addr, c_long::from_ne_bytes(bytes))?;
+        word_addr += WORD;
+    }
+    Ok(())
+}
+
+/// Reads the byte at `address`.
+pub fn read_byte(pid: Pid, address: u64) -> Result<u8> {
+    let word = read_address(pid, address & !0x7)?;
+    Ok(word.to_ne_bytes()[(address & 0x7) as usize])
+}
+
 pub fn write_to_address(pid: Pid, address: u64, data: i64) -> Result<()> {
     write(pid, address as AddressType, data)
 }
@@ -73,6 +149,43 @@ pub fn set_instruction_pointer(pid: Pid, pc: u64) -> Result<c_long> {
     Errno::result(ret).map(|_| 0)
 }
 
+/// Offset of debug register `index` in `struct user`, what `PTRACE_PEEKUSER` and
+/// `PTRACE_POKEUSER` take as the address.
+fn debug_register_offset(index: usize) -> usize {
+    std::mem::offset_of!(libc::user, u_debugreg) + index * std::mem::size_of::<usize>()
+}
+
+#[allow(deprecated)]
+pub fn read_debug_register(pid: Pid, index: usize) -> Result<u64> {
+    let ret = unsafe {
+        Errno::clear();
+        libc::ptrace(
+            Request::PTRACE_PEEKUSER as RequestType,
+            libc::pid_t::from(pid),
+            debug_register_offset(index) as *mut c_void,
+            ptr::null_mut::<c_void>(),
+        )
+    };
+    // -1 is a valid value so errno has to be checked to tell if it failed
+    match Errno::last() {
+        Errno::UnknownErrno => Ok(ret as u64),
+        e => Err(e),
+    }
+}
+
+#[allow(deprecated)]
+pub fn write_debug_register(pid: Pid, index: usize, value: u64) -> Result<()> {
+    let ret = unsafe {
+        libc::ptrace(
+            Request::PTRACE_POKEUSER as RequestType,
+            libc::pid_t::from(pid),
+            debug_register_offset(index) as *mut c_void,
+            value as *mut c_void,
+        )
+    };
+    Errno::result(ret).map(drop)
+}
+
 pub fn request_trace() -> Result<()> {
     traceme()
 }
@@ -0,0 +1,540 @@
+//! Runtime settings for the debugger. Settings are changed with `set <NAME> <VALUE>` and shown
+//! with `show <NAME>` or `show all`. Each setting is a typed field on `Settings` with an entry in
+//! the registry below which handles converting it to and from strings.
+use crate::backtrace;
+use crate::output;
+use crate::process::{parse_signal, FollowFork, DEFAULT_NOPASS_SIGNALS};
+use crate::trace;
+use nix::sys::signal::Signal;
+use thiserror::Error;
+
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum SettingsError {
+    #[error("unknown setting \"{0}\"")]
+    UnknownSetting(String),
+    #[error("invalid value \"{value}\" for {name}: {msg}")]
+    InvalidValue {
+        name: String,
+        value: String,
+        msg: String,
+    },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Settings {
+    /// Number of commands kept in the command history
+    pub history_size: usize,
+    /// Stop the process when it forks, clones, execs or exits
+    pub stop_on_events: bool,
+    /// Maximum number of instructions recorded by an instruction trace
+    pub trace_limit: usize,
+    /// Write the general purpose registers alongside every instruction in a trace
+    pub trace_registers: bool,
+    /// Maximum number of frames shown by a backtrace
+    pub backtrace_limit: usize,
+    /// Print structs and arrays of structs across multiple lines
+    pub print_pretty: bool,
+    /// How bytes the program writes to stdout are shown
+    pub charset: Charset,
+    /// Continue straight away when the process gets a SIGTRAP we didn't cause
+    pub skip_spurious_traps: bool,
+    /// Continue a launched program once it's set up instead of leaving it stopped at the start
+    pub auto_continue: bool,
+    /// Ask before doing something that ends the process being debugged
+    pub confirm: bool,
+    /// Show command output longer than a screen a page at a time instead of logging it all
+    pub pagination: bool,
+    /// Lines of program output kept for the output panel
+    pub output_lines: usize,
+    /// Check a breakpoint's int3 is still there before restoring the byte it replaced
+    pub verify_breakpoints: bool,
+    /// Instructions a source line step single steps before falling back to a breakpoint on the
+    /// next line
+    pub max_step_instructions: usize,
+    /// Lines of source shown by `list <LOCATION>`, and the context kept around the current line
+    /// in the source view
+    pub list_size: usize,
+    /// Signals dropped instead of being delivered when the process continues after stopping
+    /// with them
+    pub nopass_signals: Vec<Signal>,
+    /// Restore the registers when a function called with `call` gets a signal, rather than
+    /// leaving the process where the signal stopped it
+    pub unwind_on_signal: bool,
+    /// Which process is debugged after a fork, only forks seen with stop-on-events are followed
+    pub follow_fork: FollowFork,
+}
+
+/// How program output is turned into text.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Charset {
+    /// Decode as UTF-8 with invalid sequences replaced
+    #[default]
+    Utf8,
+    /// Printable ASCII as is and everything else as `\xNN`
+    Escape,
+    /// Every byte in hex
+    Hex,
+}
+
+const CHARSETS: &[(&str, Charset)] = &[
+    ("utf8", Charset::Utf8),
+    ("escape", Charset::Escape),
+    ("hex", Charset::Hex),
+];
+
+impl Charset {
+    /// Converts program output to text, newlines are kept in every mode so output stays split
+    /// into lines.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        match self {
+            Self::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Self::Escape => bytes
+                .iter()
+                .map(|b| match b {
+                    b'\n' | b'\t' => (*b as char).to_string(),
+                    b'\\' => "\\\\".to_string(),
+                    0x20..=0x7e => (*b as char).to_string(),
+                    _ => format!("\\x{:02x}", b),
+                })
+                .collect(),
+            // Each byte is followed by a separator so output read in chunks joins up
+            Self::Hex => bytes
+                .iter()
+                .map(|b| format!("{:02x}{}", b, if *b == b'\n' { '\n' } else { ' ' }))
+                .collect(),
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            history_size: 10,
+            stop_on_events: false,
+            trace_limit: trace::DEFAULT_MAX_INSTRUCTIONS,
+            trace_registers: false,
+            backtrace_limit: backtrace::DEFAULT_MAX_FRAMES,
+            print_pretty: false,
+            charset: Charset::default(),
+            skip_spurious_traps: false,
+            auto_continue: false,
+            confirm: true,
+            pagination: true,
+            output_lines: output::DEFAULT_CAPACITY,
+            verify_breakpoints: true,
+            max_step_instructions: crate::DEFAULT_MAX_STEP_INSTRUCTIONS,
+            list_size: 10,
+            nopass_signals: DEFAULT_NOPASS_SIGNALS.to_vec(),
+            unwind_on_signal: true,
+            follow_fork: FollowFork::default(),
+        }
+    }
+}
+
+/// Registry entry for a setting.
+struct Setting {
+    name: &'static str,
+    description: &'static str,
+    get: fn(&Settings) -> String,
+    set: fn(&mut Settings, &str) -> Result<(), String>,
+}
+
+const SETTINGS: &[Setting] = &[
+    Setting {
+        name: "history-size",
+        description: "number of commands kept in the command history",
+        get: |s| s.history_size.to_string(),
+        set: |s, v| {
+            s.history_size = parse_usize(v)?;
+            Ok(())
+        },
+    },
+    Setting {
+        name: "stop-on-events",
+        description: "stop on fork, clone, exec and exit (applied when a process is started)",
+        get: |s| show_bool(s.stop_on_events),
+        set: |s, v| {
+            s.stop_on_events = parse_bool(v)?;
+            Ok(())
+        },
+    },
+    Setting {
+        name: "trace-limit",
+        description: "maximum number of instructions recorded by `trace start`",
+        get: |s| s.trace_limit.to_string(),
+        set: |s, v| {
+            s.trace_limit = parse_usize(v)?;
+            Ok(())
+        },
+    },
+    Setting {
+        name: "trace-registers",
+        description: "write the registers for every instruction in a trace (makes it much bigger)",
+        get: |s| show_bool(s.trace_registers),
+        set: |s, v| {
+            s.trace_registers = parse_bool(v)?;
+            Ok(())
+        },
+    },
+    Setting {
+        name: "backtrace-limit",
+        description: "maximum number of frames shown by `bt`",
+        get: |s| s.backtrace_limit.to_string(),
+        set: |s, v| {
+            s.backtrace_limit = parse_usize(v)?;
+            Ok(())
+        },
+    },
+    Setting {
+        name: "print pretty",
+        description: "print structs with a line per field, indented by nesting",
+        get: |s| show_bool(s.print_pretty),
+        set: |s, v| {
+            s.print_pretty = parse_bool(v)?;
+            Ok(())
+        },
+    },
+    Setting {
+        name: "charset",
+        description: "how program output is shown: utf8, escape or hex",
+        get: |s| {
+            CHARSETS
+                .iter()
+                .find(|(_, c)| *c == s.charset)
+                .map(|(name, _)| name.to_string())
+                .unwrap_or_default()
+        },
+        set: |s, v| {
+            s.charset = CHARSETS
+                .iter()
+                .find(|(name, _)| *name == v)
+                .map(|(_, c)| *c)
+                .ok_or_else(|| "expected utf8, escape or hex".to_string())?;
+            Ok(())
+        },
+    },
+    Setting {
+        name: "skip-spurious-traps",
+        description: "continue on a SIGTRAP that isn't from a breakpoint or step",
+        get: |s| show_bool(s.skip_spurious_traps),
+        set: |s, v| {
+            s.skip_spurious_traps = parse_bool(v)?;
+            Ok(())
+        },
+    },
+    Setting {
+        name: "auto-continue",
+        description: "run launched programs straight away rather than stopping at the start",
+        get: |s| show_bool(s.auto_continue),
+        set: |s, v| {
+            s.auto_continue = parse_bool(v)?;
+            Ok(())
+        },
+    },
+    Setting {
+        name: "confirm",
+        description:
+            "ask before quitting, restarting, loading or attaching ends the current process",
+        get: |s| show_bool(s.confirm),
+        set: |s, v| {
+            s.confirm = parse_bool(v)?;
+            Ok(())
+        },
+    },
+    Setting {
+        name: "pagination",
+        description: "show command output longer than a screen in a pager a page at a time",
+        get: |s| show_bool(s.pagination),
+        set: |s, v| {
+            s.pagination = parse_bool(v)?;
+            Ok(())
+        },
+    },
+    Setting {
+        name: "output-lines",
+        description: "lines of program output kept for the output panel, the oldest are dropped",
+        get: |s| s.output_lines.to_string(),
+        set: |s, v| {
+            s.output_lines = parse_usize(v)?;
+            Ok(())
+        },
+    },
+    Setting {
+        name: "verify-breakpoints",
+        description: "check a breakpoint's int3 wasn't overwritten before restoring the original \
+                      byte, if it was the new code is kept",
+        get: |s| show_bool(s.verify_breakpoints),
+        set: |s, v| {
+            s.verify_breakpoints = parse_bool(v)?;
+            Ok(())
+        },
+    },
+    Setting {
+        name: "max-step-instructions",
+        description: "instructions single stepped by a line step before it gives up and \
+                      continues to a breakpoint on the next line",
+        get: |s| s.max_step_instructions.to_string(),
+        set: |s, v| match parse_usize(v)? {
+            0 => Err("must be at least 1".to_string()),
+            n => {
+                s.max_step_instructions = n;
+                Ok(())
+            }
+        },
+    },
+    Setting {
+        name: "listsize",
+        description: "lines of source shown by `list <LOCATION>` and kept around the current \
+                      line in the source view",
+        get: |s| s.list_size.to_string(),
+        set: |s, v| match parse_usize(v)? {
+            0 => Err("must be at least 1".to_string()),
+            n => {
+                s.list_size = n;
+                Ok(())
+            }
+        },
+    },
+    Setting {
+        name: "nopass-signals",
+        description: "signals not delivered when the process continues, comma separated or none",
+        get: |s| match &s.nopass_signals[..] {
+            [] => "none".to_string(),
+            signals => signals
+                .iter()
+                .map(|signal| signal.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+        },
+        set: |s, v| {
+            s.nopass_signals = match v {
+                "none" => vec![],
+                v => v
+                    .split(',')
+                    .map(parse_signal)
+                    .collect::<Result<Vec<_>, _>>()?,
+            };
+            Ok(())
+        },
+    },
+    Setting {
+        name: "unwind-on-signal",
+        description: "restore the registers when a called function gets a signal, off leaves the \
+                      process stopped inside the function",
+        get: |s| show_bool(s.unwind_on_signal),
+        set: |s, v| {
+            s.unwind_on_signal = parse_bool(v)?;
+            Ok(())
+        },
+    },
+    Setting {
+        name: "follow-fork",
+        description: "parent lets forked children go, child traces them as well, needs \
+                      stop-on-events",
+        get: |s| {
+            match s.follow_fork {
+                FollowFork::Parent => "parent",
+                FollowFork::Child => "child",
+            }
+            .to_string()
+        },
+        set: |s, v| {
+            s.follow_fork = match v {
+                "parent" => FollowFork::Parent,
+                "child" => FollowFork::Child,
+                _ => return Err("expected parent or child".to_string()),
+            };
+            Ok(())
+        },
+    },
+];
+
+fn find_setting(name: &str) -> Result<&'static Setting, SettingsError> {
+    SETTINGS
+        .iter()
+        .find(|s| s.name == name)
+        .ok_or_else(|| SettingsError::UnknownSetting(name.to_string()))
+}
+
+impl Settings {
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), SettingsError> {
+        let setting = find_setting(name)?;
+        (setting.set)(self, value).map_err(|msg| SettingsError::InvalidValue {
+            name: name.to_string(),
+            value: value.to_string(),
+            msg,
+        })
+    }
+
+    /// Gets the value of a setting as it would be written in a `set` command.
+    pub fn get(&self, name: &str) -> Result<String, SettingsError> {
+        let setting = find_setting(name)?;
+        Ok((setting.get)(self))
+    }
+
+    pub fn show(&self, name: &str) -> Result<String, SettingsError> {
+        let setting = find_setting(name)?;
+        Ok(format!(
+            "{} = {} ({})",
+            setting.name,
+            (setting.get)(self),
+            setting.description
+        ))
+    }
+
+    pub fn show_all(&self) -> Vec<String> {
+        SETTINGS
+            .iter()
+            .map(|s| format!("{} = {} ({})", s.name, (s.get)(self), s.description))
+            .collect()
+    }
+
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        SETTINGS.iter().map(|s| s.name)
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => Err("expected on or off".to_string()),
+    }
+}
+
+fn show_bool(value: bool) -> String {
+    if value { "on" } else { "off" }.to_string()
+}
+
+fn parse_usize(value: &str) -> Result<usize, String> {
+    value.parse::<usize>().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_and_get() {
+        let mut settings = Settings::default();
+        settings.set("history-size", "20").unwrap();
+        assert_eq!(settings.history_size, 20);
+        assert_eq!(settings.get("history-size").unwrap(), "20");
+
+        settings.set("stop-on-events", "on").unwrap();
+        assert!(settings.stop_on_events);
+        assert_eq!(settings.get("stop-on-events").unwrap(), "on");
+        settings.set("stop-on-events", "false").unwrap();
+        assert!(!settings.stop_on_events);
+
+        settings.set("print pretty", "on").unwrap();
+        assert!(settings.print_pretty);
+
+        settings.set("skip-spurious-traps", "on").unwrap();
+        assert!(settings.skip_spurious_traps);
+
+        settings.set("auto-continue", "on").unwrap();
+        assert!(settings.auto_continue);
+
+        assert!(settings.confirm);
+        settings.set("confirm", "off").unwrap();
+        assert!(!settings.confirm);
+
+        assert!(settings.pagination);
+        settings.set("pagination", "off").unwrap();
+        assert!(!settings.pagination);
+
+        settings.set("output-lines", "50").unwrap();
+        assert_eq!(settings.output_lines, 50);
+
+        assert!(settings.verify_breakpoints);
+        settings.set("verify-breakpoints", "off").unwrap();
+        assert!(!settings.verify_breakpoints);
+
+        settings.set("listsize", "20").unwrap();
+        assert_eq!(settings.list_size, 20);
+        assert!(settings.set("listsize", "0").is_err());
+
+        assert_eq!(
+            settings.get("nopass-signals").unwrap(),
+            "SIGTRAP,SIGSTOP,SIGINT"
+        );
+        settings.set("nopass-signals", "usr1, SIGALRM").unwrap();
+        assert_eq!(settings.nopass_signals, [Signal::SIGUSR1, Signal::SIGALRM]);
+        settings.set("nopass-signals", "none").unwrap();
+        assert_eq!(settings.get("nopass-signals").unwrap(), "none");
+        assert!(settings.set("nopass-signals", "SIGNOPE").is_err());
+
+        assert!(settings.unwind_on_signal);
+        settings.set("unwind-on-signal", "off").unwrap();
+        assert!(!settings.unwind_on_signal);
+        assert_eq!(settings.get("unwind-on-signal").unwrap(), "off");
+
+        assert_eq!(settings.get("follow-fork").unwrap(), "parent");
+        settings.set("follow-fork", "child").unwrap();
+        assert_eq!(settings.follow_fork, FollowFork::Child);
+        assert!(settings.set("follow-fork", "both").is_err());
+    }
+
+    #[test]
+    fn invalid_settings() {
+        let mut settings = Settings::default();
+        assert_eq!(
+            settings.set("colour", "blue"),
+            Err(SettingsError::UnknownSetting("colour".to_string()))
+        );
+        assert!(matches!(
+            settings.set("trace-limit", "lots"),
+            Err(SettingsError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            settings.set("stop-on-events", "maybe"),
+            Err(SettingsError::InvalidValue { .. })
+        ));
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn max_step_instructions_setting() {
+        let mut settings = Settings::default();
+        assert_eq!(
+            settings.get("max-step-instructions").unwrap(),
+            crate::DEFAULT_MAX_STEP_INSTRUCTIONS.to_string()
+        );
+        settings.set("max-step-instructions", "500").unwrap();
+        assert_eq!(settings.max_step_instructions, 500);
+        assert!(matches!(
+            settings.set("max-step-instructions", "0"),
+            Err(SettingsError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            settings.set("max-step-instructions", "lots"),
+            Err(SettingsError::InvalidValue { .. })
+        ));
+        assert_eq!(settings.max_step_instructions, 500);
+    }
+
+    #[test]
+    fn decode_output() {
+        let bytes = b"ok\xff\x00\\\n";
+        assert_eq!(Charset::Utf8.decode(bytes), "ok\u{fffd}\0\\\n");
+        assert_eq!(Charset::Escape.decode(bytes), "ok\\xff\\x00\\\\\n");
+        assert_eq!(Charset::Hex.decode(bytes), "6f 6b ff 00 5c 0a\n");
+
+        let mut settings = Settings::default();
+        settings.set("charset", "hex").unwrap();
+        assert_eq!(settings.charset, Charset::Hex);
+        assert_eq!(settings.get("charset").unwrap(), "hex");
+        assert!(settings.set("charset", "latin1").is_err());
+    }
+
+    #[test]
+    fn show_every_setting() {
+        let settings = Settings::default();
+        let all = settings.show_all();
+        assert_eq!(all.len(), Settings::names().count());
+        for name in Settings::names() {
+            assert!(settings.show(name).unwrap().starts_with(name));
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Snapshots of the registers and chosen ranges of memory, so what a region of code changed can
+//! be seen by saving a snapshot, running the code and diffing against the snapshot.
+use crate::process::{Process, ProcessError, Registers};
+
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    registers: Registers,
+    /// Start address and the bytes read from it
+    memory: Vec<(u64, Vec<u8>)>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RegisterChange {
+    pub name: &'static str,
+    pub before: u64,
+    pub after: u64,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ByteChange {
+    pub addr: u64,
+    pub before: u8,
+    pub after: u8,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SnapshotDiff {
+    pub registers: Vec<RegisterChange>,
+    pub memory: Vec<ByteChange>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.memory.is_empty()
+    }
+}
+
+impl Snapshot {
+    /// Captures the registers and the given `(address, length)` ranges of memory.
+    pub fn capture(process: &Process, ranges: &[(u64, usize)]) -> Result<Self, ProcessError> {
+        let registers = process.get_gp_registers()?;
+        let memory = ranges
+            .iter()
+            .map(|(addr, len)| Ok((*addr, process.read_memory(*addr, *len)?)))
+            .collect::<Result<Vec<_>, ProcessError>>()?;
+        Ok(Self { registers, memory })
+    }
+
+    /// Captures the current state of the same memory ranges and compares it to this snapshot.
+    pub fn diff(&self, process: &Process) -> Result<SnapshotDiff, ProcessError> {
+        let ranges = self
+            .memory
+            .iter()
+            .map(|(addr, data)| (*addr, data.len()))
+            .collect::<Vec<_>>();
+        let current = Self::capture(process, &ranges)?;
+
+        let mut diff = SnapshotDiff::default();
+        for name in Registers::GENERAL_PURPOSE {
+            let before = self.registers.get(name).unwrap_or_default();
+            let after = current.registers.get(name).unwrap_or_default();
+            if before != after {
+                diff.registers.push(RegisterChange {
+                    name,
+                    before,
+                    after,
+                });
+            }
+        }
+        for ((addr, before), (_, after)) in self.memory.iter().zip(&current.memory) {
+            for (offset, (before, after)) in before.iter().zip(after).enumerate() {
+                if before != after {
+                    diff.memory.push(ByteChange {
+                        addr: addr + offset as u64,
+                        before: *before,
+                        after: *after,
+                    });
+                }
+            }
+        }
+        Ok(diff)
+    }
+}
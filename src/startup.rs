@@ -0,0 +1,280 @@
+//! Reading the arguments and environment a program was started with from its initial stack.
+//!
+//! When a program is entered (either the dynamic loader or the executable's own `_start`) the
+//! kernel has laid out the stack as described in the x86-64 SysV ABI's process initialisation
+//! section:
+//!
+//! ```text
+//! rsp ->  argc                      8 bytes
+//!         argv[0] .. argv[argc - 1] 8 bytes each
+//!         NULL
+//!         envp[0] .. envp[n - 1]    8 bytes each
+//!         NULL
+//!         auxv                      pairs of 8 byte words ending with AT_NULL
+//!         ...
+//!         strings pointed to by argv and envp
+//! ```
+//!
+//! The auxiliary vector (auxv) is how the kernel passes the program details about itself, like
+//! `AT_ENTRY` the address of its entry point. Where the program was loaded can be worked out from
+//! that when `/proc` can't be read.
+//!
+//! `rsp` is 16 byte aligned at this point. Once the program has run a few instructions the stack
+//! is used for other things so this only works when stopped at the entry point, we check the
+//! layout holds together rather than trusting whatever is at `rsp`.
+use crate::process::{Process, ProcessError};
+use thiserror::Error;
+
+/// The kernel limits the arguments and environment to a quarter of the stack size limit, this is
+/// for the default 8MiB so anything claiming more is unlikely to be the initial stack.
+const MAX_TOTAL_SIZE: u64 = 2 * 1024 * 1024;
+/// Longest single argument or environment variable the kernel accepts, `MAX_ARG_STRLEN`
+const MAX_STRING_LEN: usize = 32 * 4096;
+const WORD: u64 = 8;
+/// Far more auxv entries than the kernel passes, there are around 30
+const MAX_AUXV_ENTRIES: usize = 256;
+/// Marks the end of the auxv
+const AT_NULL: u64 = 0;
+/// Address of the program's entry point
+const AT_ENTRY: u64 = 9;
+
+#[derive(Debug, Error)]
+pub enum StartupError {
+    #[error("stack pointer 0x{0:x} isn't 16 byte aligned, the process isn't at its entry point")]
+    Misaligned(u64),
+    #[error("stack doesn't look like it does at the entry point: {0}")]
+    BadLayout(&'static str),
+    #[error(transparent)]
+    Process(#[from] ProcessError),
+}
+
+/// Arguments and environment variables the program was started with.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StartupArgs {
+    pub argv: Vec<String>,
+    /// Environment variables as `NAME=value` strings
+    pub envp: Vec<String>,
+    /// Auxiliary vector entries as type and value pairs, without the `AT_NULL` that ends it
+    pub auxv: Vec<(u64, u64)>,
+}
+
+impl StartupArgs {
+    /// The address of the program's entry point in memory, from `AT_ENTRY`.
+    pub fn entry(&self) -> Option<u64> {
+        self.auxv
+            .iter()
+            .find(|(ty, _)| *ty == AT_ENTRY)
+            .map(|(_, value)| *value)
+    }
+}
+
+/// Reads the arguments and environment from the stack of a process stopped at its entry point.
+pub fn read(process: &Process) -> Result<StartupArgs, StartupError> {
+    let rsp = process.get_gp_registers()?.regs.rsp;
+    read_from(rsp, |addr, len| process.read_memory(addr, len))
+}
+
+fn read_from(
+    rsp: u64,
+    read_memory: impl Fn(u64, usize) -> Result<Vec<u8>, ProcessError>,
+) -> Result<StartupArgs, StartupError> {
+    if !rsp.is_multiple_of(16) {
+        return Err(StartupError::Misaligned(rsp));
+    }
+    let read_word = |addr: u64| -> Result<u64, StartupError> {
+        let data = read_memory(addr, WORD as usize)?;
+        Ok(u64::from_le_bytes(data.try_into().unwrap_or_default()))
+    };
+
+    let argc = read_word(rsp)?;
+    if argc > MAX_TOTAL_SIZE / WORD {
+        return Err(StartupError::BadLayout("argc is too large"));
+    }
+    let argv_start = rsp + WORD;
+    let argv_pointers = read_memory(argv_start, ((argc + 1) * WORD) as usize)?
+        .chunks_exact(WORD as usize)
+        .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    if argv_pointers.last() != Some(&0) {
+        return Err(StartupError::BadLayout("argv isn't followed by NULL"));
+    }
+
+    let envp_start = argv_start + (argc + 1) * WORD;
+    let mut envp_pointers = vec![];
+    loop {
+        if envp_pointers.len() as u64 > MAX_TOTAL_SIZE / WORD {
+            return Err(StartupError::BadLayout("envp isn't followed by NULL"));
+        }
+        let pointer = read_word(envp_start + envp_pointers.len() as u64 * WORD)?;
+        if pointer == 0 {
+            break;
+        }
+        envp_pointers.push(pointer);
+    }
+
+    // The strings are copied in above the pointer arrays and auxv so any pointer at or below the
+    // end of envp can't be one of them
+    let envp_end = envp_start + (envp_pointers.len() as u64 + 1) * WORD;
+
+    let mut auxv = vec![];
+    loop {
+        if auxv.len() >= MAX_AUXV_ENTRIES {
+            return Err(StartupError::BadLayout("auxv isn't ended by AT_NULL"));
+        }
+        let entry = envp_end + auxv.len() as u64 * 2 * WORD;
+        let ty = read_word(entry)?;
+        if ty == AT_NULL {
+            break;
+        }
+        auxv.push((ty, read_word(entry + WORD)?));
+    }
+    let mut total = 0;
+    let mut read_strings = |pointers: &[u64]| -> Result<Vec<String>, StartupError> {
+        let mut strings = Vec::with_capacity(pointers.len());
+        for pointer in pointers.iter().copied() {
+            if pointer < envp_end {
+                return Err(StartupError::BadLayout(
+                    "string is below the pointers to it",
+                ));
+            }
+            let string = read_string(pointer, &read_memory)?;
+            total += string.len() as u64 + 1;
+            if total > MAX_TOTAL_SIZE {
+                return Err(StartupError::BadLayout("strings are too large"));
+            }
+            strings.push(String::from_utf8_lossy(&string).into_owned());
+        }
+        Ok(strings)
+    };
+    let argv = read_strings(&argv_pointers[..argc as usize])?;
+    let envp = read_strings(&envp_pointers)?;
+    Ok(StartupArgs { argv, envp, auxv })
+}
+
+/// Reads a nul terminated string a word at a time so we don't read past the end of the stack.
+fn read_string(
+    addr: u64,
+    read_memory: impl Fn(u64, usize) -> Result<Vec<u8>, ProcessError>,
+) -> Result<Vec<u8>, StartupError> {
+    let mut bytes = vec![];
+    let mut current = addr;
+    while bytes.len() <= MAX_STRING_LEN {
+        let len = WORD - current % WORD;
+        let data = read_memory(current, len as usize)?;
+        if let Some(end) = data.iter().position(|b| *b == 0) {
+            bytes.extend_from_slice(&data[..end]);
+            return Ok(bytes);
+        }
+        bytes.extend_from_slice(&data);
+        current += len;
+    }
+    Err(StartupError::BadLayout("string isn't nul terminated"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u64 = 0x7fff_0000;
+
+    /// Builds an initial stack at `BASE` the way the kernel would.
+    fn stack(argv: &[&str], envp: &[&str]) -> Vec<u8> {
+        stack_with_auxv(argv, envp, &[])
+    }
+
+    fn stack_with_auxv(argv: &[&str], envp: &[&str], auxv: &[(u64, u64)]) -> Vec<u8> {
+        let pointers = 1 + argv.len() + 1 + envp.len() + 1;
+        // Room for the auxv and the AT_NULL ending it too
+        let mut strings_addr = BASE + (pointers as u64 + (auxv.len() as u64 + 1) * 2) * WORD;
+        let mut words = vec![argv.len() as u64];
+        let mut strings = vec![];
+        for list in [argv, envp] {
+            for s in list {
+                words.push(strings_addr);
+                strings.extend_from_slice(s.as_bytes());
+                strings.push(0);
+                strings_addr += s.len() as u64 + 1;
+            }
+            words.push(0);
+        }
+        words.extend(auxv.iter().flat_map(|(ty, value)| [*ty, *value]));
+        words.extend([AT_NULL, 0]);
+        let mut stack = words
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect::<Vec<_>>();
+        stack.extend(strings);
+        // Reads are a word at a time and the real stack carries on past the strings
+        stack.resize(stack.len().next_multiple_of(WORD as usize), 0);
+        stack
+    }
+
+    fn reader(stack: &[u8]) -> impl Fn(u64, usize) -> Result<Vec<u8>, ProcessError> + '_ {
+        move |addr, len| {
+            let start = addr
+                .checked_sub(BASE)
+                .ok_or(ProcessError::ReadFailed(addr))? as usize;
+            stack
+                .get(start..start + len)
+                .map(|data| data.to_vec())
+                .ok_or(ProcessError::ReadFailed(addr))
+        }
+    }
+
+    #[test]
+    fn reads_initial_stack() {
+        let data = stack(&["./prog", "-v", ""], &["HOME=/root", "TERM=xterm"]);
+        let args = read_from(BASE, reader(&data)).unwrap();
+        assert_eq!(args.argv, ["./prog", "-v", ""]);
+        assert_eq!(args.envp, ["HOME=/root", "TERM=xterm"]);
+
+        let data = stack(&[], &[]);
+        let args = read_from(BASE, reader(&data)).unwrap();
+        assert_eq!(args, StartupArgs::default());
+    }
+
+    #[test]
+    fn reads_auxv() {
+        let auxv = [(6, 4096), (AT_ENTRY, 0x5555_5555_5040)];
+        let data = stack_with_auxv(&["./prog"], &["A=b"], &auxv);
+        let args = read_from(BASE, reader(&data)).unwrap();
+        assert_eq!(args.argv, ["./prog"]);
+        assert_eq!(args.auxv, auxv);
+        assert_eq!(args.entry(), Some(0x5555_5555_5040));
+
+        let data = stack(&["./prog"], &[]);
+        assert_eq!(read_from(BASE, reader(&data)).unwrap().entry(), None);
+    }
+
+    #[test]
+    fn rejects_other_stacks() {
+        let data = stack(&["./prog"], &["A=b"]);
+        assert!(matches!(
+            read_from(BASE + 8, reader(&data)),
+            Err(StartupError::Misaligned(_))
+        ));
+
+        // argv[argc] should be NULL
+        let mut bad = data.clone();
+        bad[0] = 2;
+        assert!(matches!(
+            read_from(BASE, reader(&bad)),
+            Err(StartupError::BadLayout(_))
+        ));
+
+        // A pointer into the pointer arrays rather than the strings above them
+        let mut bad = data.clone();
+        bad[8..16].copy_from_slice(&BASE.to_le_bytes());
+        assert!(matches!(
+            read_from(BASE, reader(&bad)),
+            Err(StartupError::BadLayout(_))
+        ));
+
+        // Running off the end of the stack
+        let truncated = &data[..data.len() - 8];
+        assert!(matches!(
+            read_from(BASE, reader(truncated)),
+            Err(StartupError::Process(ProcessError::ReadFailed(_)))
+        ));
+    }
+}
@@ -0,0 +1,802 @@
+//! Decoding syscalls from the registers at a syscall stop. On x86-64 a syscall's return value is
+//! left in `rax`, failures are returned as a negated errno in the range `-4095..=-1` which libc
+//! would turn into `-1` and set `errno`. We show them how strace does, `= -1 ENOENT (No such file
+//! or directory)`.
+//!
+//! A blocking syscall interrupted by a signal returns one of the kernel's internal restart errnos
+//! (`ERESTARTSYS` and friends). When the process stops for the signal `rip` is just after the
+//! `syscall` instruction with the restart errno in `rax`. If the signal doesn't run a handler,
+//! which is always the case when we don't pass it on, the kernel moves `rip` back onto the
+//! `syscall` instruction once the process is resumed so the syscall runs again. Stepping from
+//! there steps over the whole restarted syscall, so the step only finishes when it returns.
+//!
+//! With `catch syscall` the process is continued with `PTRACE_SYSCALL` so it stops on entry to
+//! and exit from every syscall. The number is in `orig_rax` at both stops and the arguments are
+//! in `rdi`, `rsi`, `rdx`, `r10`, `r8` and `r9` at the entry. How many arguments each syscall
+//! takes isn't known so all six are shown.
+use libc::user_regs_struct;
+use std::fmt;
+
+/// Largest errno the kernel returns, anything in `-MAX_ERRNO..=-1` is an error.
+pub const MAX_ERRNO: u64 = 4095;
+
+/// On entry to a syscall the kernel sets `rax` to `-ENOSYS` before the syscall runs, so a stop
+/// with this in `rax` is an entry stop rather than an exit.
+pub const ENOSYS: i32 = 38;
+
+/// Syscall the kernel uses to resume a syscall like `nanosleep` which was interrupted with
+/// `ERESTART_RESTARTBLOCK`.
+pub const RESTART_SYSCALL: u64 = 219;
+
+/// Errno for a syscall that's restarted with `restart_syscall` rather than run again.
+const ERESTART_RESTARTBLOCK: i32 = 516;
+
+/// Errnos the kernel uses internally to restart an interrupted syscall.
+const RESTART_ERRNOS: &[i32] = &[512, 513, 514, ERESTART_RESTARTBLOCK];
+
+/// Names of the syscalls numbered from 0, with no gaps until the newer syscalls.
+const SYSCALL_NAMES: &[&str] = &[
+    "read",
+    "write",
+    "open",
+    "close",
+    "stat",
+    "fstat",
+    "lstat",
+    "poll",
+    "lseek",
+    "mmap",
+    "mprotect",
+    "munmap",
+    "brk",
+    "rt_sigaction",
+    "rt_sigprocmask",
+    "rt_sigreturn",
+    "ioctl",
+    "pread64",
+    "pwrite64",
+    "readv",
+    "writev",
+    "access",
+    "pipe",
+    "select",
+    "sched_yield",
+    "mremap",
+    "msync",
+    "mincore",
+    "madvise",
+    "shmget",
+    "shmat",
+    "shmctl",
+    "dup",
+    "dup2",
+    "pause",
+    "nanosleep",
+    "getitimer",
+    "alarm",
+    "setitimer",
+    "getpid",
+    "sendfile",
+    "socket",
+    "connect",
+    "accept",
+    "sendto",
+    "recvfrom",
+    "sendmsg",
+    "recvmsg",
+    "shutdown",
+    "bind",
+    "listen",
+    "getsockname",
+    "getpeername",
+    "socketpair",
+    "setsockopt",
+    "getsockopt",
+    "clone",
+    "fork",
+    "vfork",
+    "execve",
+    "exit",
+    "wait4",
+    "kill",
+    "uname",
+    "semget",
+    "semop",
+    "semctl",
+    "shmdt",
+    "msgget",
+    "msgsnd",
+    "msgrcv",
+    "msgctl",
+    "fcntl",
+    "flock",
+    "fsync",
+    "fdatasync",
+    "truncate",
+    "ftruncate",
+    "getdents",
+    "getcwd",
+    "chdir",
+    "fchdir",
+    "rename",
+    "mkdir",
+    "rmdir",
+    "creat",
+    "link",
+    "unlink",
+    "symlink",
+    "readlink",
+    "chmod",
+    "fchmod",
+    "chown",
+    "fchown",
+    "lchown",
+    "umask",
+    "gettimeofday",
+    "getrlimit",
+    "getrusage",
+    "sysinfo",
+    "times",
+    "ptrace",
+    "getuid",
+    "syslog",
+    "getgid",
+    "setuid",
+    "setgid",
+    "geteuid",
+    "getegid",
+    "setpgid",
+    "getppid",
+    "getpgrp",
+    "setsid",
+    "setreuid",
+    "setregid",
+    "getgroups",
+    "setgroups",
+    "setresuid",
+    "getresuid",
+    "setresgid",
+    "getresgid",
+    "getpgid",
+    "setfsuid",
+    "setfsgid",
+    "getsid",
+    "capget",
+    "capset",
+    "rt_sigpending",
+    "rt_sigtimedwait",
+    "rt_sigqueueinfo",
+    "rt_sigsuspend",
+    "sigaltstack",
+    "utime",
+    "mknod",
+    "uselib",
+    "personality",
+    "ustat",
+    "statfs",
+    "fstatfs",
+    "sysfs",
+    "getpriority",
+    "setpriority",
+    "sched_setparam",
+    "sched_getparam",
+    "sched_setscheduler",
+    "sched_getscheduler",
+    "sched_get_priority_max",
+    "sched_get_priority_min",
+    "sched_rr_get_interval",
+    "mlock",
+    "munlock",
+    "mlockall",
+    "munlockall",
+    "vhangup",
+    "modify_ldt",
+    "pivot_root",
+    "_sysctl",
+    "prctl",
+    "arch_prctl",
+    "adjtimex",
+    "setrlimit",
+    "chroot",
+    "sync",
+    "acct",
+    "settimeofday",
+    "mount",
+    "umount2",
+    "swapon",
+    "swapoff",
+    "reboot",
+    "sethostname",
+    "setdomainname",
+    "iopl",
+    "ioperm",
+    "create_module",
+    "init_module",
+    "delete_module",
+    "get_kernel_syms",
+    "query_module",
+    "quotactl",
+    "nfsservctl",
+    "getpmsg",
+    "putpmsg",
+    "afs_syscall",
+    "tuxcall",
+    "security",
+    "gettid",
+    "readahead",
+    "setxattr",
+    "lsetxattr",
+    "fsetxattr",
+    "getxattr",
+    "lgetxattr",
+    "fgetxattr",
+    "listxattr",
+    "llistxattr",
+    "flistxattr",
+    "removexattr",
+    "lremovexattr",
+    "fremovexattr",
+    "tkill",
+    "time",
+    "futex",
+    "sched_setaffinity",
+    "sched_getaffinity",
+    "set_thread_area",
+    "io_setup",
+    "io_destroy",
+    "io_getevents",
+    "io_submit",
+    "io_cancel",
+    "get_thread_area",
+    "lookup_dcookie",
+    "epoll_create",
+    "epoll_ctl_old",
+    "epoll_wait_old",
+    "remap_file_pages",
+    "getdents64",
+    "set_tid_address",
+    "restart_syscall",
+    "semtimedop",
+    "fadvise64",
+    "timer_create",
+    "timer_settime",
+    "timer_gettime",
+    "timer_getoverrun",
+    "timer_delete",
+    "clock_settime",
+    "clock_gettime",
+    "clock_getres",
+    "clock_nanosleep",
+    "exit_group",
+    "epoll_wait",
+    "epoll_ctl",
+    "tgkill",
+    "utimes",
+    "vserver",
+    "mbind",
+    "set_mempolicy",
+    "get_mempolicy",
+    "mq_open",
+    "mq_unlink",
+    "mq_timedsend",
+    "mq_timedreceive",
+    "mq_notify",
+    "mq_getsetattr",
+    "kexec_load",
+    "waitid",
+    "add_key",
+    "request_key",
+    "keyctl",
+    "ioprio_set",
+    "ioprio_get",
+    "inotify_init",
+    "inotify_add_watch",
+    "inotify_rm_watch",
+    "migrate_pages",
+    "openat",
+    "mkdirat",
+    "mknodat",
+    "fchownat",
+    "futimesat",
+    "newfstatat",
+    "unlinkat",
+    "renameat",
+    "linkat",
+    "symlinkat",
+    "readlinkat",
+    "fchmodat",
+    "faccessat",
+    "pselect6",
+    "ppoll",
+    "unshare",
+    "set_robust_list",
+    "get_robust_list",
+    "splice",
+    "tee",
+    "sync_file_range",
+    "vmsplice",
+    "move_pages",
+    "utimensat",
+    "epoll_pwait",
+    "signalfd",
+    "timerfd_create",
+    "eventfd",
+    "fallocate",
+    "timerfd_settime",
+    "timerfd_gettime",
+    "accept4",
+    "signalfd4",
+    "eventfd2",
+    "epoll_create1",
+    "dup3",
+    "pipe2",
+    "inotify_init1",
+    "preadv",
+    "pwritev",
+    "rt_tgsigqueueinfo",
+    "perf_event_open",
+    "recvmmsg",
+    "fanotify_init",
+    "fanotify_mark",
+    "prlimit64",
+    "name_to_handle_at",
+    "open_by_handle_at",
+    "clock_adjtime",
+    "syncfs",
+    "sendmmsg",
+    "setns",
+    "getcpu",
+    "process_vm_readv",
+    "process_vm_writev",
+    "kcmp",
+    "finit_module",
+    "sched_setattr",
+    "sched_getattr",
+    "renameat2",
+    "seccomp",
+    "getrandom",
+    "memfd_create",
+    "kexec_file_load",
+    "bpf",
+    "execveat",
+    "userfaultfd",
+    "membarrier",
+    "mlock2",
+    "copy_file_range",
+    "preadv2",
+    "pwritev2",
+    "pkey_mprotect",
+    "pkey_alloc",
+    "pkey_free",
+    "statx",
+    "io_pgetevents",
+    "rseq",
+];
+
+/// Number of the first syscall added after the gap left for 32 bit compat syscalls.
+const NEWER_SYSCALLS_START: u64 = 424;
+
+/// Names of the syscalls numbered from `NEWER_SYSCALLS_START`.
+const NEWER_SYSCALL_NAMES: &[&str] = &[
+    "pidfd_send_signal",
+    "io_uring_setup",
+    "io_uring_enter",
+    "io_uring_register",
+    "open_tree",
+    "move_mount",
+    "fsopen",
+    "fsconfig",
+    "fsmount",
+    "fspick",
+    "pidfd_open",
+    "clone3",
+    "close_range",
+    "openat2",
+    "pidfd_getfd",
+    "faccessat2",
+    "process_madvise",
+    "epoll_pwait2",
+    "mount_setattr",
+    "quotactl_fd",
+    "landlock_create_ruleset",
+    "landlock_add_rule",
+    "landlock_restrict_self",
+    "memfd_secret",
+    "process_mrelease",
+    "futex_waitv",
+    "set_mempolicy_home_node",
+];
+
+/// Errno numbers for Linux on x86-64 with their names and descriptions.
+const ERRNOS: &[(i32, &str, &str)] = &[
+    (1, "EPERM", "Operation not permitted"),
+    (2, "ENOENT", "No such file or directory"),
+    (3, "ESRCH", "No such process"),
+    (4, "EINTR", "Interrupted system call"),
+    (5, "EIO", "Input/output error"),
+    (6, "ENXIO", "No such device or address"),
+    (7, "E2BIG", "Argument list too long"),
+    (8, "ENOEXEC", "Exec format error"),
+    (9, "EBADF", "Bad file descriptor"),
+    (10, "ECHILD", "No child processes"),
+    (11, "EAGAIN", "Resource temporarily unavailable"),
+    (12, "ENOMEM", "Cannot allocate memory"),
+    (13, "EACCES", "Permission denied"),
+    (14, "EFAULT", "Bad address"),
+    (15, "ENOTBLK", "Block device required"),
+    (16, "EBUSY", "Device or resource busy"),
+    (17, "EEXIST", "File exists"),
+    (18, "EXDEV", "Invalid cross-device link"),
+    (19, "ENODEV", "No such device"),
+    (20, "ENOTDIR", "Not a directory"),
+    (21, "EISDIR", "Is a directory"),
+    (22, "EINVAL", "Invalid argument"),
+    (23, "ENFILE", "Too many open files in system"),
+    (24, "EMFILE", "Too many open files"),
+    (25, "ENOTTY", "Inappropriate ioctl for device"),
+    (26, "ETXTBSY", "Text file busy"),
+    (27, "EFBIG", "File too large"),
+    (28, "ENOSPC", "No space left on device"),
+    (29, "ESPIPE", "Illegal seek"),
+    (30, "EROFS", "Read-only file system"),
+    (31, "EMLINK", "Too many links"),
+    (32, "EPIPE", "Broken pipe"),
+    (33, "EDOM", "Numerical argument out of domain"),
+    (34, "ERANGE", "Numerical result out of range"),
+    (35, "EDEADLK", "Resource deadlock avoided"),
+    (36, "ENAMETOOLONG", "File name too long"),
+    (37, "ENOLCK", "No locks available"),
+    (38, "ENOSYS", "Function not implemented"),
+    (39, "ENOTEMPTY", "Directory not empty"),
+    (40, "ELOOP", "Too many levels of symbolic links"),
+    (42, "ENOMSG", "No message of desired type"),
+    (43, "EIDRM", "Identifier removed"),
+    (44, "ECHRNG", "Channel number out of range"),
+    (45, "EL2NSYNC", "Level 2 not synchronized"),
+    (46, "EL3HLT", "Level 3 halted"),
+    (47, "EL3RST", "Level 3 reset"),
+    (48, "ELNRNG", "Link number out of range"),
+    (49, "EUNATCH", "Protocol driver not attached"),
+    (50, "ENOCSI", "No CSI structure available"),
+    (51, "EL2HLT", "Level 2 halted"),
+    (52, "EBADE", "Invalid exchange"),
+    (53, "EBADR", "Invalid request descriptor"),
+    (54, "EXFULL", "Exchange full"),
+    (55, "ENOANO", "No anode"),
+    (56, "EBADRQC", "Invalid request code"),
+    (57, "EBADSLT", "Invalid slot"),
+    (59, "EBFONT", "Bad font file format"),
+    (60, "ENOSTR", "Device not a stream"),
+    (61, "ENODATA", "No data available"),
+    (62, "ETIME", "Timer expired"),
+    (63, "ENOSR", "Out of streams resources"),
+    (64, "ENONET", "Machine is not on the network"),
+    (65, "ENOPKG", "Package not installed"),
+    (66, "EREMOTE", "Object is remote"),
+    (67, "ENOLINK", "Link has been severed"),
+    (68, "EADV", "Advertise error"),
+    (69, "ESRMNT", "Srmount error"),
+    (70, "ECOMM", "Communication error on send"),
+    (71, "EPROTO", "Protocol error"),
+    (72, "EMULTIHOP", "Multihop attempted"),
+    (73, "EDOTDOT", "RFS specific error"),
+    (74, "EBADMSG", "Bad message"),
+    (75, "EOVERFLOW", "Value too large for defined data type"),
+    (76, "ENOTUNIQ", "Name not unique on network"),
+    (77, "EBADFD", "File descriptor in bad state"),
+    (78, "EREMCHG", "Remote address changed"),
+    (79, "ELIBACC", "Can not access a needed shared library"),
+    (80, "ELIBBAD", "Accessing a corrupted shared library"),
+    (81, "ELIBSCN", ".lib section in a.out corrupted"),
+    (
+        82,
+        "ELIBMAX",
+        "Attempting to link in too many shared libraries",
+    ),
+    (83, "ELIBEXEC", "Cannot exec a shared library directly"),
+    (
+        84,
+        "EILSEQ",
+        "Invalid or incomplete multibyte or wide character",
+    ),
+    (
+        85,
+        "ERESTART",
+        "Interrupted system call should be restarted",
+    ),
+    (86, "ESTRPIPE", "Streams pipe error"),
+    (87, "EUSERS", "Too many users"),
+    (88, "ENOTSOCK", "Socket operation on non-socket"),
+    (89, "EDESTADDRREQ", "Destination address required"),
+    (90, "EMSGSIZE", "Message too long"),
+    (91, "EPROTOTYPE", "Protocol wrong type for socket"),
+    (92, "ENOPROTOOPT", "Protocol not available"),
+    (93, "EPROTONOSUPPORT", "Protocol not supported"),
+    (94, "ESOCKTNOSUPPORT", "Socket type not supported"),
+    (95, "EOPNOTSUPP", "Operation not supported"),
+    (96, "EPFNOSUPPORT", "Protocol family not supported"),
+    (
+        97,
+        "EAFNOSUPPORT",
+        "Address family not supported by protocol",
+    ),
+    (98, "EADDRINUSE", "Address already in use"),
+    (99, "EADDRNOTAVAIL", "Cannot assign requested address"),
+    (100, "ENETDOWN", "Network is down"),
+    (101, "ENETUNREACH", "Network is unreachable"),
+    (102, "ENETRESET", "Network dropped connection on reset"),
+    (103, "ECONNABORTED", "Software caused connection abort"),
+    (104, "ECONNRESET", "Connection reset by peer"),
+    (105, "ENOBUFS", "No buffer space available"),
+    (106, "EISCONN", "Transport endpoint is already connected"),
+    (107, "ENOTCONN", "Transport endpoint is not connected"),
+    (
+        108,
+        "ESHUTDOWN",
+        "Cannot send after transport endpoint shutdown",
+    ),
+    (109, "ETOOMANYREFS", "Too many references: cannot splice"),
+    (110, "ETIMEDOUT", "Connection timed out"),
+    (111, "ECONNREFUSED", "Connection refused"),
+    (112, "EHOSTDOWN", "Host is down"),
+    (113, "EHOSTUNREACH", "No route to host"),
+    (114, "EALREADY", "Operation already in progress"),
+    (115, "EINPROGRESS", "Operation now in progress"),
+    (116, "ESTALE", "Stale file handle"),
+    (117, "EUCLEAN", "Structure needs cleaning"),
+    (118, "ENOTNAM", "Not a XENIX named type file"),
+    (119, "ENAVAIL", "No XENIX semaphores available"),
+    (120, "EISNAM", "Is a named type file"),
+    (121, "EREMOTEIO", "Remote I/O error"),
+    (122, "EDQUOT", "Disk quota exceeded"),
+    (123, "ENOMEDIUM", "No medium found"),
+    (124, "EMEDIUMTYPE", "Wrong medium type"),
+    (125, "ECANCELED", "Operation canceled"),
+    (126, "ENOKEY", "Required key not available"),
+    (127, "EKEYEXPIRED", "Key has expired"),
+    (128, "EKEYREVOKED", "Key has been revoked"),
+    (129, "EKEYREJECTED", "Key was rejected by service"),
+    (130, "EOWNERDEAD", "Owner died"),
+    (131, "ENOTRECOVERABLE", "State not recoverable"),
+    (132, "ERFKILL", "Operation not possible due to RF-kill"),
+    (133, "EHWPOISON", "Memory page has hardware error"),
+    // Kernel internal errors which can leak out to a tracer but never reach userspace
+    (512, "ERESTARTSYS", "To be restarted"),
+    (513, "ERESTARTNOINTR", "To be restarted"),
+    (514, "ERESTARTNOHAND", "To be restarted if no handler"),
+    (515, "ENOIOCTLCMD", "No ioctl command"),
+    (
+        516,
+        "ERESTART_RESTARTBLOCK",
+        "To be restarted with restart_syscall",
+    ),
+];
+
+/// Name of an errno like `ENOENT`.
+pub fn errno_name(errno: i32) -> Option<&'static str> {
+    ERRNOS
+        .iter()
+        .find(|(number, _, _)| *number == errno)
+        .map(|(_, name, _)| *name)
+}
+
+/// Description of an errno like `No such file or directory`.
+pub fn errno_description(errno: i32) -> Option<&'static str> {
+    ERRNOS
+        .iter()
+        .find(|(number, _, _)| *number == errno)
+        .map(|(_, _, description)| *description)
+}
+
+/// Name of a syscall like `write`.
+pub fn syscall_name(number: u64) -> Option<&'static str> {
+    match number.checked_sub(NEWER_SYSCALLS_START) {
+        Some(i) => NEWER_SYSCALL_NAMES.get(i as usize),
+        None => SYSCALL_NAMES.get(number as usize),
+    }
+    .copied()
+}
+
+/// Whether an errno means the syscall will be restarted rather than returning to the program.
+pub fn is_restart(errno: i32) -> bool {
+    RESTART_ERRNOS.contains(&errno)
+}
+
+/// The syscall run when a syscall interrupted with `errno` is restarted. Sleeps are restarted as
+/// `restart_syscall` so they carry on with the time that was left rather than starting again.
+pub fn restarted_as(number: u64, errno: i32) -> u64 {
+    if errno == ERESTART_RESTARTBLOCK {
+        RESTART_SYSCALL
+    } else {
+        number
+    }
+}
+
+/// The value a syscall returned.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SyscallReturn {
+    Value(i64),
+    Error(i32),
+}
+
+impl SyscallReturn {
+    /// Decodes the value left in `rax` when a syscall exits.
+    pub fn from_rax(rax: u64) -> Self {
+        if rax.wrapping_neg() <= MAX_ERRNO && rax != 0 {
+            Self::Error(rax.wrapping_neg() as i32)
+        } else {
+            Self::Value(rax as i64)
+        }
+    }
+}
+
+impl fmt::Display for SyscallReturn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Value(value) => write!(f, "= {}", value),
+            Self::Error(errno) => match (errno_name(*errno), errno_description(*errno)) {
+                (Some(name), Some(description)) => write!(f, "= -1 {} ({})", name, description),
+                _ => write!(f, "= -1 errno {}", errno),
+            },
+        }
+    }
+}
+
+/// Where a process stopped by `PTRACE_SYSCALL` is in a syscall.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SyscallStop {
+    /// About to run the syscall with these arguments
+    Entry { number: u64, args: [u64; 6] },
+    /// The syscall has run and returned
+    Exit { number: u64, ret: SyscallReturn },
+}
+
+impl SyscallStop {
+    /// Decodes a syscall stop from the registers, entries are told apart from exits by the
+    /// `-ENOSYS` the kernel puts in `rax` before running the syscall.
+    pub fn from_registers(regs: &user_regs_struct) -> Self {
+        let number = regs.orig_rax;
+        if regs.rax == -(ENOSYS as i64) as u64 {
+            Self::Entry {
+                number,
+                args: [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9],
+            }
+        } else {
+            Self::Exit {
+                number,
+                ret: SyscallReturn::from_rax(regs.rax),
+            }
+        }
+    }
+
+    pub fn number(&self) -> u64 {
+        match self {
+            Self::Entry { number, .. } | Self::Exit { number, .. } => *number,
+        }
+    }
+
+    pub fn is_entry(&self) -> bool {
+        matches!(self, Self::Entry { .. })
+    }
+}
+
+impl fmt::Display for SyscallStop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match syscall_name(self.number()) {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "syscall_{}", self.number())?,
+        }
+        match self {
+            Self::Entry { args, .. } => {
+                let args = args
+                    .iter()
+                    .map(|arg| format!("0x{:x}", arg))
+                    .collect::<Vec<_>>();
+                write!(f, "({})", args.join(", "))
+            }
+            Self::Exit { ret, .. } => write!(f, " {}", ret),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_returns() {
+        assert_eq!(SyscallReturn::from_rax(0), SyscallReturn::Value(0));
+        assert_eq!(SyscallReturn::from_rax(42), SyscallReturn::Value(42));
+        assert_eq!(
+            SyscallReturn::from_rax(-2i64 as u64),
+            SyscallReturn::Error(2)
+        );
+        assert_eq!(
+            SyscallReturn::from_rax(-4095i64 as u64),
+            SyscallReturn::Error(4095)
+        );
+        // mmap can return addresses that look negative but are outside the errno range
+        assert_eq!(
+            SyscallReturn::from_rax(-4096i64 as u64),
+            SyscallReturn::Value(-4096)
+        );
+
+        assert_eq!(SyscallReturn::Value(3).to_string(), "= 3");
+        assert_eq!(
+            SyscallReturn::from_rax(-2i64 as u64).to_string(),
+            "= -1 ENOENT (No such file or directory)"
+        );
+        assert_eq!(
+            SyscallReturn::from_rax(-512i64 as u64).to_string(),
+            "= -1 ERESTARTSYS (To be restarted)"
+        );
+        assert_eq!(SyscallReturn::Error(4000).to_string(), "= -1 errno 4000");
+
+        assert!(is_restart(512));
+        assert!(is_restart(516));
+        assert!(!is_restart(515));
+        assert!(!is_restart(4));
+        assert_eq!(restarted_as(35, 516), RESTART_SYSCALL);
+        assert_eq!(restarted_as(0, 512), 0);
+    }
+
+    #[test]
+    fn decode_syscall_stops() {
+        let mut regs: user_regs_struct = unsafe { std::mem::zeroed() };
+        regs.orig_rax = 1;
+        regs.rax = -(ENOSYS as i64) as u64;
+        regs.rdi = 1;
+        regs.rsi = 0x4000;
+        regs.rdx = 13;
+        let entry = SyscallStop::from_registers(&regs);
+        assert!(entry.is_entry());
+        assert_eq!(entry.to_string(), "write(0x1, 0x4000, 0xd, 0x0, 0x0, 0x0)");
+
+        regs.rax = 13;
+        let exit = SyscallStop::from_registers(&regs);
+        assert_eq!(
+            exit,
+            SyscallStop::Exit {
+                number: 1,
+                ret: SyscallReturn::Value(13)
+            }
+        );
+        assert_eq!(exit.to_string(), "write = 13");
+
+        regs.orig_rax = 400;
+        assert_eq!(
+            SyscallStop::from_registers(&regs).to_string(),
+            "syscall_400 = 13"
+        );
+    }
+
+    #[test]
+    fn syscall_table_matches_libc() {
+        use nix::libc;
+        for (number, name) in [
+            (libc::SYS_read, "read"),
+            (libc::SYS_exit_group, "exit_group"),
+            (libc::SYS_rseq, "rseq"),
+            (libc::SYS_pidfd_send_signal, "pidfd_send_signal"),
+            (libc::SYS_futex_waitv, "futex_waitv"),
+        ] {
+            assert_eq!(syscall_name(number as u64), Some(name));
+        }
+        assert_eq!(syscall_name(335), None);
+    }
+
+    #[test]
+    fn errno_table_matches_libc() {
+        use nix::libc;
+        for (errno, name) in [
+            (libc::EPERM, "EPERM"),
+            (libc::EAGAIN, "EAGAIN"),
+            (libc::ENOSYS, "ENOSYS"),
+            (libc::ECONNREFUSED, "ECONNREFUSED"),
+            (libc::EHWPOISON, "EHWPOISON"),
+        ] {
+            assert_eq!(errno_name(errno), Some(name));
+        }
+        assert_eq!(ENOSYS, libc::ENOSYS);
+    }
+}
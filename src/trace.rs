@@ -0,0 +1,133 @@
+//! Instruction level tracing. When a trace is running the debugger single steps the process and
+//! writes the program counter of every instruction executed to a file, one hexadecimal address
+//! per line. Each address is followed by the function and source line it's in where they're
+//! known, and optionally the registers before the instruction ran, separated by tabs:
+//!
+//! ```text
+//! 0x401126    increment+0x4    loop.c:6    rax=0x0 rbx=0x7ffc...
+//! ```
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Default number of instructions to trace before giving up. Stepping is slow and the traces get
+/// big so this is mainly to stop a forgotten trace filling up a disk.
+pub const DEFAULT_MAX_INSTRUCTIONS: usize = 1_000_000;
+
+#[derive(Debug)]
+pub struct InstructionTrace {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    recorded: usize,
+    max_instructions: usize,
+    /// Where each address recorded so far is, loops run the same instructions over and over
+    /// and looking them up every time would make tracing even slower
+    locations: HashMap<u64, String>,
+}
+
+impl InstructionTrace {
+    pub fn create(path: &Path, max_instructions: usize) -> io::Result<Self> {
+        let writer = BufWriter::new(File::create(path)?);
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer,
+            recorded: 0,
+            max_instructions,
+            locations: HashMap::new(),
+        })
+    }
+
+    /// Whether the location of an address has already been given to `record`.
+    pub fn has_location(&self, pc: u64) -> bool {
+        self.locations.contains_key(&pc)
+    }
+
+    /// Records an instruction address, returns false if the trace is full and the address wasn't
+    /// written. `location` is only needed the first time an address is recorded, after that the
+    /// one given before is used. Empty columns are left out from the end of the line.
+    pub fn record(
+        &mut self,
+        pc: u64,
+        location: Option<String>,
+        registers: Option<&str>,
+    ) -> io::Result<bool> {
+        if self.recorded >= self.max_instructions {
+            return Ok(false);
+        }
+        if let Some(location) = location {
+            self.locations.insert(pc, location);
+        }
+        let location = self
+            .locations
+            .get(&pc)
+            .map(String::as_str)
+            .unwrap_or_default();
+        let line = format!(
+            "0x{:x}\t{}\t{}",
+            pc,
+            location,
+            registers.unwrap_or_default()
+        );
+        writeln!(self.writer, "{}", line.trim_end_matches('\t'))?;
+        self.recorded += 1;
+        Ok(true)
+    }
+
+    pub fn recorded(&self) -> usize {
+        self.recorded
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flushes the trace to disk.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn stops_recording_at_limit() {
+        let path = std::env::temp_dir().join(format!("rustybug-trace-{}", std::process::id()));
+        let mut trace = InstructionTrace::create(&path, 2).unwrap();
+        assert!(trace.record(0x400510, None, None).unwrap());
+        assert!(trace.record(0x400514, None, None).unwrap());
+        assert!(!trace.record(0x400518, None, None).unwrap());
+        assert_eq!(trace.recorded(), 2);
+        trace.finish().unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, "0x400510\n0x400514\n");
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn locations_are_remembered() {
+        let path = std::env::temp_dir().join(format!("rustybug-trace-loc-{}", std::process::id()));
+        let mut trace = InstructionTrace::create(&path, 10).unwrap();
+        assert!(!trace.has_location(0x1000));
+        trace
+            .record(0x1000, Some("main\tmain.c:3".to_string()), None)
+            .unwrap();
+        assert!(trace.has_location(0x1000));
+        trace.record(0x1000, None, Some("rax=0x1")).unwrap();
+        trace
+            .record(0x1004, Some(String::new()), Some("rax=0x2"))
+            .unwrap();
+        trace.finish().unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            contents,
+            "0x1000\tmain\tmain.c:3\n0x1000\tmain\tmain.c:3\trax=0x1\n0x1004\t\trax=0x2\n"
+        );
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,345 @@
+//! Printing variables using the types described in the debug info. Values print compactly on one
+//! line by default, `{x = 1, pos = {x = 2, y = 3}}`, or with `set print pretty on` every struct
+//! and array of structs gets a line per field:
+//!
+//! ```text
+//! {
+//!   x = 1,
+//!   pos = {
+//!     x = 2,
+//!     y = 3
+//!   }
+//! }
+//! ```
+use crate::elf::ValueKind;
+use std::fmt::Write;
+
+/// Arrays longer than this are cut short when printed.
+pub const MAX_ARRAY_ELEMENTS: usize = 200;
+/// Spaces added for each level of nesting when pretty printing.
+const INDENT: usize = 2;
+
+/// The parts of a type from the debug info needed to print a value of it. Typedefs and
+/// qualifiers like `const` are looked through.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Type {
+    Base {
+        name: String,
+        size: u64,
+        kind: ValueKind,
+    },
+    Pointer {
+        size: u64,
+    },
+    Enum {
+        size: u64,
+        variants: Vec<(i64, String)>,
+    },
+    /// Structs, classes and unions, for a union every member has offset 0
+    Struct {
+        size: u64,
+        members: Vec<Member>,
+    },
+    Array {
+        element: Box<Type>,
+        count: u64,
+    },
+    /// A type we can't print, like a bitfield or function
+    Unknown {
+        name: String,
+        size: u64,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    /// Offset in bytes from the start of the containing struct
+    pub offset: u64,
+    pub ty: Type,
+}
+
+impl Type {
+    pub fn size(&self) -> u64 {
+        match self {
+            Self::Base { size, .. }
+            | Self::Pointer { size }
+            | Self::Enum { size, .. }
+            | Self::Struct { size, .. }
+            | Self::Unknown { size, .. } => *size,
+            Self::Array { element, count } => element.size() * count,
+        }
+    }
+
+    /// How many bytes of a value are needed to print it, which is less than its size when an
+    /// array in it is longer than `MAX_ARRAY_ELEMENTS`.
+    pub fn printed_size(&self) -> u64 {
+        match self {
+            Self::Array { element, count } if !element.is_char() => {
+                match (*count).min(MAX_ARRAY_ELEMENTS as u64) {
+                    0 => 0,
+                    shown => (shown - 1) * element.size() + element.printed_size(),
+                }
+            }
+            Self::Struct { members, .. } => members
+                .iter()
+                .map(|member| member.offset + member.ty.printed_size())
+                .max()
+                .unwrap_or_default(),
+            _ => self.size(),
+        }
+    }
+
+    fn is_char(&self) -> bool {
+        matches!(self, Self::Base { name, size: 1, .. } if name.contains("char"))
+    }
+
+    /// Whether pretty printing puts a value of this type across multiple lines.
+    fn is_multiline(&self) -> bool {
+        match self {
+            Self::Struct { .. } => true,
+            Self::Array { element, .. } => !element.is_char() && element.is_multiline(),
+            _ => false,
+        }
+    }
+}
+
+/// Formats the bytes of a value of the given type.
+pub fn format_value(ty: &Type, data: &[u8], pretty: bool) -> String {
+    let mut out = String::new();
+    write_value(&mut out, ty, data, pretty, 0);
+    out
+}
+
+fn write_value(out: &mut String, ty: &Type, data: &[u8], pretty: bool, indent: usize) {
+    let size = ty.printed_size() as usize;
+    if data.len() < size {
+        out.push_str("<unavailable>");
+        return;
+    }
+    let data = &data[..size];
+    match ty {
+        Type::Base { name, kind, .. } => write_scalar(out, name, *kind, data),
+        Type::Pointer { .. } => {
+            let _ = write!(out, "0x{:x}", read_unsigned(data));
+        }
+        Type::Enum { variants, .. } => {
+            let value = read_signed(data);
+            match variants.iter().find(|(v, _)| *v == value) {
+                Some((_, name)) => out.push_str(name),
+                None => {
+                    let _ = write!(out, "{}", value);
+                }
+            }
+        }
+        Type::Struct { members, .. } => {
+            let fields = members.iter().map(|member| {
+                let start = member.offset as usize;
+                let field = data.get(start..).unwrap_or_default();
+                (Some(member.name.as_str()), &member.ty, field)
+            });
+            write_aggregate(out, fields, pretty && ty.is_multiline(), pretty, indent);
+        }
+        Type::Array { element, count } if element.is_char() => {
+            // Print char arrays as strings, stopping at the terminator if there is one
+            let len = data.iter().position(|b| *b == 0).unwrap_or(*count as usize);
+            let _ = write!(out, "{:?}", String::from_utf8_lossy(&data[..len]));
+        }
+        Type::Array { element, count } => {
+            let element_size = element.size() as usize;
+            let shown = (*count as usize).min(MAX_ARRAY_ELEMENTS);
+            let elements = (0..shown).map(|i| {
+                let start = i * element_size;
+                (None, element.as_ref(), &data[start..])
+            });
+            write_aggregate(out, elements, pretty && ty.is_multiline(), pretty, indent);
+            if shown < *count as usize {
+                out.push_str("...");
+            }
+        }
+        Type::Unknown { name, .. } => {
+            let _ = write!(out, "<{}>", name);
+        }
+    }
+}
+
+/// Writes the fields of a struct or the elements of an array surrounded by braces, one per line
+/// if `multiline` is set.
+fn write_aggregate<'a>(
+    out: &mut String,
+    fields: impl Iterator<Item = (Option<&'a str>, &'a Type, &'a [u8])>,
+    multiline: bool,
+    pretty: bool,
+    indent: usize,
+) {
+    out.push('{');
+    for (i, (name, ty, data)) in fields.enumerate() {
+        if i > 0 {
+            out.push(',');
+            if !multiline {
+                out.push(' ');
+            }
+        }
+        if multiline {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent + INDENT));
+        }
+        if let Some(name) = name {
+            let _ = write!(out, "{} = ", name);
+        }
+        write_value(out, ty, data, pretty, indent + INDENT);
+    }
+    if multiline {
+        out.push('\n');
+        out.push_str(&" ".repeat(indent));
+    }
+    out.push('}');
+}
+
+fn write_scalar(out: &mut String, name: &str, kind: ValueKind, data: &[u8]) {
+    let _ = match kind {
+        ValueKind::Unsigned if name == "_Bool" || name == "bool" => {
+            write!(out, "{}", read_unsigned(data) != 0)
+        }
+        ValueKind::Signed => write!(out, "{}", read_signed(data)),
+        ValueKind::Unsigned => write!(out, "{}", read_unsigned(data)),
+        ValueKind::Float if data.len() == 4 => {
+            write!(out, "{}", f32::from_le_bytes(data.try_into().unwrap()))
+        }
+        ValueKind::Float if data.len() == 8 => {
+            write!(out, "{}", f64::from_le_bytes(data.try_into().unwrap()))
+        }
+        _ => {
+            out.push_str("0x");
+            for byte in data.iter().rev() {
+                let _ = write!(out, "{:02x}", byte);
+            }
+            Ok(())
+        }
+    };
+}
+
+fn read_unsigned(data: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    let len = data.len().min(8);
+    bytes[..len].copy_from_slice(&data[..len]);
+    u64::from_le_bytes(bytes)
+}
+
+fn read_signed(data: &[u8]) -> i64 {
+    let shift = 64 - 8 * data.len().clamp(1, 8) as u32;
+    ((read_unsigned(data) << shift) as i64) >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int() -> Type {
+        Type::Base {
+            name: "int".to_string(),
+            size: 4,
+            kind: ValueKind::Signed,
+        }
+    }
+
+    fn point() -> Type {
+        Type::Struct {
+            size: 8,
+            members: vec![
+                Member {
+                    name: "x".to_string(),
+                    offset: 0,
+                    ty: int(),
+                },
+                Member {
+                    name: "y".to_string(),
+                    offset: 4,
+                    ty: int(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn compact_and_pretty() {
+        let line = Type::Struct {
+            size: 24,
+            members: vec![
+                Member {
+                    name: "start".to_string(),
+                    offset: 0,
+                    ty: point(),
+                },
+                Member {
+                    name: "weights".to_string(),
+                    offset: 8,
+                    ty: Type::Array {
+                        element: Box::new(int()),
+                        count: 2,
+                    },
+                },
+                Member {
+                    name: "tag".to_string(),
+                    offset: 16,
+                    ty: Type::Array {
+                        element: Box::new(Type::Base {
+                            name: "char".to_string(),
+                            size: 1,
+                            kind: ValueKind::Signed,
+                        }),
+                        count: 8,
+                    },
+                },
+            ],
+        };
+        let mut data = vec![];
+        for i in [1i32, -2, 3, 4] {
+            data.extend_from_slice(&i.to_le_bytes());
+        }
+        data.extend_from_slice(b"ab\0\0\0\0\0\0");
+
+        assert_eq!(
+            format_value(&line, &data, false),
+            r#"{start = {x = 1, y = -2}, weights = {3, 4}, tag = "ab"}"#
+        );
+        assert_eq!(
+            format_value(&line, &data, true),
+            "{\n  start = {\n    x = 1,\n    y = -2\n  },\n  weights = {3, 4},\n  tag = \"ab\"\n}"
+        );
+    }
+
+    #[test]
+    fn arrays_of_structs() {
+        let points = Type::Array {
+            element: Box::new(point()),
+            count: 2,
+        };
+        let data = [1i32, 2, 3, 4]
+            .iter()
+            .flat_map(|i| i.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            format_value(&points, &data, false),
+            "{{x = 1, y = 2}, {x = 3, y = 4}}"
+        );
+        assert_eq!(
+            format_value(&points, &data, true),
+            "{\n  {\n    x = 1,\n    y = 2\n  },\n  {\n    x = 3,\n    y = 4\n  }\n}"
+        );
+        assert_eq!(format_value(&points, &data[..4], false), "<unavailable>");
+    }
+
+    #[test]
+    fn long_arrays_only_need_the_shown_elements() {
+        let ints = Type::Array {
+            element: Box::new(int()),
+            count: 1 << 20,
+        };
+        assert_eq!(ints.printed_size(), 4 * MAX_ARRAY_ELEMENTS as u64);
+        let data = vec![0; ints.printed_size() as usize];
+        let printed = format_value(&ints, &data, false);
+        assert!(printed.starts_with("{0, 0, "));
+        assert!(printed.ends_with("0}..."));
+    }
+}
@@ -0,0 +1,105 @@
+//! The vDSO is a small shared library the kernel maps into every process so calls like
+//! `clock_gettime` don't need to enter the kernel. It isn't a file on disk so there's no debug
+//! info, but it's a complete ELF image in the process's memory with a dynamic symbol table, which
+//! is enough to name the functions in it.
+use crate::process::{proc_entry, Process, ProcessError};
+use object::{Object, ObjectSegment, ObjectSymbol, SymbolKind};
+use procfs::process::MMapPath;
+use std::ops::Range;
+use thiserror::Error;
+use tracing::error;
+
+#[derive(Debug, Error)]
+pub enum VdsoError {
+    #[error(transparent)]
+    Process(#[from] ProcessError),
+    #[error("vDSO isn't a valid ELF image: {0}")]
+    Parse(#[from] object::Error),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+struct Symbol {
+    /// Address in the running process
+    start: u64,
+    name: String,
+    size: u64,
+}
+
+/// Function symbols of the vDSO mapped into a process.
+#[derive(Clone, Debug)]
+pub struct Vdso {
+    range: Range<u64>,
+    /// Sorted by address, where two names alias the same function only the first is kept
+    symbols: Vec<Symbol>,
+}
+
+impl Vdso {
+    /// Finds the vDSO in the process's mappings and reads its symbols. Returns `None` if the
+    /// process doesn't have one, e.g. when booted with `vdso=0`.
+    pub fn read(process: &Process) -> Result<Option<Self>, VdsoError> {
+        let maps = proc_entry(process.pid())
+            .and_then(|proc| proc.maps())
+            .map_err(|e| {
+                error!("Couldn't read /proc for {}: {}", process.pid(), e);
+                ProcessError::ProcReadFailed
+            })?;
+        let Some(map) = maps.iter().find(|map| map.pathname == MMapPath::Vdso) else {
+            return Ok(None);
+        };
+        let range = map.address.0..map.address.1;
+        let data = process.read_memory(range.start, (range.end - range.start) as usize)?;
+        Ok(Some(Self::parse(range, &data)?))
+    }
+
+    fn parse(range: Range<u64>, data: &[u8]) -> Result<Self, object::Error> {
+        let file = object::File::parse(data)?;
+        // Symbol addresses are relative to where the vDSO was linked, which is usually 0
+        let linked_at = file
+            .segments()
+            .map(|segment| segment.address())
+            .min()
+            .unwrap_or_default();
+        let bias = range.start.wrapping_sub(linked_at);
+        let mut symbols = file
+            .dynamic_symbols()
+            .filter(|sym| sym.kind() == SymbolKind::Text && sym.is_definition())
+            .filter_map(|sym| {
+                Some(Symbol {
+                    start: sym.address().wrapping_add(bias),
+                    name: sym.name().ok()?.to_string(),
+                    size: sym.size(),
+                })
+            })
+            .collect::<Vec<_>>();
+        // Functions are exported as `clock_gettime` and `__vdso_clock_gettime`, sorting by name
+        // puts the `__vdso_` one first which says where the function really is
+        symbols.sort();
+        symbols.dedup_by_key(|sym| sym.start);
+        Ok(Self { range, symbols })
+    }
+
+    /// Addresses the vDSO is mapped at.
+    pub fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    /// Address of the function with the given name, with or without the `__vdso_` prefix.
+    pub fn address_of(&self, name: &str) -> Option<u64> {
+        let prefixed = format!("__vdso_{}", name);
+        self.symbols
+            .iter()
+            .find(|sym| sym.name == name || sym.name == prefixed)
+            .map(|sym| sym.start)
+    }
+
+    /// Finds the function containing `addr`, returning its name and how far into it `addr` is.
+    pub fn function_containing(&self, addr: u64) -> Option<(&str, u64)> {
+        if !self.range.contains(&addr) {
+            return None;
+        }
+        let index = self.symbols.partition_point(|sym| sym.start <= addr);
+        let sym = self.symbols[..index].last()?;
+        let offset = addr - sym.start;
+        (offset < sym.size.max(1)).then_some((sym.name.as_str(), offset))
+    }
+}
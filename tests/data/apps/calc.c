@@ -0,0 +1,18 @@
+int calls = 0;
+
+int add(int a, int b) {
+    calls++;
+    return a + b;
+}
+
+long scale(long x, long factor) {
+    return x * factor;
+}
+
+int deref(int *p) {
+    return *p;
+}
+
+int main() {
+    return add(2, 3) == 5 ? 0 : 1;
+}
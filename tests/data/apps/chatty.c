@@ -0,0 +1,13 @@
+#include <stdio.h>
+#include <unistd.h>
+
+int main() {
+    printf("first chunk from chatty\n");
+    fflush(stdout);
+    // Give the debugger a chance to read the first chunk on its own
+    usleep(100000);
+    printf("second chunk: h\xc3\xa9llo\n");
+    fflush(stdout);
+    fprintf(stderr, "chatty on stderr\n");
+    return 0;
+}
@@ -0,0 +1,10 @@
+#include "stdio.h"
+
+int main() {
+    char line[256];
+    while(fgets(line, sizeof(line), stdin) != NULL) {
+        printf("got: %s", line);
+        fflush(stdout);
+    }
+    return 0;
+}
@@ -0,0 +1,23 @@
+#include <sys/wait.h>
+#include <unistd.h>
+
+int child_work(int x) {
+    return x + 1;
+}
+
+int main() {
+    pid_t child = fork();
+    if (child < 0) {
+        return 2;
+    }
+    if (child == 0) {
+        // Only the child calls this so a breakpoint on it only stops the child
+        _exit(child_work(2));
+    }
+    int status = 0;
+    if (waitpid(child, &status, 0) != child) {
+        return 2;
+    }
+    // The child exiting with 3 means it ran to the end without tripping on a breakpoint
+    return WIFEXITED(status) && WEXITSTATUS(status) == 3 ? 0 : 1;
+}
@@ -0,0 +1,16 @@
+#include "stdio.h"
+
+const char *greeting = "hello";
+int greeted = 0;
+float volume = 1.0f;
+
+void greet(const char *name, int times) {
+    greeted += times;
+    volume *= 2;
+}
+
+int main() {
+    greet(greeting, 3);
+    greet("world", 2);
+    return greeted == 5 ? 0 : 1;
+}
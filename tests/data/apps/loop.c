@@ -0,0 +1,14 @@
+#include "stdio.h"
+
+int counter = 0;
+
+void increment(void) {
+    counter++;
+}
+
+int main() {
+    for(int i = 0; i < 5; ++i) {
+        increment();
+    }
+    return counter == 5 ? 0 : 1;
+}
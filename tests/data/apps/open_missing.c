@@ -0,0 +1,8 @@
+#include <errno.h>
+#include <fcntl.h>
+
+int main() {
+    // Fails with ENOENT so the syscall exit shows an error
+    int fd = open("/nonexistent/rustybug", O_RDONLY);
+    return fd == -1 && errno == ENOENT ? 0 : 1;
+}
@@ -0,0 +1,11 @@
+#include "stdio.h"
+#include "unistd.h"
+
+int main() {
+    char cwd[4096];
+    if(getcwd(cwd, sizeof(cwd)) == NULL) {
+        return 1;
+    }
+    printf("%s\n", cwd);
+    return 0;
+}
@@ -0,0 +1,25 @@
+#include <stdio.h>
+
+enum colour { RED, GREEN, BLUE };
+
+struct point {
+    int x;
+    int y;
+};
+
+struct shape {
+    const char *name;
+    struct point corners[2];
+    enum colour colour;
+    double scale;
+    char tag[8];
+};
+
+struct shape square = { "square", { { 1, 2 }, { 3, -4 } }, BLUE, 0.5, "sq" };
+int grid[2][3] = { { 1, 2, 3 }, { 4, 5, 6 } };
+
+int main() {
+    square.scale *= 2;
+    printf("%s %f %d\n", square.name, square.scale, grid[1][2]);
+    return 0;
+}
@@ -0,0 +1,7 @@
+#include <time.h>
+
+int main() {
+    struct timespec duration = {0, 500000000};
+    nanosleep(&duration, NULL);
+    return 0;
+}
@@ -0,0 +1,12 @@
+#include <unistd.h>
+
+int main() {
+    // The parent can't be stopped until the child execs or exits, so attaching to it doesn't
+    // see a stop until then
+    pid_t child = vfork();
+    if (child == 0) {
+        sleep(18);
+        _exit(0);
+    }
+    return child < 0;
+}
@@ -0,0 +1,21 @@
+#include <stdio.h>
+
+int divisions = 0;
+
+__attribute__((cold, noinline)) void report(int a) {
+    printf("divide %d by zero\n", a);
+}
+
+__attribute__((noinline)) int checked_div(int a, int b) {
+    if (b == 0) {
+        report(a);
+        return 0;
+    }
+    divisions++;
+    return a / b;
+}
+
+int main(int argc, char **argv) {
+    (void)argv;
+    return checked_div(10, argc) == 10 ? 0 : 1;
+}
@@ -0,0 +1,11 @@
+#include <signal.h>
+
+int traps = 0;
+
+int main() {
+    __asm__ volatile("int3");
+    traps++;
+    raise(SIGTRAP);
+    traps++;
+    return traps;
+}
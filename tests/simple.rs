@@ -1,22 +1,65 @@
 //! In these tests we'll just run a program setting no breakpoints.
+use nix::sys::ptrace;
 use nix::sys::signal::Signal;
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::unistd::Pid;
 use rusty_fork::rusty_fork_test;
+use rustybug::address::{parse_address, parse_length, Address, AddressError};
+use rustybug::asm::Instruction;
+use rustybug::backtrace::Truncated;
+use rustybug::call::CallError;
 use rustybug::commands::Location;
+use rustybug::condition::Condition;
+use rustybug::disasm::Flow;
+use rustybug::dprintf::Dprintf;
+use rustybug::elf::{ExecutableFile, LinkKind};
+use rustybug::settings::Settings;
+use rustybug::snapshot::ByteChange;
+use rustybug::syscalls::{SyscallReturn, SyscallStop, RESTART_SYSCALL};
 use rustybug::{
-    process::{Event, Info, Process, ProcessError, TrapType},
-    Args, DebuggerStateMachine, State,
+    offset_from_entry,
+    process::{
+        DebugRegisters, Event, FollowFork, Info, Process, ProcessError, RegisterGroup, Registers,
+        TrapType,
+    },
+    Args, DebuggerStateMachine, State, STOP_HISTORY_LEN,
 };
 use std::path::Path;
-use std::process::Command;
-use std::time::Duration;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 use tracing_test::traced_test;
 
-const TESTS: &[&'static str] = &[
+const TESTS: &[&str] = &[
     "tests/data/apps/build/test_project",
     "tests/data/apps/build/threads",
-    //"tests/data/apps/build/user_signal"
+    "tests/data/apps/build/user_signal",
 ];
 
+/// Starts the threads app stopped at the breakpoint on `thread_function` in the first thread
+/// to get there.
+fn stop_in_first_thread() -> DebuggerStateMachine {
+    let args = Args {
+        input: Some("tests/data/apps/build/threads".into()),
+        pid: None,
+        ..Default::default()
+    };
+    let settings = Settings {
+        stop_on_events: true,
+        ..Default::default()
+    };
+    let mut sm = DebuggerStateMachine::start_with_settings(args, settings).unwrap();
+    sm.set_break(&Location::Function("thread_function".to_string()))
+        .unwrap();
+    loop {
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        if reason.trap_reason == Some(TrapType::SoftwareBreak) {
+            return sm;
+        }
+    }
+}
+
 rusty_fork_test! {
     #[test]
     #[traced_test]
@@ -26,6 +69,7 @@ rusty_fork_test! {
             let args = Args {
                 input: Some(test.into()),
                 pid: None,
+                ..Default::default()
             };
             let mut sm = DebuggerStateMachine::start(args).unwrap();
 
@@ -33,8 +77,13 @@ rusty_fork_test! {
 
             sm.cont().unwrap();
 
-            while !matches!(sm.wait().unwrap().map(|x| x.reason), Some(State::Exited)) {
-
+            loop {
+                match sm.wait().unwrap().map(|x| x.reason) {
+                    Some(State::Exited) => break,
+                    // Stopped by a signal, which is passed on as we continue
+                    Some(State::Stopped) => sm.cont().unwrap(),
+                    _ => {}
+                }
             }
 
             assert!(sm.wait().is_err());
@@ -54,6 +103,7 @@ rusty_fork_test! {
         let args = Args {
             input: None,
             pid: Some(pid),
+            ..Default::default()
         };
 
         let mut sm = DebuggerStateMachine::start(args).unwrap();
@@ -82,15 +132,24 @@ rusty_fork_test! {
             let expected_rax = regs.regs.rax.overflowing_add(42).0;
             regs.regs.rax = expected_rax;
 
-            let expected_st0 = regs.fpregs.st_space[0].overflowing_add(56).0;
-            regs.fpregs.st_space[0] = expected_st0;
+            let fpregs = regs.fpregs.as_mut().unwrap();
+            let expected_st0 = fpregs.st_space[0].overflowing_add(56).0;
+            fpregs.st_space[0] = expected_st0;
 
             proc.write_all_registers(regs).unwrap();
 
             let actual_regs = proc.get_all_registers().unwrap();
 
             assert_eq!(actual_regs.regs.rax, expected_rax);
-            assert_eq!(actual_regs.fpregs.st_space[0], expected_st0);
+            assert_eq!(actual_regs.fpregs.unwrap().st_space[0], expected_st0);
+
+            // Writing back only the general purpose registers leaves the fp ones alone
+            let gp_regs = proc.get_gp_registers().unwrap();
+            assert!(gp_regs.fpregs.is_none());
+            assert_eq!(gp_regs.regs.rax, expected_rax);
+            proc.write_all_registers(gp_regs).unwrap();
+            let actual_regs = proc.get_all_registers().unwrap();
+            assert_eq!(actual_regs.fpregs.unwrap().st_space[0], expected_st0);
 
     }
 
@@ -134,6 +193,9 @@ rusty_fork_test! {
         let reason =  proc.blocking_wait_on_signal(Duration::from_secs(2)).unwrap();
 
         assert_eq!(reason.event, Some(Event::Exit));
+        assert_eq!(reason.exit_status, Some(Info::Return(0)));
+        // Still around to be inspected
+        assert!(proc.get_gp_registers().is_ok());
 
         proc.resume().unwrap();
 
@@ -164,6 +226,7 @@ rusty_fork_test! {
         let args = Args {
             input: Some("tests/data/apps/build/test_project".into()),
             pid: None,
+            ..Default::default()
         };
         let mut sm = DebuggerStateMachine::start(args).unwrap();
 
@@ -171,7 +234,7 @@ rusty_fork_test! {
 
         sm.set_break(&Location::Function("main".to_string())).unwrap();
 
-        sm.cont();
+        sm.cont().unwrap();
 
         let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
 
@@ -180,6 +243,2685 @@ rusty_fork_test! {
         assert_eq!(reason.reason, State::Stopped);
         assert_eq!(reason.event, None);
 
+        let pc = sm.root_process().pc().unwrap();
+        assert_eq!(sm.function_location(pc).as_deref(), Some("main"));
+        assert_eq!(sm.function_location(pc + 1).as_deref(), Some("main+0x1"));
+
+
+    }
+
+    #[test]
+    #[traced_test]
+    fn debug_output_is_a_summary() {
+        let args = Args {
+            input: Some("tests/data/apps/build/test_project".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.set_break(&Location::Function("main".to_string())).unwrap();
+
+        let pid = sm.root_process().pid().as_raw();
+        let expected = format!(
+            "DebuggerStateMachine {{ pid: {}, state: Stopped, breakpoints: 1, elf: Some }}",
+            pid
+        );
+        assert_eq!(format!("{:?}", sm), expected);
+    }
+
+    #[test]
+    #[traced_test]
+    fn breakpoint_lookup_by_address() {
+        let mut proc = Process::launch(Path::new("tests/data/apps/build/test_project")).unwrap();
+        let pc = proc.pc().unwrap();
+        let id = proc.set_breakpoint(pc).unwrap();
+
+        assert_eq!(proc.breakpoint_at(pc).map(|bp| bp.id), Some(id));
+        assert!(proc.breakpoint_at(pc + 1).is_none());
+        assert!(proc.breakpoint_at_mut(pc).is_some());
+    }
+
+    #[test]
+    #[traced_test]
+    fn trace_to_exit() {
+        let args = Args {
+            input: Some("tests/data/apps/build/test_project".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let start_pc = sm.root_process().pc().unwrap();
+
+        let path = std::env::temp_dir().join(format!("rustybug-full-trace-{}", std::process::id()));
+        sm.start_trace(&path).unwrap();
+        assert!(sm.is_tracing());
+
+        while !matches!(sm.wait().unwrap().map(|x| x.reason), Some(State::Exited)) {}
+        assert!(!sm.is_tracing());
+
+        let trace = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let lines = trace.lines().collect::<Vec<_>>();
+        assert!(lines.len() > 1000);
+        // Starts in the dynamic loader which has no debug info, so only the address
+        assert_eq!(lines[0], format!("0x{:x}", start_pc));
+        assert!(lines.iter().all(|x| x.starts_with("0x")));
+    }
+
+    #[test]
+    #[traced_test]
+    fn trace_stops_at_limit() {
+        let args = Args {
+            input: Some("tests/data/apps/build/test_project".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.settings_mut().trace_limit = 100;
+
+        let path = std::env::temp_dir().join(format!("rustybug-short-trace-{}", std::process::id()));
+        sm.start_trace(&path).unwrap();
+        while sm.is_tracing() {
+            assert!(sm.wait().unwrap().is_none());
+        }
+        assert_eq!(sm.root_process().state(), State::Stopped);
+
+        let trace = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(trace.lines().count(), 100);
+    }
+
+    #[test]
+    #[traced_test]
+    fn remove_breakpoint_restores_code() {
+        let mut proc = Process::launch(Path::new("tests/data/apps/build/test_project")).unwrap();
+        let pc = proc.pc().unwrap();
+        let id = proc.set_breakpoint(pc).unwrap();
+        assert_eq!(proc.set_breakpoint(pc).unwrap(), id);
+        assert_eq!(proc.breakpoints().len(), 1);
+
+        proc.remove_breakpoint(id).unwrap();
+        assert!(proc.breakpoints().is_empty());
+        assert_eq!(proc.remove_breakpoint(id), Err(ProcessError::NoBreakpoint(id)));
+
+        proc.resume().unwrap();
+        let reason = proc.blocking_wait_on_signal(Duration::from_secs(1)).unwrap();
+        assert_eq!(reason.reason, State::Exited);
+        assert_eq!(reason.info, Info::Return(0));
+    }
+
+    #[test]
+    #[traced_test]
+    fn continue_through_breakpoint_in_loop() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.set_break(&Location::Function("increment".to_string())).unwrap();
+
+        let mut hits = 0;
+        loop {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if reason.reason == State::Exited {
+                // The loop checks it called increment the right number of times
+                assert_eq!(reason.info, Info::Return(0));
+                break;
+            }
+            assert_eq!(reason.trap_reason, Some(TrapType::SoftwareBreak));
+            hits += 1;
+            assert!(hits <= 5, "Breakpoint hit too many times");
+        }
+        assert_eq!(hits, 5);
+        assert_eq!(sm.root_process().breakpoints()[0].hit_count(), 5);
+    }
+
+    #[test]
+    #[traced_test]
+    fn disabled_breakpoint_isnt_hit() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let id = sm.set_break(&Location::Function("increment".to_string())).unwrap();
+
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.trap_reason, Some(TrapType::SoftwareBreak));
+
+        assert!(!sm.toggle_breakpoint(id).unwrap());
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.reason, State::Exited);
+        assert_eq!(reason.info, Info::Return(0));
+        assert_eq!(sm.root_process().breakpoints()[0].hit_count(), 1);
+    }
+
+    #[test]
+    #[traced_test]
+    fn list_breakpoints_summarises_them() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let entry = sm.root_process().pc().unwrap();
+        let entry_id = sm.set_break(&Location::Address(entry)).unwrap();
+        let increment_id = sm.set_break(&Location::Function("increment".to_string())).unwrap();
+        let main_id = sm.set_break(&Location::Function("main".to_string())).unwrap();
+
+        let breakpoints = sm.list_breakpoints();
+        assert_eq!(breakpoints.len(), 3);
+        let ids = breakpoints.iter().map(|bp| bp.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![entry_id, increment_id, main_id]);
+        for bp in &breakpoints {
+            assert_eq!(sm.root_process().breakpoint_at(bp.addr).unwrap().id, bp.id);
+            assert!(bp.enabled);
+            assert_eq!(bp.hit_count, 0);
+        }
+        assert_eq!(breakpoints[0].addr, entry);
+        assert_eq!(sm.function_location(breakpoints[1].addr).as_deref(), Some("increment"));
+        assert_eq!(sm.function_location(breakpoints[2].addr).as_deref(), Some("main"));
+
+        // The entry point is in the C runtime which has no debug info
+        assert_eq!(breakpoints[0].source_loc, None);
+        let increment = breakpoints[1].source_loc.as_ref().unwrap();
+        assert!(increment.file.ends_with("loop.c"));
+        assert_eq!(increment.line, 5);
+    }
+
+    #[test]
+    #[traced_test]
+    fn breakpoint_on_line() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let location = Location::Line {
+            file: "loop.c".into(),
+            line: 6,
+        };
+        sm.set_break(&location).unwrap();
+
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.trap_reason, Some(TrapType::SoftwareBreak));
+        let pc = sm.root_process().pc().unwrap();
+        let source = sm.source_location(pc).unwrap();
+        assert!(source.file.ends_with("loop.c"));
+        assert_eq!(source.line, 6);
+
+        let nowhere = Location::Line {
+            file: "loop.c".into(),
+            line: 2,
+        };
+        assert!(sm.set_break(&nowhere).is_err());
+    }
+
+    #[test]
+    #[traced_test]
+    fn reload_symbols_keeps_locations() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let id = sm.set_break(&Location::Function("increment".to_string())).unwrap();
+        let addr = sm.list_breakpoints()[0].addr;
+
+        sm.reload_symbols().unwrap();
+        assert_eq!(sm.function_location(addr).as_deref(), Some("increment"));
+        assert_eq!(sm.list_breakpoints()[0].id, id);
+
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.trap_reason, Some(TrapType::SoftwareBreak));
+    }
+
+    #[test]
+    #[traced_test]
+    fn list_threads() {
+        let mut proc = Process::launch(Path::new("tests/data/apps/build/threads")).unwrap();
+        let threads = proc.threads().unwrap();
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].tid, proc.pid());
+        assert_eq!(threads[0].name, "threads");
+
+        proc.resume().unwrap();
+        // The threads sleep for a second so should all still be around
+        std::thread::sleep(Duration::from_millis(200));
+        proc.stop().unwrap();
+        proc.blocking_wait_on_signal(Duration::from_secs(1)).unwrap();
+
+        let threads = proc.threads().unwrap();
+        assert_eq!(threads.len(), 6);
+        assert!(threads.iter().all(|t| t.name == "threads"));
+    }
+
+    #[test]
+    #[traced_test]
+    fn syscall_stops_are_events() {
+        let mut proc = Process::launch(Path::new("tests/data/apps/build/test_project")).unwrap();
+        proc.stop_on_events();
+        for _ in 0..2 {
+            // Stops on entry and then exit of the syscall
+            ptrace::syscall(proc.pid(), None).unwrap();
+            let reason = proc.blocking_wait_on_signal(Duration::from_secs(1)).unwrap();
+            assert_eq!(reason.reason, State::Stopped);
+            assert_eq!(reason.info, Info::Signalled(Signal::SIGTRAP));
+            assert_eq!(reason.event, Some(Event::SyscallStop));
+        }
+    }
+
+    #[test]
+    #[traced_test]
+    fn backtrace_through_frame_pointers() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        // Break after the prologue so the frame pointer is set up for increment
+        let location = Location::Line {
+            file: "loop.c".into(),
+            line: 6,
+        };
+        sm.set_break(&location).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+
+        let backtrace = sm.backtrace().unwrap();
+        assert!(backtrace.frames.len() >= 2);
+        assert!(backtrace.frames.len() <= sm.settings().backtrace_limit);
+        let increment = sm.function_location(backtrace.frames[0].pc).unwrap();
+        assert!(increment.starts_with("increment"));
+        let caller = backtrace.frames[1].lookup_address();
+        assert!(sm.function_location(caller).unwrap().starts_with("main+"));
+        assert_eq!(sm.source_location(caller).unwrap().line, 11);
+
+        sm.settings_mut().backtrace_limit = 1;
+        let backtrace = sm.backtrace().unwrap();
+        assert_eq!(backtrace.frames.len(), 1);
+        assert_eq!(backtrace.truncated, Some(Truncated::Limit(1)));
+    }
+
+    #[test]
+    #[traced_test]
+    fn dprintf_logs_and_continues() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let location = Location::from_str("loop.c:6").unwrap();
+        let dprintf = Dprintf::from_str(r#""counter = %d\n", counter"#).unwrap();
+        let id = sm.set_dprintf(&location, dprintf).unwrap();
+
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.reason, State::Exited);
+        assert_eq!(reason.info, Info::Return(0));
+        for i in 0..5 {
+            assert!(logs_contain(&format!("counter = {}", i)));
+        }
+        assert_eq!(sm.root_process().breakpoints()[0].id, id);
+        assert_eq!(sm.root_process().breakpoints()[0].hit_count(), 5);
+    }
+
+    #[test]
+    #[traced_test]
+    fn dprintf_strings() {
+        let args = Args {
+            input: Some("tests/data/apps/build/greet".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let location = Location::Function("greet".to_string());
+        let dprintf = Dprintf::from_str(r#""greeting %s %u times", rdi, rsi"#).unwrap();
+        sm.set_dprintf(&location, dprintf).unwrap();
+
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.info, Info::Return(0));
+        assert!(logs_contain("greeting hello 3 times"));
+        assert!(logs_contain("greeting world 2 times"));
+    }
+
+    #[test]
+    #[traced_test]
+    fn snapshot_diff_shows_changes() {
+        let path = Path::new("tests/data/apps/build/loop");
+        let args = Args {
+            input: Some(path.into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.set_break(&Location::from_str("loop.c:6").unwrap()).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+
+        let elf = ExecutableFile::load_blocking(path).unwrap();
+        let counter = elf.global_variable("counter").unwrap().unwrap();
+        assert_eq!(counter.size, 4);
+        let counter = counter.address + sm.root_process().addr_offset;
+
+        sm.save_snapshot("a", &[(counter, 4)]).unwrap();
+        assert!(sm.diff_snapshot("a").unwrap().is_empty());
+        assert!(sm.diff_snapshot("b").is_err());
+
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        let diff = sm.diff_snapshot("a").unwrap();
+        assert_eq!(
+            diff.memory,
+            vec![ByteChange {
+                addr: counter,
+                before: 0,
+                after: 1
+            }]
+        );
+        // Stopped on the same breakpoint so the instruction pointer is the same
+        assert!(diff.registers.iter().all(|change| change.name != "rip"));
+    }
+
+    #[test]
+    #[traced_test]
+    fn partial_read_stops_at_unmapped_memory() {
+        let proc = Process::launch(Path::new("tests/data/apps/build/test_project")).unwrap();
+        let rsp = proc.get_all_registers().unwrap().regs.rsp;
+        // Nothing is mapped directly above the stack and it's much smaller than this at startup
+        let len = 512 * 1024;
+
+        let read = proc.read_memory_partial(rsp, len);
+        let fault = read.fault.unwrap();
+        assert!(fault > rsp);
+        assert_eq!(fault % 4096, 0);
+        assert_eq!(read.data.len() as u64, fault - rsp);
+        assert_eq!(proc.read_memory(rsp, len), Err(ProcessError::ReadFailed(fault)));
+
+        let read = proc.read_memory_partial(rsp, 16);
+        assert_eq!(read.fault, None);
+        assert_eq!(read.data.len(), 16);
+
+        // Only what's mapped is read rather than making room for all of it
+        let read = proc.read_memory_partial(rsp, 1 << 40);
+        assert_eq!(read.fault, Some(fault));
+        assert_eq!(proc.read_memory(rsp, 1 << 40), Err(ProcessError::ReadFailed(fault)));
+    }
+
+    #[test]
+    #[traced_test]
+    fn conditional_breakpoint_on_variable() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let location = Location::from_str("loop.c:6").unwrap();
+        let condition = Condition::from_str("counter == 3").unwrap();
+        let id = sm.set_conditional_break(&location, &condition).unwrap();
+        assert_eq!(
+            sm.list_breakpoints()[0].condition.as_deref(),
+            Some("counter == 3")
+        );
+
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.trap_reason, Some(TrapType::SoftwareBreak));
+        assert_eq!(sm.list_breakpoints()[0].hit_count, 1);
+
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.info, Info::Return(0));
+        assert_eq!(sm.list_breakpoints()[0].id, id);
+        assert_eq!(sm.list_breakpoints()[0].hit_count, 1);
+
+        let unknown = Condition::from_str("not_a_variable == 3").unwrap();
+        assert!(sm.set_conditional_break(&location, &unknown).is_err());
+    }
+
+    #[test]
+    #[traced_test]
+    fn conditional_breakpoint_on_register_and_float() {
+        let args = Args {
+            input: Some("tests/data/apps/build/greet".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args.clone()).unwrap();
+        let location = Location::Function("greet".to_string());
+        let condition = Condition::from_str("rsi == 2").unwrap();
+        sm.set_conditional_break(&location, &condition).unwrap();
+
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(sm.get_registers().unwrap().regs.rsi, 2);
+
+        // Volume doubles every call so is 2 on the second
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let condition = Condition::from_str("volume > 1.5").unwrap();
+        sm.set_conditional_break(&location, &condition).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(sm.get_registers().unwrap().regs.rsi, 2);
+    }
+
+    #[test]
+    #[traced_test]
+    fn stop_history_is_bounded() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.set_break(&Location::from_str("loop.c:6").unwrap()).unwrap();
+
+        for _ in 0..2 {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            sm.record_stop(&reason);
+        }
+        let stops = sm.stop_history().cloned().collect::<Vec<_>>();
+        assert_eq!(stops.len(), 2);
+        assert_eq!(stops[0].pc, stops[1].pc);
+        assert_eq!(stops[0].trap_reason, Some(TrapType::SoftwareBreak));
+        assert_eq!(stops[0].source_loc.as_ref().unwrap().line, 6);
+        assert!(stops[0].function.as_ref().unwrap().starts_with("increment"));
+
+        for _ in 0..STOP_HISTORY_LEN {
+            sm.step().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            sm.record_stop(&reason);
+        }
+        let stops = sm.stop_history().collect::<Vec<_>>();
+        assert_eq!(stops.len(), STOP_HISTORY_LEN);
+        assert_eq!(stops[0].pc, sm.root_process().pc().unwrap());
+        assert_eq!(stops[0].trap_reason, Some(TrapType::SingleStep));
+    }
+
+    #[test]
+    fn split_function_ranges() {
+        let elf = ExecutableFile::load_blocking(Path::new("tests/data/apps/build/split")).unwrap();
+        let function = elf.find_functions("checked_div").unwrap().remove(0);
+        let ranges = elf.function_ranges(&function);
+        assert_eq!(ranges.len(), 2, "checked_div wasn't split: {:?}", ranges);
+        assert_eq!(elf.function_start(&function), Some(ranges[0].start));
 
+        // The cold part comes before the function in the binary but still belongs to it
+        for range in &ranges {
+            let found = elf
+                .function_containing_address(range.start)
+                .unwrap()
+                .unwrap();
+            assert_eq!(elf.function_name(&found).as_deref(), Some("checked_div"));
+        }
+
+        let args = Args {
+            input: Some("tests/data/apps/build/split".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.set_break(&Location::Function("checked_div".to_string()))
+            .unwrap();
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.trap_reason, Some(TrapType::SoftwareBreak));
+        let pc = sm.root_process().pc().unwrap();
+        assert_eq!(sm.function_location(pc).as_deref(), Some("checked_div"));
+    }
+
+    #[test]
+    fn print_structs() {
+        let args = Args {
+            input: Some("tests/data/apps/build/shapes".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.set_break(&Location::Function("main".to_string()))
+            .unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+
+        let square = sm.print_variable("square").unwrap();
+        let name = square
+            .strip_prefix("{name = 0x")
+            .and_then(|s| s.split_once(','))
+            .map(|(_, rest)| rest)
+            .unwrap();
+        assert_eq!(
+            name,
+            r#" corners = {{x = 1, y = 2}, {x = 3, y = -4}}, colour = BLUE, scale = 0.5, tag = "sq"}"#
+        );
+        assert_eq!(
+            sm.print_variable("grid").unwrap(),
+            "{{1, 2, 3}, {4, 5, 6}}"
+        );
+
+        sm.settings_mut().set("print pretty", "on").unwrap();
+        let pretty = sm.print_variable("square").unwrap();
+        let lines = pretty.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "{");
+        assert!(lines[1].starts_with("  name = 0x"));
+        assert_eq!(
+            &lines[2..],
+            &[
+                "  corners = {",
+                "    {",
+                "      x = 1,",
+                "      y = 2",
+                "    },",
+                "    {",
+                "      x = 3,",
+                "      y = -4",
+                "    }",
+                "  },",
+                "  colour = BLUE,",
+                "  scale = 0.5,",
+                "  tag = \"sq\"",
+                "}",
+            ]
+        );
+        // Arrays of numbers stay on one line
+        assert_eq!(
+            sm.print_variable("grid").unwrap(),
+            "{{1, 2, 3}, {4, 5, 6}}"
+        );
+        assert!(sm.print_variable("not_a_variable").is_err());
+    }
+
+    #[test]
+    #[traced_test]
+    fn assemble_patches_instructions() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let id = sm
+            .set_break(&Location::Function("increment".to_string()))
+            .unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        let pc = sm.root_process().pc().unwrap();
+
+        // Patch under the breakpoint, the breakpoint stays and deleting it leaves the patch
+        let patch = sm.assemble(pc, &Instruction::Ret).unwrap();
+        assert_eq!(patch.new, vec![0xc3]);
+        assert_eq!(sm.root_process().read_memory(pc, 1).unwrap(), vec![0xcc]);
+        sm.delete_breakpoint(id).unwrap();
+        assert_eq!(sm.root_process().read_memory(pc, 1).unwrap(), vec![0xc3]);
+
+        let elf = ExecutableFile::load_blocking(Path::new("tests/data/apps/build/loop")).unwrap();
+        let function = elf.find_functions("increment").unwrap().remove(0);
+        let range = elf.function_ranges(&function).remove(0);
+        let offset = sm.root_process().addr_offset;
+        let long = Instruction::from_str("mov rax, 0x123456789").unwrap();
+        assert!(sm.assemble(range.end + offset - 2, &long).is_err());
+        // Two bytes ending just after the start of the second line in increment
+        let next_row = elf.line_rows_in(range).unwrap()[1] + offset;
+        sm.assemble(next_row - 1, &Instruction::Ud2).unwrap();
+        assert!(logs_contain("overwrites the instruction"));
+
+        // increment now returns straight away so the counter is never incremented
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.info, Info::Return(1));
+    }
+
+    #[test]
+    fn stdout_is_captured() {
+        let args = Args {
+            input: Some("tests/data/apps/build/chatty".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.cont().unwrap();
+
+        // Drain stdout while the process runs, the way the UI does, so output that arrives in
+        // several chunks is all picked up
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut output = String::new();
+        loop {
+            if let Some(chunk) = sm.root_process_mut().read_stdout() {
+                output.push_str(&chunk);
+            }
+            if let Some(stop) = sm.wait().unwrap() {
+                if stop.reason.is_closed() {
+                    assert_eq!(stop.info, Info::Return(0));
+                    break;
+                }
+            }
+            assert!(Instant::now() < deadline, "chatty didn't exit: {:?}", output);
+        }
+        while let Some(chunk) = sm.root_process_mut().read_stdout() {
+            output.push_str(&chunk);
+        }
+        assert_eq!(output, "first chunk from chatty\nsecond chunk: héllo\n");
+        // stderr goes down its own pipe
+        assert_eq!(
+            sm.root_process_mut().read_stderr().as_deref(),
+            Some("chatty on stderr\n")
+        );
+        assert_eq!(sm.root_process_mut().read_stderr(), None);
+    }
+
+    #[test]
+    fn breakpoints_in_each_link_kind() {
+        let kinds = [
+            ("loop", LinkKind::DynamicPie),
+            ("loop_no_pie", LinkKind::Dynamic),
+            ("loop_static", LinkKind::Static),
+            ("loop_static_pie", LinkKind::StaticPie),
+        ];
+        for (name, kind) in kinds {
+            let path = Path::new("tests/data/apps/build").join(name);
+            let elf = ExecutableFile::load_blocking(&path).unwrap();
+            assert_eq!(elf.link_kind(), kind, "{}", name);
+            assert_eq!(
+                elf.is_dynamic(),
+                matches!(kind, LinkKind::Dynamic | LinkKind::DynamicPie)
+            );
+
+            let args = Args {
+                input: Some(path),
+                pid: None,
+                ..Default::default()
+            };
+            let mut sm = DebuggerStateMachine::start(args).unwrap();
+            if matches!(kind, LinkKind::Static | LinkKind::Dynamic) {
+                assert_eq!(sm.root_process().addr_offset, 0, "{}", name);
+            } else {
+                assert_ne!(sm.root_process().addr_offset, 0, "{}", name);
+            }
+            sm.set_break(&Location::from_str("loop.c:6").unwrap()).unwrap();
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            assert_eq!(
+                reason.trap_reason,
+                Some(TrapType::SoftwareBreak),
+                "{}",
+                name
+            );
+            let pc = sm.root_process().pc().unwrap();
+            assert_eq!(sm.source_location(pc).unwrap().line, 6, "{}", name);
+        }
+    }
+
+    #[test]
+    #[traced_test]
+    fn call_functions_in_process() {
+        let args = Args {
+            input: Some("tests/data/apps/build/calc".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.set_break(&Location::Function("main".to_string()))
+            .unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        let before = sm.get_registers().unwrap().regs;
+
+        assert_eq!(sm.call_function("add", &[40, 2]).unwrap(), 42);
+        assert_eq!(
+            sm.call_function("scale", &[-3i64 as u64, 4]).unwrap() as i64,
+            -12
+        );
+        // Side effects stay but registers are back how they were
+        assert_eq!(sm.print_variable("calls").unwrap(), "1");
+        let after = sm.get_registers().unwrap().regs;
+        assert_eq!(before.rip, after.rip);
+        assert_eq!(before.rsp, after.rsp);
+        assert_eq!(before.rdi, after.rdi);
+
+        // Dereferencing null crashes, the call is abandoned and registers restored
+        let err = sm.call_function("deref", &[0]).unwrap_err();
+        assert!(err.to_string().contains("before the function returned"), "{}", err);
+        assert!(err.to_string().contains("aborted by SIGSEGV"), "{}", err);
+        assert_eq!(sm.get_registers().unwrap().regs.rip, before.rip);
+        assert!(sm.call_function("missing", &[]).is_err());
+        assert!(sm.call_function("add", &[0; 7]).is_err());
+
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.info, Info::Return(0));
+    }
+
+    #[test]
+    #[traced_test]
+    fn spurious_traps() {
+        let args = Args {
+            input: Some("tests/data/apps/build/traps".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args.clone()).unwrap();
+        sm.set_break(&Location::Function("main".to_string()))
+            .unwrap();
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.trap_reason, Some(TrapType::SoftwareBreak));
+        sm.step().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.trap_reason, Some(TrapType::SingleStep));
+
+        // The int3 in the program then the raised SIGTRAP
+        for _ in 0..2 {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            assert_eq!(reason.info, Info::Signalled(Signal::SIGTRAP));
+            assert_eq!(reason.trap_reason, Some(TrapType::Spurious));
+        }
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.info, Info::Return(2));
+
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.settings_mut().skip_spurious_traps = true;
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.info, Info::Return(2));
+        assert!(logs_contain("Continuing past spurious SIGTRAP"));
+    }
+
+    #[test]
+    #[traced_test]
+    fn auto_continue_after_launch() {
+        let args = Args {
+            input: Some("tests/data/apps/build/test_project".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let settings = Settings {
+            auto_continue: true,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start_with_settings(args, settings).unwrap();
+        assert_eq!(sm.root_process().state(), State::Running);
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.reason, State::Exited);
+        assert_eq!(reason.info, Info::Return(0));
+    }
+
+    #[test]
+    fn register_groups() {
+        let args = Args {
+            input: Some("tests/data/apps/build/test_project".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let sm = DebuggerStateMachine::start(args).unwrap();
+        let regs = sm.get_registers().unwrap();
+
+        let names = |group| {
+            regs.format_group(group)
+                .iter()
+                .map(|line| line.split_whitespace().next().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(RegisterGroup::All).len(), Registers::GENERAL_PURPOSE.len());
+        let general = names(RegisterGroup::General);
+        assert!(general.contains(&"rax".to_string()) && general.contains(&"rip".to_string()));
+        assert!(!general.contains(&"cs".to_string()));
+        assert_eq!(
+            names(RegisterGroup::Segment),
+            ["cs", "ss", "ds", "es", "fs", "gs", "fs_base", "gs_base"]
+        );
+
+        let flags = regs.format_group(RegisterGroup::Flags);
+        assert_eq!(flags.len(), 1);
+        assert!(flags[0].starts_with("eflags"));
+        // Interrupts are always enabled in user space
+        assert!(flags[0].contains(" IF "), "{}", flags[0]);
+        let rip = regs.format_group(RegisterGroup::General)
+            .into_iter()
+            .find(|line| line.starts_with("rip"))
+            .unwrap();
+        assert!(rip.contains(&format!("0x{:x}", regs.regs.rip)), "{}", rip);
+    }
+
+    #[test]
+    fn current_signal_tracks_stops() {
+        let args = Args {
+            input: Some("tests/data/apps/build/traps".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.set_break(&Location::Function("main".to_string()))
+            .unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        // Our own breakpoint isn't a signal for the process
+        assert_eq!(sm.root_process().current_signal(), None);
+
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(sm.root_process().current_signal(), Some(Signal::SIGTRAP));
+        sm.step().unwrap();
+        assert_eq!(sm.root_process().current_signal(), None);
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(sm.root_process().current_signal(), None);
+
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(sm.root_process().current_signal(), Some(Signal::SIGTRAP));
+        sm.cont().unwrap();
+        assert_eq!(sm.root_process().current_signal(), None);
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.info, Info::Return(2));
+        assert_eq!(sm.root_process().current_signal(), None);
+    }
+
+    #[test]
+    #[traced_test]
+    fn step_over_restarted_syscall() {
+        let mut proc = Process::launch(Path::new("tests/data/apps/build/sleeper")).unwrap();
+        assert_eq!(proc.interrupted_syscall(), None);
+        proc.resume().unwrap();
+        // Interrupt the nanosleep part way through
+        std::thread::sleep(Duration::from_millis(100));
+        proc.stop().unwrap();
+        let reason = proc.blocking_wait_on_signal(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.info, Info::Signalled(Signal::SIGSTOP));
+        let pc = proc.pc().unwrap();
+        // nanosleep carries on with the time left
+        assert_eq!(proc.interrupted_syscall(), Some(RESTART_SYSCALL));
+
+        // The step runs the rest of the sleep and stops after the syscall instruction
+        proc.step().unwrap();
+        let reason = proc.blocking_wait_on_signal(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.trap_reason, Some(TrapType::SingleStep));
+        assert_eq!(proc.pc().unwrap(), pc);
+        assert_eq!(proc.interrupted_syscall(), None);
+        assert_eq!(proc.get_gp_registers().unwrap().regs.rax, 0);
+        assert!(logs_contain("will be restarted"));
+
+        proc.resume().unwrap();
+        let reason = proc.blocking_wait_on_signal(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.info, Info::Return(0));
+    }
+
+    #[test]
+    #[traced_test]
+    fn continue_until_hit_count() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let id = sm
+            .set_break(&Location::Function("increment".to_string()))
+            .unwrap();
+        assert!(sm.continue_until(id + 1, 3).is_err());
+
+        sm.continue_until(id, 3).unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.trap_reason, Some(TrapType::SoftwareBreak));
+        assert_eq!(sm.list_breakpoints()[0].hit_count, 3);
+        assert_eq!(sm.print_variable("counter").unwrap(), "2");
+        assert!(logs_contain("hit 3 times, stopped at"));
+        assert!(sm.continue_until(id, 3).is_err());
+
+        // A plain continue stops at the next hit again
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(sm.list_breakpoints()[0].hit_count, 4);
+
+        // Exiting first ends it
+        sm.continue_until(id, 10).unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.info, Info::Return(0));
+        assert!(logs_contain("Stopped before breakpoint"));
+    }
+
+    #[test]
+    #[traced_test]
+    fn run_to_location() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let increment = Location::Function("increment".to_string());
+        sm.continue_to(&increment).unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.trap_reason, Some(TrapType::SoftwareBreak));
+        assert!(logs_contain("Reached run-to location"));
+        assert!(sm.list_breakpoints().is_empty());
+        assert_eq!(sm.print_variable("counter").unwrap(), "0");
+
+        // An existing breakpoint is left alone
+        let id = sm.set_break(&increment).unwrap();
+        sm.continue_to(&increment).unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(sm.print_variable("counter").unwrap(), "1");
+        assert_eq!(sm.list_breakpoints().len(), 1);
+        sm.delete_breakpoint(id).unwrap();
+
+        // main has already started so the process exits first
+        sm.continue_to(&Location::Function("main".to_string()))
+            .unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.info, Info::Return(0));
+        assert!(logs_contain("Process ended before reaching the run-to location"));
+    }
+
+    #[test]
+    #[traced_test]
+    fn status_shows_source_location() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.set_break(&Location::Function("increment".to_string()))
+            .unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        sm.log_status();
+        assert!(logs_contain("loop.c:5 in increment (0x"));
+    }
+
+    #[test]
+    fn startup_args_at_entry() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let startup = sm.startup_args().unwrap();
+        assert_eq!(startup.argv, ["tests/data/apps/build/loop"]);
+        // Our environment is passed on
+        assert_eq!(startup.envp.len(), std::env::vars().count());
+
+        // Once the program is running the stack has moved on
+        sm.set_break(&Location::Function("increment".to_string())).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert!(sm.startup_args().is_err());
+    }
+
+    #[test]
+    fn line_address_ranges() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let offset = sm.root_process().addr_offset;
+
+        let body = Location::Line {
+            file: "loop.c".into(),
+            line: 6,
+        };
+        let (file, line, ranges) = sm.line_ranges(Some(&body)).unwrap();
+        assert_eq!((file.to_str(), line), (Some("loop.c"), 6));
+        assert_eq!(ranges.len(), 1);
+        assert!(ranges[0].start > offset && ranges[0].end > ranges[0].start);
+
+        let function = Location::Function("increment".to_string());
+        let (file, line, function_ranges) = sm.line_ranges(Some(&function)).unwrap();
+        assert!(file.ends_with("loop.c"));
+        assert_eq!(line, 5);
+        // The opening line is straight before the body
+        assert_eq!(function_ranges.last().unwrap().end, ranges[0].start);
+
+        sm.set_break(&function).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        let pc = sm.root_process().pc().unwrap();
+        let (_, line, current) = sm.line_ranges(None).unwrap();
+        assert_eq!(line, 5);
+        assert!(current.iter().any(|range| range.contains(&pc)));
+
+        let (_, _, ranges) = sm.line_ranges(Some(&Location::Line {
+            file: "loop.c".into(),
+            line: 2,
+        }))
+        .unwrap();
+        assert!(ranges.is_empty());
+    }
+
+    #[test]
+    fn read_memory_matches_text_section() {
+        use object::{Object, ObjectSection};
+
+        let path = Path::new("tests/data/apps/build/test_project");
+        let args = Args {
+            input: Some(path.into()),
+            ..Default::default()
+        };
+        let sm = DebuggerStateMachine::start(args).unwrap();
+        let data = std::fs::read(path).unwrap();
+        let file = object::File::parse(&*data).unwrap();
+        let text = file.section_by_name(".text").unwrap();
+        let expected = text.data_range(file.entry(), 16).unwrap().unwrap();
+
+        let entry = file.entry() + sm.root_process().addr_offset;
+        let proc = sm.root_process();
+        assert_eq!(proc.read_memory(entry, 16).unwrap(), expected);
+        // Starting part way into a word and ending part way into another
+        assert_eq!(proc.read_memory(entry + 3, 9).unwrap(), &expected[3..12]);
+        assert_eq!(proc.read_memory(entry + 5, 0).unwrap(), Vec::<u8>::new());
+        assert_eq!(proc.read_memory(0, 8), Err(ProcessError::ReadFailed(0)));
+
+        // All of it in one read agrees with reading a word at a time
+        let start = text.address() + proc.addr_offset;
+        let whole = proc.read_memory(start, text.size() as usize).unwrap();
+        assert_eq!(whole, text.data().unwrap());
+        assert_eq!(
+            proc.read_memory_partial(start, whole.len()).data,
+            whole
+        );
+    }
+
+    #[test]
+    fn write_memory_round_trip() {
+        let mut proc = Process::launch(Path::new("tests/data/apps/build/test_project")).unwrap();
+        // argc and the start of argv, always mapped and writable at the start
+        let rsp = proc.get_gp_registers().unwrap().regs.rsp;
+        let before = proc.read_memory(rsp, 24).unwrap();
+
+        // Starts and ends part way through words so both ends are read-modify-write
+        let pattern = (1..=13).collect::<Vec<u8>>();
+        proc.write_memory(rsp + 3, &pattern).unwrap();
+        let after = proc.read_memory(rsp, 24).unwrap();
+        assert_eq!(&after[3..16], pattern.as_slice());
+        assert_eq!(&after[..3], &before[..3]);
+        assert_eq!(&after[16..], &before[16..]);
+
+        proc.write_memory(rsp, &before).unwrap();
+        assert_eq!(proc.read_memory(rsp, 24).unwrap(), before);
+        assert!(proc.write_memory(0, &[1, 2]).is_err());
+    }
+
+    #[test]
+    #[traced_test]
+    fn overwritten_breakpoint_isnt_restored() {
+        use rustybug::ptrace_control::{read_address, write_to_address};
+
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let pid = sm.root_process().pid();
+        let id = sm
+            .set_break(&Location::Function("increment".to_string()))
+            .unwrap();
+        let addr = sm.list_breakpoints()[0].addr;
+        assert_eq!(sm.root_process().read_memory(addr, 1).unwrap(), [0xcc]);
+        // Behind the breakpoint's back like self-modifying code would
+        let poke = |byte: u8| {
+            let aligned = addr & !7;
+            let shift = 8 * (addr - aligned);
+            let word = read_address(pid, aligned).unwrap();
+            let word = (word & !(0xff << shift)) | (i64::from(byte) << shift);
+            write_to_address(pid, aligned, word).unwrap();
+        };
+        poke(0x90);
+        sm.delete_breakpoint(id).unwrap();
+        assert!(logs_contain("the code changed underneath us"));
+        assert_eq!(sm.root_process().read_memory(addr, 1).unwrap(), [0x90]);
+
+        let mut settings = sm.settings().clone();
+        settings.verify_breakpoints = false;
+        sm.apply_settings(settings);
+        let id = sm
+            .set_break(&Location::Function("increment".to_string()))
+            .unwrap();
+        poke(0xf4);
+        sm.delete_breakpoint(id).unwrap();
+        // Without checking the byte saved when the breakpoint was set is written back
+        assert_eq!(sm.root_process().read_memory(addr, 1).unwrap(), [0x90]);
+    }
+
+    #[test]
+    fn evaluate_expressions() {
+        use rustybug::eval::Expr;
+
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.set_break(&Location::Function("increment".to_string()))
+            .unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+
+        let eval = |s: &str| sm.evaluate(&Expr::from_str(s).unwrap());
+        let rsp = sm.root_process().get_gp_registers().unwrap().regs.rsp;
+        assert_eq!(eval("rsp + 8 * 2").unwrap(), rsp + 16);
+        // counter is incremented after the breakpoint so is 1 on the second hit
+        assert_eq!(eval("counter").unwrap(), 1);
+        assert_eq!(eval("(counter - 5) / 2").unwrap() as i64, -2);
+        let return_address = sm.root_process().read_memory(rsp, 8).unwrap();
+        assert_eq!(
+            eval("*rsp").unwrap().to_le_bytes().as_slice(),
+            return_address.as_slice()
+        );
+        assert_eq!(eval("*(rsp + 0)").unwrap(), eval("*rsp").unwrap());
+
+        assert!(eval("1 / (counter - counter)").is_err());
+        assert!(eval("*0").is_err());
+        assert!(eval("not_a_variable + 1").is_err());
+    }
+
+    #[test]
+    fn stepping_running_process_fails() {
+        let mut proc = Process::launch(Path::new("tests/data/apps/build/dont_stop")).unwrap();
+        proc.resume().unwrap();
+        assert_eq!(proc.step(), Err(ProcessError::NotStopped(State::Running)));
+        assert_eq!(proc.resume(), Err(ProcessError::NotStopped(State::Running)));
+
+        proc.stop().unwrap();
+        proc.blocking_wait_on_signal(Duration::from_secs(1)).unwrap();
+        proc.step().unwrap();
+
+        let args = Args {
+            input: Some("tests/data/apps/build/dont_stop".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.cont().unwrap();
+        let err = sm.step().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProcessError>(),
+            Some(&ProcessError::NotStopped(State::Running))
+        );
+        assert!(sm.cont().is_err());
+    }
+
+    #[test]
+    #[traced_test]
+    fn conditional_breakpoint_on_memory() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let elf = ExecutableFile::load_blocking(Path::new("tests/data/apps/build/loop")).unwrap();
+        let counter = elf.global_variable("counter").unwrap().unwrap();
+        let addr = counter.address + sm.root_process().addr_offset;
+        // counter is an int so the word read also covers whatever comes after it
+        let word = sm.root_process().read_memory(addr, 8).unwrap();
+        let upper = u64::from_le_bytes(word.try_into().unwrap()) & !0xffff_ffff;
+
+        let location = Location::from_str("loop.c:6").unwrap();
+        let condition = Condition::from_str(&format!("*0x{:x} == {}", addr, upper | 2)).unwrap();
+        sm.set_conditional_break(&location, &condition).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        let value = sm.root_process().read_memory(addr, 4).unwrap();
+        assert_eq!(value, 2u32.to_le_bytes());
+        assert_eq!(sm.list_breakpoints()[0].hit_count, 1);
+    }
+
+    #[test]
+    #[traced_test]
+    fn vdso_functions_are_named() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let sm = DebuggerStateMachine::start(args).unwrap();
+        let vdso = sm.vdso().unwrap();
+        let addr = vdso.address_of("__vdso_clock_gettime").unwrap();
+        assert!(vdso.range().contains(&addr));
+        assert_eq!(
+            sm.function_location(addr).as_deref(),
+            Some("__vdso_clock_gettime")
+        );
+        assert_eq!(
+            sm.function_location(addr + 4).as_deref(),
+            Some("__vdso_clock_gettime+0x4")
+        );
+        assert_eq!(vdso.address_of("clock_gettime"), Some(addr));
+    }
+
+    #[test]
+    #[traced_test]
+    fn ignored_breakpoint_hits_dont_stop() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let id = sm.set_break(&Location::from_str("loop.c:6").unwrap()).unwrap();
+        sm.ignore_breakpoint(id, 3).unwrap();
+        assert_eq!(sm.list_breakpoints()[0].ignore_count, 3);
+        assert!(sm.ignore_breakpoint(id + 100, 1).is_err());
+
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.trap_reason, Some(TrapType::SoftwareBreak));
+        let bp = &sm.list_breakpoints()[0];
+        assert_eq!(bp.hit_count, 4);
+        assert_eq!(bp.ignore_count, 0);
+
+        // Stopped before the fourth increment
+        let elf = ExecutableFile::load_blocking(Path::new("tests/data/apps/build/loop")).unwrap();
+        let counter = elf.global_variable("counter").unwrap().unwrap();
+        let addr = counter.address + sm.root_process().addr_offset;
+        let value = sm.root_process().read_memory(addr, 4).unwrap();
+        assert_eq!(value, 3u32.to_le_bytes());
+    }
+
+    #[test]
+    #[traced_test]
+    fn temporary_breakpoint_only_stops_once() {
+        let args = Args {
+            input: Some("tests/data/apps/build/greet".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let location = Location::Function("greet".to_string());
+        let id = sm.set_temp_break(&location).unwrap();
+        assert!(sm.list_breakpoints()[0].temporary);
+
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.trap_reason, Some(TrapType::SoftwareBreak));
+        // First call is greet(greeting, 3)
+        assert_eq!(sm.get_registers().unwrap().regs.rsi, 3);
+        assert!(sm.list_breakpoints().is_empty());
+        assert!(sm.delete_breakpoint(id).is_err());
+
+        // greet is called again but there's nothing left to stop it
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.info, Info::Return(0));
+
+        // A temporary breakpoint doesn't take over an existing one
+        let mut sm = DebuggerStateMachine::start(Args {
+            input: Some("tests/data/apps/build/greet".into()),
+            ..Default::default()
+        })
+        .unwrap();
+        let id = sm.set_break(&location).unwrap();
+        assert_eq!(sm.set_temp_break(&location).unwrap(), id);
+        assert!(!sm.list_breakpoints()[0].temporary);
+    }
+
+    #[test]
+    #[traced_test]
+    fn finish_returns_to_caller() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        // Nothing to return to from the entry point
+        assert!(sm.finish().is_err());
+
+        let id = sm.set_break(&Location::from_str("loop.c:6").unwrap()).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        sm.delete_breakpoint(id).unwrap();
+
+        let addr = sm.finish().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.trap_reason, Some(TrapType::SoftwareBreak));
+        assert_eq!(sm.root_process().pc().unwrap(), addr);
+        assert!(sm.function_location(addr).unwrap().starts_with("main+"));
+        assert!(sm.source_location(addr).unwrap().file.ends_with("loop.c"));
+        assert!(sm.list_breakpoints().is_empty());
+        assert!(logs_contain("Returned to"));
+
+        // From the first instruction, before the frame pointer is pushed
+        let id = sm.set_break(&Location::Function("increment".to_string())).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        sm.delete_breakpoint(id).unwrap();
+        assert_eq!(sm.finish().unwrap(), addr);
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(sm.root_process().pc().unwrap(), addr);
+    }
+
+    #[test]
+    fn source_location_of_locations() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            ..Default::default()
+        };
+        let sm = DebuggerStateMachine::start(args).unwrap();
+        let loc = sm
+            .source_location_of(&Location::Function("increment".to_string()))
+            .unwrap();
+        assert!(loc.file.ends_with("loop.c"));
+        assert_eq!(loc.line, 5);
+        let loc = sm
+            .source_location_of(&Location::from_str("loop.c:11").unwrap())
+            .unwrap();
+        assert_eq!(loc.line, 11);
+        assert!(sm
+            .source_location_of(&Location::Function("missing".to_string()))
+            .is_err());
+    }
+
+    #[test]
+    #[traced_test]
+    fn next_steps_over_calls() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let id = sm.set_break(&Location::from_str("loop.c:11").unwrap()).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        sm.delete_breakpoint(id).unwrap();
+        assert_eq!(sm.print_variable("counter").unwrap(), "0");
+        let start = sm.root_process().pc().unwrap();
+
+        sm.step_over().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        let pc = sm.root_process().pc().unwrap();
+        // increment ran without stopping in it
+        assert_eq!(sm.print_variable("counter").unwrap(), "1");
+        assert!(sm.function_location(pc).unwrap().starts_with("main+"));
+        assert_ne!(sm.source_location(pc).unwrap().line, 11);
+        assert_ne!(pc, start);
+        assert!(sm.list_breakpoints().is_empty());
+
+        // Back round the loop
+        sm.step_over().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        let pc = sm.root_process().pc().unwrap();
+        assert_eq!(sm.source_location(pc).unwrap().line, 11);
+
+        // A breakpoint in the called function still stops the process
+        let id = sm
+            .set_break(&Location::Function("increment".to_string()))
+            .unwrap();
+        sm.step_over().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        let pc = sm.root_process().pc().unwrap();
+        assert!(sm.function_location(pc).unwrap().starts_with("increment"));
+        assert_eq!(sm.list_breakpoints().len(), 1);
+        sm.delete_breakpoint(id).unwrap();
+    }
+
+    #[test]
+    #[traced_test]
+    fn max_step_instructions_bounds_next() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let id = sm
+            .set_break(&Location::Function("increment".to_string()))
+            .unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        sm.delete_breakpoint(id).unwrap();
+        let line = sm
+            .source_location(sm.root_process().pc().unwrap())
+            .unwrap()
+            .line;
+
+        // One single step isn't enough for the line so the rest of it is run by continuing
+        let mut settings = sm.settings().clone();
+        settings.max_step_instructions = 1;
+        sm.apply_settings(settings);
+        sm.step_over().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert!(logs_contain("without leaving the line"));
+        let pc = sm.root_process().pc().unwrap();
+        assert!(sm.function_location(pc).unwrap().starts_with("increment"));
+        assert_ne!(sm.source_location(pc).unwrap().line, line);
+    }
+
+    #[test]
+    #[traced_test]
+    fn breakpoint_mid_instruction_warns() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let id = sm
+            .set_break(&Location::Function("increment".to_string()))
+            .unwrap();
+        let start = sm.list_breakpoints()[0].addr;
+        sm.delete_breakpoint(id).unwrap();
+        assert!(!logs_contain("in the middle of the instruction"));
+
+        // push rbp is one byte, followed by the three byte mov rbp, rsp
+        sm.set_break(&Location::Address(start + 1)).unwrap();
+        assert!(!logs_contain("in the middle of the instruction"));
+        sm.set_break(&Location::Address(start + 2)).unwrap();
+        assert!(logs_contain(&format!(
+            "0x{:x} is in the middle of the instruction at 0x{:x} (mov rbp, rsp in increment+0x1)",
+            start + 2,
+            start + 1
+        )));
+    }
+
+    #[test]
+    fn step_line_enters_functions() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let id = sm.set_break(&Location::from_str("loop.c:11").unwrap()).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        sm.delete_breakpoint(id).unwrap();
+
+        sm.step_line().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        let pc = sm.root_process().pc().unwrap();
+        assert_eq!(sm.function_location(pc).unwrap(), "increment");
+        assert_eq!(sm.source_location(pc).unwrap().line, 5);
+
+        sm.step_line().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        let pc = sm.root_process().pc().unwrap();
+        assert_eq!(sm.source_location(pc).unwrap().line, 6);
+        assert_eq!(sm.print_variable("counter").unwrap(), "0");
+
+        sm.step_line().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        let pc = sm.root_process().pc().unwrap();
+        assert_eq!(sm.source_location(pc).unwrap().line, 7);
+        assert_eq!(sm.print_variable("counter").unwrap(), "1");
+
+        // Returning stops back in main
+        sm.step_line().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        let pc = sm.root_process().pc().unwrap();
+        assert!(sm.function_location(pc).unwrap().starts_with("main+"));
+    }
+
+    #[test]
+    fn step_line_skips_functions_without_lines() {
+        let args = Args {
+            input: Some("tests/data/apps/build/chatty".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let id = sm.set_break(&Location::from_str("chatty.c:5").unwrap()).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        sm.delete_breakpoint(id).unwrap();
+
+        // printf, fflush and usleep have no line info so they're stepped over
+        for line in [6, 8, 9, 10, 11] {
+            sm.step_line().unwrap();
+            sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            let pc = sm.root_process().pc().unwrap();
+            assert_eq!(sm.source_location(pc).unwrap().line, line);
+        }
+    }
+
+    #[test]
+    fn signal_state_from_proc() {
+        let args = Args {
+            input: Some("tests/data/apps/build/user_signal".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let state = sm.root_process().signal_state().unwrap();
+        assert!(state.state.starts_with('t'), "{}", state.state);
+        assert!(!state.caught.contains(&"SIGUSR1".to_string()));
+
+        // Stopped after the handler is installed
+        let id = sm
+            .set_break(&Location::from_str("user_signal.c:22").unwrap())
+            .unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        sm.delete_breakpoint(id).unwrap();
+        let state = sm.root_process().signal_state().unwrap();
+        assert_eq!(state.caught, vec!["SIGUSR1".to_string()]);
+        assert!(state.pending.is_empty());
+    }
+
+    #[test]
+    #[traced_test]
+    fn killed_externally() {
+        let args = Args {
+            input: Some("tests/data/apps/build/sleeper".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let id = sm
+            .set_break(&Location::Function("main".to_string()))
+            .unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        sm.delete_breakpoint(id).unwrap();
+        sm.cont().unwrap();
+
+        // Like the OOM killer would while the process runs
+        let pid = sm.root_process().pid();
+        nix::sys::signal::kill(pid, Signal::SIGKILL).unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.reason, State::Terminated);
+        assert_eq!(reason.info, Info::Signalled(Signal::SIGKILL));
+
+        let process = sm.root_process();
+        assert_eq!(process.pid().as_raw(), 0);
+        assert_eq!(process.pc(), Err(ProcessError::Gone));
+        assert_eq!(process.read_memory(0x1000, 8), Err(ProcessError::Gone));
+        assert!(process.get_gp_registers().is_err());
+        assert_eq!(
+            sm.root_process_mut().wait_on_signal(),
+            Err(ProcessError::Gone)
+        );
+        assert!(sm.cont().is_err());
+        assert!(sm.step().is_err());
+        assert!(sm
+            .set_break(&Location::Function("main".to_string()))
+            .is_err());
+        assert!(!logs_contain("Couldn't get sig info"));
+
+        drop(sm);
+        assert!(!logs_contain("teardown"));
+    }
+
+    #[test]
+    fn launch_with_program_args() {
+        let args = Args {
+            input: Some("tests/data/apps/build/echo_args".into()),
+            program_args: vec!["foo".to_string(), "bar baz".to_string()],
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let startup = sm.startup_args().unwrap();
+        assert_eq!(
+            startup.argv,
+            ["tests/data/apps/build/echo_args", "foo", "bar baz"]
+        );
+
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.info, Info::Return(2));
+        let mut output = String::new();
+        while let Some(chunk) = sm.root_process_mut().read_stdout() {
+            output.push_str(&chunk);
+        }
+        assert_eq!(output, "foo\nbar baz\n");
+    }
+
+    #[test]
+    fn launch_with_environment() {
+        let mut args = Args {
+            input: Some("tests/data/apps/build/echo_env".into()),
+            program_args: vec!["RUSTYBUG_GREETING".to_string(), "PATH".to_string()],
+            ..Default::default()
+        };
+        args.set_env("RUSTYBUG_GREETING".to_string(), "hello there".to_string());
+        let output = |args: Args| {
+            let mut sm = DebuggerStateMachine::start(args).unwrap();
+            sm.cont().unwrap();
+            sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            let mut output = String::new();
+            while let Some(chunk) = sm.root_process_mut().read_stdout() {
+                output.push_str(&chunk);
+            }
+            output
+        };
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(
+            output(args.clone()),
+            format!("RUSTYBUG_GREETING=hello there\nPATH={}\n", path)
+        );
+
+        args.clean_env = true;
+        assert_eq!(
+            output(args),
+            "RUSTYBUG_GREETING=hello there\nPATH=(unset)\n"
+        );
+    }
+
+    #[test]
+    #[traced_test]
+    fn trace_record_resolves_lines() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let id = sm.set_break(&Location::from_str("loop.c:11").unwrap()).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        sm.delete_breakpoint(id).unwrap();
+        sm.settings_mut().trace_limit = 30;
+        sm.settings_mut().trace_registers = true;
+
+        let path = std::env::temp_dir().join(format!("rustybug-line-trace-{}", std::process::id()));
+        sm.start_trace(&path).unwrap();
+        assert!(logs_contain("will run very slowly"));
+        while sm.is_tracing() {
+            assert!(sm.wait().unwrap().is_none());
+        }
+
+        let trace = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let lines = trace.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 30);
+        assert!(lines[0].contains("\tmain+0x"));
+        assert!(lines[0].contains("loop.c:11\t"));
+        assert!(lines.iter().any(|x| x.contains("\tincrement") && x.contains("loop.c:6")));
+        assert!(lines.iter().all(|x| x.contains("rax=0x") && x.contains("rip=0x")));
+    }
+
+    #[test]
+    #[traced_test]
+    fn launch_in_working_directory() {
+        let mut args = Args {
+            input: Some("tests/data/apps/build/print_cwd".into()),
+            cwd: Some("tests/data".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args.clone()).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        let mut output = String::new();
+        while let Some(chunk) = sm.root_process_mut().read_stdout() {
+            output.push_str(&chunk);
+        }
+        let expected = Path::new("tests/data").canonicalize().unwrap();
+        assert_eq!(output.trim_end(), expected.display().to_string());
+
+        args.cwd = Some("tests/data/not-a-directory".into());
+        assert!(DebuggerStateMachine::start(args).is_err());
+        assert!(logs_contain("tests/data/not-a-directory isn't a directory"));
+    }
+
+    #[test]
+    #[traced_test]
+    fn diff_function_layouts() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            ..Default::default()
+        };
+        let sm = DebuggerStateMachine::start(args).unwrap();
+        let same = sm.diff_symbols(Path::new("tests/data/apps/build/loop")).unwrap();
+        assert!(same.is_empty());
+        assert_eq!(same.unchanged, 2);
+
+        let diff = sm.diff_symbols(Path::new("tests/data/apps/build/echo_args")).unwrap();
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].0, "increment");
+        assert!(diff.added.is_empty());
+        assert_eq!(diff.resized.len(), 1);
+        assert_eq!(diff.resized[0].0, "main");
+        assert_eq!(diff.unchanged, 0);
+
+        assert!(sm.diff_symbols(Path::new("tests/data/apps/build/not-a-file")).is_err());
+    }
+
+    #[test]
+    #[traced_test]
+    fn input_sent_to_stdin() {
+        let mut proc = Process::launch(Path::new("tests/data/apps/build/echo_line")).unwrap();
+        proc.write_stdin(b"hello\n").unwrap();
+        proc.resume().unwrap();
+
+        let mut output = String::new();
+        let start = Instant::now();
+        while !output.contains('\n') && start.elapsed() < Duration::from_secs(5) {
+            match proc.read_stdout() {
+                Some(chunk) => output.push_str(&chunk),
+                None => std::thread::sleep(Duration::from_millis(10)),
+            }
+        }
+        assert_eq!(output, "got: hello\n");
+
+        // Still running waiting on the next line
+        proc.write_stdin(b"second line\n").unwrap();
+        let start = Instant::now();
+        while !output.ends_with("second line\n") && start.elapsed() < Duration::from_secs(5) {
+            match proc.read_stdout() {
+                Some(chunk) => output.push_str(&chunk),
+                None => std::thread::sleep(Duration::from_millis(10)),
+            }
+        }
+        assert_eq!(output, "got: hello\ngot: second line\n");
+    }
+
+    #[test]
+    #[traced_test]
+    fn detach_leaves_process_running() {
+        let mut child = Command::new("tests/data/apps/build/dont_stop")
+            .spawn()
+            .unwrap();
+        let args = Args {
+            pid: Some(child.id() as i32),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        // No symbols when attaching, but it's stopped somewhere in its endless loop
+        let pc = sm.root_process().pc().unwrap();
+        sm.set_break(&Location::Address(pc)).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(sm.list_breakpoints()[0].hit_count, 1);
+
+        assert!(sm.detach().is_ok());
+        assert_eq!(sm.root_process().state(), State::Detached);
+        assert_eq!(sm.root_process().pc(), Err(ProcessError::Detached));
+        std::mem::drop(sm);
+
+        // A breakpoint left behind would kill it with a SIGTRAP
+        std::thread::sleep(Duration::from_millis(500));
+        assert!(child.try_wait().unwrap().is_none());
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    #[test]
+    #[traced_test]
+    fn detach_launched_process_runs_to_completion() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.set_break(&Location::Function("increment".to_string())).unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        let pid = sm.root_process().pid();
+
+        sm.cont().unwrap();
+        assert!(sm.detach().is_err());
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        sm.detach().unwrap();
+        std::mem::drop(sm);
+
+        // Still our child so we can see it exit, returning 0 means increment ran all 5 times
+        assert_eq!(waitpid(pid, None).unwrap(), WaitStatus::Exited(pid, 0));
+    }
+
+    #[test]
+    #[traced_test]
+    fn detach_lets_go_of_every_thread() {
+        let args = Args {
+            input: Some("tests/data/apps/build/threads".into()),
+            ..Default::default()
+        };
+        let settings = Settings {
+            stop_on_events: true,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start_with_settings(args, settings).unwrap();
+        sm.set_break(&Location::Function("thread_function".to_string()))
+            .unwrap();
+        loop {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if reason.trap_reason == Some(TrapType::SoftwareBreak) {
+                break;
+            }
+        }
+        let pid = sm.root_process().pid();
+        assert_ne!(sm.root_process().stopped_thread(), pid);
+        assert!(sm.root_process().traced_threads().len() > 1);
+
+        sm.detach().unwrap();
+        // Threads left traced would never be continued and main would wait on them forever.
+        // The debugger is kept until it exits so the program's output can still be written
+        assert_eq!(waitpid(pid, None).unwrap(), WaitStatus::Exited(pid, 0));
+        std::mem::drop(sm);
+    }
+
+    #[test]
+    #[traced_test]
+    fn dropping_attached_debugger_lets_threads_finish() {
+        let mut child = Command::new("tests/data/apps/build/threads")
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        // Give it time to start its threads, they sleep for a second before finishing
+        std::thread::sleep(Duration::from_millis(200));
+        let args = Args {
+            pid: Some(child.id() as i32),
+            ..Default::default()
+        };
+        let settings = Settings {
+            stop_on_events: true,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start_with_settings(args, settings).unwrap();
+        // main is waiting for the threads to be joined and carries on from here once they are
+        let pc = sm.root_process().pc().unwrap();
+        sm.set_break(&Location::Address(pc)).unwrap();
+        sm.cont().unwrap();
+        std::mem::drop(sm);
+
+        // A breakpoint left behind would kill it with a SIGTRAP
+        let start = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait().unwrap() {
+                break status;
+            }
+            if start.elapsed() > Duration::from_secs(5) {
+                let _ = child.kill();
+                panic!("Still running after being let go");
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        };
+        assert!(status.success(), "{:?}", status);
+    }
+
+    #[test]
+    #[traced_test]
+    fn disassemble_whole_function() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.set_break(&Location::Function("increment".to_string())).unwrap();
+
+        let ranges = sm.disassemble_function("increment").unwrap();
+        assert_eq!(ranges.len(), 1);
+        let (range, instructions) = &ranges[0];
+        let first = &instructions[0];
+        assert_eq!(first.addr, range.start);
+        // push rbp with the breakpoint's int3 taken back out
+        assert_eq!(first.bytes, [0x55]);
+        assert_eq!(first.text.as_deref(), Some("push rbp"));
+        assert!(first.breakpoint);
+        assert!(instructions[1..].iter().all(|i| !i.breakpoint));
+        let last = instructions.last().unwrap();
+        assert_eq!(last.flow, Some(Flow::Return));
+        assert_eq!(last.text.as_deref(), Some("ret"));
+        let len = instructions.iter().map(|i| i.bytes.len() as u64).sum::<u64>();
+        assert_eq!(range.start + len, range.end);
+
+        // main calls increment
+        let main = sm.disassemble_function("main").unwrap();
+        assert!(main[0]
+            .1
+            .iter()
+            .any(|i| i.flow == Some(Flow::Call(Some(range.start)))));
+
+        assert!(sm.disassemble_function("not_a_function").is_err());
+    }
+
+    #[test]
+    #[traced_test]
+    fn disassemble_split_function() {
+        let args = Args {
+            input: Some("tests/data/apps/build/split".into()),
+            ..Default::default()
+        };
+        let sm = DebuggerStateMachine::start(args).unwrap();
+        let ranges = sm.disassemble_function("checked_div").unwrap();
+        assert_eq!(ranges.len(), 2);
+        for (range, instructions) in &ranges {
+            assert_eq!(instructions[0].addr, range.start);
+            assert!(instructions.iter().all(|i| range.contains(&i.addr)));
+        }
+    }
+
+    #[test]
+    #[traced_test]
+    fn signals_forwarded_on_continue() {
+        let args = Args {
+            input: Some("tests/data/apps/build/user_signal".into()),
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.cont().unwrap();
+        let stop = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(stop.info, Info::Signalled(Signal::SIGUSR1));
+        assert_eq!(sm.root_process().current_signal(), Some(Signal::SIGUSR1));
+
+        // The handler exits cleanly, if the signal were dropped it would loop forever
+        sm.cont().unwrap();
+        let stop = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(stop.reason, State::Exited);
+        assert_eq!(stop.info, Info::Return(0));
+        assert!(logs_contain("Passing SIGUSR1 on to the process"));
+    }
+
+    #[test]
+    #[traced_test]
+    fn nopass_signal_then_inject() {
+        let args = Args {
+            input: Some("tests/data/apps/build/user_signal".into()),
+            ..Default::default()
+        };
+        let mut settings = Settings::default();
+        settings.nopass_signals.push(Signal::SIGUSR1);
+        let mut sm = DebuggerStateMachine::start_with_settings(args, settings).unwrap();
+        sm.cont().unwrap();
+        let stop = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(stop.info, Info::Signalled(Signal::SIGUSR1));
+
+        // Dropped so it carries on in its loop
+        sm.cont().unwrap();
+        assert_eq!(
+            sm.blocking_wait(Duration::from_millis(1500))
+                .unwrap_err()
+                .downcast::<ProcessError>()
+                .unwrap(),
+            ProcessError::Timeout
+        );
+        assert_eq!(sm.root_process().state(), State::Running);
+
+        // Interrupting doesn't pass the SIGSTOP on, then the signal can be sent by hand
+        sm.root_process().stop().unwrap();
+        let stop = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(stop.info, Info::Signalled(Signal::SIGSTOP));
+        sm.cont_with_signal(Some(Signal::SIGUSR1)).unwrap();
+        let stop = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(stop.reason, State::Exited);
+        assert_eq!(stop.info, Info::Return(0));
+    }
+
+    #[test]
+    #[traced_test]
+    fn register_relative_addresses() {
+        let proc = Process::launch(Path::new("tests/data/apps/build/test_project")).unwrap();
+        let rsp = proc.get_all_registers().unwrap().regs.rsp;
+
+        let addr = parse_address("rsp").unwrap();
+        assert_eq!(addr.resolve(&proc).unwrap(), rsp);
+        let addr = parse_address("rsp+0x10").unwrap();
+        assert_eq!(addr.resolve(&proc).unwrap(), rsp + 16);
+        let addr = parse_address("rsp-8").unwrap();
+        assert_eq!(addr.resolve(&proc).unwrap(), rsp - 8);
+
+        let len = parse_length("2g").unwrap();
+        let addr = parse_address("rsp").unwrap();
+        let start = addr.resolve_range(&proc, len).unwrap();
+        assert_eq!(proc.read_memory(start, len).unwrap().len(), 16);
+
+        let end = Address::Absolute(u64::MAX - 4);
+        assert!(matches!(
+            end.resolve_range(&proc, 8),
+            Err(AddressError::OutOfRange(_))
+        ));
+    }
+
+    #[test]
+    #[traced_test]
+    fn catch_syscalls_counts_writes() {
+        let args = Args {
+            input: Some("tests/data/apps/build/chatty".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.set_catch_syscalls(true);
+        let mut entries = 0;
+        let mut writes = 0;
+        let mut exits = 0;
+        loop {
+            sm.cont().unwrap();
+            let stop = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if stop.reason.is_closed() {
+                assert_eq!(stop.info, Info::Return(0));
+                break;
+            }
+            if stop.event != Some(Event::SyscallStop) {
+                continue;
+            }
+            let syscall = sm.syscall_stop().unwrap();
+            if syscall.is_entry() {
+                entries += 1;
+                if syscall.number() == 1 {
+                    writes += 1;
+                }
+            } else {
+                exits += 1;
+            }
+        }
+        assert_eq!(writes, 3);
+        // exit_group never returns so has no exit stop
+        assert_eq!(entries, exits + 1);
+        assert!(logs_contain("syscall write(0x1, "));
+        assert!(logs_contain("syscall write = 24"));
+    }
+
+    #[test]
+    #[traced_test]
+    fn call_left_at_signal_without_unwinding() {
+        let args = Args {
+            input: Some("tests/data/apps/build/calc".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut settings = Settings::default();
+        settings.set("unwind-on-signal", "off").unwrap();
+        let mut sm = DebuggerStateMachine::start_with_settings(args, settings).unwrap();
+        sm.set_break(&Location::Function("main".to_string()))
+            .unwrap();
+        sm.cont().unwrap();
+        sm.blocking_wait(Duration::from_secs(5)).unwrap();
+
+        let err = sm.call_function("deref", &[0]).unwrap_err();
+        let Some(CallError::Signalled { signal, pc }) = err.downcast_ref::<CallError>() else {
+            panic!("call wasn't stopped by a signal: {}", err);
+        };
+        assert_eq!(*signal, Signal::SIGSEGV);
+        // Left at the crash inside deref rather than back in main
+        let regs = sm.get_registers().unwrap().regs;
+        assert_eq!(regs.rip, *pc);
+        assert!(sm.function_location(*pc).unwrap().starts_with("deref"));
+
+        // The process still has the signal so continuing delivers it
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.info, Info::Signalled(Signal::SIGSEGV));
+        assert!(reason.reason.is_closed());
+        assert_eq!(
+            sm.last_exit_status(),
+            Some(Info::Signalled(Signal::SIGSEGV))
+        );
+    }
+
+    #[test]
+    fn exit_status_kept_after_exit() {
+        let args = Args {
+            input: Some("tests/data/apps/build/echo_args".into()),
+            program_args: vec!["one".to_string()],
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        assert_eq!(sm.last_exit_status(), None);
+
+        sm.cont().unwrap();
+        let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.reason, State::Exited);
+        assert!(!reason.core_dumped);
+        assert_eq!(sm.last_exit_status(), Some(Info::Return(1)));
+    }
+
+    #[test]
+    #[traced_test]
+    fn thread_breakpoint_skips_other_threads() {
+        let args = Args {
+            input: Some("tests/data/apps/build/threads".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let settings = Settings {
+            stop_on_events: true,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start_with_settings(args, settings).unwrap();
+        let start = Location::Function("thread_function".to_string());
+        assert!(sm.set_thread_break(&start, Pid::from_raw(1)).is_err());
+        sm.set_break(&start).unwrap();
+        let thread = loop {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if reason.trap_reason == Some(TrapType::SoftwareBreak) {
+                break sm.root_process().stopped_thread();
+            }
+        };
+
+        // Every thread gets to the printf after the sleep, only the one picked stops there
+        let done = Location::Line {
+            file: "threads.c".into(),
+            line: 12,
+        };
+        let id = sm.set_thread_break(&done, thread).unwrap();
+        let bp = sm.list_breakpoints().into_iter().find(|bp| bp.id == id).unwrap();
+        assert_eq!(bp.thread, Some(thread));
+        let mut stopped = vec![];
+        loop {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if reason.reason == State::Exited {
+                assert_eq!(reason.info, Info::Return(0));
+                break;
+            }
+            if sm.root_process().pc().ok() == Some(bp.addr) {
+                stopped.push(sm.root_process().stopped_thread());
+            }
+        }
+        assert_eq!(stopped, [thread]);
+        let bp = sm.list_breakpoints().into_iter().find(|bp| bp.id == id).unwrap();
+        assert_eq!(bp.hit_count, 1);
+        assert!(logs_contain(&format!("Breakpoint is for thread {}", thread)));
+    }
+
+    #[test]
+    #[traced_test]
+    fn threads_are_tracked_and_stop() {
+        let args = Args {
+            input: Some("tests/data/apps/build/threads".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let settings = Settings {
+            stop_on_events: true,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start_with_settings(args, settings).unwrap();
+        let pid = sm.root_process().pid();
+        assert_eq!(sm.root_process().traced_threads(), &[pid]);
+        sm.set_break(&Location::Function("thread_function".to_string()))
+            .unwrap();
+
+        let mut stopped = vec![];
+        loop {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if reason.reason == State::Exited {
+                assert_eq!(reason.info, Info::Return(0));
+                break;
+            }
+            if reason.trap_reason == Some(TrapType::SoftwareBreak) {
+                let process = sm.root_process();
+                let thread = process.stopped_thread();
+                assert_ne!(thread, pid);
+                assert!(process.traced_threads().contains(&thread));
+                let pcs = process.thread_pcs();
+                let (_, pc) = pcs.iter().find(|(tid, _)| *tid == thread).unwrap();
+                assert_eq!(*pc, Some(process.pc().unwrap()));
+                assert!(sm
+                    .function_location(pc.unwrap())
+                    .unwrap()
+                    .starts_with("thread_function"));
+                stopped.push(thread);
+            }
+        }
+        stopped.sort();
+        stopped.dedup();
+        assert_eq!(stopped.len(), 5);
+    }
+
+    #[test]
+    fn function_signatures_from_debug_info() {
+        let args = Args {
+            input: Some("tests/data/apps/build/calc".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        for (function, signature) in [
+            ("add", "add(a: int, b: int) -> int"),
+            ("scale", "scale(x: long int, factor: long int) -> long int"),
+            ("deref", "deref(p: *mut int) -> int"),
+            ("main", "main() -> int"),
+        ] {
+            let id = sm
+                .set_break(&Location::Function(function.to_string()))
+                .unwrap();
+            let addr = sm
+                .list_breakpoints()
+                .iter()
+                .find(|bp| bp.id == id)
+                .unwrap()
+                .addr;
+            assert_eq!(sm.function_signature(addr).as_deref(), Some(signature));
+            // Only the start of a function has a signature
+            assert_eq!(sm.function_signature(addr + 1), None);
+        }
+    }
+
+    #[test]
+    #[traced_test]
+    fn follow_fork_parent_lets_child_go() {
+        let args = Args {
+            input: Some("tests/data/apps/build/forker".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let settings = Settings {
+            stop_on_events: true,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start_with_settings(args, settings).unwrap();
+        sm.set_break(&Location::Function("child_work".to_string()))
+            .unwrap();
+
+        let mut forked = false;
+        loop {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if reason.reason == State::Exited {
+                // The parent saw the child exit normally so it didn't hit our breakpoint
+                assert_eq!(reason.info, Info::Return(0));
+                break;
+            }
+            assert_ne!(reason.trap_reason, Some(TrapType::SoftwareBreak));
+            forked |= reason.event == Some(Event::Fork);
+        }
+        assert!(forked);
+        assert!(logs_contain("Detached from forked child"));
+    }
+
+    #[test]
+    #[traced_test]
+    fn follow_fork_child_traces_child() {
+        let args = Args {
+            input: Some("tests/data/apps/build/forker".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let settings = Settings {
+            stop_on_events: true,
+            follow_fork: FollowFork::Child,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start_with_settings(args, settings).unwrap();
+        let pid = sm.root_process().pid();
+        sm.set_break(&Location::Function("child_work".to_string()))
+            .unwrap();
+
+        let mut child = None;
+        loop {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if reason.reason == State::Exited {
+                // The parent reaped the child so it didn't become a zombie
+                assert_eq!(reason.info, Info::Return(0));
+                break;
+            }
+            if reason.trap_reason == Some(TrapType::SoftwareBreak) {
+                let process = sm.root_process();
+                let thread = process.stopped_thread();
+                assert_ne!(thread, pid);
+                assert_eq!(process.forked_children(), &[thread]);
+                child = Some(thread);
+            }
+        }
+        let child = child.unwrap();
+        assert!(logs_contain(&format!(
+            "Forked child {} exited with code 3",
+            child
+        )));
+    }
+
+    #[test]
+    fn breakpoints_left_alone_while_forked_child_stopped() {
+        let args = Args {
+            input: Some("tests/data/apps/build/forker".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let settings = Settings {
+            stop_on_events: true,
+            follow_fork: FollowFork::Child,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start_with_settings(args, settings).unwrap();
+        let id = sm
+            .set_break(&Location::Function("child_work".to_string()))
+            .unwrap();
+        let child = loop {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if reason.trap_reason == Some(TrapType::SoftwareBreak) {
+                break sm.root_process().stopped_thread();
+            }
+        };
+
+        // These would write to the child's copy of the parent's memory
+        let err = ProcessError::InForkedChild(child);
+        let main = Location::Function("main".to_string());
+        let set = sm.set_break(&main).unwrap_err();
+        assert_eq!(set.downcast_ref::<ProcessError>(), Some(&err));
+        let toggle = sm.toggle_breakpoint(id).unwrap_err();
+        assert_eq!(toggle.downcast_ref::<ProcessError>(), Some(&err));
+        let delete = sm.delete_breakpoint(id).unwrap_err();
+        assert_eq!(delete.downcast_ref::<ProcessError>(), Some(&err));
+        assert_eq!(sm.list_breakpoints().len(), 1);
+
+        loop {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if reason.reason == State::Exited {
+                assert_eq!(reason.info, Info::Return(0));
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn debug_registers_read_back() {
+        let mut proc = Process::launch(Path::new("tests/data/apps/build/loop")).unwrap();
+        let regs = proc.get_debug_registers().unwrap();
+        assert_eq!(regs.dr0, 0);
+        assert_eq!(regs.dr7, 0);
+
+        let pc = proc.pc().unwrap();
+        proc.set_debug_registers(DebugRegisters { dr0: pc, ..regs })
+            .unwrap();
+        let read = proc.get_debug_registers().unwrap();
+        assert_eq!(read.dr0, pc);
+        assert_eq!(read, DebugRegisters { dr0: pc, ..regs });
+    }
+
+    #[test]
+    fn load_offset_from_entry_matches_proc() {
+        for name in ["loop", "loop_no_pie", "loop_static", "loop_static_pie"] {
+            let path = Path::new("tests/data/apps/build").join(name);
+            let elf = ExecutableFile::load_blocking(&path).unwrap();
+            let args = Args {
+                input: Some(path),
+                pid: None,
+                ..Default::default()
+            };
+            let sm = DebuggerStateMachine::start(args).unwrap();
+            let process = sm.root_process();
+            assert!(process.proc_accessible());
+            // Without /proc the offset worked out from the auxv is used, it should agree
+            assert_eq!(
+                offset_from_entry(process, &elf),
+                Some(process.addr_offset),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    #[traced_test]
+    fn hardware_breakpoint_hit_across_continues() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let id = sm
+            .set_hardware_break(&Location::Function("increment".to_string()))
+            .unwrap();
+        let bp = sm.root_process().breakpoints()[0];
+        assert!(bp.is_hardware());
+        let addr = bp.pc;
+        // The code is left as it is
+        assert_ne!(sm.root_process().read_memory(addr, 1).unwrap(), [0xCC]);
+
+        let mut hits = 0;
+        loop {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if reason.reason == State::Exited {
+                assert_eq!(reason.info, Info::Return(0));
+                break;
+            }
+            assert_eq!(reason.trap_reason, Some(TrapType::HardwareBreak));
+            assert_eq!(sm.root_process().pc().unwrap(), addr);
+            hits += 1;
+            assert!(hits <= 5, "Breakpoint hit too many times");
+        }
+        assert_eq!(hits, 5);
+        let bp = sm.root_process().breakpoints()[0];
+        assert_eq!((bp.id, bp.hit_count()), (id, 5));
+    }
+
+    #[test]
+    fn hardware_breakpoint_slots_run_out() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let mut ids = vec![];
+        for function in ["main", "increment"] {
+            let location = Location::Function(function.to_string());
+            ids.push(sm.set_hardware_break(&location).unwrap());
+            let addr = sm.root_process().breakpoints().last().unwrap().pc;
+            ids.push(sm.set_hardware_break(&Location::Address(addr + 1)).unwrap());
+        }
+        let main = sm.root_process().breakpoints()[0].pc;
+        let err = sm
+            .set_hardware_break(&Location::Address(main + 2))
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProcessError>(),
+            Some(&ProcessError::NoFreeDebugRegister)
+        );
+        // Software breakpoints don't need a slot
+        sm.set_break(&Location::Address(main + 3)).unwrap();
+
+        // Disabling one frees its slot up for another
+        assert!(!sm.toggle_breakpoint(ids[0]).unwrap());
+        sm.set_hardware_break(&Location::Address(main + 2)).unwrap();
+        assert!(sm.toggle_breakpoint(ids[0]).is_err());
+        sm.delete_breakpoint(ids[1]).unwrap();
+        assert!(sm.toggle_breakpoint(ids[0]).unwrap());
+    }
+
+    #[test]
+    fn hardware_breakpoint_set_before_threads_start() {
+        let args = Args {
+            input: Some("tests/data/apps/build/threads".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let settings = Settings {
+            stop_on_events: true,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start_with_settings(args, settings).unwrap();
+        // None of the threads exist yet
+        sm.set_hardware_break(&Location::Function("thread_function".to_string()))
+            .unwrap();
+
+        let mut stopped = vec![];
+        loop {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if reason.reason == State::Exited {
+                assert_eq!(reason.info, Info::Return(0));
+                break;
+            }
+            if reason.trap_reason == Some(TrapType::HardwareBreak) {
+                stopped.push(sm.root_process().stopped_thread());
+            }
+        }
+        stopped.sort();
+        stopped.dedup();
+        assert_eq!(stopped.len(), 5);
+    }
+
+    #[test]
+    fn hardware_breakpoint_stops_running_threads() {
+        let mut sm = stop_in_first_thread();
+        // The other threads are already running and sleep before getting here
+        let done = Location::Line {
+            file: "threads.c".into(),
+            line: 12,
+        };
+        sm.set_hardware_break(&done).unwrap();
+
+        let mut stopped = vec![];
+        loop {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if reason.reason == State::Exited {
+                assert_eq!(reason.info, Info::Return(0));
+                break;
+            }
+            if reason.trap_reason == Some(TrapType::HardwareBreak) {
+                stopped.push(sm.root_process().stopped_thread());
+            }
+        }
+        stopped.sort();
+        stopped.dedup();
+        assert_eq!(stopped.len(), 5);
+    }
+
+    #[test]
+    fn hardware_breakpoints_shared_by_threads() {
+        let mut sm = stop_in_first_thread();
+        let first = sm.root_process().stopped_thread();
+        let done = Location::Line {
+            file: "threads.c".into(),
+            line: 12,
+        };
+        let id = sm.set_hardware_break(&done).unwrap();
+        let bp = sm.list_breakpoints().into_iter().find(|bp| bp.id == id).unwrap();
+        let addr = bp.addr;
+        loop {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if reason.trap_reason == Some(TrapType::SoftwareBreak)
+                && sm.root_process().stopped_thread() != first
+            {
+                break;
+            }
+        }
+
+        // Stopped in another thread the slots used from the first are still taken
+        let mut others = vec![];
+        for offset in 1..4 {
+            let location = Location::Address(addr + offset);
+            others.push(sm.set_hardware_break(&location).unwrap());
+        }
+        let err = sm
+            .set_hardware_break(&Location::Address(addr + 4))
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProcessError>(),
+            Some(&ProcessError::NoFreeDebugRegister)
+        );
+        for other in others {
+            sm.delete_breakpoint(other).unwrap();
+        }
+
+        // Turned off from here none of the threads stop at it
+        assert!(!sm.toggle_breakpoint(id).unwrap());
+        loop {
+            sm.cont().unwrap();
+            let reason = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if reason.reason == State::Exited {
+                assert_eq!(reason.info, Info::Return(0));
+                break;
+            }
+            assert_ne!(reason.trap_reason, Some(TrapType::HardwareBreak));
+        }
+    }
+
+    #[test]
+    #[traced_test]
+    fn initial_stop_timing_out_leaves_process_running() {
+        let mut child = Command::new("tests/data/apps/build/slow_vfork")
+            .spawn()
+            .unwrap();
+        // Give it time to get into the vfork
+        std::thread::sleep(Duration::from_millis(500));
+
+        let mut proc = Process::attach(Pid::from_raw(child.id() as i32)).unwrap();
+        assert!(logs_contain("Timed out waiting for the process to stop"));
+        assert_eq!(proc.state(), State::Running);
+
+        // The stop turns up once the vfork child exits
+        let stop = proc.blocking_wait_on_signal(Duration::from_secs(10)).unwrap();
+        assert_eq!(stop.reason, State::Stopped);
+        assert_eq!(proc.state(), State::Stopped);
+
+        proc.detach().unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+
+    #[test]
+    #[traced_test]
+    fn seccomp_trace_is_an_event() {
+        let mut proc = Process::launch(Path::new("tests/data/apps/build/seccomp_trace")).unwrap();
+        proc.stop_on_events();
+        proc.resume().unwrap();
+        let reason = proc.blocking_wait_on_signal(Duration::from_secs(5)).unwrap();
+        assert_eq!(reason.reason, State::Stopped);
+        assert_eq!(reason.event, Some(Event::Seccomp));
+
+        // Then the exit event before it's gone
+        let mut reason = reason;
+        while reason.reason != State::Exited {
+            proc.resume().unwrap();
+            reason = proc.blocking_wait_on_signal(Duration::from_secs(5)).unwrap();
+            assert_ne!(reason.event, Some(Event::Seccomp));
+        }
+        assert_eq!(reason.info, Info::Return(0));
+    }
+
+    #[test]
+    #[traced_test]
+    fn assemble_outside_the_executable_doesnt_panic() {
+        let args = Args {
+            input: Some("tests/data/apps/build/loop".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        let offset = sm.root_process().addr_offset;
+        assert!(offset > 0x1000);
+        // Below where the executable is loaded, nothing's mapped there
+        assert!(sm.assemble(0x1000, &Instruction::Nop).is_err());
+        // Would wrap around the end of memory
+        let long = Instruction::from_str("mov rax, 0x123456789").unwrap();
+        assert!(sm.assemble(u64::MAX - 2, &long).is_err());
+    }
+
+    #[test]
+    #[traced_test]
+    fn failing_syscall_shows_errno() {
+        let args = Args {
+            input: Some("tests/data/apps/build/open_missing".into()),
+            pid: None,
+            ..Default::default()
+        };
+        let mut sm = DebuggerStateMachine::start(args).unwrap();
+        sm.set_catch_syscalls(true);
+        let mut failed_open = None;
+        loop {
+            sm.cont().unwrap();
+            let stop = sm.blocking_wait(Duration::from_secs(5)).unwrap();
+            if stop.reason.is_closed() {
+                assert_eq!(stop.info, Info::Return(0));
+                break;
+            }
+            if stop.event != Some(Event::SyscallStop) {
+                continue;
+            }
+            // openat of the missing file, the loader's openats before it succeed
+            if let SyscallStop::Exit {
+                number: 257,
+                ret: SyscallReturn::Error(errno),
+            } = sm.syscall_stop().unwrap()
+            {
+                failed_open = Some(errno);
+            }
+        }
+        assert_eq!(failed_open, Some(libc::ENOENT));
+        assert!(logs_contain(
+            "syscall openat = -1 ENOENT (No such file or directory)"
+        ));
     }
 }